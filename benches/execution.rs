@@ -0,0 +1,70 @@
+//! `execution` : Benchmarks of the core execution loop, in instructions per second.
+//! Run them with `cargo bench`; each workload is a `criterion` group, run once with `Vm::run` and once
+//! with `Vm::run_precompiled`, and its throughput is the number of instructions the workload executes.
+//!
+//! - `add_loop` : a tight loop of register ADDs and a BR
+//! - `ldi_loop` : a loop of indirect loads and stores, which go through the memory bus
+//! - `mixed` : `fixtures/mixed.asm`, which fills, sorts and sums an array with subroutines
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use lc3_vm::assembler;
+use lc3_vm::hardware::io::BufferIo;
+use lc3_vm::vm::{RunResult, Vm};
+
+const ADD_LOOP: &str = ".ORIG x3000
+        LD R1, COUNT
+LOOP    ADD R0, R0, #1
+        ADD R2, R2, R0
+        ADD R3, R2, #-1
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #30000
+        .END";
+
+const LDI_LOOP: &str = ".ORIG x3000
+        LD R1, COUNT
+LOOP    LDI R0, PTR
+        ADD R0, R0, #1
+        STI R0, PTR
+        LDI R2, PTR
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #20000
+PTR     .FILL DATA
+DATA    .FILL #0
+        .END";
+
+const MIXED: &str = include_str!("fixtures/mixed.asm");
+
+fn execution(c: &mut Criterion) {
+    for (name, source) in [
+        ("add_loop", ADD_LOOP),
+        ("ldi_loop", LDI_LOOP),
+        ("mixed", MIXED),
+    ] {
+        let program = assembler::assemble(source).expect("The workload assembles.");
+        let load = || {
+            let mut vm = Vm::with_io(BufferIo::new());
+            vm.load_program(&program);
+            vm
+        };
+        let mut vm = load();
+        assert_eq!(RunResult::Halted, vm.run());
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(vm.steps_executed()));
+        for (path, run) in [
+            ("run", Vm::run as fn(&mut Vm<BufferIo>) -> RunResult),
+            ("precompiled", Vm::run_precompiled),
+        ] {
+            group.bench_function(path, |b| {
+                b.iter_batched_ref(load, run, BatchSize::SmallInput)
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, execution);
+criterion_main!(benches);
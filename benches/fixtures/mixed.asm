@@ -0,0 +1,69 @@
+; mixed.asm : a workload for `benches/execution.rs` which touches most of the ISA.
+; It fills an array with a pseudo-random sequence, bubble sorts it with a subroutine
+; and sums it, 16 times over, then halts. Nothing is printed.
+        .ORIG x3000
+        LD R6, ROUNDS
+ROUND   JSR FILL
+        JSR SORT
+        JSR SUM
+        ADD R6, R6, #-1
+        BRp ROUND
+        HALT
+ROUNDS  .FILL #16
+
+; FILL : ARRAY[i] = seed, seed = seed * 5 + 3 (mod 2^16), for i in 0 .. LEN
+FILL    LEA R1, ARRAY
+        LD R2, LEN
+        LD R0, SEED
+FLOOP   STR R0, R1, #0
+        ADD R3, R0, R0
+        ADD R3, R3, R3
+        ADD R0, R3, R0
+        ADD R0, R0, #3
+        ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRp FLOOP
+        ST R0, SEED
+        RET
+
+; SORT : bubble sort ARRAY, comparing the words as signed numbers
+SORT    LD R2, LEN
+        ADD R2, R2, #-1
+OUTER   LEA R1, ARRAY
+        ADD R5, R2, #0
+INNER   LDR R3, R1, #0
+        LDR R4, R1, #1
+        NOT R0, R4
+        ADD R0, R0, #1
+        ADD R0, R3, R0
+        BRnz NOSWAP
+        STR R4, R1, #0
+        STR R3, R1, #1
+NOSWAP  ADD R1, R1, #1
+        ADD R5, R5, #-1
+        BRp INNER
+        ADD R2, R2, #-1
+        BRp OUTER
+        RET
+
+; SUM : TOTAL = ARRAY[0] + ... + ARRAY[LEN - 1], through a pointer
+SUM     AND R0, R0, #0
+        LEA R1, ARRAY
+        ST R1, PTR
+        LD R2, LEN
+SLOOP   LDI R3, PTR
+        ADD R0, R0, R3
+        LD R1, PTR
+        ADD R1, R1, #1
+        ST R1, PTR
+        ADD R2, R2, #-1
+        BRp SLOOP
+        ST R0, TOTAL
+        RET
+
+LEN     .FILL #32
+SEED    .FILL #7
+PTR     .FILL #0
+TOTAL   .FILL #0
+ARRAY   .BLKW #32
+        .END
@@ -0,0 +1,42 @@
+; golden.asm : executes every implemented opcode with known inputs (see `vm_test::golden_program_...`).
+; Reads one key, so expects "!" as input; prints "ok!HALT".
+        .ORIG x3000
+        AND R0, R0, #0      ; R0 = 0
+        ADD R0, R0, #7      ; R0 = 7
+        ADD R1, R0, R0      ; R1 = 14
+        NOT R2, R1          ; R2 = xFFF1
+        LD R3, CONST        ; R3 = x1234
+        LEA R4, DATA        ; R4 = x3021
+        LDR R5, R4, #1      ; R5 = COUNT = 3
+        STR R1, R4, #0      ; DATA = 14
+        ST R2, NEG          ; NEG = xFFF1
+        LDI R6, PTR         ; R6 = CONST = x1234
+        STI R0, OUTPTR      ; RESULT = 7
+        AND R1, R1, #0
+LOOP    ADD R1, R1, R0
+        ADD R5, R5, #-1
+        BRp LOOP            ; R1 = 21
+        JSR DOUBLE          ; R1 = 42
+        LEA R4, INC
+        JSRR R4             ; R1 = 43
+        LEA R5, DONE
+        JMP R5
+SKIPPED ADD R1, R1, #10     ; never executed
+DONE    LEA R0, MSG
+        PUTS
+        GETC                ; R0 = '!'
+        OUT
+        HALT                ; R7 = x301A
+DOUBLE  ADD R1, R1, R1
+        RET
+INC     ADD R1, R1, #1
+        RET
+CONST   .FILL x1234
+PTR     .FILL CONST
+OUTPTR  .FILL RESULT
+DATA    .FILL #0
+COUNT   .FILL #3
+NEG     .FILL #0
+RESULT  .FILL #0
+MSG     .STRINGZ "ok"
+        .END
@@ -0,0 +1,130 @@
+; A minimal LC-3 operating system.
+; It fills in the trap vector table and implements the standard trap routines
+; (GETC, OUT, PUTS, IN, PUTSP and HALT) on top of the memory-mapped devices.
+; `resources/os.obj` is the assembled form of this file; it is embedded by `Vm::load_os`.
+
+        .ORIG x0000
+; trap vector table, x0000 - x00FF
+; (`.BLKW count, value` fills the block with `value` instead of zeros)
+        .BLKW x20, BAD_TRAP
+        .FILL T_GETC    ; x20
+        .FILL T_OUT     ; x21
+        .FILL T_PUTS    ; x22
+        .FILL T_IN      ; x23
+        .FILL T_PUTSP   ; x24
+        .FILL T_HALT    ; x25
+        .BLKW xDA, BAD_TRAP
+
+; interrupt vector table, x0100 - x01FF
+        .BLKW x100, x0000
+
+; trap routines, starting at x0200
+; GETC : read a single character into R0, without echo.
+T_GETC  LDI R0, KBSR_A
+        BRzp T_GETC
+        LDI R0, KBDR_A
+        RET
+
+; OUT : write the character in R0 to the display.
+T_OUT   ST R1, OUT_R1
+OUT_W   LDI R1, DSR_A
+        BRzp OUT_W
+        STI R0, DDR_A
+        LD R1, OUT_R1
+        RET
+OUT_R1  .FILL x0000
+
+; PUTS : write the null-terminated string (one character per word) starting at R0.
+T_PUTS  ST R0, PUTS_R0
+        ST R1, PUTS_R1
+        ST R2, PUTS_R2
+PUTS_L  LDR R1, R0, #0
+        BRz PUTS_D
+PUTS_W  LDI R2, DSR_A
+        BRzp PUTS_W
+        STI R1, DDR_A
+        ADD R0, R0, #1
+        BRnzp PUTS_L
+PUTS_D  LD R0, PUTS_R0
+        LD R1, PUTS_R1
+        LD R2, PUTS_R2
+        RET
+PUTS_R0 .FILL x0000
+PUTS_R1 .FILL x0000
+PUTS_R2 .FILL x0000
+
+; IN : print a prompt, then read a single character into R0.
+T_IN    ST R7, IN_R7
+        LEA R0, IN_MSG
+        TRAP x22
+        TRAP x20
+        LD R7, IN_R7
+        RET
+IN_R7   .FILL x0000
+IN_MSG  .STRINGZ "Enter a character : "
+
+; PUTSP : write the null-terminated string (two characters per word, low byte first) starting at R0.
+T_PUTSP ST R0, PSP_R0
+        ST R1, PSP_R1
+        ST R2, PSP_R2
+        ST R3, PSP_R3
+PSP_L   LDR R1, R0, #0
+        BRz PSP_D
+        LD R2, LOW_M
+        AND R2, R1, R2
+PSP_W1  LDI R3, DSR_A
+        BRzp PSP_W1
+        STI R2, DDR_A
+; there is no right shift, so move the high byte down one bit at a time
+        AND R2, R2, #0
+        AND R3, R3, #0
+        ADD R3, R3, #8
+PSP_S   ADD R2, R2, R2
+        ADD R1, R1, #0
+        BRzp PSP_S0
+        ADD R2, R2, #1
+PSP_S0  ADD R1, R1, R1
+        ADD R3, R3, #-1
+        BRp PSP_S
+        ADD R2, R2, #0
+        BRz PSP_N
+PSP_W2  LDI R3, DSR_A
+        BRzp PSP_W2
+        STI R2, DDR_A
+PSP_N   ADD R0, R0, #1
+        BRnzp PSP_L
+PSP_D   LD R0, PSP_R0
+        LD R1, PSP_R1
+        LD R2, PSP_R2
+        LD R3, PSP_R3
+        RET
+PSP_R0  .FILL x0000
+PSP_R1  .FILL x0000
+PSP_R2  .FILL x0000
+PSP_R3  .FILL x0000
+LOW_M   .FILL x00FF
+
+; HALT : print a message and stop the clock by clearing bit [15] of the MCR.
+T_HALT  LEA R0, HALT_MSG
+        TRAP x22
+        LDI R1, MCR_A
+        LD R2, CLK_M
+        AND R1, R1, R2
+        STI R1, MCR_A
+        BRnzp T_HALT
+
+; BAD_TRAP : every unused vector ends up here.
+BAD_TRAP
+        LEA R0, BAD_MSG
+        TRAP x22
+        BRnzp T_HALT
+
+HALT_MSG .STRINGZ "HALT"
+BAD_MSG  .STRINGZ "Unknown trap vector. "
+CLK_M   .FILL x7FFF
+KBSR_A  .FILL xFE00
+KBDR_A  .FILL xFE02
+DSR_A   .FILL xFE04
+DDR_A   .FILL xFE06
+MCR_A   .FILL xFFFE
+        .END
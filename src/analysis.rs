@@ -0,0 +1,350 @@
+//! `analysis` : Static analysis of a program in memory, for linters. Nothing is executed.
+//!
+//! `analyze_reachability` follows the control flow from an entry point: every instruction falls through to the
+//! next one, except that BR (with all of n, z and p set), JMP, RET, RTI, HALT and the reserved opcode do not,
+//! and BR and JSR also reach their PC-relative target. A TRAP reaches the routine in the trap vector table,
+//! if the table has one. The target of JMP and JSRR depends on a register, so the analysis stops there
+//! (a JSRR is assumed to return) and records the address as an indirect jump.
+//!
+//! `validate_image` uses the same analysis to look a loaded `.obj` image over for signs of corruption
+//! before it is run. Its checks are heuristics: they warn, and a correct program may still trip them.
+//!
+//! `diff_programs` compares two memories word by word, e.g. a reference solution and a student's, and renders
+//! every address as a side-by-side disassembly.
+
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hardware::memory::{AddrRange, Memory};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// `RESERVED_RUN` is the number of consecutive reserved-opcode words from which `validate_image` reports them.
+/// A single one may be data, but neither code nor typical data has many in a row.
+pub const RESERVED_RUN: u16 = 4;
+
+/// `ReachabilitySet` holds the addresses reachable from an entry point.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReachabilitySet {
+    reachable: BTreeSet<u16>,
+    indirect: BTreeSet<u16>,
+}
+
+impl ReachabilitySet {
+    /// `is_reachable` tells whether the instruction at `address` can be executed.
+    pub fn is_reachable(&self, address: u16) -> bool {
+        self.reachable.contains(&address)
+    }
+
+    /// `addresses` visits the reachable addresses, in ascending order.
+    pub fn addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.reachable.iter().copied()
+    }
+
+    /// `indirect_jumps` visits the reachable JMP and JSRR instructions (other than RET) whose target is unknown,
+    /// in ascending order. Code reached only through them is reported as unreachable.
+    pub fn indirect_jumps(&self) -> impl Iterator<Item = u16> + '_ {
+        self.indirect.iter().copied()
+    }
+
+    /// `unreachable_regions` returns the runs of unreachable addresses in `range` (typically the extent of the
+    /// program), in ascending order. The data of a program is unreachable too.
+    pub fn unreachable_regions(&self, range: RangeInclusive<u16>) -> Vec<RangeInclusive<u16>> {
+        let mut regions = Vec::new();
+        let mut start = None;
+        for address in range.clone() {
+            match (self.is_reachable(address), start) {
+                (false, None) => start = Some(address),
+                (true, Some(first)) => {
+                    regions.push(first..=address - 1);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start {
+            regions.push(first..=*range.end());
+        }
+        regions
+    }
+}
+
+/// `analyze_reachability` marks the addresses of `memory` reachable from `entry` (see the module documentation).
+pub fn analyze_reachability(memory: &Memory, entry: u16) -> ReachabilitySet {
+    let mut set = ReachabilitySet::default();
+    let mut pending = vec![entry];
+    while let Some(address) = pending.pop() {
+        if !set.reachable.insert(address) {
+            continue;
+        }
+        let next = address.wrapping_add(1);
+        let relative = |offset: i16| next.wrapping_add(offset as u16);
+        match DecodedInstr::decode(memory.read(address)) {
+            DecodedInstr::Br {
+                nzp: 0b111,
+                offset9,
+            } => pending.push(relative(offset9)),
+            DecodedInstr::Br { nzp: 0, .. } => pending.push(next),
+            DecodedInstr::Br { offset9, .. } => pending.extend(&[relative(offset9), next]),
+            DecodedInstr::Jsr { offset11 } => pending.extend(&[relative(offset11), next]),
+            DecodedInstr::Jmp { base: 7 } => {}
+            DecodedInstr::Jmp { .. } => {
+                set.indirect.insert(address);
+            }
+            DecodedInstr::Jsrr { .. } => {
+                set.indirect.insert(address);
+                pending.push(next);
+            }
+            DecodedInstr::Trap { vector: 0x25 } => {}
+            DecodedInstr::Trap { vector } => {
+                match memory.read(u16::from(vector)) {
+                    0 => {}
+                    routine => pending.push(routine),
+                }
+                pending.push(next);
+            }
+            DecodedInstr::Rti | DecodedInstr::Reserved(_) => {}
+            _ => pending.push(next),
+        }
+    }
+    set
+}
+
+/// `ValidationIssue` is a sign of corruption found by `validate_image`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// No HALT in the image can be reached from its origin, so the program never stops on its own.
+    NoHalt,
+    /// The reachable BR or JSR at `address` jumps to `target`, outside of the image.
+    BranchOutOfImage { address: u16, target: u16 },
+    /// `len` consecutive words from `start` have the reserved opcode (see `RESERVED_RUN`).
+    ReservedRun { start: u16, len: u16 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::NoHalt => write!(f, "no HALT is reachable from the origin"),
+            ValidationIssue::BranchOutOfImage { address, target } => write!(
+                f,
+                "x{:04X}: jumps to x{:04X}, outside of the image",
+                address, target
+            ),
+            ValidationIssue::ReservedRun { start, len } => write!(
+                f,
+                "x{:04X}: {} consecutive words with the reserved opcode",
+                start, len
+            ),
+        }
+    }
+}
+
+/// `validate_image` checks the `len` words loaded at `origin` in `memory`, whose execution starts at `origin`,
+/// and returns the issues found (see `ValidationIssue`): the out-of-image jumps in ascending order, then the
+/// runs of reserved opcodes, then `NoHalt`.
+pub fn validate_image(memory: &Memory, origin: u16, len: u16) -> Vec<ValidationIssue> {
+    let image = AddrRange::new(origin, len);
+    let reachable = analyze_reachability(memory, origin);
+    let mut issues = Vec::new();
+    let mut halts = false;
+    for address in reachable.addresses().filter(|&a| image.contains(a)) {
+        let next = address.wrapping_add(1);
+        let target = match DecodedInstr::decode(memory.read(address)) {
+            DecodedInstr::Br { nzp: 0, .. } => None,
+            DecodedInstr::Br { offset9, .. } => Some(next.wrapping_add(offset9 as u16)),
+            DecodedInstr::Jsr { offset11 } => Some(next.wrapping_add(offset11 as u16)),
+            DecodedInstr::Trap { vector: 0x25 } => {
+                halts = true;
+                None
+            }
+            _ => None,
+        };
+        if let Some(target) = target.filter(|&t| !image.contains(t)) {
+            issues.push(ValidationIssue::BranchOutOfImage { address, target });
+        }
+    }
+    let mut run: Option<(u16, u16)> = None;
+    for address in image
+        .iter()
+        .chain(std::iter::once(origin.wrapping_add(len)))
+    {
+        let reserved = image.contains(address)
+            && matches!(
+                DecodedInstr::decode(memory.read(address)),
+                DecodedInstr::Reserved(_)
+            );
+        run = match (reserved, run) {
+            (true, Some((start, count))) => Some((start, count + 1)),
+            (true, None) => Some((address, 1)),
+            (false, Some((start, count))) => {
+                if count >= RESERVED_RUN {
+                    issues.push(ValidationIssue::ReservedRun { start, len: count });
+                }
+                None
+            }
+            (false, None) => None,
+        };
+    }
+    if !halts {
+        issues.push(ValidationIssue::NoHalt);
+    }
+    issues
+}
+
+/// `DiffLine` is one address of `diff_programs`, with the words the two memories hold there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffLine {
+    pub address: u16,
+    pub left: u16,
+    pub right: u16,
+}
+
+impl DiffLine {
+    /// `matches` tells whether both memories hold the same word.
+    pub fn matches(&self) -> bool {
+        self.left == self.right
+    }
+}
+
+/// A line is displayed as the address, both instructions side by side and `=` or `!` for a match or a difference,
+/// e.g. `x3001  ADD R0, R0, #1         | ADD R0, R0, #2         !`.
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "x{:04X}  {:<22} | {:<22} {}",
+            self.address,
+            DecodedInstr::decode(self.left).to_string(),
+            DecodedInstr::decode(self.right).to_string(),
+            if self.matches() { '=' } else { '!' }
+        )
+    }
+}
+
+/// `diff_programs` compares the words of `range` in `a` and `b`, giving one line per address, in ascending order.
+pub fn diff_programs(a: &Memory, b: &Memory, range: AddrRange) -> Vec<DiffLine> {
+    range
+        .iter()
+        .map(|address| DiffLine {
+            address,
+            left: a.read(address),
+            right: b.read(address),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod analysis_test {
+    use super::*;
+    use crate::lc3_instr;
+
+    #[test]
+    fn code_after_an_unconditional_branch_should_be_unreachable() {
+        let mut memory = Memory::new();
+        let program = [
+            lc3_instr!(AND R0, R0, #0),
+            lc3_instr!(BRnzp #3),
+            //dead: the branch above always jumps over it
+            lc3_instr!(ADD R0, R0, #1),
+            lc3_instr!(ADD R0, R0, #2),
+            lc3_instr!(BRnzp #-3),
+            lc3_instr!(JSR #1),
+            0xF025,
+            lc3_instr!(RET),
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write(0x3000 + i, word);
+        }
+        let set = analyze_reachability(&memory, 0x3000);
+        assert_eq!(
+            vec![0x3000, 0x3001, 0x3005, 0x3006, 0x3007],
+            set.addresses().collect::<Vec<u16>>()
+        );
+        assert_eq!(
+            vec![0x3002..=0x3004],
+            set.unreachable_regions(0x3000..=0x3007)
+        );
+        assert_eq!(0, set.indirect_jumps().count());
+    }
+
+    #[test]
+    fn indirect_jump_should_stop_the_analysis() {
+        let mut memory = Memory::new();
+        memory.write(0x3000, lc3_instr!(JMP R2));
+        memory.write(0x3001, 0xF025);
+        let set = analyze_reachability(&memory, 0x3000);
+        assert_eq!(vec![0x3000], set.indirect_jumps().collect::<Vec<u16>>());
+        assert!(!set.is_reachable(0x3001));
+    }
+
+    #[test]
+    fn image_without_a_halt_should_be_reported() {
+        let mut memory = Memory::new();
+        let program = [
+            lc3_instr!(AND R0, R0, #0),
+            lc3_instr!(ADD R0, R0, #1),
+            lc3_instr!(BRp #-2),
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write(0x3000 + i, word);
+        }
+        let issues = validate_image(&memory, 0x3000, 3);
+        assert_eq!(vec![ValidationIssue::NoHalt], issues);
+        assert_eq!(
+            "no HALT is reachable from the origin",
+            issues[0].to_string()
+        );
+        memory.write(0x3003, 0xF025);
+        assert!(validate_image(&memory, 0x3000, 4).is_empty());
+    }
+
+    #[test]
+    fn stray_branch_and_reserved_run_should_be_reported() {
+        let mut memory = Memory::new();
+        let program = [lc3_instr!(BRz #8), 0xF025, 0xD000, 0xDEAD, 0xDEAD, 0xDFFF];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write(0x3000 + i, word);
+        }
+        assert_eq!(
+            vec![
+                ValidationIssue::BranchOutOfImage {
+                    address: 0x3000,
+                    target: 0x3009
+                },
+                ValidationIssue::ReservedRun {
+                    start: 0x3002,
+                    len: 4
+                },
+            ],
+            validate_image(&memory, 0x3000, 6)
+        );
+    }
+
+    #[test]
+    fn diff_should_flag_only_the_differing_address() {
+        let (mut a, mut b) = (Memory::new(), Memory::new());
+        let program = [
+            lc3_instr!(AND R0, R0, #0),
+            lc3_instr!(ADD R0, R0, #1),
+            0xF025,
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            a.write(0x3000 + i, word);
+            b.write(0x3000 + i, word);
+        }
+        b.write(0x3001, lc3_instr!(ADD R0, R0, #2));
+        let lines = diff_programs(&a, &b, AddrRange::new(0x3000, 3));
+        assert_eq!(
+            vec![0x3001],
+            lines
+                .iter()
+                .filter(|line| !line.matches())
+                .map(|line| line.address)
+                .collect::<Vec<u16>>()
+        );
+        assert_eq!(
+            "x3001  ADD R0, R0, #1         | ADD R0, R0, #2         !",
+            lines[1].to_string()
+        );
+        assert!(lines[2].to_string().ends_with('='));
+    }
+}
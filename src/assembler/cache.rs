@@ -0,0 +1,147 @@
+//! `cache` : An assembled program in a compact binary form, so a playground which re-runs the same source
+//! does not need to assemble it every time.
+//!
+//! Layout (all big-endian): the magic `LC3C`, the source hash (u64), the number of sections (u16),
+//! every section as origin (u16), length (u16) and words, the number of labels (u16),
+//! and every label as length (u16), UTF-8 bytes and address (u16). Labels are stored sorted, so the bytes are deterministic.
+
+use super::{assemble, AssembleError, Section, SymbolTable};
+use crate::fnv::Fnv64;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read};
+
+const MAGIC: &[u8; 4] = b"LC3C";
+
+/// `CachedProgram` holds the words and labels of an assembled program, with a hash of the source it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedProgram {
+    pub source_hash: u64,
+    pub sections: Vec<Section>,
+    pub symbols: SymbolTable,
+}
+
+/// `assemble_to_cache` assembles `source` into a `CachedProgram`. Warnings are not kept.
+pub fn assemble_to_cache(source: &str) -> Result<CachedProgram, AssembleError> {
+    let program = assemble(source)?;
+    Ok(CachedProgram {
+        source_hash: source_hash(source),
+        sections: program.sections,
+        symbols: program.symbols,
+    })
+}
+
+/// `source_hash` is the 64-bit FNV-1a hash of `source`. Unlike `std`'s hasher, it is stable across builds.
+pub fn source_hash(source: &str) -> u64 {
+    let mut hash = Fnv64::new();
+    hash.write(source.as_bytes());
+    hash.finish()
+}
+
+impl CachedProgram {
+    /// `is_valid_for` tells whether the cache was built from `source`.
+    pub fn is_valid_for(&self, source: &str) -> bool {
+        self.source_hash == source_hash(source)
+    }
+
+    /// `to_bytes` serializes the cache in the layout described in the module documentation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        //writing to a `Vec` cannot fail
+        let mut bytes = MAGIC.to_vec();
+        bytes.write_u64::<BigEndian>(self.source_hash).unwrap();
+        bytes
+            .write_u16::<BigEndian>(self.sections.len() as u16)
+            .unwrap();
+        for section in self.sections.iter() {
+            bytes.write_u16::<BigEndian>(section.origin).unwrap();
+            bytes
+                .write_u16::<BigEndian>(section.words.len() as u16)
+                .unwrap();
+            for &word in section.words.iter() {
+                bytes.write_u16::<BigEndian>(word).unwrap();
+            }
+        }
+        let mut symbols: Vec<(&str, u16)> = self.symbols.iter().collect();
+        symbols.sort_unstable();
+        bytes.write_u16::<BigEndian>(symbols.len() as u16).unwrap();
+        for (label, address) in symbols {
+            bytes.write_u16::<BigEndian>(label.len() as u16).unwrap();
+            bytes.extend_from_slice(label.as_bytes());
+            bytes.write_u16::<BigEndian>(address).unwrap();
+        }
+        bytes
+    }
+
+    /// `from_bytes` reads a cache written by `to_bytes`.
+    /// Malformed or truncated input gives an `InvalidData` or `UnexpectedEof` error.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<CachedProgram> {
+        let mut rdr = Cursor::new(bytes);
+        let mut magic = [0; 4];
+        rdr.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a cached program"));
+        }
+        let source_hash = rdr.read_u64::<BigEndian>()?;
+        let mut sections = Vec::new();
+        for _ in 0..rdr.read_u16::<BigEndian>()? {
+            let origin = rdr.read_u16::<BigEndian>()?;
+            let mut words = vec![0; rdr.read_u16::<BigEndian>()? as usize];
+            rdr.read_u16_into::<BigEndian>(&mut words)?;
+            sections.push(Section { origin, words });
+        }
+        let mut symbols = SymbolTable::new();
+        for _ in 0..rdr.read_u16::<BigEndian>()? {
+            let mut label = vec![0; rdr.read_u16::<BigEndian>()? as usize];
+            rdr.read_exact(&mut label)?;
+            let label = String::from_utf8(label).map_err(|_| invalid_data("label is not UTF-8"))?;
+            if !symbols.insert(&label, rdr.read_u16::<BigEndian>()?) {
+                return Err(invalid_data("duplicate label"));
+            }
+        }
+        Ok(CachedProgram {
+            source_hash,
+            sections,
+            symbols,
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod cache_test {
+    use super::*;
+
+    const SOURCE: &str = ".ORIG x3000
+                          LEA R0, MSG
+                          PUTS
+                          HALT
+                      MSG .STRINGZ \"hi\"
+                          .END";
+
+    #[test]
+    fn round_trip_should_reproduce_the_program() {
+        let cache = assemble_to_cache(SOURCE).unwrap();
+        let restored = CachedProgram::from_bytes(&cache.to_bytes()).unwrap();
+        assert_eq!(cache, restored);
+        assert_eq!(assemble(SOURCE).unwrap().sections, restored.sections);
+        assert_eq!(Some(0x3003), restored.symbols.get("MSG"));
+    }
+
+    #[test]
+    fn modified_source_should_not_match_the_cache() {
+        let cache = assemble_to_cache(SOURCE).unwrap();
+        assert!(cache.is_valid_for(SOURCE));
+        let modified = SOURCE.replace("hi", "ho");
+        assert_ne!(cache.source_hash, source_hash(&modified));
+        assert!(!cache.is_valid_for(&modified));
+    }
+
+    #[test]
+    fn truncated_bytes_should_be_rejected() {
+        let bytes = assemble_to_cache(SOURCE).unwrap().to_bytes();
+        assert!(CachedProgram::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(CachedProgram::from_bytes(b"nope").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! `conditional` : Conditional assembly. `.IFDEF NAME` (or `.IFNDEF NAME`) ... `.ELSE` ... `.ENDIF` keeps the lines
+//! of the first part if the constant `NAME` is defined (is not defined), and the lines of the optional `.ELSE`
+//! part otherwise. A name is defined by a `.CONST` on an earlier line which is kept, or by
+//! `AssembleOptions::constants`. Conditionals can be nested.
+//!
+//! After the includes are read and the macros expanded, the lines which are left out are dropped,
+//! so the rest of the assembler never sees them.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::parser::{Operand, SourceLine};
+use super::{expect_operands, invalid_operand};
+use std::collections::HashSet;
+
+//an open `.IFDEF` / `.IFNDEF`
+struct Frame {
+    line: usize,
+    //whether the enclosing lines are kept
+    outer: bool,
+    //whether the current part (before or after `.ELSE`) is kept, if the enclosing lines are
+    condition: bool,
+    in_else: bool,
+}
+
+/// `select` drops the lines of `lines` which conditionals leave out, and the conditional directives themselves.
+/// `predefined` are the constants defined before the first line.
+pub(crate) fn select(
+    lines: Vec<SourceLine>,
+    predefined: &[(String, i32)],
+) -> Result<Vec<SourceLine>, AssembleError> {
+    let mut defined: HashSet<String> = predefined.iter().map(|(name, _)| name.clone()).collect();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut kept = Vec::new();
+    for line in lines {
+        let active = frames.last().is_none_or(|f| f.outer && f.condition);
+        match line.op.as_deref() {
+            Some(op @ (".IFDEF" | ".IFNDEF")) => {
+                expect_operands(&line, 1)?;
+                let name = match &line.operands[0] {
+                    Operand::Label(name) => name,
+                    other => return Err(invalid_operand(&line, other)),
+                };
+                frames.push(Frame {
+                    line: line.line,
+                    outer: active,
+                    condition: defined.contains(name) == (op == ".IFDEF"),
+                    in_else: false,
+                });
+            }
+            Some(op @ (".ELSE" | ".ENDIF")) => {
+                expect_operands(&line, 0)?;
+                let unmatched = || {
+                    AssembleError::new(
+                        line.line,
+                        AssembleErrorKind::UnmatchedConditional(op.to_string()),
+                    )
+                };
+                if op == ".ENDIF" {
+                    frames.pop().ok_or_else(unmatched)?;
+                } else {
+                    let frame = frames
+                        .last_mut()
+                        .filter(|f| !f.in_else)
+                        .ok_or_else(unmatched)?;
+                    frame.in_else = true;
+                    frame.condition = !frame.condition;
+                }
+            }
+            _ if active => {
+                if let (Some(".CONST"), Some(name)) = (line.op.as_deref(), &line.label) {
+                    defined.insert(name.clone());
+                }
+                kept.push(line);
+            }
+            _ => {}
+        }
+    }
+    match frames.last() {
+        Some(frame) => Err(AssembleError::new(
+            frame.line,
+            AssembleErrorKind::MissingEndif,
+        )),
+        None => Ok(kept),
+    }
+}
@@ -0,0 +1,110 @@
+//! `constant` : Named compile-time constants. `MAX .CONST #100` defines `MAX`, which then stands for the number 100
+//! wherever a number is accepted: immediates, `.FILL`, `.BLKW`, `TRAP` vectors, even `.ORIG`. A `.CONST` emits no words,
+//! may appear outside an `.ORIG` / `.END` block, and its name is not an address label (it is not in the `SymbolTable`).
+//!
+//! Constants are not addresses: a constant used as the target of BR, JSR, LD, LDI, LEA, ST or STI is rejected
+//! with `ConstantAsAddress`, because the PC-relative offset it would silently become is almost never what was meant.
+//!
+//! Before the first pass, every use of a constant is replaced by its number, so the rest of the assembler
+//! only sees plain numbers.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::parser::{Operand, SourceLine};
+use std::collections::{HashMap, HashSet};
+
+/// `substitute` collects the `.CONST` definitions in `lines` and replaces every use of them (or of the
+/// `predefined` constants) by their value.
+pub(crate) fn substitute(
+    lines: &mut [SourceLine],
+    predefined: &[(String, i32)],
+) -> Result<(), AssembleError> {
+    let labels: HashSet<&str> = lines
+        .iter()
+        .filter(|line| line.op.as_deref() != Some(".CONST"))
+        .filter_map(|line| line.label.as_deref())
+        .collect();
+    if let Some(line) = lines.iter().find(|line| {
+        line.op.as_deref() != Some(".CONST")
+            && line
+                .label
+                .as_ref()
+                .is_some_and(|label| predefined.iter().any(|(name, _)| name == label))
+    }) {
+        return Err(AssembleError::new(
+            line.line,
+            AssembleErrorKind::DuplicateLabel(line.label.clone().unwrap_or_default()),
+        ));
+    }
+    let mut constants: HashMap<String, i32> = predefined.iter().cloned().collect();
+    for line in lines
+        .iter()
+        .filter(|line| line.op.as_deref() == Some(".CONST"))
+    {
+        let name = line.label.as_ref().ok_or_else(|| {
+            AssembleError::new(
+                line.line,
+                AssembleErrorKind::InvalidOperand(".CONST".to_string()),
+            )
+        })?;
+        let value = match line.operands.as_slice() {
+            [Operand::Number(value)] => *value,
+            [Operand::Label(other)] => {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::InvalidOperand(other.clone()),
+                ))
+            }
+            operands => {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::OperandCount {
+                        expected: 1,
+                        found: operands.len(),
+                    },
+                ))
+            }
+        };
+        if labels.contains(name.as_str()) || constants.insert(name.clone(), value).is_some() {
+            return Err(AssembleError::new(
+                line.line,
+                AssembleErrorKind::DuplicateLabel(name.clone()),
+            ));
+        }
+    }
+    if constants.is_empty() {
+        return Ok(());
+    }
+    for line in lines.iter_mut() {
+        let address_operand = address_operand(line.op.as_deref().unwrap_or_default());
+        for (i, operand) in line.operands.iter_mut().enumerate() {
+            let (name, value) = match operand {
+                Operand::Label(name) => match constants.get(name) {
+                    Some(&value) => (name, value),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if address_operand == Some(i) {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::ConstantAsAddress(name.clone()),
+                ));
+            }
+            //the name of `.EXTERNAL NAME, vector` is a trap name, not a use
+            if line.op.as_deref() == Some(".EXTERNAL") && i == 0 {
+                continue;
+            }
+            *operand = Operand::Number(value);
+        }
+    }
+    Ok(())
+}
+
+//the index of the PC-relative operand of `op`, if it has one
+fn address_operand(op: &str) -> Option<usize> {
+    match op {
+        "BR" | "BRN" | "BRZ" | "BRP" | "BRNZ" | "BRNP" | "BRZP" | "BRNZP" | "JSR" => Some(0),
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => Some(1),
+        _ => None,
+    }
+}
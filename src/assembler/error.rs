@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+
+/// `AssembleError` is a hard error found while assembling, together with the (1-based) source line it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+/// `AssembleErrorKind` lists the ways in which a source file can fail to assemble.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AssembleErrorKind {
+    /// The first word of the line is neither a label nor a known opcode or directive.
+    UnknownOpcode(String),
+    /// An operand could not be parsed, or has the wrong type (e.g. a number where a register is expected).
+    InvalidOperand(String),
+    /// A register operand names something which is not one of the general purpose registers R0 - R7,
+    /// such as `PC`: no instruction can name the PC as a register.
+    NotARegister(String),
+    /// The instruction or directive takes `expected` operands, but `found` were given.
+    OperandCount { expected: usize, found: usize },
+    /// The label is defined more than once.
+    DuplicateLabel(String),
+    /// The label is used, but never defined.
+    UndefinedLabel(String),
+    /// The constant (see `.CONST`) is used as the target of a PC-relative instruction; constants are not addresses.
+    ConstantAsAddress(String),
+    /// The immediate value does not fit in its field.
+    ImmediateOutOfRange { value: i32, bits: u32 },
+    /// The PC-relative offset (to `label`, if one was used) does not fit in its `bits`-bit signed field.
+    OffsetOutOfRange {
+        label: Option<String>,
+        offset: i32,
+        bits: u32,
+    },
+    /// Code or data appears outside an `.ORIG` / `.END` block.
+    MissingOrig,
+    /// An `.ORIG` block is not closed by `.END` (before the file ends, or before the next `.ORIG`).
+    MissingEnd,
+    /// The program runs past the end of memory (xFFFF), or one section holds all 65,536 words of memory.
+    ProgramTooLarge,
+    /// A string literal is not closed, or uses an unknown escape sequence.
+    InvalidString,
+    /// A character literal is not closed, is empty, holds more than one character or a character which is not ASCII,
+    /// or uses an unknown escape sequence.
+    InvalidCharacter,
+    /// The file of an `.INCBIN` or `.INCLUDE` could not be read.
+    IncludeFailed { path: String, reason: String },
+    /// A `.MACRO` is not closed by `.ENDM` before the end of its file.
+    MissingEndm,
+    /// The expansion of the macro never ends, as the macro (indirectly) uses itself.
+    MacroRecursion(String),
+    /// An `.ELSE` or `.ENDIF` (the directive) has no open `.IFDEF` / `.IFNDEF`, or an `.ELSE` is the second one.
+    UnmatchedConditional(String),
+    /// An `.IFDEF` / `.IFNDEF` is not closed by `.ENDIF` before the end of the source.
+    MissingEndif,
+    /// The words of the section at `second` would overwrite those of the earlier section at `first`,
+    /// starting at `addr`. The error is on the `.ORIG` line of the later section.
+    SectionOverlap { addr: u16, first: u16, second: u16 },
+}
+
+impl AssembleError {
+    pub fn new(line: usize, kind: AssembleErrorKind) -> AssembleError {
+        AssembleError { line, kind }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AssembleErrorKind::UnknownOpcode(op) => write!(f, "unknown opcode `{}`", op),
+            AssembleErrorKind::InvalidOperand(operand) => {
+                write!(f, "invalid operand `{}`", operand)
+            }
+            AssembleErrorKind::NotARegister(name) => write!(
+                f,
+                "`{}` is not a register; the only general purpose registers are R0 - R7",
+                name
+            ),
+            AssembleErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operand(s), found {}", expected, found)
+            }
+            AssembleErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{}` is already defined", label)
+            }
+            AssembleErrorKind::UndefinedLabel(label) => {
+                write!(f, "label `{}` is not defined", label)
+            }
+            AssembleErrorKind::ConstantAsAddress(name) => {
+                write!(f, "constant `{}` is not an address", name)
+            }
+            AssembleErrorKind::ImmediateOutOfRange { value, bits } => {
+                write!(f, "immediate {} does not fit in {} bits", value, bits)
+            }
+            AssembleErrorKind::OffsetOutOfRange {
+                label,
+                offset,
+                bits,
+            } => {
+                let (min, max) = (-(1 << (bits - 1)), (1 << (bits - 1)) - 1);
+                match label {
+                    Some(label) => write!(f, "label `{}` is {} word(s) away", label, offset)?,
+                    None => write!(f, "offset {}", offset)?,
+                }
+                write!(
+                    f,
+                    ", but a {}-bit offset must be within {}..={}",
+                    bits, min, max
+                )
+            }
+            AssembleErrorKind::MissingOrig => write!(f, "code outside an .ORIG/.END block"),
+            AssembleErrorKind::MissingEnd => write!(f, ".ORIG without a matching .END"),
+            AssembleErrorKind::ProgramTooLarge => write!(f, "program runs past the end of memory"),
+            AssembleErrorKind::InvalidString => write!(f, "invalid string literal"),
+            AssembleErrorKind::InvalidCharacter => write!(f, "invalid character literal"),
+            AssembleErrorKind::IncludeFailed { path, reason } => {
+                write!(f, "cannot include `{}`: {}", path, reason)
+            }
+            AssembleErrorKind::MissingEndm => write!(f, ".MACRO without a matching .ENDM"),
+            AssembleErrorKind::MacroRecursion(name) => {
+                write!(f, "macro `{}` expands into itself", name)
+            }
+            AssembleErrorKind::UnmatchedConditional(directive) => {
+                write!(f, "{} without a matching .IFDEF or .IFNDEF", directive)
+            }
+            AssembleErrorKind::MissingEndif => write!(f, ".IFDEF without a matching .ENDIF"),
+            AssembleErrorKind::SectionOverlap {
+                addr,
+                first,
+                second,
+            } => write!(
+                f,
+                "section at x{:04X} overlaps the section at x{:04X} from x{:04X}",
+                second, first, addr
+            ),
+        }
+    }
+}
+
+impl Error for AssembleError {}
@@ -0,0 +1,185 @@
+//! `include` : Reads a source with the files it pulls in. `.INCLUDE "file"` splices in the lines of another source file,
+//! which shares the labels of the including file (labels are global), and `.INCBIN "file"` inserts the bytes
+//! of a file as words. The files come from a `Files`: the disk, relative to the directory of the source
+//! (`assemble_in`), or an in-memory map of file name to source (`assemble_project`).
+//!
+//! A path is relative to the file which names it: `lib/io.asm` including `"putd.asm"` gets `lib/putd.asm`.
+//! Errors in an included file report the line number within that file.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::macros::{self, Macro, Macros};
+use super::parser::{self, Operand, SourceLine};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `Files` gives the contents of the file called `name`, or why it cannot.
+pub(crate) trait Files {
+    fn read(&self, name: &str) -> Result<Vec<u8>, String>;
+}
+
+/// `Disk` reads files relative to a directory.
+pub(crate) struct Disk<'a>(pub(crate) &'a Path);
+
+impl Files for Disk<'_> {
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.0.join(name)).map_err(|e| e.to_string())
+    }
+}
+
+/// `Project` reads files from a map of file name to source.
+pub(crate) struct Project<'a>(pub(crate) &'a HashMap<String, String>);
+
+impl Files for Project<'_> {
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        self.0
+            .get(name)
+            .map(|source| source.as_bytes().to_vec())
+            .ok_or_else(|| "no such file in the project".to_string())
+    }
+}
+
+/// `read` parses `source` (the file `including`, if it has a name), with every `.INCLUDE` replaced by the lines
+/// of its file (recursively), the path operand of every `.INCBIN` replaced by the words of its file,
+/// and every macro expanded (see `macros`).
+pub(crate) fn read(
+    source: &str,
+    files: &dyn Files,
+    including: Vec<String>,
+) -> Result<Vec<SourceLine>, AssembleError> {
+    let mut reader = Reader {
+        files,
+        including,
+        macros: Macros::default(),
+        lines: Vec::new(),
+    };
+    reader.read_lines(numbered(source), 0)?;
+    Ok(reader.lines)
+}
+
+struct Reader<'a> {
+    files: &'a dyn Files,
+    //the files being included, innermost last, to refuse cycles
+    including: Vec<String>,
+    macros: Macros,
+    lines: Vec<SourceLine>,
+}
+
+impl Reader<'_> {
+    //reads the (line number, text) `lines`, which are nested in `depth` macro expansions
+    fn read_lines(
+        &mut self,
+        lines: Vec<(usize, String)>,
+        depth: usize,
+    ) -> Result<(), AssembleError> {
+        //the line, name and body of the macro being defined
+        let mut definition: Option<(usize, String, Macro)> = None;
+        for (number, text) in lines {
+            if let Some((_, _, body)) = definition.as_mut() {
+                if macros::push_body(body, &text) {
+                    let (at, name, body) = definition.take().expect("A macro is being defined.");
+                    self.macros.define(at, name, body)?;
+                }
+                continue;
+            }
+            if let Some(call) = self.macros.invocation(&text) {
+                if let Some(label) = call.label.clone() {
+                    self.lines.push(macros::label_line(number, label)?);
+                }
+                let body = self.macros.instantiate(&call, number, depth)?;
+                let body = body.into_iter().map(|text| (number, text)).collect();
+                self.read_lines(body, depth + 1)?;
+                continue;
+            }
+            let mut line = parser::parse_line(number, &text)?;
+            match line.op.as_deref() {
+                Some(".MACRO") => {
+                    let (name, body) = Macros::begin(&line)?;
+                    definition = Some((number, name, body));
+                }
+                Some(".INCLUDE") => self.include(line, depth)?,
+                Some(".INCBIN") => {
+                    let name = resolve(&self.including, &path(&line)?);
+                    let bytes = self.files.read(&name).map_err(|reason| {
+                        AssembleError::new(
+                            line.line,
+                            AssembleErrorKind::IncludeFailed { path: name, reason },
+                        )
+                    })?;
+                    line.operands = words(&bytes);
+                    self.lines.push(line);
+                }
+                _ => self.lines.push(line),
+            }
+        }
+        match definition {
+            Some((at, _, _)) => Err(AssembleError::new(at, AssembleErrorKind::MissingEndm)),
+            None => Ok(()),
+        }
+    }
+
+    //reads the file of the `.INCLUDE "file"` line `line` in its place
+    fn include(&mut self, mut line: SourceLine, depth: usize) -> Result<(), AssembleError> {
+        let name = resolve(&self.including, &path(&line)?);
+        let failed = |reason: String| {
+            AssembleError::new(
+                line.line,
+                AssembleErrorKind::IncludeFailed {
+                    path: name.clone(),
+                    reason,
+                },
+            )
+        };
+        if self.including.contains(&name) {
+            return Err(failed("the file includes itself".to_string()));
+        }
+        let source = String::from_utf8(self.files.read(&name).map_err(failed)?)
+            .map_err(|_| failed("the file is not UTF-8".to_string()))?;
+        //a label in front of the `.INCLUDE` labels the first included word
+        if line.label.is_some() {
+            line.op = None;
+            line.operands.clear();
+            self.lines.push(line);
+        }
+        self.including.push(name);
+        self.read_lines(numbered(&source), depth)?;
+        self.including.pop();
+        Ok(())
+    }
+}
+
+//the lines of `source`, with their (1-based) numbers
+fn numbered(source: &str) -> Vec<(usize, String)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| (i + 1, text.to_string()))
+        .collect()
+}
+
+//`path` relative to the directory of the innermost file being included
+fn resolve(including: &[String], path: &str) -> String {
+    match including.last().and_then(|file| Path::new(file).parent()) {
+        Some(dir) => dir.join(path).to_string_lossy().into_owned(),
+        None => PathBuf::from(path).to_string_lossy().into_owned(),
+    }
+}
+
+//the path operand of `.INCLUDE "file"` / `.INCBIN "file"`
+fn path(line: &SourceLine) -> Result<String, AssembleError> {
+    match line.operands.as_slice() {
+        [Operand::Str(path)] => Ok(path.clone()),
+        [operand] => Err(super::invalid_operand(line, operand)),
+        _ => Err(super::operand_count(line, 1)),
+    }
+}
+
+//the bytes of a file, packed two per word (big-endian); an odd byte is padded with zero
+fn words(bytes: &[u8]) -> Vec<Operand> {
+    bytes
+        .chunks(2)
+        .map(|pair| {
+            Operand::Number(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as i32)
+        })
+        .collect()
+}
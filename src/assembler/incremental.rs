@@ -0,0 +1,75 @@
+//! `incremental` : Re-assembling after an edit, for a live editor. An inserted or deleted line shifts every address
+//! below it, and with them the labels and the PC-relative offsets which reach across the edit, so patching the one
+//! edited word is not enough. `assemble_incremental` re-assembles the source and compares the result with the
+//! previous program: an editor only has to write the words which changed (`patches`) into the running machine,
+//! and can update its label display from `moved`.
+//!
+//! Limits: the whole source is assembled again (it is fast; only the comparison is incremental), and cells
+//! which the previous program covered but the new one does not are not cleared, as they might hold run-time data.
+
+use super::{assemble, AssembleError, Program};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// `LabelMove` is a label whose address changed: `old` is `None` for a new label, `new` for a removed one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelMove {
+    pub label: String,
+    pub old: Option<u16>,
+    pub new: Option<u16>,
+}
+
+/// `IncrementalUpdate` is a re-assembled program and how it differs from the previous one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncrementalUpdate {
+    pub program: Program,
+    /// Every (address, word) of the new program which the previous one did not have, in address order.
+    pub patches: Vec<(u16, u16)>,
+    /// Every label which was added, removed or moved, sorted by name.
+    pub moved: Vec<LabelMove>,
+}
+
+/// `assemble_incremental` assembles the edited `source` and compares it with `previous`, the program
+/// assembled before the edit.
+pub fn assemble_incremental(
+    previous: &Program,
+    source: &str,
+) -> Result<IncrementalUpdate, AssembleError> {
+    let program = assemble(source)?;
+    let old_words = words(previous);
+    let patches = words(&program)
+        .into_iter()
+        .filter(|(address, word)| old_words.get(address) != Some(word))
+        .collect();
+
+    let old: HashMap<&str, u16> = previous.symbols.iter().collect();
+    let new: HashMap<&str, u16> = program.symbols.iter().collect();
+    let labels: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+    let moved = labels
+        .into_iter()
+        .map(|label| LabelMove {
+            label: label.to_string(),
+            old: old.get(label).copied(),
+            new: new.get(label).copied(),
+        })
+        .filter(|change| change.old != change.new)
+        .collect();
+
+    Ok(IncrementalUpdate {
+        program,
+        patches,
+        moved,
+    })
+}
+
+//every (address, word) of the sections of `program`
+fn words(program: &Program) -> BTreeMap<u16, u16> {
+    program
+        .sections
+        .iter()
+        .flat_map(|section| {
+            (0..)
+                .map(move |i| section.origin.wrapping_add(i))
+                .zip(section.words.iter().copied())
+        })
+        .collect()
+}
@@ -0,0 +1,72 @@
+//! `local` : Numeric local labels. `1:` defines local label 1, which can be defined any number of times;
+//! `1b` refers to its nearest definition at or before the reference and `1f` to the nearest one after it.
+//! Named labels stay global.
+//!
+//! Before the first pass, the k-th definition of local label N is renamed `N:k` (a name no source can use),
+//! and every reference to it is renamed to match, so the rest of the assembler only sees plain labels.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::parser::{Operand, SourceLine};
+use std::collections::HashMap;
+
+/// `is_local` returns `true` for the name of a (renamed) local label.
+pub(crate) fn is_local(label: &str) -> bool {
+    label.contains(':')
+}
+
+/// `resolve` renames the definitions of and references to local labels in `lines`.
+pub(crate) fn resolve(lines: &mut [SourceLine]) -> Result<(), AssembleError> {
+    //every definition of every local label: the line index and the new name
+    let mut definitions: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    for (i, line) in lines.iter_mut().enumerate() {
+        if let Some(number) = line.label.as_deref().and_then(|l| l.strip_suffix(':')) {
+            let number = number.to_string();
+            let found = definitions.entry(number.clone()).or_default();
+            let name = format!("{}:{}", number, found.len());
+            found.push((i, name.clone()));
+            line.label = Some(name);
+        }
+    }
+    for (i, line) in lines.iter_mut().enumerate() {
+        for operand in line.operands.iter_mut() {
+            let reference = match operand {
+                Operand::Label(label) if is_reference(label) => label,
+                _ => continue,
+            };
+            let (number, direction) = reference.split_at(reference.len() - 1);
+            let found = definitions.get(number).map_or(&[][..], Vec::as_slice);
+            let target = if direction.eq_ignore_ascii_case("b") {
+                found.iter().rev().find(|&&(at, _)| at <= i)
+            } else {
+                found.iter().find(|&&(at, _)| at > i)
+            };
+            match target {
+                Some((_, name)) => *reference = name.clone(),
+                None => {
+                    return Err(AssembleError::new(
+                        line.line,
+                        AssembleErrorKind::UndefinedLabel(reference.clone()),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `is_definition` returns `true` for `N:`, the definition of a local label.
+pub(crate) fn is_definition(word: &str) -> bool {
+    word.strip_suffix(':').is_some_and(is_number)
+}
+
+/// `is_reference` returns `true` for `Nb` and `Nf`, a reference to a local label.
+pub(crate) fn is_reference(word: &str) -> bool {
+    match word.char_indices().last() {
+        Some((i, 'b' | 'B' | 'f' | 'F')) => is_number(&word[..i]),
+        _ => false,
+    }
+}
+
+fn is_number(digits: &str) -> bool {
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
@@ -0,0 +1,203 @@
+//! `macros` : Text macros, expanded while the source is read (before the first pass).
+//!
+//! ```text
+//! .MACRO PUSH, REG
+//!        ADD R6, R6, #-1
+//!        STR REG, R6, #0
+//! .ENDM
+//! ```
+//!
+//! defines `PUSH` with one parameter, and `PUSH R1` (optionally after a label) is replaced by the body,
+//! with every whole-word `REG` replaced by `R1`. A macro may use macros defined before it is used.
+//! In the body, `@` in a word stands for a number unique to each expansion, so that `SKIP@` names
+//! a different label every time the macro is used. Expanded lines report the line of the invocation.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::parser::{self, Operand, SourceLine};
+use std::collections::HashMap;
+
+/// Expansions nested deeper than this are taken to be a macro which (indirectly) uses itself.
+const MAX_DEPTH: usize = 64;
+
+/// `Macro` is a macro definition: its parameter names and the text of its body.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// `Invocation` is a line which uses a macro: an optional label, the macro name and the argument texts.
+pub(crate) struct Invocation {
+    pub(crate) label: Option<String>,
+    name: String,
+    args: Vec<String>,
+}
+
+/// `Macros` are the macros defined so far, by upper-cased name.
+#[derive(Debug, Default)]
+pub(crate) struct Macros {
+    defined: HashMap<String, Macro>,
+    expansions: usize,
+}
+
+impl Macros {
+    /// `begin` starts the definition in the `.MACRO name, params` line `line`; the body follows.
+    pub(crate) fn begin(line: &SourceLine) -> Result<(String, Macro), AssembleError> {
+        let mut names = line.operands.iter().map(|operand| match operand {
+            Operand::Label(name) => Ok(name.clone()),
+            operand => Err(super::invalid_operand(line, operand)),
+        });
+        let name = names
+            .next()
+            .ok_or_else(|| super::operand_count(line, 1))??;
+        let params = names.collect::<Result<Vec<String>, AssembleError>>()?;
+        let definition = Macro {
+            params,
+            body: Vec::new(),
+        };
+        Ok((name.to_ascii_uppercase(), definition))
+    }
+
+    /// `define` adds the finished definition of `name`.
+    pub(crate) fn define(
+        &mut self,
+        line: usize,
+        name: String,
+        definition: Macro,
+    ) -> Result<(), AssembleError> {
+        if self.defined.insert(name.clone(), definition).is_some() {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::DuplicateLabel(name),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `invocation` returns the use of a macro on the line `text`, if it is one.
+    pub(crate) fn invocation(&self, text: &str) -> Option<Invocation> {
+        if self.defined.is_empty() {
+            return None;
+        }
+        let code = text.split(';').next().unwrap_or_default();
+        let words: Vec<&str> = code
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .collect();
+        let is_macro = |word: &str| self.defined.contains_key(&word.to_ascii_uppercase());
+        let (label, rest) = match words.as_slice() {
+            [first, ..] if is_macro(first) => (None, &words[..]),
+            [label, second, ..] if is_macro(second) && !parser::is_mnemonic(label) => {
+                (Some(label.to_string()), &words[1..])
+            }
+            _ => return None,
+        };
+        Some(Invocation {
+            label,
+            name: rest[0].to_ascii_uppercase(),
+            args: rest[1..].iter().map(|arg| arg.to_string()).collect(),
+        })
+    }
+
+    /// `instantiate` returns the body of the macro used by `call` on line `line`, with the arguments
+    /// and the expansion number substituted. `depth` is the number of expansions `call` is nested in.
+    pub(crate) fn instantiate(
+        &mut self,
+        call: &Invocation,
+        line: usize,
+        depth: usize,
+    ) -> Result<Vec<String>, AssembleError> {
+        if depth >= MAX_DEPTH {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::MacroRecursion(call.name.clone()),
+            ));
+        }
+        let definition = &self.defined[&call.name];
+        if call.args.len() != definition.params.len() {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::OperandCount {
+                    expected: definition.params.len(),
+                    found: call.args.len(),
+                },
+            ));
+        }
+        self.expansions += 1;
+        let unique = format!("__{}", self.expansions);
+        let args: HashMap<&str, &str> = definition
+            .params
+            .iter()
+            .map(String::as_str)
+            .zip(call.args.iter().map(String::as_str))
+            .collect();
+        Ok(definition
+            .body
+            .iter()
+            .map(|text| substitute(text, &args, &unique))
+            .collect())
+    }
+}
+
+/// `push_body` adds `text` to the body of the macro being defined, or returns `true` at its `.ENDM`.
+pub(crate) fn push_body(definition: &mut Macro, text: &str) -> bool {
+    if is_endm(text) {
+        return true;
+    }
+    definition.body.push(text.to_string());
+    false
+}
+
+fn is_endm(text: &str) -> bool {
+    text.split(';')
+        .next()
+        .and_then(|code| code.split_whitespace().next())
+        .is_some_and(|word| word.eq_ignore_ascii_case(".ENDM"))
+}
+
+//replaces the whole words of `text` which are parameters by their arguments, and `@` by `unique`;
+//string literals and comments are left alone
+fn substitute(text: &str, args: &HashMap<&str, &str>, unique: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let mut in_string = false;
+    let mut chars = text.chars();
+    let flush = |word: &mut String, out: &mut String| {
+        match args.get(word.as_str()) {
+            Some(arg) => out.push_str(arg),
+            None => out.push_str(&word.replace('@', unique)),
+        }
+        word.clear();
+    };
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '@' => word.push(c),
+            ';' => {
+                flush(&mut word, &mut out);
+                out.push(c);
+                out.extend(chars.by_ref());
+            }
+            c => {
+                flush(&mut word, &mut out);
+                in_string = c == '"';
+                out.push(c);
+            }
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// `label_line` is a line which only defines `label`, put in front of an expansion.
+pub(crate) fn label_line(line: usize, label: String) -> Result<SourceLine, AssembleError> {
+    parser::parse_line(line, &label)
+}
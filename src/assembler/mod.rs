@@ -0,0 +1,1568 @@
+//! # Assembler - LC-3 assembly to machine code.
+//! `assemble` translates LC-3 assembly source into the words of a program, in two passes:
+//! the first pass assigns an address to every line and records the labels in a `SymbolTable`,
+//! the second pass encodes each instruction and directive (see `hardware::instructions::encode`).
+//!
+//! Besides hard errors (`AssembleError`), the second pass collects lint-style warnings (`AssembleWarning`)
+//! for code which is legal but suspicious. `peephole` separately reports pairs of instructions which could be
+//! written shorter, for teaching.
+//!
+//! Supported directives are `.ORIG`, `.FILL`, `.BLKW` (`.BLKW count` or `.BLKW count, value`), `.STRINGZ` and `.END`.
+//! A value (of `.FILL` or `.BLKW`) may be written signed or unsigned: anything in -32768..=65535 is stored as
+//! its 16-bit two's-complement word, so `#-1` and `#65535` (or `xFFFF`) are both xFFFF. Anything else is an error.
+//! Wherever a number is accepted, a character literal such as `'A'` or `'\n'` (with the escape sequences of
+//! `.STRINGZ`) stands for its ASCII code. It must still fit its field: `.FILL 'A'` is x0041, but `ADD R0, R0, '0'`
+//! is an error, as x30 does not fit in a 5-bit immediate.
+//! A file may hold several `.ORIG` / `.END` blocks, e.g. to keep data at a different origin than the code;
+//! each becomes its own `Section`. Sections may come in any order, but must not overlap.
+//!
+//! Labels are global, except for numeric local labels: `1:` can be defined many times, and `1b` / `1f` refer to
+//! the nearest definition before / after the reference (see `local`).
+//! Wherever a label is accepted, `*` stands for the address of the line itself (of the first word of a `.BLKW`),
+//! and a label or `*` may be followed by a constant offset: `.FILL *`, `.FILL TABLE+2`, `BRnzp *-1`.
+//!
+//! `.EXTERNAL NAME, x30` names the trap vector `x30`, so that custom trap routines can be called as `TRAP NAME`.
+//! It emits no words and, unlike the other directives, may also appear outside an `.ORIG` / `.END` block.
+//!
+//! `.MACRO NAME, PARAM ...` / `.ENDM` defines a text macro, expanded where `NAME` is used (see `macros`).
+//!
+//! `NAME .CONST value` defines a named constant, which can be used wherever a number is accepted (see `constant`).
+//! `.IFDEF NAME` / `.IFNDEF NAME`, `.ELSE` and `.ENDIF` assemble a region only if a constant is (is not) defined,
+//! in the source or by `AssembleOptions::constants` (see `conditional`).
+//!
+//! `.INCBIN "file"` inserts the bytes of a file, packed two per word (big-endian, as in `.obj` files);
+//! a file with an odd number of bytes is padded with a zero byte. `.INCLUDE "file"` splices in the lines of another
+//! source file, whose labels are shared with the rest of the program. Paths are relative to the directory
+//! of the source (see `assemble_in`), or name files of an in-memory project (see `assemble_project`).
+//!
+//! BR, LD, LDI, LEA, ST, STI and JSR reach labels PC-relative, so they still work when a program is loaded
+//! at another origin, but a label used as a value (`.FILL LABEL`, `.BLKW n, LABEL`) is stored as an absolute
+//! address, e.g. for JSRR or LDI. `AssembleOptions::relocation_warnings` reports these with an `AbsoluteAddress` warning.
+mod cache;
+mod conditional;
+mod constant;
+mod error;
+mod include;
+mod incremental;
+mod local;
+mod macros;
+mod parser;
+mod peephole;
+mod symbol;
+mod warning;
+
+pub use self::cache::{assemble_to_cache, source_hash, CachedProgram};
+pub use self::error::{AssembleError, AssembleErrorKind};
+pub use self::incremental::{assemble_incremental, IncrementalUpdate, LabelMove};
+pub use self::peephole::{peephole, Optimization, OptimizationKind};
+pub use self::symbol::SymbolTable;
+pub use self::warning::{AssembleWarning, WarningKind};
+
+use self::include::{Disk, Files};
+use self::parser::{Operand, SourceLine};
+use crate::hardware::instructions::encode;
+use crate::hardware::io::Io;
+use crate::vm::{LoadInfo, Vm};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// A PC-relative offset which is within `NEAR_LIMIT` words of the end of its range gets an `OffsetNearLimit` warning.
+const NEAR_LIMIT: i32 = 8;
+
+/// `AssembleOptions` turns on the optional checks of `assemble_with`, and defines constants from outside the source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssembleOptions {
+    /// Warn about every absolute address of a label, as it would break if the program were relocated.
+    pub relocation_warnings: bool,
+    /// Constants defined as if by `.CONST` before the first line, e.g. to choose the `.IFDEF` regions of a
+    /// configurable program. A `.CONST` of the same name in the source is a `DuplicateLabel`.
+    pub constants: Vec<(String, i32)>,
+}
+
+/// `Section` is the output of one `.ORIG` / `.END` block: the words to load at `origin`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub origin: u16,
+    pub words: Vec<u16>,
+}
+
+impl Section {
+    /// `to_obj` returns the section in `.obj` format: the origin followed by the words, all big-endian.
+    pub fn to_obj(&self) -> Vec<u8> {
+        std::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+}
+
+/// `Program` is the output of `assemble`: one `Section` per `.ORIG` / `.END` block (in source order),
+/// the labels of all sections and any warnings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub sections: Vec<Section>,
+    pub symbols: SymbolTable,
+    pub warnings: Vec<AssembleWarning>,
+}
+
+impl Program {
+    /// `entry` is the origin of the first section, where execution starts.
+    pub fn entry(&self) -> u16 {
+        self.sections[0].origin
+    }
+}
+
+/// `assemble` translates `source` into a `Program`, or returns the first error found.
+/// Every line of code or data must be inside an `.ORIG` / `.END` block; a file may contain several blocks.
+/// `.INCBIN` and `.INCLUDE` paths are relative to the current directory.
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    assemble_in(source, Path::new("."))
+}
+
+/// `assemble_in` is `assemble` for a source which lives in `dir`: `.INCBIN` and `.INCLUDE` paths are relative to `dir`.
+pub fn assemble_in(source: &str, dir: &Path) -> Result<Program, AssembleError> {
+    assemble_with(source, dir, AssembleOptions::default())
+}
+
+/// `assemble_with` is `assemble_in` with the optional checks of `options`.
+pub fn assemble_with(
+    source: &str,
+    dir: &Path,
+    options: AssembleOptions,
+) -> Result<Program, AssembleError> {
+    assemble_from(source, &Disk(dir), Vec::new(), options)
+}
+
+/// `assemble_project` assembles a project without a filesystem (e.g. in a browser): `files` maps file names
+/// to sources, and `entry` names the file to assemble. `.INCLUDE` and `.INCBIN` paths name files of the map,
+/// so a subroutine in one file can be called by label from another which includes it.
+pub fn assemble_project(
+    files: &HashMap<String, String>,
+    entry: &str,
+) -> Result<Program, AssembleError> {
+    let source = files.get(entry).ok_or_else(|| {
+        AssembleError::new(
+            1,
+            AssembleErrorKind::IncludeFailed {
+                path: entry.to_string(),
+                reason: "no such file in the project".to_string(),
+            },
+        )
+    })?;
+    assemble_from(
+        source,
+        &include::Project(files),
+        vec![entry.to_string()],
+        AssembleOptions::default(),
+    )
+}
+
+/// `assemble_into` assembles `source` straight into the memory of `vm`, without building a `Program`: the words of
+/// every line are written as the second pass encodes them. Like `Vm::load_program`, it points PC at the entry
+/// point and keeps the labels (see `Vm::symbols`), and it returns where the first section went.
+/// Unlike `Vm::assemble_and_load`, an error in the second pass (e.g. an undefined label) is found after
+/// the lines before it were written, so memory may be left partly loaded. The warnings are dropped.
+pub fn assemble_into<I: Io>(source: &str, vm: &mut Vm<I>) -> Result<LoadInfo, AssembleError> {
+    let (sections, symbols, _) = assemble_lines(
+        source,
+        &Disk(Path::new(".")),
+        Vec::new(),
+        AssembleOptions::default(),
+        &mut |address, words| vm.load_words(address, words),
+    )?;
+    let (origin, len) = sections[0];
+    vm.registers_mut().set_pc(origin);
+    vm.set_symbols(symbols);
+    Ok(LoadInfo {
+        origin,
+        len,
+        end: origin.wrapping_add(len),
+    })
+}
+
+//assembles `source` (the file `including`, if it has a name), which reads the files it includes from `files`
+fn assemble_from(
+    source: &str,
+    files: &dyn Files,
+    including: Vec<String>,
+    options: AssembleOptions,
+) -> Result<Program, AssembleError> {
+    let mut words = Vec::new();
+    let (sections, symbols, warnings) =
+        assemble_lines(source, files, including, options, &mut |_, line| {
+            words.extend_from_slice(line)
+        })?;
+    let mut rest = words.as_slice();
+    let sections = sections
+        .into_iter()
+        .map(|(origin, len)| {
+            let (words, after) = rest.split_at(len as usize);
+            rest = after;
+            Section {
+                origin,
+                words: words.to_vec(),
+            }
+        })
+        .collect();
+    Ok(Program {
+        sections,
+        symbols,
+        warnings,
+    })
+}
+
+//the output of `assemble_lines`: the origin and length of every section, the labels and the warnings
+type Assembled = (Vec<(u16, u16)>, SymbolTable, Vec<AssembleWarning>);
+
+//the two passes of `assemble_from`, which hand the words of every line to `emit` with their address, in order
+fn assemble_lines(
+    source: &str,
+    files: &dyn Files,
+    including: Vec<String>,
+    options: AssembleOptions,
+    emit: &mut dyn FnMut(u16, &[u16]),
+) -> Result<Assembled, AssembleError> {
+    let lines = include::read(source, files, including)?;
+    let mut lines = conditional::select(lines, &options.constants)?;
+    local::resolve(&mut lines)?;
+    constant::substitute(&mut lines, &options.constants)?;
+
+    //first pass: find the sections and the address of every label
+    let mut symbols = SymbolTable::new();
+    let mut traps = HashMap::new();
+    //origin, the `.ORIG` line and the lines in between `.ORIG` and `.END`
+    let mut sections: Vec<(u16, &SourceLine, &[SourceLine])> = Vec::new();
+    //origin, the `.ORIG` line and the index of the first line of the open section
+    let mut open: Option<(u16, &SourceLine, usize)> = None;
+    //the origin and end (exclusive) of every closed section
+    let mut extents: Vec<(u32, u32)> = Vec::new();
+    let mut address: u32 = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if line.op.as_deref() == Some(".CONST") {
+            continue;
+        }
+        if line.op.as_deref() == Some(".EXTERNAL") {
+            let (name, vector) = trap_name(line)?;
+            if traps.insert(name.to_string(), vector).is_some() {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::DuplicateLabel(name.to_string()),
+                ));
+            }
+            if line.label.is_none() {
+                continue;
+            }
+        }
+        match (line.op.as_deref(), open) {
+            (Some(".ORIG"), Some(_)) => {
+                return Err(AssembleError::new(line.line, AssembleErrorKind::MissingEnd))
+            }
+            (Some(".ORIG"), None) => {
+                let value =
+                    expect_operands(line, 1).and_then(|_| number(line, &line.operands[0]))?;
+                let value = check_range(line, value, 0, 0xFFFF, 16)?;
+                open = Some((value as u16, line, i + 1));
+                address = value as u32;
+                continue;
+            }
+            (Some(".END"), Some((origin, orig_line, first))) => {
+                let start = origin as u32;
+                if let Some(&(other, _)) = extents
+                    .iter()
+                    .find(|&&(other, end)| start < end && other < address)
+                {
+                    return Err(AssembleError::new(
+                        orig_line.line,
+                        AssembleErrorKind::SectionOverlap {
+                            addr: start.max(other) as u16,
+                            first: other as u16,
+                            second: origin,
+                        },
+                    ));
+                }
+                extents.push((start, address));
+                sections.push((origin, orig_line, &lines[first..i]));
+                open = None;
+                continue;
+            }
+            (_, None) if line.label.is_some() || line.op.is_some() => {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::MissingOrig,
+                ))
+            }
+            (_, None) => continue,
+            _ => {}
+        }
+        if let Some(label) = &line.label {
+            if !symbols.insert(label, address as u16) {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::DuplicateLabel(label.clone()),
+                ));
+            }
+        }
+        address += size_of(line)?;
+        //a section of all 65,536 words would not fit the 16-bit length of a section (or `LoadInfo`)
+        let origin = open.map_or(0, |(origin, _, _)| origin as u32);
+        if address > 0x10000 || address - origin > 0xFFFF {
+            return Err(AssembleError::new(
+                line.line,
+                AssembleErrorKind::ProgramTooLarge,
+            ));
+        }
+    }
+    if let Some((_, orig_line, _)) = open {
+        return Err(AssembleError::new(
+            orig_line.line,
+            AssembleErrorKind::MissingEnd,
+        ));
+    }
+    if sections.is_empty() {
+        return Err(AssembleError::new(1, AssembleErrorKind::MissingOrig));
+    }
+
+    //second pass: encode every line
+    let mut encoder = Encoder {
+        symbols: &symbols,
+        traps: &traps,
+        referenced: HashSet::new(),
+        warnings: Vec::new(),
+        relocation_warnings: options.relocation_warnings,
+    };
+    let mut encoded = Vec::new();
+    for &(origin, _, body) in sections.iter() {
+        let mut len: u16 = 0;
+        let mut after_unconditional_branch = false;
+        for line in body.iter() {
+            match line.op.as_deref() {
+                None => continue,
+                Some(op) => {
+                    let is_instruction = !op.starts_with('.');
+                    if after_unconditional_branch && is_instruction && line.label.is_none() {
+                        encoder.warn(line, WarningKind::UnreachableInstruction);
+                    }
+                    after_unconditional_branch = matches!(op, "BR" | "BRNZP" | "JMP" | "RET");
+                }
+            }
+            let address = origin.wrapping_add(len);
+            let words = encoder.encode_line(line, address)?;
+            emit(address, &words);
+            len += words.len() as u16;
+        }
+        encoded.push((origin, len));
+    }
+
+    let mut warnings = encoder.warnings;
+    let referenced = encoder.referenced;
+    for line in sections.iter().flat_map(|&(_, _, body)| body.iter()) {
+        if let Some(label) = line
+            .label
+            .as_ref()
+            .filter(|_| line.op.as_deref() != Some(".CONST"))
+        {
+            if !referenced.contains(label) && !local::is_local(label) {
+                warnings.push(AssembleWarning::new(
+                    line.line,
+                    WarningKind::UnusedLabel(label.clone()),
+                ));
+            }
+        }
+    }
+    warnings.sort_by_key(|warning| warning.line);
+
+    Ok((encoded, symbols, warnings))
+}
+
+/// `syntax_errors` returns every line of `source` which cannot be parsed, with one error per malformed line,
+/// e.g. for an editor to underline all of them at once (`assemble` stops at the first error).
+/// Only the syntax of each line is checked: labels, operand counts and ranges are left to `assemble`.
+pub fn syntax_errors(source: &str) -> Vec<AssembleError> {
+    parser::parse_source(source).1
+}
+
+/// `assemble_line` encodes the single instruction in `source_line`, placed at `address`, e.g. to patch one
+/// instruction of a loaded program from an editor. Labels are resolved with `symbols` (typically the
+/// `symbols` of the assembled program); a label in front of the instruction is ignored.
+/// Directives and empty lines are not instructions, and give `UnknownOpcode`.
+pub fn assemble_line(
+    source_line: &str,
+    address: u16,
+    symbols: &SymbolTable,
+) -> Result<u16, AssembleError> {
+    let line = parser::parse_line(1, source_line)?;
+    let op = line.op.as_deref().unwrap_or_default();
+    if op.is_empty() || op.starts_with('.') {
+        return Err(AssembleError::new(
+            1,
+            AssembleErrorKind::UnknownOpcode(op.to_string()),
+        ));
+    }
+    let traps = HashMap::new();
+    let mut encoder = Encoder {
+        symbols,
+        traps: &traps,
+        referenced: HashSet::new(),
+        warnings: Vec::new(),
+        relocation_warnings: false,
+    };
+    let words = encoder.encode_line(&line, address)?;
+    Ok(words[0])
+}
+
+//the name and vector of `.EXTERNAL NAME, vector`
+fn trap_name(line: &SourceLine) -> Result<(&str, u8), AssembleError> {
+    expect_operands(line, 2)?;
+    match &line.operands[0] {
+        Operand::Label(name) => {
+            let vector = number(line, &line.operands[1])?;
+            Ok((name, check_range(line, vector, 0, 0xFF, 8)? as u8))
+        }
+        operand => Err(invalid_operand(line, operand)),
+    }
+}
+
+//the number of words `line` occupies in memory
+fn size_of(line: &SourceLine) -> Result<u32, AssembleError> {
+    match line.op.as_deref() {
+        None | Some(".EXTERNAL") | Some(".CONST") => Ok(0),
+        Some(".BLKW") => {
+            let count = line
+                .operands
+                .first()
+                .ok_or_else(|| operand_count(line, 1))
+                .and_then(|operand| number(line, operand))?;
+            Ok(check_range(line, count, 0, 0xFFFF, 16)? as u32)
+        }
+        Some(".INCBIN") => Ok(line.operands.len() as u32),
+        Some(".STRINGZ") => match line.operands.as_slice() {
+            [Operand::Str(s)] => Ok(s.chars().count() as u32 + 1),
+            [operand] => Err(invalid_operand(line, operand)),
+            _ => Err(operand_count(line, 1)),
+        },
+        Some(_) => Ok(1),
+    }
+}
+
+struct Encoder<'a> {
+    symbols: &'a SymbolTable,
+    //the trap vectors named by `.EXTERNAL`
+    traps: &'a HashMap<String, u8>,
+    referenced: HashSet<String>,
+    warnings: Vec<AssembleWarning>,
+    //warn about label addresses used as values
+    relocation_warnings: bool,
+}
+
+impl<'a> Encoder<'a> {
+    fn warn(&mut self, line: &SourceLine, kind: WarningKind) {
+        self.warnings.push(AssembleWarning::new(line.line, kind));
+    }
+
+    //encodes `line`, which is placed at `address`
+    fn encode_line(&mut self, line: &SourceLine, address: u16) -> Result<Vec<u16>, AssembleError> {
+        let op = line.op.as_deref().unwrap_or_default();
+        let ops = &line.operands;
+        let word = match op {
+            ".FILL" => {
+                expect_operands(line, 1)?;
+                self.value(line, &ops[0], address)?
+            }
+            ".BLKW" => {
+                let count = number(line, &ops[0])? as usize;
+                let fill = match ops.len() {
+                    1 => 0,
+                    2 => self.value(line, &ops[1], address)?,
+                    n => return Err(count_error(line, 2, n)),
+                };
+                return Ok(vec![fill; count]);
+            }
+            ".EXTERNAL" | ".CONST" => return Ok(Vec::new()),
+            //the words of the file, put in place of its path by `include::expand`
+            ".INCBIN" => {
+                return ops
+                    .iter()
+                    .map(|operand| self.value(line, operand, address))
+                    .collect()
+            }
+            ".STRINGZ" => {
+                if let [Operand::Str(s)] = ops.as_slice() {
+                    return Ok(s.chars().map(|c| c as u16).chain(Some(0)).collect());
+                }
+                return Err(operand_count(line, 1));
+            }
+            "ADD" | "AND" => {
+                expect_operands(line, 3)?;
+                let (dr, sr1) = (register(line, &ops[0])?, register(line, &ops[1])?);
+                match (&ops[2], op) {
+                    (Operand::Register(sr2), "ADD") => encode::add_reg(dr, sr1, *sr2),
+                    (Operand::Register(sr2), _) => encode::and_reg(dr, sr1, *sr2),
+                    (operand, "ADD") => encode::add_imm(dr, sr1, immediate(line, operand, 5)?),
+                    (operand, _) => encode::and_imm(dr, sr1, immediate(line, operand, 5)?),
+                }
+            }
+            "NOT" => {
+                expect_operands(line, 2)?;
+                encode::not(register(line, &ops[0])?, register(line, &ops[1])?)
+            }
+            "BR" | "BRN" | "BRZ" | "BRP" | "BRNZ" | "BRNP" | "BRZP" | "BRNZP" => {
+                expect_operands(line, 1)?;
+                let flags = &op[2..];
+                let nzp = match flags {
+                    "" => 0b111,
+                    _ => flags.chars().fold(0, |nzp, flag| {
+                        nzp | match flag {
+                            'N' => 0b100,
+                            'Z' => 0b010,
+                            _ => 0b001,
+                        }
+                    }),
+                };
+                encode::br(nzp, self.pc_offset(line, &ops[0], address, 9)?)
+            }
+            "JMP" => {
+                expect_operands(line, 1)?;
+                encode::jmp(register(line, &ops[0])?)
+            }
+            "RET" => {
+                expect_operands(line, 0)?;
+                encode::ret()
+            }
+            "JSR" => {
+                expect_operands(line, 1)?;
+                encode::jsr(self.pc_offset(line, &ops[0], address, 11)?)
+            }
+            "JSRR" => {
+                expect_operands(line, 1)?;
+                encode::jsrr(register(line, &ops[0])?)
+            }
+            "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+                expect_operands(line, 2)?;
+                let r = register(line, &ops[0])?;
+                let offset = self.pc_offset(line, &ops[1], address, 9)?;
+                match op {
+                    "LD" => encode::ld(r, offset),
+                    "LDI" => encode::ldi(r, offset),
+                    "LEA" => encode::lea(r, offset),
+                    "ST" => encode::st(r, offset),
+                    _ => encode::sti(r, offset),
+                }
+            }
+            "LDR" | "STR" => {
+                expect_operands(line, 3)?;
+                let (r, base) = (register(line, &ops[0])?, register(line, &ops[1])?);
+                let offset = immediate(line, &ops[2], 6)?;
+                if op == "LDR" {
+                    encode::ldr(r, base, offset)
+                } else {
+                    encode::str(r, base, offset)
+                }
+            }
+            "TRAP" => {
+                expect_operands(line, 1)?;
+                match &ops[0] {
+                    Operand::Label(name) => match self.traps.get(name) {
+                        Some(&vector) => encode::trap(vector),
+                        None => {
+                            return Err(AssembleError::new(
+                                line.line,
+                                AssembleErrorKind::UndefinedLabel(name.clone()),
+                            ))
+                        }
+                    },
+                    operand => {
+                        let vector = number(line, operand)?;
+                        encode::trap(check_range(line, vector, 0, 0xFF, 8)? as u8)
+                    }
+                }
+            }
+            "RTI" => {
+                expect_operands(line, 0)?;
+                encode::rti()
+            }
+            "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => {
+                expect_operands(line, 0)?;
+                encode::trap(match op {
+                    "GETC" => 0x20,
+                    "OUT" => 0x21,
+                    "PUTS" => 0x22,
+                    "IN" => 0x23,
+                    "PUTSP" => 0x24,
+                    _ => 0x25,
+                })
+            }
+            _ => {
+                return Err(AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::UnknownOpcode(op.to_string()),
+                ))
+            }
+        };
+        Ok(vec![word])
+    }
+
+    //a number (signed or unsigned, see the module documentation) or the address of a label,
+    //as a 16-bit word (`.FILL` / `.BLKW`) placed at `address`
+    fn value(
+        &mut self,
+        line: &SourceLine,
+        operand: &Operand,
+        address: u16,
+    ) -> Result<u16, AssembleError> {
+        match operand {
+            Operand::Label(label) => {
+                if self.relocation_warnings {
+                    self.warn(line, WarningKind::AbsoluteAddress(label.clone()));
+                }
+                self.resolve(line, label, address)
+            }
+            operand => {
+                let value = number(line, operand)?;
+                Ok(check_range(line, value, i16::MIN as i32, u16::MAX as i32, 16)? as u16)
+            }
+        }
+    }
+
+    //the offset from the incremented PC to a label, or a literal offset
+    fn pc_offset(
+        &mut self,
+        line: &SourceLine,
+        operand: &Operand,
+        address: u16,
+        bits: u32,
+    ) -> Result<i16, AssembleError> {
+        let (min, max) = (-(1 << (bits - 1)), (1 << (bits - 1)) - 1);
+        let (label, offset) = match operand {
+            Operand::Label(label) => {
+                let target = self.resolve(line, label, address)?;
+                let offset = target as i32 - (address as i32 + 1);
+                if (min..=max).contains(&offset)
+                    && (offset < min + NEAR_LIMIT || offset > max - NEAR_LIMIT)
+                {
+                    self.warn(line, WarningKind::OffsetNearLimit { offset, bits });
+                }
+                (Some(label), offset)
+            }
+            operand => (None, number(line, operand)?),
+        };
+        if !(min..=max).contains(&offset) {
+            return Err(AssembleError::new(
+                line.line,
+                AssembleErrorKind::OffsetOutOfRange {
+                    label: label.cloned(),
+                    offset,
+                    bits,
+                },
+            ));
+        }
+        Ok(offset as i16)
+    }
+
+    //the address of a label or an address expression (see `parser::split_expression`), on the line at `address`
+    fn resolve(
+        &mut self,
+        line: &SourceLine,
+        label: &str,
+        address: u16,
+    ) -> Result<u16, AssembleError> {
+        let (label, offset) = parser::split_expression(label).unwrap_or((label, 0));
+        let base = if label == "*" {
+            address
+        } else {
+            let base = self.symbols.get(label).ok_or_else(|| {
+                AssembleError::new(
+                    line.line,
+                    AssembleErrorKind::UndefinedLabel(label.to_string()),
+                )
+            })?;
+            self.referenced.insert(label.to_string());
+            base
+        };
+        Ok(base.wrapping_add(offset as u16))
+    }
+}
+
+fn register(line: &SourceLine, operand: &Operand) -> Result<u16, AssembleError> {
+    match operand {
+        Operand::Register(r) => Ok(*r),
+        //the PC is reached through PC-relative offsets, LEA and JSR, never as a register
+        Operand::Label(name) if name.eq_ignore_ascii_case("PC") => Err(AssembleError::new(
+            line.line,
+            AssembleErrorKind::NotARegister(name.clone()),
+        )),
+        operand => Err(invalid_operand(line, operand)),
+    }
+}
+
+fn number(line: &SourceLine, operand: &Operand) -> Result<i32, AssembleError> {
+    match operand {
+        Operand::Number(n) => Ok(*n),
+        operand => Err(invalid_operand(line, operand)),
+    }
+}
+
+//a signed immediate which must fit in `bits` bits
+fn immediate(line: &SourceLine, operand: &Operand, bits: u32) -> Result<i16, AssembleError> {
+    let value = number(line, operand)?;
+    Ok(check_range(line, value, -(1 << (bits - 1)), (1 << (bits - 1)) - 1, bits)? as i16)
+}
+
+fn check_range(
+    line: &SourceLine,
+    value: i32,
+    min: i32,
+    max: i32,
+    bits: u32,
+) -> Result<i32, AssembleError> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(AssembleError::new(
+            line.line,
+            AssembleErrorKind::ImmediateOutOfRange { value, bits },
+        ))
+    }
+}
+
+fn expect_operands(line: &SourceLine, expected: usize) -> Result<(), AssembleError> {
+    if line.operands.len() == expected {
+        Ok(())
+    } else {
+        Err(operand_count(line, expected))
+    }
+}
+
+pub(crate) fn operand_count(line: &SourceLine, expected: usize) -> AssembleError {
+    count_error(line, expected, line.operands.len())
+}
+
+fn count_error(line: &SourceLine, expected: usize, found: usize) -> AssembleError {
+    AssembleError::new(
+        line.line,
+        AssembleErrorKind::OperandCount { expected, found },
+    )
+}
+
+pub(crate) fn invalid_operand(line: &SourceLine, operand: &Operand) -> AssembleError {
+    let text = match operand {
+        Operand::Register(r) => format!("R{}", r),
+        Operand::Number(n) => format!("#{}", n),
+        Operand::Label(label) => label.clone(),
+        Operand::Str(s) => format!("{:?}", s),
+    };
+    AssembleError::new(line.line, AssembleErrorKind::InvalidOperand(text))
+}
+
+#[cfg(test)]
+mod assembler_test {
+    use super::*;
+    use proptest::prelude::*;
+    use std::fs;
+
+    #[test]
+    fn program_should_assemble_to_the_documented_words() {
+        let program = assemble(
+            "        .ORIG x3000
+                     LEA R0, MSG
+                     PUTS
+                     HALT
+             MSG     .STRINGZ \"hi\"
+                     .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![Section {
+                origin: 0x3000,
+                words: vec![0xE002, 0xF022, 0xF025, 0x68, 0x69, 0]
+            }],
+            program.sections
+        );
+        assert_eq!(Some(0x3003), program.symbols.get("MSG"));
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn os_source_should_assemble_to_the_os_image() {
+        let program = assemble(include_str!("../../resources/os.asm")).unwrap();
+        assert_eq!(
+            include_bytes!("../../resources/os.obj").to_vec(),
+            program.sections[0].to_obj()
+        );
+    }
+
+    #[test]
+    fn dead_label_should_warn_as_unused() {
+        let program = assemble(
+            ".ORIG x3000
+             ADD R0, R0, #1
+             DEAD ADD R0, R0, #1
+             HALT
+             .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![AssembleWarning::new(
+                3,
+                WarningKind::UnusedLabel("DEAD".to_string())
+            )],
+            program.warnings
+        );
+    }
+
+    #[test]
+    fn instruction_after_unconditional_branch_should_warn_as_unreachable() {
+        let program = assemble(
+            ".ORIG x3000
+             LOOP BRnzp LOOP
+             ADD R0, R0, #1
+             .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![AssembleWarning::new(3, WarningKind::UnreachableInstruction)],
+            program.warnings
+        );
+    }
+
+    #[test]
+    fn label_address_as_value_should_warn_about_relocation() {
+        let options = AssembleOptions {
+            relocation_warnings: true,
+            ..AssembleOptions::default()
+        };
+        let program = assemble_with(
+            ".ORIG x3000
+             LD R1, SUBPTR
+             JSRR R1
+             HALT
+             SUBPTR .FILL SUB
+             SUB RET
+             .END",
+            Path::new("."),
+            options.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![AssembleWarning::new(
+                5,
+                WarningKind::AbsoluteAddress("SUB".to_string())
+            )],
+            program.warnings
+        );
+
+        let relative = assemble_with(
+            ".ORIG x3000
+             LEA R0, MSG
+             JSR SUB
+             HALT
+             MSG .STRINGZ \"hi\"
+             SUB BRnzp DONE
+             DONE RET
+             .END",
+            Path::new("."),
+            options,
+        )
+        .unwrap();
+        assert!(relative.warnings.is_empty());
+    }
+
+    #[test]
+    fn local_labels_should_resolve_to_the_nearest_definition() {
+        let program = assemble(
+            ".ORIG x3000
+             AND R0, R0, #0
+             ADD R1, R0, #3
+          1: ADD R0, R0, #1
+             ADD R1, R1, #-1
+             BRp 1b
+             ADD R1, R0, #2
+          1: ADD R0, R0, #-1
+             ADD R1, R1, #-1
+             BRp 1b
+             BRnzp 1f
+             ADD R0, R0, #0
+          1: HALT
+             .END",
+        )
+        .unwrap();
+        let words = &program.sections[0].words;
+        assert_eq!(encode::br(0b001, -3), words[4]);
+        assert_eq!(encode::br(0b001, -3), words[8]);
+        assert_eq!(encode::br(0b111, 1), words[9]);
+        assert_eq!(Some(0x3002), program.symbols.get("1:0"));
+        assert_eq!(Some(0x3006), program.symbols.get("1:1"));
+        assert!(!program
+            .warnings
+            .iter()
+            .any(|warning| matches!(warning.kind, WarningKind::UnusedLabel(_))));
+    }
+
+    #[test]
+    fn local_reference_without_definition_should_fail() {
+        let error = assemble(
+            ".ORIG x3000
+             BRnzp 2f
+          2: HALT
+             BRnzp 2f
+             .END",
+        )
+        .unwrap_err();
+        assert_eq!(
+            AssembleError::new(4, AssembleErrorKind::UndefinedLabel("2f".to_string())),
+            error
+        );
+    }
+
+    #[test]
+    fn offset_near_its_limit_should_warn() {
+        let program = assemble(
+            ".ORIG x3000
+             BRz FAR
+             .BLKW #250
+             FAR HALT
+             .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![AssembleWarning::new(
+                2,
+                WarningKind::OffsetNearLimit {
+                    offset: 250,
+                    bits: 9
+                }
+            )],
+            program.warnings
+        );
+    }
+
+    #[test]
+    fn errors_should_report_their_line() {
+        let error = assemble(".ORIG x3000\nADD R0, R0, #16\n.END").unwrap_err();
+        assert_eq!(
+            AssembleError::new(
+                2,
+                AssembleErrorKind::ImmediateOutOfRange { value: 16, bits: 5 }
+            ),
+            error
+        );
+        let error = assemble(".ORIG x3000\nBR NOWHERE\n.END").unwrap_err();
+        assert_eq!(
+            AssembleError::new(2, AssembleErrorKind::UndefinedLabel("NOWHERE".to_string())),
+            error
+        );
+    }
+
+    #[test]
+    fn code_outside_a_section_should_error() {
+        assert_eq!(
+            AssembleError::new(1, AssembleErrorKind::MissingOrig),
+            assemble("ADD R0, R0, #1\n.ORIG x3000\nHALT\n.END").unwrap_err()
+        );
+        assert_eq!(
+            AssembleError::new(4, AssembleErrorKind::MissingOrig),
+            assemble(".ORIG x3000\nHALT\n.END\nHALT").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn section_of_all_memory_should_be_too_large() {
+        let source = ".ORIG x0000\n.BLKW xFFFF\n.FILL 1\n.END";
+        assert_eq!(
+            AssembleError::new(3, AssembleErrorKind::ProgramTooLarge),
+            assemble(source).unwrap_err()
+        );
+        let mut vm = Vm::new();
+        assert_eq!(
+            AssembleError::new(3, AssembleErrorKind::ProgramTooLarge),
+            assemble_into(source, &mut vm).unwrap_err()
+        );
+        assert_eq!(
+            0xFFFF,
+            assemble(".ORIG x0000\n.BLKW xFFFF\n.END").unwrap().sections[0]
+                .words
+                .len()
+        );
+    }
+
+    #[test]
+    fn missing_end_should_error() {
+        assert_eq!(
+            AssembleError::new(1, AssembleErrorKind::MissingEnd),
+            assemble(".ORIG x3000\nHALT").unwrap_err()
+        );
+        assert_eq!(
+            AssembleError::new(3, AssembleErrorKind::MissingEnd),
+            assemble(".ORIG x3000\nHALT\n.ORIG x4000\nHALT\n.END").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn sections_should_load_at_their_own_origins() {
+        let program = assemble(
+            ".ORIG x3000
+                  LD R0, DATA
+                  HALT
+                  .END
+             ; the data lives elsewhere
+                  .ORIG x3100
+             DATA .FILL #42
+                  .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Section {
+                    origin: 0x3000,
+                    words: vec![encode::ld(0, 0xFF), 0xF025]
+                },
+                Section {
+                    origin: 0x3100,
+                    words: vec![42]
+                },
+            ],
+            program.sections
+        );
+        assert_eq!(0x3000, program.entry());
+    }
+
+    #[test]
+    fn overlapping_sections_should_error() {
+        let error = assemble(
+            ".ORIG x3000
+                  .BLKW #4
+                  .END
+                  .ORIG x3003
+                  .FILL #1
+                  .END",
+        )
+        .unwrap_err();
+        assert_eq!(
+            AssembleError::new(
+                4,
+                AssembleErrorKind::SectionOverlap {
+                    addr: 0x3003,
+                    first: 0x3000,
+                    second: 0x3003
+                }
+            ),
+            error
+        );
+
+        //a section which ends right where the next one starts does not overlap
+        let program = assemble(
+            ".ORIG x3004
+                  .FILL #2
+                  .END
+                  .ORIG x3000
+                  .BLKW #4
+                  .END",
+        )
+        .unwrap();
+        assert_eq!(2, program.sections.len());
+    }
+
+    #[test]
+    fn label_out_of_branch_range_should_error_with_the_distance() {
+        let error = assemble(
+            ".ORIG x3000
+             BRz FAR
+             .BLKW #300
+             FAR HALT
+             .END",
+        )
+        .unwrap_err();
+        assert_eq!(
+            AssembleError::new(
+                2,
+                AssembleErrorKind::OffsetOutOfRange {
+                    label: Some("FAR".to_string()),
+                    offset: 300,
+                    bits: 9
+                }
+            ),
+            error
+        );
+        assert_eq!(
+            "line 2: label `FAR` is 300 word(s) away, but a 9-bit offset must be within -256..=255",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn named_trap_should_emit_its_vector() {
+        let program = assemble(
+            ".EXTERNAL PUTD, x31
+             .ORIG x3000
+             TRAP PUTD
+             TRAP x31
+             HALT
+             .END",
+        )
+        .unwrap();
+        assert_eq!(vec![0xF031, 0xF031, 0xF025], program.sections[0].words);
+        assert_eq!(
+            AssembleError::new(3, AssembleErrorKind::UndefinedLabel("PUTX".to_string())),
+            assemble(".EXTERNAL PUTD, x31\n.ORIG x3000\nTRAP PUTX\n.END").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn included_binary_should_land_at_the_current_location() {
+        let dir = std::env::temp_dir().join(format!("lc3_incbin_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("sprite.bin"), [0x12, 0x34, 0x56]).unwrap();
+        let program = assemble_in(
+            ".ORIG x3000
+                    LEA R0, SPRITE
+                    HALT
+             SPRITE .INCBIN \"sprite.bin\"
+             AFTER  .FILL #-1
+                    .END",
+            &dir,
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            vec![0xE001, 0xF025, 0x1234, 0x5600, 0xFFFF],
+            program.sections[0].words
+        );
+        assert_eq!(Some(0x3004), program.symbols.get("AFTER"));
+        assert!(matches!(
+            assemble(".ORIG x3000\n.INCBIN \"/nonexistent/file.bin\"\n.END"),
+            Err(AssembleError {
+                line: 2,
+                kind: AssembleErrorKind::IncludeFailed { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn included_file_should_share_its_definitions() {
+        let dir = std::env::temp_dir().join(format!("lc3_include_{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/defs.asm"),
+            ".INCLUDE \"step.asm\"\nLIMIT .CONST #9",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/step.asm"), "STEP .CONST #2").unwrap();
+        let program = assemble_in(
+            "      .INCLUDE \"lib/defs.asm\"
+                   .ORIG x3000
+                   ADD R0, R0, STEP
+                   .FILL LIMIT
+                   .END",
+            &dir,
+        );
+        fs::write(dir.join("lib/step.asm"), ".INCLUDE \"defs.asm\"").unwrap();
+        let recursive = assemble_in(".INCLUDE \"lib/defs.asm\"", &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            vec![encode::add_imm(0, 0, 2), 9],
+            program.unwrap().sections[0].words
+        );
+        assert!(matches!(
+            recursive,
+            Err(AssembleError {
+                line: 1,
+                kind: AssembleErrorKind::IncludeFailed { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn single_line_should_assemble_in_isolation() {
+        let symbols = SymbolTable::new();
+        assert_eq!(
+            Ok(0x1021),
+            assemble_line("ADD R0, R0, #1", 0x3000, &symbols)
+        );
+        let program = assemble(".ORIG x3000\nLOOP BRnzp LOOP\n.END").unwrap();
+        assert_eq!(
+            Ok(0x0FFD),
+            assemble_line("BRnzp LOOP ; back", 0x3002, &program.symbols)
+        );
+        assert!(assemble_line(".FILL #1", 0x3000, &symbols).is_err());
+        assert!(assemble_line("", 0x3000, &symbols).is_err());
+    }
+
+    //a register, R0 - R7
+    fn register() -> impl Strategy<Value = u16> {
+        0..8u16
+    }
+
+    //a sign-extendable `bits`-bit value
+    fn signed(bits: u32) -> impl Strategy<Value = i16> {
+        -(1i16 << (bits - 1))..(1i16 << (bits - 1))
+    }
+
+    //a well-formed instruction, of any form but `BR` with no flags (`NOP` does not assemble)
+    fn instruction() -> impl Strategy<Value = u16> {
+        use crate::hardware::instructions::encode;
+        prop_oneof![
+            (register(), register(), register()).prop_map(|(a, b, c)| encode::add_reg(a, b, c)),
+            (register(), register(), signed(5)).prop_map(|(a, b, n)| encode::add_imm(a, b, n)),
+            (register(), register(), register()).prop_map(|(a, b, c)| encode::and_reg(a, b, c)),
+            (register(), register(), signed(5)).prop_map(|(a, b, n)| encode::and_imm(a, b, n)),
+            (register(), register()).prop_map(|(a, b)| encode::not(a, b)),
+            (1..8u16, signed(9)).prop_map(|(flags, n)| encode::br(flags, n)),
+            register().prop_map(encode::jmp),
+            Just(encode::ret()),
+            signed(11).prop_map(encode::jsr),
+            register().prop_map(encode::jsrr),
+            (register(), signed(9)).prop_map(|(a, n)| encode::ld(a, n)),
+            (register(), signed(9)).prop_map(|(a, n)| encode::ldi(a, n)),
+            (register(), register(), signed(6)).prop_map(|(a, b, n)| encode::ldr(a, b, n)),
+            (register(), signed(9)).prop_map(|(a, n)| encode::lea(a, n)),
+            (register(), signed(9)).prop_map(|(a, n)| encode::st(a, n)),
+            (register(), signed(9)).prop_map(|(a, n)| encode::sti(a, n)),
+            (register(), register(), signed(6)).prop_map(|(a, b, n)| encode::str(a, b, n)),
+            any::<u8>().prop_map(encode::trap),
+            (0x20..0x26u8).prop_map(encode::trap),
+            Just(encode::rti()),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(5000))]
+        #[test]
+        fn disassembly_should_reassemble_to_the_same_word(word in instruction()) {
+            use crate::hardware::instructions::decode::DecodedInstr;
+            let text = DecodedInstr::decode(word).to_string();
+            prop_assert_eq!(
+                Ok(word),
+                assemble_line(&text, 0x3000, &SymbolTable::new()),
+                "x{:04X} disassembles to `{}`",
+                word,
+                text
+            );
+        }
+    }
+
+    const CONFIGURABLE: &str = "        .ORIG x3000
+                     .IFDEF DEBUG
+                     LEA R0, MSG
+                     PUTS
+                     .ENDIF
+                     .IFNDEF FAST
+                     ADD R1, R1, #1
+                     .ELSE
+                     ADD R1, R1, #2
+                     .ENDIF
+                     HALT
+                     .IFDEF DEBUG
+             MSG     .STRINGZ \"d\"
+                     .ENDIF
+                     .END";
+
+    #[test]
+    fn conditional_regions_should_follow_the_defined_constants() {
+        let plain = assemble(CONFIGURABLE).unwrap();
+        assert_eq!(vec![0x1261, 0xF025], plain.sections[0].words);
+        assert_eq!(None, plain.symbols.get("MSG"));
+
+        let options = AssembleOptions {
+            constants: vec![("DEBUG".to_string(), 1), ("FAST".to_string(), 1)],
+            ..AssembleOptions::default()
+        };
+        let debug = assemble_with(CONFIGURABLE, Path::new("."), options).unwrap();
+        assert_eq!(
+            vec![0xE003, 0xF022, 0x1262, 0xF025, 0x64, 0],
+            debug.sections[0].words
+        );
+    }
+
+    #[test]
+    fn conditional_should_see_earlier_constants_and_nest() {
+        let program = assemble(
+            "LEVEL   .CONST #2
+                     .ORIG x3000
+                     .IFDEF LEVEL
+                     .IFDEF OTHER
+                     ADD R0, R0, #1
+                     .ELSE
+                     ADD R0, R0, LEVEL
+                     .ENDIF
+                     .ENDIF
+                     .END",
+        )
+        .unwrap();
+        assert_eq!(vec![0x1022], program.sections[0].words);
+    }
+
+    #[test]
+    fn unbalanced_conditionals_should_be_rejected() {
+        assert_eq!(
+            AssembleErrorKind::MissingEndif,
+            assemble(".ORIG x3000\n.IFDEF X\nHALT\n.END")
+                .unwrap_err()
+                .kind
+        );
+        assert_eq!(
+            AssembleError::new(
+                3,
+                AssembleErrorKind::UnmatchedConditional(".ENDIF".to_string())
+            ),
+            assemble(".ORIG x3000\nHALT\n.ENDIF\n.END").unwrap_err()
+        );
+        assert_eq!(
+            AssembleErrorKind::UnmatchedConditional(".ELSE".to_string()),
+            assemble(".IFDEF X\n.ELSE\n.ELSE\n.ENDIF").unwrap_err().kind
+        );
+    }
+
+    #[test]
+    fn constant_should_be_usable_in_fill_and_immediates() {
+        let program = assemble(
+            "MAX   .CONST #100
+                   .ORIG x3000
+             STEP  .CONST #-3
+                   ADD R0, R0, STEP
+                   HALT
+             LIMIT .FILL MAX
+                   .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![encode::add_imm(0, 0, -3), 0xF025, 100],
+            program.sections[0].words
+        );
+        assert_eq!(None, program.symbols.get("MAX"));
+        assert_eq!(Some(0x3002), program.symbols.get("LIMIT"));
+    }
+
+    #[test]
+    fn constant_should_not_be_used_as_an_address() {
+        assert_eq!(
+            AssembleError::new(3, AssembleErrorKind::ConstantAsAddress("MAX".to_string())),
+            assemble(".ORIG x3000\nMAX .CONST #5\nLD R0, MAX\n.END").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn project_should_link_labels_across_files() {
+        let mut files = HashMap::new();
+        files.insert(
+            "main.asm".to_string(),
+            ".ORIG x3000
+             JSR DOUBLE
+             HALT
+             .INCLUDE \"double.asm\"
+       TABLE .INCBIN \"table.bin\"
+             .END"
+                .to_string(),
+        );
+        files.insert(
+            "double.asm".to_string(),
+            "DOUBLE ADD R0, R0, R0\n       RET".to_string(),
+        );
+        files.insert("table.bin".to_string(), "AB".to_string());
+        let program = assemble_project(&files, "main.asm").unwrap();
+        assert_eq!(
+            vec![0x4801, 0xF025, 0x1000, 0xC1C0, 0x4142],
+            program.sections[0].words
+        );
+        assert_eq!(Some(0x3002), program.symbols.get("DOUBLE"));
+        assert_eq!(Some(0x3004), program.symbols.get("TABLE"));
+
+        files.insert(
+            "double.asm".to_string(),
+            ".INCLUDE \"main.asm\"".to_string(),
+        );
+        assert!(matches!(
+            assemble_project(&files, "main.asm"),
+            Err(AssembleError {
+                kind: AssembleErrorKind::IncludeFailed { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn macros_should_expand_with_their_arguments() {
+        let program = assemble(
+            ".MACRO PUSH, REG
+                    ADD R6, R6, #-1
+                    STR REG, R6, #0
+             .ENDM
+             .MACRO POP REG ; pops into REG
+                    LDR REG, R6, #0
+                    ADD R6, R6, #1
+             .ENDM
+                    .ORIG x3000
+             START  PUSH R1
+                    pop R2
+                    .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                encode::add_imm(6, 6, -1),
+                encode::str(1, 6, 0),
+                encode::ldr(2, 6, 0),
+                encode::add_imm(6, 6, 1),
+            ],
+            program.sections[0].words
+        );
+        assert_eq!(Some(0x3000), program.symbols.get("START"));
+    }
+
+    #[test]
+    fn macro_labels_should_be_unique_per_expansion() {
+        let program = assemble(
+            ".MACRO ABS, REG
+                    ADD REG, REG, #0
+                    BRzp DONE@
+                    NOT REG, REG
+                    ADD REG, REG, #1
+             DONE@
+             .ENDM
+                    .ORIG x3000
+                    ABS R0
+                    ABS R1
+                    .END",
+        )
+        .unwrap();
+        assert_eq!(8, program.sections[0].words.len());
+        assert_eq!(encode::br(0b011, 2), program.sections[0].words[5]);
+        assert_eq!(
+            AssembleError::new(1, AssembleErrorKind::MissingEndm),
+            assemble(".MACRO NOP\nADD R0, R0, #0").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn character_literals_should_assemble_to_their_ascii_code() {
+        let program = assemble(
+            ".ORIG x3000\n.FILL 'A'\n.FILL '\\n'\n.BLKW 1, '\\0'\nADD R0, R0, '\\t'\n.END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![0x0041, 0x000A, 0x0000, 0x1029],
+            program.sections[0].words
+        );
+        assert_eq!(
+            Err(AssembleError::new(
+                2,
+                AssembleErrorKind::ImmediateOutOfRange { value: 48, bits: 5 }
+            )),
+            assemble(".ORIG x3000\nADD R0, R0, '0'\n.END")
+        );
+        for literal in ["''", "'ab'", "'A", "'é'", "'\\q'"].iter() {
+            assert_eq!(
+                Err(AssembleError::new(2, AssembleErrorKind::InvalidCharacter)),
+                assemble(&format!(".ORIG x3000\n.FILL {}\n.END", literal))
+            );
+        }
+    }
+
+    #[test]
+    fn fill_should_store_twos_complement_words() {
+        let fill = |value: &str| {
+            assemble(&format!(".ORIG x3000\n.FILL {}\n.END", value))
+                .map(|program| program.sections[0].words[0])
+        };
+        assert_eq!(Ok(0xFFFF), fill("#-1"));
+        assert_eq!(Ok(0x8000), fill("#-32768"));
+        assert_eq!(Ok(0x7FFF), fill("#32767"));
+        assert_eq!(Ok(0xFFFF), fill("#65535"));
+        assert_eq!(Ok(0xFFFF), fill("xFFFF"));
+        for value in ["#-32769", "#65536", "x10000"].iter() {
+            assert!(matches!(
+                fill(value),
+                Err(AssembleError {
+                    line: 2,
+                    kind: AssembleErrorKind::ImmediateOutOfRange { bits: 16, .. }
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn inserted_instruction_should_keep_branches_on_their_labels() {
+        let before = ".ORIG x3000
+                      AND R0, R0, #0
+                      ADD R1, R0, #3
+                 LOOP ADD R0, R0, #2
+                      ADD R1, R1, #-1
+                      BRp LOOP
+                      ST R0, RESULT
+                      HALT
+               RESULT .FILL #0
+                      .END";
+        let previous = assemble(before).unwrap();
+        let edited = before.replace(
+            "ADD R1, R0, #3",
+            "ADD R1, R0, #3\n                      ADD R0, R0, #1",
+        );
+        let update = assemble_incremental(&previous, &edited).unwrap();
+        assert_eq!(assemble(&edited).unwrap(), update.program);
+        assert_eq!(
+            vec![
+                LabelMove {
+                    label: "LOOP".to_string(),
+                    old: Some(0x3002),
+                    new: Some(0x3003)
+                },
+                LabelMove {
+                    label: "RESULT".to_string(),
+                    old: Some(0x3007),
+                    new: Some(0x3008)
+                },
+            ],
+            update.moved
+        );
+        //the BR and the ST moved along with their targets, so their offsets stay the same
+        assert_eq!(encode::br(0b001, -3), update.program.sections[0].words[5]);
+        assert_eq!(encode::st(0, 1), update.program.sections[0].words[6]);
+        assert_eq!(
+            vec![0x3002, 0x3003, 0x3004, 0x3005, 0x3006, 0x3007, 0x3008],
+            update
+                .patches
+                .iter()
+                .map(|&(address, _)| address)
+                .collect::<Vec<u16>>()
+        );
+    }
+
+    #[test]
+    fn pc_should_be_rejected_as_a_register() {
+        let error = assemble(".ORIG x3000\nADD R0, PC, #1\n.END").unwrap_err();
+        assert_eq!(
+            AssembleError::new(2, AssembleErrorKind::NotARegister("PC".to_string())),
+            error
+        );
+        assert_eq!(
+            "line 2: `PC` is not a register; the only general purpose registers are R0 - R7",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn current_address_and_label_offsets_should_resolve() {
+        let program = assemble(
+            ".ORIG x3000
+                   .FILL *
+                   .FILL TABLE+1
+                   LD R0, TABLE+2
+                   BRnzp *-1
+             TABLE .BLKW 2, *
+                   .FILL TABLE-1
+                   .END",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![0x3000, 0x3005, 0x2003, 0x0FFE, 0x3004, 0x3004, 0x3003],
+            program.sections[0].words
+        );
+        //the base of an expression counts as a use of the label
+        assert!(program.warnings.is_empty());
+        assert_eq!(
+            Err(AssembleError::new(
+                2,
+                AssembleErrorKind::UndefinedLabel("NOWHERE".to_string())
+            )),
+            assemble(".ORIG x3000\n.FILL NOWHERE+1\n.END")
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! `parser` : Splits a line of LC-3 assembly into its (optional) label, opcode or directive, and operands.
+//! A line looks like `LABEL OPCODE OPERAND, OPERAND ; comment`, where every part is optional.
+
+use super::error::{AssembleError, AssembleErrorKind};
+use super::local;
+
+/// `Operand` is a single parsed operand.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Operand {
+    /// `R0` - `R7`, the only registers an operand can name (there is no encoding for the PC).
+    Register(u16),
+    /// `#-5` (decimal), `x1F` (hexadecimal), `12` (decimal) or `'A'` (a character literal, as its ASCII code)
+    Number(i32),
+    /// A label, a reference to a local label (`1b` / `1f`, see `assembler::local`), or an address expression:
+    /// `*` (the address of the line itself), optionally followed by `+n` / `-n`, or `LABEL+n` / `LABEL-n`
+    /// (see `split_expression`).
+    Label(String),
+    /// A `"string literal"`, with its escape sequences resolved.
+    Str(String),
+}
+
+/// `SourceLine` is a parsed line of source; `op` is upper-cased.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SourceLine {
+    pub line: usize,
+    pub label: Option<String>,
+    pub op: Option<String>,
+    pub operands: Vec<Operand>,
+}
+
+enum Token {
+    Word(String),
+    Str(String),
+    Char(u8),
+}
+
+const OPCODES: [&str; 28] = [
+    "ADD", "AND", "NOT", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP", "BRNZP", "JMP", "RET",
+    "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI", "GETC", "OUT",
+    "PUTS", "PUTSP",
+];
+
+/// `is_mnemonic` returns `true` if `word` is an opcode, a trap alias or a directive, in any case.
+pub(crate) fn is_mnemonic(word: &str) -> bool {
+    let upper = word.to_ascii_uppercase();
+    upper.starts_with('.') || upper == "IN" || upper == "HALT" || OPCODES.contains(&upper.as_str())
+}
+
+/// `parse_line` parses the `line`-th line of the source, `text`.
+pub(crate) fn parse_line(line: usize, text: &str) -> Result<SourceLine, AssembleError> {
+    let mut tokens = tokenize(line, text)?.into_iter().peekable();
+
+    let label = match tokens.peek() {
+        Some(Token::Word(word)) if !is_mnemonic(word) => {
+            if !is_label(word) && !local::is_definition(word) {
+                return Err(AssembleError::new(
+                    line,
+                    AssembleErrorKind::UnknownOpcode(word.clone()),
+                ));
+            }
+            let label = word.clone();
+            tokens.next();
+            Some(label)
+        }
+        _ => None,
+    };
+
+    let op = match tokens.next() {
+        Some(Token::Word(word)) if is_mnemonic(&word) => Some(word.to_ascii_uppercase()),
+        Some(Token::Word(word)) => {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::UnknownOpcode(word),
+            ))
+        }
+        Some(Token::Str(_)) => {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::InvalidOperand("\"...\"".to_string()),
+            ))
+        }
+        Some(Token::Char(_)) => {
+            return Err(AssembleError::new(
+                line,
+                AssembleErrorKind::InvalidOperand("'...'".to_string()),
+            ))
+        }
+        None => None,
+    };
+
+    let operands = tokens
+        .map(|token| match token {
+            Token::Word(word) => parse_operand(line, &word),
+            Token::Str(s) => Ok(Operand::Str(s)),
+            Token::Char(c) => Ok(Operand::Number(i32::from(c))),
+        })
+        .collect::<Result<Vec<Operand>, AssembleError>>()?;
+
+    Ok(SourceLine {
+        line,
+        label,
+        op,
+        operands,
+    })
+}
+
+/// `parse_source` parses every line of `source`, recovering from errors: a malformed line gives exactly one error
+/// and is left out of the lines, and parsing resumes at the next line. Lines are numbered from 1.
+pub(crate) fn parse_source(source: &str) -> (Vec<SourceLine>, Vec<AssembleError>) {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        match parse_line(i + 1, text) {
+            Ok(line) => lines.push(line),
+            Err(error) => errors.push(error),
+        }
+    }
+    (lines, errors)
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AssembleError> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '"' => {
+                push_word(&mut tokens, &mut word);
+                tokens.push(Token::Str(parse_string(line, &mut chars)?));
+            }
+            //a quote inside a word is left to the operand, which is then invalid
+            '\'' if word.is_empty() => tokens.push(Token::Char(parse_char(line, &mut chars)?)),
+            c if c.is_whitespace() || c == ',' => push_word(&mut tokens, &mut word),
+            c => word.push(c),
+        }
+    }
+    push_word(&mut tokens, &mut word);
+    Ok(tokens)
+}
+
+fn push_word(tokens: &mut Vec<Token>, word: &mut String) {
+    if !word.is_empty() {
+        tokens.push(Token::Word(std::mem::take(word)));
+    }
+}
+
+//reads the rest of a string literal, after its opening quote
+fn parse_string(line: usize, chars: &mut std::str::Chars) -> Result<String, AssembleError> {
+    let invalid = || AssembleError::new(line, AssembleErrorKind::InvalidString);
+    let mut s = String::new();
+    loop {
+        match chars.next().ok_or_else(invalid)? {
+            '"' => return Ok(s),
+            '\\' => s.push(escape(chars.next().ok_or_else(invalid)?).ok_or_else(invalid)?),
+            c => s.push(c),
+        }
+    }
+}
+
+//reads the rest of a character literal, after its opening quote: one ASCII character or escape sequence
+fn parse_char(line: usize, chars: &mut std::str::Chars) -> Result<u8, AssembleError> {
+    let invalid = || AssembleError::new(line, AssembleErrorKind::InvalidCharacter);
+    let c = match chars.next().ok_or_else(invalid)? {
+        '\\' => escape(chars.next().ok_or_else(invalid)?).ok_or_else(invalid)?,
+        '\'' => return Err(invalid()),
+        c => c,
+    };
+    match chars.next() {
+        Some('\'') if c.is_ascii() => Ok(c as u8),
+        _ => Err(invalid()),
+    }
+}
+
+//the character an escape sequence (after its backslash) stands for, in strings and character literals
+fn escape(c: char) -> Option<char> {
+    Some(match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        'e' => '\x1b',
+        '"' => '"',
+        '\'' => '\'',
+        '\\' => '\\',
+        _ => return None,
+    })
+}
+
+fn parse_operand(line: usize, word: &str) -> Result<Operand, AssembleError> {
+    if let Some(register) = parse_register(word) {
+        return Ok(Operand::Register(register));
+    }
+    if let Some(number) = parse_number(word) {
+        return Ok(Operand::Number(number));
+    }
+    if is_label(word) || local::is_reference(word) || split_expression(word).is_some() {
+        return Ok(Operand::Label(word.to_string()));
+    }
+    Err(AssembleError::new(
+        line,
+        AssembleErrorKind::InvalidOperand(word.to_string()),
+    ))
+}
+
+fn parse_register(word: &str) -> Option<u16> {
+    let bytes = word.as_bytes();
+    match bytes {
+        [b'R', n] | [b'r', n] if (b'0'..=b'7').contains(n) => Some(u16::from(n - b'0')),
+        _ => None,
+    }
+}
+
+/// `parse_number` parses `#12` / `#-12` / `12` (decimal) and `x1F` / `x-1F` (hexadecimal).
+pub(crate) fn parse_number(word: &str) -> Option<i32> {
+    let (digits, radix) = match word.chars().next()? {
+        '#' => (&word[1..], 10),
+        'x' | 'X' => (&word[1..], 16),
+        _ => (word, 10),
+    };
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i32::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// `split_expression` splits the address expression `*`, `*+n`, `*-n`, `LABEL+n` or `LABEL-n` into its base
+/// (`*` or the label) and its offset; `n` is written as a number (`2`, `#2` or `x2`).
+/// Anything else is not an expression.
+pub(crate) fn split_expression(word: &str) -> Option<(&str, i32)> {
+    if word == "*" {
+        return Some(("*", 0));
+    }
+    let at = word.rfind(['+', '-'])?;
+    let (base, offset) = (&word[..at], &word[at + 1..]);
+    if base != "*" && !is_label(base) {
+        return None;
+    }
+    let offset = match parse_number(offset)? {
+        n if n < 0 => return None,
+        n if word.as_bytes()[at] == b'-' => -n,
+        n => n,
+    };
+    Some((base, offset))
+}
+
+fn is_label(word: &str) -> bool {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && parse_register(word).is_none()
+        && !is_mnemonic(word)
+}
+
+#[cfg(test)]
+mod parser_test {
+    use super::*;
+    #[test]
+    fn malformed_line_should_give_one_error_and_parsing_should_resume() {
+        let (lines, errors) =
+            parse_source("LOOP ADD R1, R1, #-1\nBAD_OP!? R1, \"open, %%\nBRp LOOP");
+        assert_eq!(1, errors.len());
+        assert_eq!(2, errors[0].line);
+        assert_eq!(vec![1, 3], lines.iter().map(|l| l.line).collect::<Vec<_>>());
+        assert_eq!(Some("BRP".to_string()), lines[1].op);
+        assert_eq!(vec![Operand::Label("LOOP".to_string())], lines[1].operands);
+    }
+
+    #[test]
+    fn line_should_be_split_into_label_op_and_operands() {
+        let line = parse_line(3, "LOOP add R1, r1, #-1 ; count down").unwrap();
+        assert_eq!(3, line.line);
+        assert_eq!(Some("LOOP".to_string()), line.label);
+        assert_eq!(Some("ADD".to_string()), line.op);
+        assert_eq!(
+            vec![
+                Operand::Register(1),
+                Operand::Register(1),
+                Operand::Number(-1)
+            ],
+            line.operands
+        );
+    }
+
+    #[test]
+    fn strings_should_keep_semicolons_and_resolve_escapes() {
+        let line = parse_line(1, r#"MSG .STRINGZ "a;b\n" ; comment"#).unwrap();
+        assert_eq!(vec![Operand::Str("a;b\n".to_string())], line.operands);
+    }
+
+    #[test]
+    fn numbers_should_parse_in_both_radixes() {
+        assert_eq!(Some(-12), parse_number("#-12"));
+        assert_eq!(Some(0x3000), parse_number("x3000"));
+        assert_eq!(Some(7), parse_number("7"));
+        assert_eq!(None, parse_number("xZZ"));
+    }
+}
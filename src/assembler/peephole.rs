@@ -0,0 +1,179 @@
+//! `peephole` : An educational optimizer, which looks at pairs of neighbouring instructions and reports
+//! the ones which could be written shorter or clearer. Nothing is rewritten: every `Optimization` names
+//! the line of the first instruction of its pair, and the programmer decides.
+//!
+//! A pair is only considered when nothing can jump between its instructions, so the second one must not be labeled.
+//! Empty and comment lines in between are skipped; any other line (including a malformed one) breaks the pair.
+
+use super::parser::{self, Operand, SourceLine};
+use std::fmt;
+
+/// `Optimization` is an opportunity found by `peephole`, on the (1-based) source line of its first instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Optimization {
+    pub line: usize,
+    pub kind: OptimizationKind,
+}
+
+/// `OptimizationKind` lists the patterns recognized by `peephole`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptimizationKind {
+    /// `AND Rd, Rd, #0` followed by `ADD Rd, Rd, #value`: the pair only loads the constant `value`,
+    /// which reads better as one step (e.g. a macro) and can be folded if the constant is used again.
+    ClearThenAdd { reg: u16, value: i32 },
+    /// `AND Rd, Rd, #0` (or `AND Rd, Rs, #0`) right after an instruction which already cleared Rd.
+    RedundantClear { reg: u16 },
+    /// `LEA Rb, LABEL` followed by `LDR Rd, Rb, #0` is `LD Rd, LABEL`, if Rb is not used afterwards.
+    LeaThenLdr { base: u16, dr: u16, label: String },
+}
+
+impl fmt::Display for Optimization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            OptimizationKind::ClearThenAdd { reg, value } => write!(
+                f,
+                "R{} is cleared and then #{} is added: the pair loads the constant #{}",
+                reg, value, value
+            ),
+            OptimizationKind::RedundantClear { reg } => {
+                write!(f, "R{} is cleared twice in a row", reg)
+            }
+            OptimizationKind::LeaThenLdr { base, dr, label } => write!(
+                f,
+                "`LEA R{}, {}` then `LDR R{}, R{}, #0` can be `LD R{}, {}`",
+                base, label, dr, base, dr, label
+            ),
+        }
+    }
+}
+
+/// `peephole` returns the optimization opportunities in `source`, in source order.
+/// Macros and includes are not expanded, so only the instructions written in `source` itself are looked at.
+pub fn peephole(source: &str) -> Vec<Optimization> {
+    let (lines, errors) = parser::parse_source(source);
+    let mut found = Vec::new();
+    let mut previous: Option<&SourceLine> = None;
+    for line in lines.iter() {
+        if line.label.is_none() && line.op.is_none() {
+            //a blank or comment line
+            continue;
+        }
+        //a malformed line between the two is left out of `lines`, but still breaks the pair
+        let adjacent =
+            previous.is_some_and(|p| !errors.iter().any(|e| p.line < e.line && e.line < line.line));
+        if let (Some(first), true, None) = (previous, adjacent, &line.label) {
+            if let Some(kind) = pair(first, line) {
+                found.push(Optimization {
+                    line: first.line,
+                    kind,
+                });
+            }
+        }
+        previous = Some(line);
+    }
+    found
+}
+
+//the opportunity in `first` directly followed by `second`, if any
+fn pair(first: &SourceLine, second: &SourceLine) -> Option<OptimizationKind> {
+    use Operand::{Label, Number, Register};
+    let cleared = clears(first);
+    match (second.op.as_deref()?, second.operands.as_slice()) {
+        ("ADD", [Register(dr), Register(sr), Number(value)])
+            if cleared == Some(*dr) && dr == sr && *value != 0 =>
+        {
+            Some(OptimizationKind::ClearThenAdd {
+                reg: *dr,
+                value: *value,
+            })
+        }
+        _ if cleared.is_some() && clears(second) == cleared => {
+            Some(OptimizationKind::RedundantClear { reg: cleared? })
+        }
+        ("LDR", [Register(dr), Register(base), Number(0)]) => {
+            match (first.op.as_deref()?, first.operands.as_slice()) {
+                ("LEA", [Register(lea), Label(label)]) if lea == base => {
+                    Some(OptimizationKind::LeaThenLdr {
+                        base: *base,
+                        dr: *dr,
+                        label: label.clone(),
+                    })
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+//the register `line` sets to zero, if it is an `AND Rd, Rs, #0`
+fn clears(line: &SourceLine) -> Option<u16> {
+    match (line.op.as_deref()?, line.operands.as_slice()) {
+        ("AND", [Operand::Register(dr), Operand::Register(_), Operand::Number(0)]) => Some(*dr),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod peephole_test {
+    use super::*;
+
+    #[test]
+    fn clear_then_add_should_be_reported() {
+        let source = ".ORIG x3000
+                      AND R1, R1, #0
+                      ; the counter starts at 5
+                      ADD R1, R1, #5
+                      HALT
+                      .END";
+        let found = peephole(source);
+        assert_eq!(
+            vec![Optimization {
+                line: 2,
+                kind: OptimizationKind::ClearThenAdd { reg: 1, value: 5 },
+            }],
+            found
+        );
+        assert_eq!(
+            "line 2: R1 is cleared and then #5 is added: the pair loads the constant #5",
+            found[0].to_string()
+        );
+    }
+
+    #[test]
+    fn labeled_second_instruction_should_break_the_pair() {
+        let source = ".ORIG x3000
+                      AND R1, R1, #0
+                 LOOP ADD R1, R1, #5
+                      AND R2, R2, #0
+                      ADD R3, R3, #1
+                      BRnzp LOOP
+                      .END";
+        assert!(peephole(source).is_empty());
+    }
+
+    #[test]
+    fn redundant_clear_and_lea_ldr_should_be_reported() {
+        let source = ".ORIG x3000
+                      AND R2, R2, #0
+                      AND R2, R3, #0
+                      LEA R4, DATA
+                      LDR R0, R4, #0
+                      HALT
+                 DATA .FILL #7
+                      .END";
+        let kinds: Vec<OptimizationKind> = peephole(source).into_iter().map(|o| o.kind).collect();
+        assert_eq!(
+            vec![
+                OptimizationKind::RedundantClear { reg: 2 },
+                OptimizationKind::LeaThenLdr {
+                    base: 4,
+                    dr: 0,
+                    label: "DATA".to_string()
+                },
+            ],
+            kinds
+        );
+    }
+}
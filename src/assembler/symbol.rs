@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+/// `SymbolTable` maps each label of a program to its address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// `insert` defines `label` at `address`. It returns `false` (and keeps the old address) if `label` is already defined.
+    pub fn insert(&mut self, label: &str, address: u16) -> bool {
+        if self.symbols.contains_key(label) {
+            return false;
+        }
+        self.symbols.insert(label.to_string(), address);
+        true
+    }
+
+    /// `get` returns the address of `label`, if it is defined.
+    pub fn get(&self, label: &str) -> Option<u16> {
+        self.symbols.get(label).copied()
+    }
+
+    /// `label_at` returns the label defined at `address`.
+    /// If several labels share the address, the alphabetically first named label is returned,
+    /// and a local label (`1:0`, see `assembler::local`) only if there is no named one.
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .filter(|&(_, &a)| a == address)
+            .map(|(label, _)| label.as_str())
+            .min_by_key(|&label| (super::local::is_local(label), label))
+    }
+
+    /// `nearest_below` returns the closest label at or before `address` (chosen as `label_at` does, if several
+    /// share its address) and how far `address` is past it, e.g. to show an address as `FUNC+3`.
+    pub fn nearest_below(&self, address: u16) -> Option<(&str, u16)> {
+        let base = self
+            .symbols
+            .values()
+            .copied()
+            .filter(|&a| a <= address)
+            .max()?;
+        Some((self.label_at(base)?, address - base))
+    }
+
+    /// `iter` visits every label and its address, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.symbols
+            .iter()
+            .map(|(label, &address)| (label.as_str(), address))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// `AssembleWarning` flags code which is legal but suspicious, together with the (1-based) source line it was found on.
+/// Warnings never stop a program from assembling.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleWarning {
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+/// `WarningKind` lists the lint-style checks done by the assembler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// An unlabeled instruction directly follows an unconditional branch (`BR`, `BRnzp`, `JMP` or `RET`),
+    /// so it can never be executed.
+    UnreachableInstruction,
+    /// The label is defined, but never referenced.
+    UnusedLabel(String),
+    /// The PC-relative offset fits in its `bits`-bit field, but only just:
+    /// a few more words between the instruction and its target will break the program.
+    OffsetNearLimit { offset: i32, bits: u32 },
+    /// The address of the label is stored as a value, which is wrong once the program is relocated
+    /// (only reported with `AssembleOptions::relocation_warnings`).
+    AbsoluteAddress(String),
+}
+
+impl AssembleWarning {
+    pub fn new(line: usize, kind: WarningKind) -> AssembleWarning {
+        AssembleWarning { line, kind }
+    }
+}
+
+impl fmt::Display for AssembleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            WarningKind::UnreachableInstruction => write!(f, "unreachable instruction"),
+            WarningKind::UnusedLabel(label) => write!(f, "label `{}` is never used", label),
+            WarningKind::OffsetNearLimit { offset, bits } => {
+                write!(
+                    f,
+                    "offset {} is close to the limit of {} bits",
+                    offset, bits
+                )
+            }
+            WarningKind::AbsoluteAddress(label) => write!(
+                f,
+                "absolute address of `{}` breaks if the program is relocated",
+                label
+            ),
+        }
+    }
+}
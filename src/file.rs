@@ -5,8 +5,9 @@
 
 extern crate byteorder;
 
-use crate::hardware::memory::Memory;
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::hardware::memory::{Endianness, Memory};
+use crate::hex::{self, LoadError};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use std::{
     fs::File,
     io::{self, BufReader, Read},
@@ -20,6 +21,34 @@ pub fn read_file(name: String) -> io::Result<Memory> {
     read_file_into_lc3_memory(f)
 }
 
+/// `read_obj` fn reads a program file without loading it: it returns the origin and the words that follow it.
+/// An image which runs past xFFFF is refused with `LoadError::ImageTooLarge` (as `io::ErrorKind::InvalidData`).
+pub fn read_obj(name: &str) -> io::Result<(u16, Vec<u16>)> {
+    read_obj_as(name, Endianness::Big)
+}
+
+/// `read_obj_as` is `read_obj` for a file whose words are in the given byte order (see `Memory::write_obj_as`).
+pub fn read_obj_as(name: &str, endianness: Endianness) -> io::Result<(u16, Vec<u16>)> {
+    let mut rdr = BufReader::new(File::open(name)?);
+    let read_word = |rdr: &mut BufReader<File>| match endianness {
+        Endianness::Big => rdr.read_u16::<BigEndian>(),
+        Endianness::Little => rdr.read_u16::<LittleEndian>(),
+    };
+    let origin = read_word(&mut rdr)?;
+    let mut words = Vec::new();
+    loop {
+        match read_word(&mut rdr) {
+            Ok(word) => words.push(word),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                hex::check_fits(origin, words.len())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                return Ok((origin, words));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 fn read_file_into_lc3_memory<R>(mut rdr: R) -> io::Result<Memory>
 where
     R: Read,
@@ -31,6 +60,18 @@ where
     loop {
         match rdr.read_u16::<BigEndian>() {
             Ok(instruction) => {
+                if address > 0xFFFF {
+                    //count the rest, to tell how long the image is
+                    let mut len = address - base_address as usize + 1;
+                    while rdr.read_u16::<BigEndian>().is_ok() {
+                        len += 1;
+                    }
+                    let error = LoadError::ImageTooLarge {
+                        origin: base_address,
+                        len,
+                    };
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+                }
                 mem.write(address, instruction);
                 address += 1;
             }
@@ -44,3 +85,56 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod file_test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn written_obj_should_match_the_file_it_was_loaded_from() {
+        let bytes = fs::read("resources/os.obj").unwrap();
+        let memory = read_file("resources/os.obj".to_string()).unwrap();
+        let (origin, words) = read_obj("resources/os.obj").unwrap();
+        assert_eq!(bytes, memory.write_obj(origin, words.len() as u16));
+    }
+
+    #[test]
+    fn little_endian_obj_should_round_trip() {
+        let mut memory = Memory::new();
+        memory.write(0x3000, 0x1021);
+        memory.write(0x3001, 0xF025);
+        let bytes = memory.write_obj_as(0x3000, 2, Endianness::Little);
+        assert_eq!(vec![0x00, 0x30, 0x21, 0x10, 0x25, 0xF0], bytes);
+        let path = std::env::temp_dir().join(format!("lc3_le_{}.obj", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+        let read = read_obj_as(path.to_str().unwrap(), Endianness::Little);
+        fs::remove_file(&path).unwrap();
+        assert_eq!((0x3000, vec![0x1021, 0xF025]), read.unwrap());
+    }
+
+    #[test]
+    fn read_obj_should_refuse_an_image_running_past_the_end_of_memory() {
+        let path = std::env::temp_dir().join(format!("lc3_wrap_{}.obj", std::process::id()));
+        fs::write(&path, [0xFF, 0xFF, 0x12, 0x34, 0x56, 0x78]).unwrap();
+        let error = read_obj(path.to_str().unwrap()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(
+            "object file of 2 word(s) at xFFFF runs past the end of memory",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn image_running_past_the_end_of_memory_should_be_refused() {
+        let mut bytes = vec![0xFF, 0xF0];
+        bytes.extend(std::iter::repeat_n([0x12, 0x34], 18).flatten());
+        let error = read_file_into_lc3_memory(&bytes[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(
+            "object file of 18 word(s) at xFFF0 runs past the end of memory",
+            error.to_string()
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! `fnv` : The 64-bit FNV-1a hash. Unlike `std`'s hasher, it is stable across builds and platforms,
+//! so its values can be stored (e.g. in a program cache) and compared between runs.
+
+const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// `Fnv64` hashes the bytes written to it, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Fnv64(u64);
+
+impl Fnv64 {
+    pub(crate) fn new() -> Fnv64 {
+        Fnv64(OFFSET_BASIS)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(PRIME);
+        }
+    }
+
+    /// `write_u16` hashes `word` as its two big-endian bytes.
+    pub(crate) fn write_u16(&mut self, word: u16) {
+        self.write(&word.to_be_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
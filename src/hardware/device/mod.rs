@@ -0,0 +1,33 @@
+//! `device` : Custom memory-mapped devices.
+//! Besides the built-in console and machine control registers, a `Vm` can have any number of devices
+//! attached to address ranges (see `Vm::attach_device`). Every load, store and fetch consults the attached
+//! devices first, so a device can also take over the address of a built-in device register.
+
+use std::fmt;
+
+/// `Device` is a memory-mapped device.
+/// Devices must be `Clone` (through `DeviceClone`), so that a cloned `Vm` gets its own copy of every device.
+pub trait Device: DeviceClone + fmt::Debug {
+    /// `read` returns the value at `addr`, or `None` to let the access fall through to memory.
+    fn read(&mut self, addr: u16) -> Option<u16>;
+
+    /// `write` handles a store of `val` to `addr`, returning `false` to let the store fall through to memory.
+    fn write(&mut self, addr: u16, val: u16) -> bool;
+}
+
+/// `DeviceClone` lets boxed devices be cloned. It is implemented for every `Device` which is `Clone`.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Box<dyn Device> {
+        self.clone_box()
+    }
+}
@@ -7,11 +7,11 @@ use crate::hardware::register::Registers;
 /// - In immediate mode, the second value is embedded in the right-most 5 bits of the instruction.
 /// - Values which are shorter than 16 bits need to be sign extended.
 /// - Any time an instruction modifies a register, the condition flags need to be updated
+///
 /// If bit [5] is 0, the second source operand is obtained from SR2.
 /// If bit [5] is 1, the second source operand is obtained by sign-extending the imm5 field to 16 bits.
 /// In both cases, the second source operand is added to the contents of SR1 and the result stored in DR.
 /// The condition codes are set, based on whether the result is negative, zero, or positive.
-
 pub fn add(instr: u16, registers: &mut Registers) {
     /* destination register (DR) */
     let dr = (instr >> 9) & 0x7;
@@ -33,3 +33,29 @@ pub fn add(instr: u16, registers: &mut Registers) {
     }
     condition_flag::update_r_cond_register(dr, registers);
 }
+
+#[cfg(test)]
+mod add_test {
+    use super::*;
+
+    #[test]
+    fn immediate_should_only_use_bits_4_to_0() {
+        let mut registers = Registers::new();
+        registers.update(7, 10);
+        // ADD R7, R7, #-1: every bit above bit 5 is set too
+        add(0x1FFF, &mut registers);
+        assert_eq!(9, registers.get(7));
+        assert!(registers.is_positive());
+    }
+
+    #[test]
+    fn register_mode_should_ignore_bits_4_and_3() {
+        let mut registers = Registers::new();
+        registers.update(1, 2);
+        registers.update(3, 0x8000);
+        // ADD R0, R1, R3 with the unused bits [4:3] set
+        add(0x1058 | 0x0003, &mut registers);
+        assert_eq!(0x8002, registers.get(0));
+        assert!(registers.is_negative());
+    }
+}
@@ -21,3 +21,29 @@ pub fn and(instr: u16, registers: &mut Registers) {
     }
     condition_flag::update_r_cond_register(dr, registers);
 }
+
+#[cfg(test)]
+mod and_test {
+    use super::*;
+
+    #[test]
+    fn immediate_should_only_use_bits_4_to_0() {
+        let mut registers = Registers::new();
+        registers.update(7, 0xFFFF);
+        // AND R7, R7, #-16: every bit above bit 5 is set too
+        and(0x5FF0, &mut registers);
+        assert_eq!(0xFFF0, registers.get(7));
+        assert!(registers.is_negative());
+    }
+
+    #[test]
+    fn register_mode_should_ignore_bits_4_and_3() {
+        let mut registers = Registers::new();
+        registers.update(1, 0x00FF);
+        registers.update(3, 0x0F0F);
+        // AND R0, R1, R3 with the unused bits [4:3] set
+        and(0x5058 | 0x0003, &mut registers);
+        assert_eq!(0x000F, registers.get(0));
+        assert!(registers.is_positive());
+    }
+}
@@ -1,15 +1,57 @@
-use super::sign_extend;
 use crate::hardware::register::Registers;
+use crate::hardware::word::Word;
 
 /// The condition codes specified by the state of bits [11:9] are tested.
 // If bit [11] is set, N is tested; if bit [11] is clear, N is not tested.
 /// If bit [10] is set, Z is tested, etc. If any of the condition codes tested is set,
 /// the program branches to the location specified by adding the sign-extended PCoffset9 field to the incremented PC.
+///
+/// As the offset is relative to the incremented PC, a zero offset targets the next instruction: `BRnzp #0` (x0E00)
+/// and every other BR with offset 0 falls through to it whether or not the condition matches, i.e. a NOP.
+/// x0000 is a BR too, which tests no condition codes at all and so never branches; it is also a NOP, which is why
+/// zeroed memory executes harmlessly until it runs into something else. The self-relative branch is offset -1:
+/// `BRnzp #-1` (x0FFF) jumps back to itself forever.
 pub fn br(instr: u16, registers: &mut Registers) {
-    let pc_offset = sign_extend((instr) & 0x1ff, 9);
     let cond_flag = (instr >> 9) & 0x7;
-    if cond_flag & registers.r_cond != 0 {
-        let val: u32 = registers.r_pc as u32 + pc_offset as u32;
-        registers.r_pc = val as u16;
+    if registers
+        .r_cond
+        .is_some_and(|condition| condition.matches(cond_flag))
+    {
+        let target = Word(registers.pc()) + Word::sign_extend(instr, 9);
+        registers.set_pc(target.into());
+    }
+}
+
+#[cfg(test)]
+mod br_test {
+    use super::*;
+    use crate::hardware::register::condition_flag::ConditionFlag;
+
+    #[test]
+    fn zero_offset_should_fall_through_whether_or_not_the_condition_matches() {
+        for &flag in [
+            ConditionFlag::FlNeg,
+            ConditionFlag::FlZro,
+            ConditionFlag::FlPos,
+        ]
+        .iter()
+        {
+            for &instr in [0x0E00, 0x0800, 0x0400, 0x0200, 0x0000].iter() {
+                let mut registers = Registers::new();
+                registers.set_condition(flag);
+                registers.set_pc(0x3001);
+                br(instr, &mut registers);
+                assert_eq!(0x3001, registers.pc(), "x{:04X}", instr);
+            }
+        }
+    }
+
+    #[test]
+    fn minus_one_offset_should_branch_to_itself() {
+        let mut registers = Registers::new();
+        registers.set_condition(ConditionFlag::FlZro);
+        registers.set_pc(0x3001);
+        br(0x0FFF, &mut registers);
+        assert_eq!(0x3000, registers.pc());
     }
 }
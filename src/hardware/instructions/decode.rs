@@ -0,0 +1,411 @@
+//! `decode` : Splits an instruction word into its opcode and operand fields, without executing it.
+//! This is what static analysis tools (and the disassembler) work with.
+
+use super::fields::{self, dr, imm5, offset11, offset6, offset9, sr1, sr2};
+use super::opcode::{self, OpCode};
+use std::fmt;
+
+/// `DecodedInstr` is an instruction word with its operand fields extracted.
+/// Registers are numbers 0 - 7 and offsets / immediates are already sign-extended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodedInstr {
+    AddReg {
+        dr: u16,
+        sr1: u16,
+        sr2: u16,
+    },
+    AddImm {
+        dr: u16,
+        sr1: u16,
+        imm5: i16,
+    },
+    AndReg {
+        dr: u16,
+        sr1: u16,
+        sr2: u16,
+    },
+    AndImm {
+        dr: u16,
+        sr1: u16,
+        imm5: i16,
+    },
+    Not {
+        dr: u16,
+        sr: u16,
+    },
+    /// `nzp` holds the n, z and p bits [11:9].
+    Br {
+        nzp: u16,
+        offset9: i16,
+    },
+    Jmp {
+        base: u16,
+    },
+    Jsr {
+        offset11: i16,
+    },
+    Jsrr {
+        base: u16,
+    },
+    Ld {
+        dr: u16,
+        offset9: i16,
+    },
+    Ldi {
+        dr: u16,
+        offset9: i16,
+    },
+    Ldr {
+        dr: u16,
+        base: u16,
+        offset6: i16,
+    },
+    Lea {
+        dr: u16,
+        offset9: i16,
+    },
+    St {
+        sr: u16,
+        offset9: i16,
+    },
+    Sti {
+        sr: u16,
+        offset9: i16,
+    },
+    Str {
+        sr: u16,
+        base: u16,
+        offset6: i16,
+    },
+    Trap {
+        vector: u8,
+    },
+    Rti,
+    /// The reserved opcode (1101), with the whole instruction word.
+    Reserved(u16),
+}
+
+impl DecodedInstr {
+    /// `decode` extracts the fields of `instr`. Every word decodes to something.
+    pub fn decode(instr: u16) -> DecodedInstr {
+        let imm_mode = fields::imm_flag(instr);
+        match opcode::extract_op_code(&instr).expect("Every 4-bit value is an opcode.") {
+            OpCode::Add if imm_mode => DecodedInstr::AddImm {
+                dr: dr(instr),
+                sr1: sr1(instr),
+                imm5: imm5(instr),
+            },
+            OpCode::Add => DecodedInstr::AddReg {
+                dr: dr(instr),
+                sr1: sr1(instr),
+                sr2: sr2(instr),
+            },
+            OpCode::And if imm_mode => DecodedInstr::AndImm {
+                dr: dr(instr),
+                sr1: sr1(instr),
+                imm5: imm5(instr),
+            },
+            OpCode::And => DecodedInstr::AndReg {
+                dr: dr(instr),
+                sr1: sr1(instr),
+                sr2: sr2(instr),
+            },
+            OpCode::Not => DecodedInstr::Not {
+                dr: dr(instr),
+                sr: sr1(instr),
+            },
+            OpCode::Br => DecodedInstr::Br {
+                nzp: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::Jmp => DecodedInstr::Jmp { base: sr1(instr) },
+            OpCode::Jsr if (instr >> 11) & 1 == 1 => DecodedInstr::Jsr {
+                offset11: offset11(instr),
+            },
+            OpCode::Jsr => DecodedInstr::Jsrr { base: sr1(instr) },
+            OpCode::Ld => DecodedInstr::Ld {
+                dr: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::Ldi => DecodedInstr::Ldi {
+                dr: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::Ldr => DecodedInstr::Ldr {
+                dr: dr(instr),
+                base: sr1(instr),
+                offset6: offset6(instr),
+            },
+            OpCode::Lea => DecodedInstr::Lea {
+                dr: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::St => DecodedInstr::St {
+                sr: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::Sti => DecodedInstr::Sti {
+                sr: dr(instr),
+                offset9: offset9(instr),
+            },
+            OpCode::Str => DecodedInstr::Str {
+                sr: dr(instr),
+                base: sr1(instr),
+                offset6: offset6(instr),
+            },
+            OpCode::Trap => DecodedInstr::Trap {
+                vector: fields::trapvect8(instr),
+            },
+            OpCode::Rti => DecodedInstr::Rti,
+            OpCode::Res => DecodedInstr::Reserved(instr),
+        }
+    }
+
+    /// `op_code` returns the opcode of the instruction.
+    pub fn op_code(&self) -> OpCode {
+        match self {
+            DecodedInstr::AddReg { .. } | DecodedInstr::AddImm { .. } => OpCode::Add,
+            DecodedInstr::AndReg { .. } | DecodedInstr::AndImm { .. } => OpCode::And,
+            DecodedInstr::Not { .. } => OpCode::Not,
+            DecodedInstr::Br { .. } => OpCode::Br,
+            DecodedInstr::Jmp { .. } => OpCode::Jmp,
+            DecodedInstr::Jsr { .. } | DecodedInstr::Jsrr { .. } => OpCode::Jsr,
+            DecodedInstr::Ld { .. } => OpCode::Ld,
+            DecodedInstr::Ldi { .. } => OpCode::Ldi,
+            DecodedInstr::Ldr { .. } => OpCode::Ldr,
+            DecodedInstr::Lea { .. } => OpCode::Lea,
+            DecodedInstr::St { .. } => OpCode::St,
+            DecodedInstr::Sti { .. } => OpCode::Sti,
+            DecodedInstr::Str { .. } => OpCode::Str,
+            DecodedInstr::Trap { .. } => OpCode::Trap,
+            DecodedInstr::Rti => OpCode::Rti,
+            DecodedInstr::Reserved(_) => OpCode::Res,
+        }
+    }
+}
+
+impl DecodedInstr {
+    /// `explain` describes the instruction in plain English, for a tutorial mode, as it would execute at `address`
+    /// (so PC-relative targets are shown as addresses), e.g.
+    /// "Add the value in R1 to 5 and store the result in R0, then set condition codes."
+    pub fn explain(&self, address: u16) -> String {
+        let target = |offset: i16| {
+            format!(
+                "x{:04X}",
+                address.wrapping_add(1).wrapping_add(offset as u16)
+            )
+        };
+        const SET_CC: &str = ", then set condition codes.";
+        match *self {
+            DecodedInstr::AddReg { dr, sr1, sr2 } => format!(
+                "Add the values in R{} and R{} and store the result in R{}{}",
+                sr1, sr2, dr, SET_CC
+            ),
+            DecodedInstr::AddImm { dr, sr1, imm5 } => format!(
+                "Add the value in R{} to {} and store the result in R{}{}",
+                sr1, imm5, dr, SET_CC
+            ),
+            DecodedInstr::AndReg { dr, sr1, sr2 } => format!(
+                "Bitwise AND the values in R{} and R{} and store the result in R{}{}",
+                sr1, sr2, dr, SET_CC
+            ),
+            DecodedInstr::AndImm { dr, sr1, imm5 } => format!(
+                "Bitwise AND the value in R{} with {} and store the result in R{}{}",
+                sr1, imm5, dr, SET_CC
+            ),
+            DecodedInstr::Not { dr, sr } => format!(
+                "Invert every bit of the value in R{} and store the result in R{}{}",
+                sr, dr, SET_CC
+            ),
+            DecodedInstr::Br { nzp: 0, .. } => {
+                "Do nothing: this branch tests none of the condition codes.".to_string()
+            }
+            DecodedInstr::Br {
+                nzp: 0b111,
+                offset9,
+            } => format!("Jump to {}.", target(offset9)),
+            DecodedInstr::Br { nzp, offset9 } => {
+                let conditions: Vec<&str> = [(0b100, "negative"), (0b010, "zero"), (0b001, "positive")]
+                    .iter()
+                    .filter(|&&(bit, _)| nzp & bit != 0)
+                    .map(|&(_, name)| name)
+                    .collect();
+                format!(
+                    "Jump to {} if the last result was {}.",
+                    target(offset9),
+                    conditions.join(" or ")
+                )
+            }
+            DecodedInstr::Jmp { base: 7 } => {
+                "Return from the subroutine: jump to the address in R7.".to_string()
+            }
+            DecodedInstr::Jmp { base } => format!("Jump to the address in R{}.", base),
+            DecodedInstr::Jsr { offset11 } => format!(
+                "Call the subroutine at {}, saving the return address in R7.",
+                target(offset11)
+            ),
+            DecodedInstr::Jsrr { base } => format!(
+                "Call the subroutine at the address in R{}, saving the return address in R7.",
+                base
+            ),
+            DecodedInstr::Ld { dr, offset9 } => {
+                format!("Load the word at {} into R{}{}", target(offset9), dr, SET_CC)
+            }
+            DecodedInstr::Ldi { dr, offset9 } => format!(
+                "Load into R{} the word at the address stored at {}{}",
+                dr,
+                target(offset9),
+                SET_CC
+            ),
+            DecodedInstr::Ldr { dr, base, offset6 } => format!(
+                "Load the word at the address in R{} plus {} into R{}{}",
+                base, offset6, dr, SET_CC
+            ),
+            DecodedInstr::Lea { dr, offset9 } => {
+                format!("Put the address {} into R{}{}", target(offset9), dr, SET_CC)
+            }
+            DecodedInstr::St { sr, offset9 } => {
+                format!("Store the value in R{} at {}.", sr, target(offset9))
+            }
+            DecodedInstr::Sti { sr, offset9 } => format!(
+                "Store the value in R{} at the address stored at {}.",
+                sr,
+                target(offset9)
+            ),
+            DecodedInstr::Str { sr, base, offset6 } => format!(
+                "Store the value in R{} at the address in R{} plus {}.",
+                sr, base, offset6
+            ),
+            DecodedInstr::Trap { vector } => match vector {
+                0x20 => "Read a character from the keyboard into R0.".to_string(),
+                0x21 => "Write the character in R0 to the display.".to_string(),
+                0x22 => "Write the string which starts at the address in R0 to the display.".to_string(),
+                0x23 => "Print a prompt, then read a character from the keyboard into R0.".to_string(),
+                0x24 => "Write the packed string (two characters per word) which starts at the address in R0 \
+                         to the display."
+                    .to_string(),
+                0x25 => "Halt the machine.".to_string(),
+                0x31 => "Write the value in R0 to the display as a signed decimal number.".to_string(),
+                0x32 => "Write every register to the display, for debugging.".to_string(),
+                _ => format!(
+                    "Call the trap routine x{:02X}, saving the return address in R7.",
+                    vector
+                ),
+            },
+            DecodedInstr::Rti => {
+                "Return from an interrupt, restoring PC and the condition codes.".to_string()
+            }
+            DecodedInstr::Reserved(_) => {
+                "Nothing: the reserved opcode is not an instruction.".to_string()
+            }
+        }
+    }
+}
+
+/// Instructions are displayed in assembly syntax, e.g. `ADD R0, R0, #1` or `BRnzp #-3`.
+/// PC-relative offsets are shown as offsets, since the address of the instruction is not known here.
+impl fmt::Display for DecodedInstr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodedInstr::AddReg { dr, sr1, sr2 } => write!(f, "ADD R{}, R{}, R{}", dr, sr1, sr2),
+            DecodedInstr::AddImm { dr, sr1, imm5 } => write!(f, "ADD R{}, R{}, #{}", dr, sr1, imm5),
+            DecodedInstr::AndReg { dr, sr1, sr2 } => write!(f, "AND R{}, R{}, R{}", dr, sr1, sr2),
+            DecodedInstr::AndImm { dr, sr1, imm5 } => write!(f, "AND R{}, R{}, #{}", dr, sr1, imm5),
+            DecodedInstr::Not { dr, sr } => write!(f, "NOT R{}, R{}", dr, sr),
+            DecodedInstr::Br { nzp: 0, .. } => write!(f, "NOP"),
+            DecodedInstr::Br { nzp, offset9 } => {
+                write!(f, "BR")?;
+                for (bit, flag) in [(0b100, 'n'), (0b010, 'z'), (0b001, 'p')].iter() {
+                    if nzp & bit != 0 {
+                        write!(f, "{}", flag)?;
+                    }
+                }
+                write!(f, " #{}", offset9)
+            }
+            DecodedInstr::Jmp { base: 7 } => write!(f, "RET"),
+            DecodedInstr::Jmp { base } => write!(f, "JMP R{}", base),
+            DecodedInstr::Jsr { offset11 } => write!(f, "JSR #{}", offset11),
+            DecodedInstr::Jsrr { base } => write!(f, "JSRR R{}", base),
+            DecodedInstr::Ld { dr, offset9 } => write!(f, "LD R{}, #{}", dr, offset9),
+            DecodedInstr::Ldi { dr, offset9 } => write!(f, "LDI R{}, #{}", dr, offset9),
+            DecodedInstr::Ldr { dr, base, offset6 } => {
+                write!(f, "LDR R{}, R{}, #{}", dr, base, offset6)
+            }
+            DecodedInstr::Lea { dr, offset9 } => write!(f, "LEA R{}, #{}", dr, offset9),
+            DecodedInstr::St { sr, offset9 } => write!(f, "ST R{}, #{}", sr, offset9),
+            DecodedInstr::Sti { sr, offset9 } => write!(f, "STI R{}, #{}", sr, offset9),
+            DecodedInstr::Str { sr, base, offset6 } => {
+                write!(f, "STR R{}, R{}, #{}", sr, base, offset6)
+            }
+            DecodedInstr::Trap { vector } => match vector {
+                0x20 => write!(f, "GETC"),
+                0x21 => write!(f, "OUT"),
+                0x22 => write!(f, "PUTS"),
+                0x23 => write!(f, "IN"),
+                0x24 => write!(f, "PUTSP"),
+                0x25 => write!(f, "HALT"),
+                _ => write!(f, "TRAP x{:02X}", vector),
+            },
+            DecodedInstr::Rti => write!(f, "RTI"),
+            DecodedInstr::Reserved(_) => write!(f, "RESERVED"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod decode_test {
+    use super::*;
+    use crate::hardware::instructions::encode;
+    #[test]
+    fn decode_should_invert_the_encoders() {
+        assert_eq!(
+            DecodedInstr::AddImm {
+                dr: 2,
+                sr1: 3,
+                imm5: -1
+            },
+            DecodedInstr::decode(encode::add_imm(2, 3, -1))
+        );
+        assert_eq!(
+            DecodedInstr::Br {
+                nzp: 0b101,
+                offset9: -2
+            },
+            DecodedInstr::decode(encode::br(0b101, -2))
+        );
+        assert_eq!(
+            DecodedInstr::Jsr { offset11: -1024 },
+            DecodedInstr::decode(encode::jsr(-1024))
+        );
+        assert_eq!(
+            DecodedInstr::Jsrr { base: 3 },
+            DecodedInstr::decode(encode::jsrr(3))
+        );
+        assert_eq!(
+            DecodedInstr::Ldr {
+                dr: 5,
+                base: 0,
+                offset6: -1
+            },
+            DecodedInstr::decode(encode::ldr(5, 0, -1))
+        );
+        assert_eq!(
+            DecodedInstr::Trap { vector: 0x25 },
+            DecodedInstr::decode(encode::trap(0x25))
+        );
+        assert_eq!(DecodedInstr::Reserved(0xD123), DecodedInstr::decode(0xD123));
+    }
+
+    #[test]
+    fn display_should_use_assembly_syntax() {
+        let text = |instr: u16| DecodedInstr::decode(instr).to_string();
+        assert_eq!("ADD R0, R0, #1", text(0x1021));
+        assert_eq!("AND R5, R1, R2", text(0x5A42));
+        assert_eq!("BRnp #-2", text(0x0BFE));
+        assert_eq!("NOP", text(0x0000));
+        assert_eq!("RET", text(0xC1C0));
+        assert_eq!("LDR R5, R0, #-1", text(0x6A3F));
+        assert_eq!("HALT", text(0xF025));
+        assert_eq!("TRAP x31", text(0xF031));
+    }
+}
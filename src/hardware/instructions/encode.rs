@@ -0,0 +1,254 @@
+//! `encode` : Building instruction words by hand is error-prone, so each instruction form has a `const fn`
+//! returning its machine word. The `lc3_instr!` macro wraps them with an assembly-like syntax,
+//! e.g. `lc3_instr!(ADD R0, R1, #-1)`.
+//!
+//! Registers are given as numbers 0 - 7. Immediates and offsets are two's-complement values
+//! which are truncated to the width of their field; checking that they fit is up to the caller (e.g. the assembler).
+
+use super::opcode::OpCode;
+
+const fn op(op_code: OpCode) -> u16 {
+    (op_code as u16) << 12
+}
+
+const fn reg(r: u16, shift: u16) -> u16 {
+    (r & 0x7) << shift
+}
+
+const fn field(value: i16, bits: u16) -> u16 {
+    (value as u16) & ((1 << bits) - 1)
+}
+
+/// `ADD DR, SR1, SR2`
+pub const fn add_reg(dr: u16, sr1: u16, sr2: u16) -> u16 {
+    op(OpCode::Add) | reg(dr, 9) | reg(sr1, 6) | reg(sr2, 0)
+}
+
+/// `ADD DR, SR1, #imm5`
+pub const fn add_imm(dr: u16, sr1: u16, imm5: i16) -> u16 {
+    op(OpCode::Add) | reg(dr, 9) | reg(sr1, 6) | 1 << 5 | field(imm5, 5)
+}
+
+/// `AND DR, SR1, SR2`
+pub const fn and_reg(dr: u16, sr1: u16, sr2: u16) -> u16 {
+    op(OpCode::And) | reg(dr, 9) | reg(sr1, 6) | reg(sr2, 0)
+}
+
+/// `AND DR, SR1, #imm5`
+pub const fn and_imm(dr: u16, sr1: u16, imm5: i16) -> u16 {
+    op(OpCode::And) | reg(dr, 9) | reg(sr1, 6) | 1 << 5 | field(imm5, 5)
+}
+
+/// `NOT DR, SR`
+pub const fn not(dr: u16, sr: u16) -> u16 {
+    op(OpCode::Not) | reg(dr, 9) | reg(sr, 6) | 0x3F
+}
+
+/// `BRnzp PCoffset9`, where `nzp` holds the n, z and p bits (e.g. `0b101` for `BRnp`).
+pub const fn br(nzp: u16, offset9: i16) -> u16 {
+    op(OpCode::Br) | reg(nzp, 9) | field(offset9, 9)
+}
+
+/// `JMP BaseR`
+pub const fn jmp(base: u16) -> u16 {
+    op(OpCode::Jmp) | reg(base, 6)
+}
+
+/// `RET`, i.e. `JMP R7`
+pub const fn ret() -> u16 {
+    jmp(7)
+}
+
+/// `JSR PCoffset11`
+pub const fn jsr(offset11: i16) -> u16 {
+    op(OpCode::Jsr) | 1 << 11 | field(offset11, 11)
+}
+
+/// `JSRR BaseR`
+pub const fn jsrr(base: u16) -> u16 {
+    op(OpCode::Jsr) | reg(base, 6)
+}
+
+/// `LD DR, PCoffset9`
+pub const fn ld(dr: u16, offset9: i16) -> u16 {
+    op(OpCode::Ld) | reg(dr, 9) | field(offset9, 9)
+}
+
+/// `LDI DR, PCoffset9`
+pub const fn ldi(dr: u16, offset9: i16) -> u16 {
+    op(OpCode::Ldi) | reg(dr, 9) | field(offset9, 9)
+}
+
+/// `LDR DR, BaseR, offset6`
+pub const fn ldr(dr: u16, base: u16, offset6: i16) -> u16 {
+    op(OpCode::Ldr) | reg(dr, 9) | reg(base, 6) | field(offset6, 6)
+}
+
+/// `LEA DR, PCoffset9`
+pub const fn lea(dr: u16, offset9: i16) -> u16 {
+    op(OpCode::Lea) | reg(dr, 9) | field(offset9, 9)
+}
+
+/// `ST SR, PCoffset9`
+pub const fn st(sr: u16, offset9: i16) -> u16 {
+    op(OpCode::St) | reg(sr, 9) | field(offset9, 9)
+}
+
+/// `STI SR, PCoffset9`
+pub const fn sti(sr: u16, offset9: i16) -> u16 {
+    op(OpCode::Sti) | reg(sr, 9) | field(offset9, 9)
+}
+
+/// `STR SR, BaseR, offset6`
+pub const fn str(sr: u16, base: u16, offset6: i16) -> u16 {
+    op(OpCode::Str) | reg(sr, 9) | reg(base, 6) | field(offset6, 6)
+}
+
+/// `TRAP trapvect8`
+pub const fn trap(vector: u8) -> u16 {
+    op(OpCode::Trap) | vector as u16
+}
+
+/// `RTI`
+pub const fn rti() -> u16 {
+    op(OpCode::Rti)
+}
+
+/// `lc3_instr!` encodes a single instruction written in (a subset of) LC-3 assembly syntax.
+///
+/// ```
+/// use lc3_vm::lc3_instr;
+/// assert_eq!(0x1261, lc3_instr!(ADD R1, R1, #1));
+/// assert_eq!(0x0FFF, lc3_instr!(BRnzp #-1));
+/// assert_eq!(0xF025, lc3_instr!(HALT));
+/// ```
+#[macro_export]
+macro_rules! lc3_instr {
+    (@reg R0) => { 0 };
+    (@reg R1) => { 1 };
+    (@reg R2) => { 2 };
+    (@reg R3) => { 3 };
+    (@reg R4) => { 4 };
+    (@reg R5) => { 5 };
+    (@reg R6) => { 6 };
+    (@reg R7) => { 7 };
+    (ADD $dr:ident, $sr1:ident, #$imm:expr) => {
+        $crate::hardware::instructions::encode::add_imm(
+            $crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $sr1), $imm)
+    };
+    (ADD $dr:ident, $sr1:ident, $sr2:ident) => {
+        $crate::hardware::instructions::encode::add_reg(
+            $crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $sr1), $crate::lc3_instr!(@reg $sr2))
+    };
+    (AND $dr:ident, $sr1:ident, #$imm:expr) => {
+        $crate::hardware::instructions::encode::and_imm(
+            $crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $sr1), $imm)
+    };
+    (AND $dr:ident, $sr1:ident, $sr2:ident) => {
+        $crate::hardware::instructions::encode::and_reg(
+            $crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $sr1), $crate::lc3_instr!(@reg $sr2))
+    };
+    (NOT $dr:ident, $sr:ident) => {
+        $crate::hardware::instructions::encode::not($crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $sr))
+    };
+    (BR #$off:expr) => { $crate::hardware::instructions::encode::br(0b111, $off) };
+    (BRn #$off:expr) => { $crate::hardware::instructions::encode::br(0b100, $off) };
+    (BRz #$off:expr) => { $crate::hardware::instructions::encode::br(0b010, $off) };
+    (BRp #$off:expr) => { $crate::hardware::instructions::encode::br(0b001, $off) };
+    (BRnz #$off:expr) => { $crate::hardware::instructions::encode::br(0b110, $off) };
+    (BRnp #$off:expr) => { $crate::hardware::instructions::encode::br(0b101, $off) };
+    (BRzp #$off:expr) => { $crate::hardware::instructions::encode::br(0b011, $off) };
+    (BRnzp #$off:expr) => { $crate::hardware::instructions::encode::br(0b111, $off) };
+    (JMP $base:ident) => { $crate::hardware::instructions::encode::jmp($crate::lc3_instr!(@reg $base)) };
+    (RET) => { $crate::hardware::instructions::encode::ret() };
+    (JSR #$off:expr) => { $crate::hardware::instructions::encode::jsr($off) };
+    (JSRR $base:ident) => { $crate::hardware::instructions::encode::jsrr($crate::lc3_instr!(@reg $base)) };
+    (LD $dr:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::ld($crate::lc3_instr!(@reg $dr), $off)
+    };
+    (LDI $dr:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::ldi($crate::lc3_instr!(@reg $dr), $off)
+    };
+    (LDR $dr:ident, $base:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::ldr(
+            $crate::lc3_instr!(@reg $dr), $crate::lc3_instr!(@reg $base), $off)
+    };
+    (LEA $dr:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::lea($crate::lc3_instr!(@reg $dr), $off)
+    };
+    (ST $sr:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::st($crate::lc3_instr!(@reg $sr), $off)
+    };
+    (STI $sr:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::sti($crate::lc3_instr!(@reg $sr), $off)
+    };
+    (STR $sr:ident, $base:ident, #$off:expr) => {
+        $crate::hardware::instructions::encode::str(
+            $crate::lc3_instr!(@reg $sr), $crate::lc3_instr!(@reg $base), $off)
+    };
+    (TRAP $vector:expr) => { $crate::hardware::instructions::encode::trap($vector) };
+    (RTI) => { $crate::hardware::instructions::encode::rti() };
+    (GETC) => { $crate::hardware::instructions::encode::trap(0x20) };
+    (OUT) => { $crate::hardware::instructions::encode::trap(0x21) };
+    (PUTS) => { $crate::hardware::instructions::encode::trap(0x22) };
+    (IN) => { $crate::hardware::instructions::encode::trap(0x23) };
+    (PUTSP) => { $crate::hardware::instructions::encode::trap(0x24) };
+    (HALT) => { $crate::hardware::instructions::encode::trap(0x25) };
+}
+
+#[cfg(test)]
+mod encode_test {
+    use super::*;
+    #[test]
+    fn add_and_and_should_encode_both_forms() {
+        assert_eq!(0x14C4, add_reg(2, 3, 4));
+        assert_eq!(0x14FF, add_imm(2, 3, -1));
+        assert_eq!(0x5A42, and_reg(5, 1, 2));
+        assert_eq!(0x5260, and_imm(1, 1, 0));
+        assert_eq!(0x14C4, lc3_instr!(ADD R2, R3, R4));
+        assert_eq!(0x14FF, lc3_instr!(ADD R2, R3, #-1));
+        assert_eq!(0x5260, lc3_instr!(AND R1, R1, #0));
+    }
+
+    #[test]
+    fn not_should_set_the_low_six_bits() {
+        assert_eq!(0x967F, not(3, 1));
+        assert_eq!(0x967F, lc3_instr!(NOT R3, R1));
+    }
+
+    #[test]
+    fn control_flow_should_encode_offsets_and_bases() {
+        assert_eq!(0x0E00, br(0b111, 0));
+        assert_eq!(0x0BFE, br(0b101, -2));
+        assert_eq!(0x0BFE, lc3_instr!(BRnp #-2));
+        assert_eq!(0x0402, lc3_instr!(BRz #2));
+        assert_eq!(0xC080, jmp(2));
+        assert_eq!(0xC1C0, ret());
+        assert_eq!(0xC1C0, lc3_instr!(RET));
+        assert_eq!(0x4FFF, jsr(-1));
+        assert_eq!(0x4C00, lc3_instr!(JSR #-1024));
+        assert_eq!(0x40C0, jsrr(3));
+    }
+
+    #[test]
+    fn loads_and_stores_should_encode_offsets() {
+        assert_eq!(0x2205, ld(1, 5));
+        assert_eq!(0xA1FF, ldi(0, -1));
+        assert_eq!(0x6A3F, ldr(5, 0, -1));
+        assert_eq!(0xE002, lea(0, 2));
+        assert_eq!(0x3E01, st(7, 1));
+        assert_eq!(0xB3FE, sti(1, -2));
+        assert_eq!(0x7381, str(1, 6, 1));
+        assert_eq!(0x2205, lc3_instr!(LD R1, #5));
+        assert_eq!(0x6A3F, lc3_instr!(LDR R5, R0, #-1));
+        assert_eq!(0x7381, lc3_instr!(STR R1, R6, #1));
+    }
+
+    #[test]
+    fn traps_should_encode_their_vector() {
+        assert_eq!(0xF025, trap(0x25));
+        assert_eq!(0xF022, lc3_instr!(PUTS));
+        assert_eq!(0xF031, lc3_instr!(TRAP 0x31));
+        assert_eq!(0x8000, rti());
+    }
+}
@@ -0,0 +1,136 @@
+//! `fields` : Extract the operand fields of an instruction word, with the masks and sign extension of the ISA.
+//! The executor, the decoder and external tools all use these, so they cannot disagree on the bit layout.
+//!
+//! ```text
+//!  15  12 11  9 8   6 5 4   0
+//! | op   | dr  | sr1 | i | imm5 |
+//! ```
+
+use super::sign_extend;
+
+/// `dr` is the destination register, bits [11:9] (also SR for stores and nzp for BR).
+pub fn dr(instr: u16) -> u16 {
+    (instr >> 9) & 0x7
+}
+
+/// `sr1` is the first source register, bits [8:6] (also BaseR for JMP, JSRR, LDR and STR).
+pub fn sr1(instr: u16) -> u16 {
+    (instr >> 6) & 0x7
+}
+
+/// `sr2` is the second source register, bits [2:0].
+pub fn sr2(instr: u16) -> u16 {
+    instr & 0x7
+}
+
+/// `imm_flag` is bit [5] of ADD and AND: `true` for the `#imm5` form.
+pub fn imm_flag(instr: u16) -> bool {
+    (instr >> 5) & 0x1 == 1
+}
+
+/// `imm5` is the sign-extended immediate, bits [4:0].
+pub fn imm5(instr: u16) -> i16 {
+    sign_extend(instr & 0x1F, 5) as i16
+}
+
+/// `offset6` is the sign-extended base offset of LDR and STR, bits [5:0].
+pub fn offset6(instr: u16) -> i16 {
+    sign_extend(instr & 0x3F, 6) as i16
+}
+
+/// `offset9` is the sign-extended PC offset of BR, LD, LDI, LEA, ST and STI, bits [8:0].
+pub fn offset9(instr: u16) -> i16 {
+    sign_extend(instr & 0x1FF, 9) as i16
+}
+
+/// `offset11` is the sign-extended PC offset of JSR, bits [10:0].
+pub fn offset11(instr: u16) -> i16 {
+    sign_extend(instr & 0x7FF, 11) as i16
+}
+
+/// `trapvect8` is the trap vector, bits [7:0].
+pub fn trapvect8(instr: u16) -> u8 {
+    (instr & 0xFF) as u8
+}
+
+/// `is_well_formed` tells whether the bits of `instr` which the ISA fixes hold their required values:
+/// bits [4:3] of the register forms of ADD and AND, bits [11:9] and [5:0] of JMP, bits [10:9] and [5:0] of JSRR,
+/// bits [11:8] of TRAP and bits [11:0] of RTI must be zero, and bits [5:0] of NOT must be one.
+/// Hardware ignores these bits, so a word which is not well-formed still executes (see `Vm::set_strict_encoding`).
+pub fn is_well_formed(instr: u16) -> bool {
+    let fixed = |mask: u16, value: u16| instr & mask == value;
+    match instr >> 12 {
+        //ADD, AND
+        0b0001 | 0b0101 => imm_flag(instr) || fixed(0x0018, 0),
+        //NOT
+        0b1001 => fixed(0x003F, 0x003F),
+        //JMP / RET
+        0b1100 => fixed(0x0E3F, 0),
+        //JSRR
+        0b0100 => (instr >> 11) & 0x1 == 1 || fixed(0x063F, 0),
+        //TRAP
+        0b1111 => fixed(0x0F00, 0),
+        //RTI
+        0b1000 => fixed(0x0FFF, 0),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod fields_test {
+    use super::*;
+    use crate::hardware::instructions::encode;
+
+    #[test]
+    fn register_fields_should_be_extracted() {
+        let instr = encode::add_reg(5, 3, 6);
+        assert_eq!(5, dr(instr));
+        assert_eq!(3, sr1(instr));
+        assert_eq!(6, sr2(instr));
+        assert!(!imm_flag(instr));
+        assert!(imm_flag(encode::add_imm(5, 3, 1)));
+    }
+
+    #[test]
+    fn imm5_should_be_sign_extended() {
+        assert_eq!(-16, imm5(encode::add_imm(0, 0, -16)));
+        assert_eq!(15, imm5(encode::and_imm(7, 7, 15)));
+    }
+
+    #[test]
+    fn offset6_should_be_sign_extended() {
+        assert_eq!(-32, offset6(encode::ldr(1, 2, -32)));
+        assert_eq!(31, offset6(encode::str(1, 2, 31)));
+    }
+
+    #[test]
+    fn offset9_should_be_sign_extended() {
+        assert_eq!(-256, offset9(encode::ld(7, -256)));
+        assert_eq!(255, offset9(encode::br(0b111, 255)));
+    }
+
+    #[test]
+    fn offset11_should_be_sign_extended() {
+        assert_eq!(-1024, offset11(encode::jsr(-1024)));
+        assert_eq!(1023, offset11(encode::jsr(1023)));
+    }
+
+    #[test]
+    fn trapvect8_should_be_zero_extended() {
+        assert_eq!(0x25, trapvect8(encode::trap(0x25)));
+        assert_eq!(0xFF, trapvect8(encode::trap(0xFF)));
+    }
+
+    #[test]
+    fn stray_reserved_bits_should_not_be_well_formed() {
+        assert!(is_well_formed(encode::add_reg(1, 2, 3)));
+        assert!(!is_well_formed(encode::add_reg(1, 2, 3) | 0x0008));
+        assert!(is_well_formed(encode::add_imm(1, 2, -1)));
+        assert!(is_well_formed(encode::not(1, 2)));
+        assert!(!is_well_formed(encode::not(1, 2) & !0x0001));
+        assert!(!is_well_formed(encode::jmp(7) | 0x0200));
+        assert!(is_well_formed(encode::jsr(-1)));
+        assert!(!is_well_formed(encode::trap(0x25) | 0x0100));
+        assert!(is_well_formed(encode::br(0, 0x1FF)));
+    }
+}
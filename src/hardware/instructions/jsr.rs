@@ -1,23 +1,23 @@
-use super::sign_extend;
 use crate::hardware::register::Registers;
+use crate::hardware::word::Word;
 
-/// First, the incremented PC is saved in R7.
+/// First, the incremented PC is saved in R7. `Vm::execute_next` increments PC before executing,
+/// so R7 holds the address of the instruction after the JSR and a RET does not run the JSR again.
 /// This is the linkage back to the calling routine.
 /// Then the PC is loaded with the address of the first instruction of the subroutine,
 /// causing an unconditional jump to that address.
 /// The address of the subroutine is obtained from the base register (if bit [11] is 0),
 /// or the address is computed by sign-extending bits [10:0] and adding this value to the incremented PC (if bit [11] is 1).
-
 pub fn jsr(instr: u16, registers: &mut Registers) {
     let base_reg = (instr >> 6) & 0x7;
-    let long_pc_offset = sign_extend(instr & 0x7ff, 11);
     let long_flag = (instr >> 11) & 1;
-    registers.r_07 = registers.r_pc;
 
-    if long_flag != 0 {
-        let val: u32 = registers.r_pc as u32 + long_pc_offset as u32;
-        registers.r_pc = val as u16; /* JSR */
+    //the target is read before R7 is written, so that `JSRR R7` jumps to the old value of R7
+    let target = if long_flag != 0 {
+        (Word(registers.pc()) + Word::sign_extend(instr, 11)).into() /* JSR */
     } else {
-        registers.r_pc = registers.get(base_reg); /* JSRR */
-    }
+        registers.get(base_reg) /* JSRR */
+    };
+    registers.r_07 = registers.pc();
+    registers.set_pc(target);
 }
@@ -1,17 +1,19 @@
-use super::sign_extend;
+use crate::hardware::memory::Bus;
 use crate::hardware::register::condition_flag;
 use crate::hardware::register::Registers;
-use crate::hardware::Memory;
+use crate::hardware::word::Word;
+use crate::vm::VmError;
 
 /// An address is computed by sign-extending bits [5:0] to 16 bits
 /// and adding this value to the contents of the register specified by bits [8:6].
 /// The contents of memory at this address are loaded into DR.
 /// The condition codes are set, based on whether the value loaded is negative, zero, or positive.
-pub fn ldr(instr: u16, registers: &mut Registers, memory: &mut Memory) {
+pub fn ldr(instr: u16, registers: &mut Registers, bus: &mut dyn Bus) -> Result<(), VmError> {
     let dr = (instr >> 9) & 0x7;
     let sr1 = (instr >> 6) & 0x7;
-    let offset = sign_extend(instr & 0x3F, 6);
-    let val: u32 = registers.get(sr1) as u32 + offset as u32;
-    registers.update(dr, memory.read(val as u16));
+    let address = Word(registers.get(sr1)) + Word::sign_extend(instr, 6);
+    let value = bus.load(address.into())?;
+    registers.update(dr, value);
     condition_flag::update_r_cond_register(dr, registers);
+    Ok(())
 }
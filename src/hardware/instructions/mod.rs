@@ -4,6 +4,9 @@
 pub mod add;
 pub mod and;
 pub mod br;
+pub mod decode;
+pub mod encode;
+pub mod fields;
 pub mod jmp;
 pub mod jsr;
 pub mod ld;
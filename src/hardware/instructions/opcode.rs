@@ -13,10 +13,14 @@
 /// sequence of these simple super. Each instruction is 16 bits long,
 /// with the left 4 bits storing the opcode.
 /// The rest of the bits are used to store the parameters.
-use crate::hardware::memory::Memory;
-use crate::hardware::Registers;
+use crate::hardware::memory::Bus;
+use crate::hardware::register::Registers;
+use crate::vm::VmError;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum OpCode {
     /// `Br` is an `OpCode` for branch.
     Br = 0,
@@ -75,36 +79,84 @@ impl OpCode {
             _ => None,
         }
     }
+
+    /// `is_implemented` returns `false` for the opcodes `execute_instruction` rejects with `VmError::IllegalOpcode`:
+    /// `Res`, and `Rti` (the VM only executes an RTI which returns from a keyboard interrupt handler).
+    pub fn is_implemented(&self) -> bool {
+        !matches!(self, OpCode::Rti | OpCode::Res)
+    }
+}
+
+/// `InvalidOpCode` is a value outside 0 - 15, which cannot be an `OpCode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidOpCode(pub u16);
+
+impl fmt::Display for InvalidOpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not an opcode (0 - 15)", self.0)
+    }
+}
+
+impl Error for InvalidOpCode {}
+
+/// `OpCode::try_from(n)` is `OpCode::get(n)`, with an error for values above 15.
+impl TryFrom<u16> for OpCode {
+    type Error = InvalidOpCode;
+
+    fn try_from(op_code: u16) -> Result<OpCode, InvalidOpCode> {
+        OpCode::get(op_code).ok_or(InvalidOpCode(op_code))
+    }
+}
+
+/// An `OpCode` converts to its 4-bit value, the same as `op_code as u16`.
+impl From<OpCode> for u16 {
+    fn from(op_code: OpCode) -> u16 {
+        op_code as u16
+    }
 }
 
-pub fn execute_instruction(instr: u16, mut registers: &mut Registers, mut memory: &mut Memory) {
+/// `execute_instruction` executes a single (already fetched) instruction.
+/// The PC must already point to the next instruction, as every PC-relative offset is relative to the incremented PC.
+/// `Rti` and `Res` are not supported and produce `VmError::IllegalOpcode`.
+pub fn execute_instruction(
+    instr: u16,
+    registers: &mut Registers,
+    bus: &mut dyn Bus,
+) -> Result<(), VmError> {
     //extract op_code from the instruction
     let op_code = extract_op_code(&instr);
     //match op_code and execute instruction
     match op_code {
-        Some(OpCode::Add) => super::add::add(instr, &mut registers),
-        Some(OpCode::And) => super::and::and(instr, &mut registers),
-        Some(OpCode::Not) => super::not::not(instr, &mut registers),
-        Some(OpCode::Br) => super::br::br(instr, &mut registers),
-        Some(OpCode::Jmp) => super::jmp::jmp(instr, &mut registers),
-        Some(OpCode::Jsr) => super::jsr::jsr(instr, &mut registers),
-        Some(OpCode::Ld) => super::ld::ld(instr, &mut registers, &mut memory),
-        Some(OpCode::Ldi) => super::ldi::ldi(instr, &mut registers, &mut memory),
-        Some(OpCode::Ldr) => super::ldr::ldr(instr, &mut registers, &mut memory),
-        Some(OpCode::Lea) => super::lea::lea(instr, &mut registers),
-        Some(OpCode::St) => super::st::st(instr, &mut registers, &mut memory),
-        Some(OpCode::Sti) => super::sti::sti(instr, &mut registers, &mut memory),
-        Some(OpCode::Str) => super::str::str(instr, &mut registers, &mut memory),
-        Some(OpCode::Trap) => super::trap::trap(instr, &mut registers, &mut memory),
-        _ => {}
+        Some(OpCode::Add) => super::add::add(instr, registers),
+        Some(OpCode::And) => super::and::and(instr, registers),
+        Some(OpCode::Not) => super::not::not(instr, registers),
+        Some(OpCode::Br) => super::br::br(instr, registers),
+        Some(OpCode::Jmp) => super::jmp::jmp(instr, registers),
+        Some(OpCode::Jsr) => super::jsr::jsr(instr, registers),
+        Some(OpCode::Ld) => super::ld::ld(instr, registers, bus)?,
+        Some(OpCode::Ldi) => super::ldi::ldi(instr, registers, bus)?,
+        Some(OpCode::Ldr) => super::ldr::ldr(instr, registers, bus)?,
+        Some(OpCode::Lea) => super::lea::lea(instr, registers),
+        Some(OpCode::St) => super::st::st(instr, registers, bus)?,
+        Some(OpCode::Sti) => super::sti::sti(instr, registers, bus)?,
+        Some(OpCode::Str) => super::str::str(instr, registers, bus)?,
+        Some(OpCode::Trap) => super::trap::trap(instr, registers, bus)?,
+        _ => {
+            return Err(VmError::IllegalOpcode {
+                pc: registers.pc().wrapping_sub(1),
+                instr,
+            })
+        }
     }
+    Ok(())
 }
 
+/// `extract_op_code` returns the opcode of `instruction` (see `fields` for the operand fields).
 //Each instruction is 16 bits long, with the left 4 bits storing the opcode.
 //The rest of the bits are used to store the parameters.
 //To extract left 4 bits out of the instruction, we'll use ">>" shift-right
 //operator and shift first 4 bits 12 positions towards right
-fn extract_op_code(instruction: &u16) -> Option<OpCode> {
+pub fn extract_op_code(instruction: &u16) -> Option<OpCode> {
     OpCode::get(instruction >> 12)
 }
 
@@ -140,4 +192,33 @@ mod op_code_test {
         assert_eq!(Some(OpCode::Lea), OpCode::get(14));
         assert_eq!(Some(OpCode::Trap), OpCode::get(15));
     }
+
+    #[test]
+    fn only_rti_and_res_should_be_unimplemented() {
+        let unimplemented: Vec<OpCode> = (0..16)
+            .filter_map(OpCode::get)
+            .filter(|op_code| !op_code.is_implemented())
+            .collect();
+        assert_eq!(vec![OpCode::Rti, OpCode::Res], unimplemented);
+    }
+}
+
+#[cfg(test)]
+mod op_code_conversion_test {
+    use super::*;
+    #[test]
+    fn try_from_should_round_trip_every_nibble() {
+        for n in 0..16 {
+            let op_code = OpCode::try_from(n).unwrap();
+            assert_eq!(n, u16::from(op_code));
+            assert_eq!(n, op_code as u16);
+        }
+        assert_eq!(Err(InvalidOpCode(16)), OpCode::try_from(16));
+    }
+
+    #[test]
+    fn discriminants_should_be_the_encoding() {
+        assert_eq!(4, OpCode::Jsr as u16);
+        assert_eq!(15, u16::from(OpCode::Trap));
+    }
 }
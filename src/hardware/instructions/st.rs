@@ -1,13 +1,12 @@
-use super::sign_extend;
+use crate::hardware::memory::Bus;
 use crate::hardware::register::Registers;
-use crate::hardware::Memory;
+use crate::hardware::word::Word;
+use crate::vm::VmError;
 
 /// The contents of the register specified by SR are stored in the memory location
 /// whose address is computed by sign-extending bits [8:0] to 16 bits and adding this value to the incremented PC.
-pub fn st(instr: u16, registers: &mut Registers, memory: &mut Memory) {
+pub fn st(instr: u16, registers: &mut Registers, bus: &mut dyn Bus) -> Result<(), VmError> {
     let dr = (instr >> 9) & 0x7;
-    let pc_offset = sign_extend(instr & 0x1ff, 9);
-    let val: u32 = registers.r_pc as u32 + pc_offset as u32;
-    let val: u16 = val as u16;
-    memory.write(val as usize, registers.get(dr));
+    let address = Word(registers.pc()) + Word::sign_extend(instr, 9);
+    bus.store(address.into(), registers.get(dr))
 }
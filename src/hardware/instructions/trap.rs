@@ -4,13 +4,9 @@
 //! These are called trap routines which you can think of as the operating system or API for the LC-3.
 //! Each trap routine is assigned a trap code which identifies it (similar to an opcode).
 //! To execute one, the TRAP instruction is called with the trap code of the desired routine.
+use crate::hardware::memory::{Bus, MemoryMappedReg};
 use crate::hardware::register::Registers;
-use crate::hardware::Memory;
-use crate::sys::getchar;
-use crate::sys::terminal;
-use std::io;
-use std::io::Write;
-use std::process;
+use crate::vm::VmError;
 
 // TRAP Codes
 pub enum TrapCode {
@@ -26,6 +22,20 @@ pub enum TrapCode {
     Putsp = 0x24, /* output a byte string */
     /// halt the program
     Halt = 0x25, /* halt the program */
+    /// output R0 as a signed decimal number
+    Putd = 0x31, /* output a signed decimal */
+    /// output the registers, for debugging
+    Regs = 0x32, /* output the register file */
+}
+
+/// `IN_PROMPT` is what the built-in IN routine prints before it reads a character, like the OS image's IN
+/// (see `Vm::set_input_prompt`).
+pub const IN_PROMPT: &str = "Enter a character : ";
+
+/// `is_console_trap` tells whether the built-in routine for `vector` uses the console:
+/// GETC, OUT, PUTS, IN, PUTSP, PUTD and REGS do, while HALT only prints its banner.
+pub(crate) fn is_console_trap(vector: u8) -> bool {
+    matches!(vector, 0x20..=0x24 | 0x31 | 0x32)
 }
 
 /// `trap` fn allows interacting with I/O devices
@@ -34,58 +44,264 @@ pub enum TrapCode {
 /// after the service routine has completed execution.)
 /// Then the PC is loaded with the starting address of the system call specified by trapvector8.
 /// The starting address is contained in the memory location whose address is obtained by zero-extending trapvector8 to 16 bits.
-pub fn trap(instr: u16, registers: &mut Registers, memory: &mut Memory) {
-    terminal::turn_off_canonical_and_echo_modes();
+///
+/// `trap` is the built-in (Rust) implementation of the trap routines; it talks to the console through
+/// the memory-mapped device registers on the `bus`, just like the routines of an LC-3 operating system would.
+/// The built-in routines return straight to the instruction after the TRAP, but R7 is still loaded with
+/// the incremented PC, as on real hardware, so R7 holds the same value whichever implementation ran.
+/// A trap vector without a built-in routine fails with `VmError::UnknownTrap`, as it was most likely encoded by mistake;
+/// routines of the program's own go in the trap vector table (see `Vm::load_os`) or are registered with the `Vm`.
+pub fn trap(instr: u16, registers: &mut Registers, bus: &mut dyn Bus) -> Result<(), VmError> {
+    registers.r_07 = registers.pc();
     match instr & 0xFF {
         0x20 => {
-            registers.update(0, getchar::get_char() as u16);
+            let c = bus.load(MemoryMappedReg::Kbdr as u16)?;
+            registers.update(0, c);
         }
         0x21 => {
-            print!("{}", (registers.r_00 as u8) as char);
-            io::stdout().flush().expect("Flushed.");
+            put_char(bus, registers.r_00)?;
         }
         0x22 => {
             // /* one char per word */
-            let mut index = registers.r_00 as usize;
-            let mut c = memory.cells[index];
+            let mut address = registers.r_00;
+            let mut c = bus.load(address)?;
             while c != 0x0000 {
-                print!("{}", (c as u8) as char);
-                index += 1;
-                c = memory.cells[index];
+                put_char(bus, c)?;
+                address = address.wrapping_add(1);
+                c = bus.load(address)?;
             }
-            io::stdout().flush().expect("Flushed.");
-        }
-        0x23 => {
-            print!("Enter a character : ");
-            io::stdout().flush().expect("Flushed.");
-            registers.update(0, getchar::get_char() as u16);
         }
+        0x23 => input(IN_PROMPT, registers, bus)?,
         0x24 => {
-            let mut index = registers.r_00 as usize;
-            let mut c = memory.cells[index];
+            let mut address = registers.r_00;
+            let mut c = bus.load(address)?;
             while c != 0x0000 {
-                let c1 = ((c & 0xFF) as u8) as char;
-                print!("{}", c1);
-                let c2 = ((c >> 8) as u8) as char;
-                if c2 != '\0' {
-                    print!("{}", c2);
+                put_char(bus, c & 0xFF)?;
+                let c2 = c >> 8;
+                if c2 != 0 {
+                    put_char(bus, c2)?;
                 }
-                index += 1;
-                c = memory.cells[index];
+                address = address.wrapping_add(1);
+                c = bus.load(address)?;
             }
-            io::stdout().flush().expect("Flushed.");
         }
         0x25 => {
             /* TRAP HALT */
-            print!("HALT");
-            io::stdout().flush().expect("Flushed.");
-            terminal::restore_terminal_settings();
-            process::exit(1);
+            put_str(bus, "HALT")?;
+            //stop the clock
+            let mcr = bus.load(MemoryMappedReg::Mcr as u16)?;
+            bus.store(MemoryMappedReg::Mcr as u16, mcr & 0x7FFF)?;
         }
-        _ => {
-            terminal::restore_terminal_settings();
-            process::exit(1);
+        0x31 => {
+            put_str(bus, &signed_decimal(registers.r_00))?;
         }
+        0x32 => {
+            //R7 already holds the return address, and IR the TRAP itself
+            put_str(bus, &registers.dump())?;
+        }
+        vector => {
+            return Err(VmError::UnknownTrap {
+                vector: vector as u8,
+            })
+        }
+    }
+    Ok(())
+}
+
+/// `trap_through_vector_table` performs TRAP the way the hardware does:
+/// R7 is loaded with the incremented PC and the PC is loaded from the trap vector table,
+/// so the trap routine itself has to be present in memory (e.g. an operating system image).
+pub fn trap_through_vector_table(
+    instr: u16,
+    registers: &mut Registers,
+    bus: &mut dyn Bus,
+) -> Result<(), VmError> {
+    registers.r_07 = registers.pc();
+    registers.set_pc(bus.load(instr & 0xFF)?);
+    Ok(())
+}
+
+/// `input` is the IN routine: it prints `prompt` (if any), then reads a single character into R0.
+pub fn input(prompt: &str, registers: &mut Registers, bus: &mut dyn Bus) -> Result<(), VmError> {
+    put_str(bus, prompt)?;
+    let c = bus.load(MemoryMappedReg::Kbdr as u16)?;
+    registers.update(0, c);
+    Ok(())
+}
+
+/// `put_char` writes the low byte of `c` to the display data register.
+fn put_char(bus: &mut dyn Bus, c: u16) -> Result<(), VmError> {
+    bus.store(MemoryMappedReg::Ddr as u16, c & 0xFF)
+}
+
+fn put_str(bus: &mut dyn Bus, s: &str) -> Result<(), VmError> {
+    for b in s.bytes() {
+        put_char(bus, u16::from(b))?;
+    }
+    Ok(())
+}
+
+/// `signed_decimal` renders a word as a 16-bit two's-complement decimal number, e.g. `0xFFFF` is `-1`.
+fn signed_decimal(value: u16) -> String {
+    (value as i16).to_string()
+}
+
+#[cfg(test)]
+mod trap_test {
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm, VmError};
+
+    // LD R0, #1 ; TRAP x31 ; .FILL value
+    fn putd(value: u16) -> String {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x2001, 0xF031, value]);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        vm.io().output_string()
+    }
+
+    #[test]
+    fn putd_should_print_zero() {
+        assert_eq!("0", putd(0x0000));
+    }
+
+    #[test]
+    fn putd_should_print_most_negative_value() {
+        assert_eq!("-32768", putd(0x8000));
+    }
+
+    #[test]
+    fn putd_should_print_most_positive_value() {
+        assert_eq!("32767", putd(0x7FFF));
+    }
+
+    #[test]
+    fn putd_should_print_minus_one() {
+        assert_eq!("-1", putd(0xFFFF));
+    }
+
+    #[test]
+    fn regs_should_print_the_register_dump() {
+        // AND R0, R0, #0 ; ADD R0, R0, #5 ; ADD R1, R0, #-6 ; TRAP x32
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x5020, 0x1025, 0x123A, 0xF032]);
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(
+            "R0 x0005      5  R1 xFFFF     -1  R2 x0000      0  R3 x0000      0\n\
+             R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x3004  12292\n\
+             PC x3004  IR xF032  CC N\n",
+            vm.io().output_string()
+        );
+    }
+
+    #[test]
+    fn trap_without_a_routine_should_fail() {
+        // TRAP x99
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF099]);
+        assert_eq!(
+            RunResult::Error(VmError::UnknownTrap { vector: 0x99 }),
+            vm.run()
+        );
+        assert_eq!(0x3001, vm.registers().pc());
+    }
+
+    #[test]
+    fn getc_and_out_should_use_io() {
+        // TRAP x20 ; TRAP x21
+        let mut vm = Vm::with_io(BufferIo::with_input("A"));
+        vm.load_words(0x3000, &[0xF020, 0xF021]);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!("A", vm.io().output_string());
+    }
+
+    #[test]
+    fn getc_should_return_multi_byte_characters_one_byte_at_a_time() {
+        // TRAP x20 ; TRAP x20
+        let mut io = BufferIo::new();
+        io.push_char('é');
+        let mut vm = Vm::with_io(io);
+        vm.load_words(0x3000, &[0xF020, 0xF020]);
+        vm.step().unwrap();
+        assert_eq!(0xC3, vm.registers().r_00);
+        vm.step().unwrap();
+        assert_eq!(0xA9, vm.registers().r_00);
+        assert_eq!(0, vm.io().pending_input());
+    }
+
+    #[test]
+    fn puts_should_translate_newlines_when_enabled() {
+        // LEA R0, #1 ; PUTS ; "a\nb"
+        let mut io = BufferIo::new();
+        io.set_translate_newlines(true);
+        let mut vm = Vm::with_io(io);
+        vm.load_words(0x3000, &[0xE001, 0xF022, 0x61, 0x0A, 0x62, 0]);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(b"a\r\nb", vm.io().output());
+    }
+
+    #[test]
+    fn built_in_traps_should_load_r7_with_the_return_address() {
+        // LD R7, #2 ; TRAP x21 ; ADD R0, R0, #1 ; .FILL xBEEF
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x2E02, 0xF021, 0x1021, 0xBEEF]);
+        vm.step().unwrap();
+        assert_eq!(0xBEEF, vm.registers().r_07);
+        vm.step().unwrap();
+        assert_eq!(0x3002, vm.registers().r_07);
+        assert_eq!(0x3002, vm.registers().pc());
+    }
+
+    #[test]
+    fn halt_should_stop_the_machine() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF025]);
+        vm.set_halt_banner(true);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert!(vm.is_halted());
+        assert_eq!("HALT", vm.io().output_string());
+    }
+
+    #[test]
+    fn halt_banner_should_only_be_printed_when_enabled() {
+        // LD R0, #2 ; OUT ; HALT ; .FILL 'k'
+        let program = [0x2002, 0xF021, 0xF025, 0x006B];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        let mut verbose = vm.clone();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("k", vm.io().output_string());
+        assert_eq!(0x3003, vm.registers().r_07);
+
+        verbose.set_halt_banner(true);
+        assert_eq!(RunResult::Halted, verbose.run());
+        assert_eq!("kHALT", verbose.io().output_string());
+    }
+
+    #[test]
+    fn in_should_print_the_chosen_prompt() {
+        // IN ; OUT ; HALT
+        let program = [0xF023, 0xF021, 0xF025];
+        let mut vm = Vm::with_io(BufferIo::with_input("yy"));
+        vm.load_words(0x3000, &program);
+        let mut quiet = vm.clone();
+        let mut default = vm.clone();
+        vm.set_input_prompt(Some("? ".to_string()));
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("? y", vm.io().output_string());
+
+        quiet.set_input_prompt(None);
+        assert_eq!(RunResult::Halted, quiet.run());
+        assert_eq!("y", quiet.io().output_string());
+
+        assert_eq!(RunResult::Halted, default.run());
+        assert_eq!(
+            format!("{}y", super::IN_PROMPT),
+            default.io().output_string()
+        );
     }
-    terminal::restore_terminal_settings();
 }
@@ -0,0 +1,569 @@
+//! `io` : The LC-3 talks to the outside world through a keyboard (input) and a display (output).
+//! The `Io` trait abstracts over both, so that the same program can run against the real terminal (`StdIo`)
+//! or against in-memory buffers (`BufferIo`), which is what tests and embedders usually want.
+//!
+//! The console works on bytes, not characters: the keyboard delivers one byte at a time and GETC returns
+//! the next byte in R0. A character outside ASCII arrives as its UTF-8 bytes, one per read
+//! (`BufferIo::push_char` enqueues a `char` that way). For a program which uses a single-byte code page,
+//! `CodePageIo` translates the bytes between the program and the console.
+
+use crate::sys::getchar;
+use crate::sys::select::{self, FdSet};
+use crate::sys::terminal;
+use crate::sys::time::{TimeVal, TimeValLike};
+use libc::STDIN_FILENO;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// `Io` represents the console devices (keyboard and display) of the LC-3.
+pub trait Io {
+    /// `read_byte` blocks until an input byte is available and returns it.
+    /// It returns `None` when there is no more input.
+    fn read_byte(&mut self) -> Option<u8>;
+
+    /// `input_ready` returns `true` if a byte can be read without blocking.
+    fn input_ready(&mut self) -> bool;
+
+    /// `write_byte` sends a single byte to the display.
+    fn write_byte(&mut self, byte: u8);
+
+    /// `flush` makes sure everything written so far reaches the display.
+    fn flush(&mut self) {}
+
+    /// `write_bytes` sends all the bytes to the display, in order.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// `halt_banner` tells whether the built-in HALT routine prints its banner to this console.
+    /// A terminal shows it, as the reference simulator does; a console whose output is captured does not,
+    /// so the output is only the program's own (see `Vm::set_halt_banner` to choose per machine).
+    fn halt_banner(&self) -> bool {
+        true
+    }
+
+    /// `output_len` is the number of bytes the display has collected so far, if it keeps them (like `BufferIo`).
+    /// It is `None` for a console whose output is gone once written, like a terminal.
+    fn output_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// `truncate_output` takes back the collected output after its first `len` bytes (see `output_len`),
+    /// e.g. when `Vm::step_back` undoes an OUT. Consoles which do not keep their output ignore it.
+    fn truncate_output(&mut self, _len: usize) {}
+
+    /// `unread` puts `bytes` back at the front of the input, to be read again, e.g. when `Vm::step_back`
+    /// undoes a GETC. Consoles which do not queue their input ignore it.
+    fn unread(&mut self, _bytes: &[u8]) {}
+}
+
+/// `StdIo` connects the LC-3 console to the terminal (stdin / stdout) of the host process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StdIo {
+    /// When `true`, every `\n` written to the display is sent as `\r\n`.
+    translate_newlines: bool,
+}
+
+impl StdIo {
+    pub fn new() -> StdIo {
+        StdIo::default()
+    }
+
+    /// `set_translate_newlines` turns the translation of `\n` into `\r\n` on output on or off (it is off by default).
+    pub fn set_translate_newlines(&mut self, enabled: bool) {
+        self.translate_newlines = enabled;
+    }
+}
+
+impl Io for StdIo {
+    fn read_byte(&mut self) -> Option<u8> {
+        //read a single key press, without waiting for the Enter key and without echoing it.
+        terminal::turn_off_canonical_and_echo_modes();
+        let c = getchar::get_char();
+        terminal::restore_terminal_settings();
+        if c < 0 {
+            None
+        } else {
+            Some(c as u8)
+        }
+    }
+
+    fn input_ready(&mut self) -> bool {
+        let mut fd = FdSet::new();
+        fd.insert(STDIN_FILENO);
+
+        let mut timeout = TimeVal::seconds(0);
+        select::select(1, &mut fd, None, None, &mut timeout).is_ok()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        let mut stdout = io::stdout();
+        if self.translate_newlines && byte == b'\n' {
+            stdout.write_all(b"\r").expect("Written.");
+        }
+        stdout.write_all(&[byte]).expect("Written.");
+    }
+
+    fn flush(&mut self) {
+        io::stdout().flush().expect("Flushed.");
+    }
+}
+
+/// `BufferIo` keeps the console in memory.
+/// Input is taken from a queue of bytes and everything written to the display is collected in a buffer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BufferIo {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    /// When `true`, every `\n` written to the display is collected as `\r\n`.
+    translate_newlines: bool,
+}
+
+impl BufferIo {
+    /// `new()` creates a `BufferIo` with no pending input and an empty output buffer.
+    pub fn new() -> BufferIo {
+        BufferIo::default()
+    }
+
+    /// `with_input` creates a `BufferIo` whose input queue holds the bytes of `input`.
+    pub fn with_input(input: &str) -> BufferIo {
+        let mut io = BufferIo::new();
+        io.push_input(input.as_bytes());
+        io
+    }
+
+    /// `push_input` appends bytes to the end of the input queue.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// `push_char` appends the UTF-8 bytes of `c` to the input queue, so e.g. `é` is read as `xC3` then `xA9`.
+    pub fn push_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.push_input(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    /// `pending_input` returns the number of bytes not yet consumed by the program.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// `pending_bytes` returns the bytes not yet consumed by the program, in the order they will be read.
+    pub fn pending_bytes(&self) -> Vec<u8> {
+        self.input.iter().copied().collect()
+    }
+
+    /// `restore` replaces the input queue with `pending` and the output buffer with `output`, as they were
+    /// saved by `pending_bytes` and `output`. The newline translation is kept.
+    pub fn restore(&mut self, pending: &[u8], output: &[u8]) {
+        self.input = pending.iter().copied().collect();
+        self.output = output.to_vec();
+    }
+
+    /// `output` returns everything written to the display so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// `output_string` returns the output as a `String`, replacing invalid UTF-8 sequences.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// `clear_output` empties the output buffer.
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    /// `set_translate_newlines` turns the translation of `\n` into `\r\n` on output on or off (it is off by default).
+    pub fn set_translate_newlines(&mut self, enabled: bool) {
+        self.translate_newlines = enabled;
+    }
+}
+
+impl Io for BufferIo {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn input_ready(&mut self) -> bool {
+        !self.input.is_empty()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if self.translate_newlines && byte == b'\n' {
+            self.output.push(b'\r');
+        }
+        self.output.push(byte);
+    }
+
+    fn halt_banner(&self) -> bool {
+        false
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        Some(self.output.len())
+    }
+
+    fn truncate_output(&mut self, len: usize) {
+        self.output.truncate(len);
+    }
+
+    fn unread(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().rev() {
+            self.input.push_front(byte);
+        }
+    }
+}
+
+/// `FileInputIo` reads the keyboard from the bytes of a file (or any fixed input) and sends the display to another console.
+/// This is what the CLI's `--input <file>` uses to run a program unattended.
+/// Once every byte has been consumed the input is at its end: `input_ready` is `false` and `read_byte` returns `None`,
+/// so GETC and IN read the value of the `EofPolicy` (x0000 by default) instead of waiting for a key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileInputIo<I> {
+    input: VecDeque<u8>,
+    output: I,
+}
+
+impl<I: Io> FileInputIo<I> {
+    /// `open` reads the whole of the file at `path` as the input.
+    pub fn open<P: AsRef<Path>>(path: P, output: I) -> io::Result<FileInputIo<I>> {
+        Ok(FileInputIo::from_bytes(fs::read(path)?, output))
+    }
+
+    /// `from_bytes` takes `input` as the input, as if it had been read from a file.
+    pub fn from_bytes(input: Vec<u8>, output: I) -> FileInputIo<I> {
+        FileInputIo {
+            input: input.into(),
+            output,
+        }
+    }
+
+    /// `pending_input` returns the number of bytes not yet consumed by the program.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// `output` gives access to the console the display is sent to.
+    pub fn output(&self) -> &I {
+        &self.output
+    }
+
+    pub fn into_output(self) -> I {
+        self.output
+    }
+}
+
+impl<I: Io> Io for FileInputIo<I> {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn input_ready(&mut self) -> bool {
+        !self.input.is_empty()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.output.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.output.flush();
+    }
+
+    fn halt_banner(&self) -> bool {
+        self.output.halt_banner()
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        self.output.output_len()
+    }
+
+    fn truncate_output(&mut self, len: usize) {
+        self.output.truncate_output(len);
+    }
+
+    fn unread(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().rev() {
+            self.input.push_front(byte);
+        }
+    }
+}
+
+/// `TranscriptEntry` is one byte which crossed the console, in a `TranscriptIo` transcript.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriptEntry {
+    /// The program read the byte from the keyboard.
+    Input(u8),
+    /// The program wrote the byte to the display.
+    Output(u8),
+}
+
+/// `TranscriptIo` wraps another console, and records every byte read or written in the order it happened,
+/// e.g. to grade an interactive program by the way its prompts and the answers interleave.
+/// Output is recorded as the program wrote it, before any newline translation of the wrapped console.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TranscriptIo<I> {
+    inner: I,
+    transcript: Vec<TranscriptEntry>,
+}
+
+impl<I: Io> TranscriptIo<I> {
+    /// `new` wraps `inner`, with an empty transcript.
+    pub fn new(inner: I) -> TranscriptIo<I> {
+        TranscriptIo {
+            inner,
+            transcript: Vec::new(),
+        }
+    }
+
+    /// `transcript` returns every byte read or written so far, oldest first.
+    pub fn transcript(&self) -> &[TranscriptEntry] {
+        &self.transcript
+    }
+
+    /// `inner` gives access to the wrapped console, e.g. to queue input.
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Io> Io for TranscriptIo<I> {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.inner.read_byte();
+        self.transcript.extend(byte.map(TranscriptEntry::Input));
+        byte
+    }
+
+    fn input_ready(&mut self) -> bool {
+        self.inner.input_ready()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.transcript.push(TranscriptEntry::Output(byte));
+        self.inner.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn halt_banner(&self) -> bool {
+        self.inner.halt_banner()
+    }
+}
+
+/// `RAW` is the translation table which leaves every byte as it is.
+pub const RAW: [u8; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        table[byte] = byte as u8;
+        byte += 1;
+    }
+    table
+};
+
+/// `CodePageIo` wraps another console, and translates every byte through a table on its way: the display gets
+/// `output_table[byte]` for every byte the program writes (OUT, PUTS, the DDR), and the program reads
+/// `input_table[byte]` for every byte of the keyboard (GETC, IN, the KBDR). This lets a program written for a
+/// single-byte code page talk to a console which uses another. Both tables start out as `RAW`.
+/// Output is counted and truncated on the wrapped console, but input which `Vm::step_back` gives back is dropped,
+/// as a translated byte cannot in general be turned back into the byte which was read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodePageIo<I> {
+    inner: I,
+    input_table: [u8; 256],
+    output_table: [u8; 256],
+}
+
+impl<I: Io> CodePageIo<I> {
+    /// `new` wraps `inner`, without translating anything yet.
+    pub fn new(inner: I) -> CodePageIo<I> {
+        CodePageIo {
+            inner,
+            input_table: RAW,
+            output_table: RAW,
+        }
+    }
+
+    /// `set_input_table` translates the bytes read from the keyboard through `table` from now on.
+    pub fn set_input_table(&mut self, table: [u8; 256]) {
+        self.input_table = table;
+    }
+
+    /// `set_output_table` translates the bytes written to the display through `table` from now on.
+    pub fn set_output_table(&mut self, table: [u8; 256]) {
+        self.output_table = table;
+    }
+
+    /// `inner` gives access to the wrapped console, e.g. to queue input.
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Io> Io for CodePageIo<I> {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.inner
+            .read_byte()
+            .map(|byte| self.input_table[byte as usize])
+    }
+
+    fn input_ready(&mut self) -> bool {
+        self.inner.input_ready()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.inner.write_byte(self.output_table[byte as usize]);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn halt_banner(&self) -> bool {
+        self.inner.halt_banner()
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        self.inner.output_len()
+    }
+
+    fn truncate_output(&mut self, len: usize) {
+        self.inner.truncate_output(len);
+    }
+}
+
+#[cfg(test)]
+mod buffer_io_test {
+    use super::*;
+    #[test]
+    fn input_should_be_read_in_order() {
+        let mut io = BufferIo::with_input("ab");
+        assert!(io.input_ready());
+        assert_eq!(Some(b'a'), io.read_byte());
+        assert_eq!(Some(b'b'), io.read_byte());
+        assert!(!io.input_ready());
+        assert_eq!(None, io.read_byte());
+    }
+
+    #[test]
+    fn output_should_be_collected() {
+        let mut io = BufferIo::new();
+        io.write_bytes(b"hi");
+        io.write_byte(b'!');
+        assert_eq!(b"hi!", io.output());
+        assert_eq!("hi!", io.output_string());
+    }
+
+    #[test]
+    fn newlines_should_only_be_translated_when_enabled() {
+        let mut io = BufferIo::new();
+        io.write_bytes(b"a\nb");
+        assert_eq!(b"a\nb", io.output());
+        io.clear_output();
+        io.set_translate_newlines(true);
+        io.write_bytes(b"a\nb");
+        assert_eq!(b"a\r\nb", io.output());
+    }
+
+    #[test]
+    fn code_page_io_should_translate_out_and_getc() {
+        use crate::vm::{RunResult, Vm};
+        //x84 is ä in code page 437, xE4 in Latin-1
+        let mut output_table = RAW;
+        output_table[0x84] = 0xE4;
+        let mut input_table = RAW;
+        input_table[0xE4] = 0x84;
+        let mut io = CodePageIo::new(BufferIo::new());
+        io.set_output_table(output_table);
+        io.set_input_table(input_table);
+        io.inner_mut().push_input(&[0xE4]);
+        // LD R0, #3 ; OUT ; GETC ; HALT ; .FILL x84
+        let mut vm = Vm::with_io(io);
+        vm.load_words(0x3000, &[0x2003, 0xF021, 0xF020, 0xF025, 0x0084]);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(&[0xE4], vm.io().inner().output());
+        assert_eq!(0x84, vm.reg(0));
+    }
+
+    #[test]
+    fn transcript_should_interleave_input_and_output() {
+        use crate::assembler;
+        use crate::vm::Vm;
+
+        let program = assembler::assemble(
+            ".ORIG x3000
+             GETC
+             OUT
+             GETC
+             OUT
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(TranscriptIo::new(BufferIo::with_input("ab")));
+        vm.load_program(&program);
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(
+            &[
+                TranscriptEntry::Input(b'a'),
+                TranscriptEntry::Output(b'a'),
+                TranscriptEntry::Input(b'b'),
+                TranscriptEntry::Output(b'b'),
+            ],
+            vm.io().transcript()
+        );
+        assert_eq!("ab", vm.io().inner().output_string());
+    }
+
+    #[test]
+    fn file_input_should_feed_getc_until_eof() {
+        use crate::assembler;
+        use crate::vm::{RunResult, Vm};
+        let path = std::env::temp_dir().join(format!("lc3_vm_input_{}.txt", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        let io = FileInputIo::open(&path, BufferIo::new()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let program = assembler::assemble(
+            ".ORIG x3000
+             LOOP GETC
+                  ADD R0, R0, #0
+                  BRz DONE
+                  TRAP x21
+                  BR LOOP
+             DONE HALT
+                  .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(io);
+        vm.load_program(&program);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(0, vm.reg(0));
+        assert_eq!(0, vm.io().pending_input());
+        assert_eq!("abc", vm.io().output().output_string());
+    }
+}
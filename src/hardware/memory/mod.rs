@@ -1,72 +1,453 @@
 //! `Memory` : LC-3 has 65,536 memory locations (the maximum that is addressable by a 16-bit unsigned integer 2^16),
 //! each of which stores a 16-bit value. This means it can store a total of only 128kb.
 
-use crate::sys::getchar::get_char;
-use crate::sys::select::{self, FdSet};
-use crate::sys::time::TimeVal;
-use crate::sys::time::TimeValLike;
-use libc::STDIN_FILENO;
+mod protection;
+mod range;
+
+pub use self::protection::{Access, Perms};
+pub use self::range::AddrRange;
+
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hex::{self, LoadError};
+use crate::vm::VmError;
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::RangeInclusive;
 
 /// `MEMORY_SIZE` is a constant to represent size of memory in LC-3.
-pub const MEMORY_SIZE: usize = std::u16::MAX as usize;
+pub const MEMORY_SIZE: usize = u16::MAX as usize + 1;
+
+/// `Endianness` is the byte order of the words in an `.obj` file.
+/// `.obj` files are big-endian, but some tools read and write them little-endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+impl Endianness {
+    /// `to_bytes` returns `word` in this byte order.
+    pub fn to_bytes(self, word: u16) -> [u8; 2] {
+        match self {
+            Endianness::Big => word.to_be_bytes(),
+            Endianness::Little => word.to_le_bytes(),
+        }
+    }
+}
 
 /// `Memory` : LC-3 has 65,536 memory locations (the maximum that is addressable by a 16-bit unsigned integer 2^16),
 /// each of which stores a 16-bit value. This means it can store a total of only 128kb.
-
-#[derive(Copy)]
+/// `Memory` is plain storage: reading or writing a device register address has no side effects here.
+/// Memory-mapped I/O is the job of whatever `Bus` sits in front of it (see `crate::vm::Vm`).
+/// The cells live on the heap, so moving a `Memory` around stays cheap; `clone` copies all 128kb.
+/// Loads, stores and instruction fetches made through a `Bus` are checked against the protected regions (see `protect`);
+/// `read` and `write` are not, so that loaders and debuggers can always reach every cell.
+#[derive(Clone, PartialEq, Eq)]
 pub struct Memory {
     /// Memory is an array of `u16` cells, with length = 65,536.
-    pub cells: [u16; MEMORY_SIZE],
+    pub cells: Box<[u16; MEMORY_SIZE]>,
+    /// Protected regions, in the order they were added. The last region containing an address wins.
+    regions: Vec<(RangeInclusive<u16>, Perms)>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+impl AsRef<[u16]> for Memory {
+    fn as_ref(&self) -> &[u16] {
+        self.as_slice()
+    }
+}
+
+impl AsMut<[u16]> for Memory {
+    fn as_mut(&mut self) -> &mut [u16] {
+        self.as_mut_slice()
+    }
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        let cells = vec![0; MEMORY_SIZE].into_boxed_slice();
         Memory {
-            cells: [0; MEMORY_SIZE],
+            cells: cells.try_into().expect("Memory has MEMORY_SIZE cells."),
+            regions: Vec::new(),
         }
     }
+
+    /// `from_cells` creates an empty memory in the buffer `cells`, which is cleared rather than reallocated
+    /// (e.g. a buffer given back by `into_cells`).
+    pub fn from_cells(mut cells: Box<[u16; MEMORY_SIZE]>) -> Memory {
+        cells.fill(0);
+        Memory {
+            cells,
+            regions: Vec::new(),
+        }
+    }
+
+    /// `into_cells` gives back the buffer of the cells, to be reused by `from_cells`.
+    pub fn into_cells(self) -> Box<[u16; MEMORY_SIZE]> {
+        self.cells
+    }
+
+    /// `as_slice` gives direct access to all 65,536 cells, indexed by address, e.g. for fast checksums and searches.
+    /// Like `read`, it bypasses memory-mapped I/O: the device registers are seen as the values last stored in them.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.cells[..]
+    }
+
+    /// `as_mut_slice` is the mutable `as_slice`. Writes bypass memory-mapped I/O and the protected regions.
+    pub fn as_mut_slice(&mut self) -> &mut [u16] {
+        &mut self.cells[..]
+    }
+
     pub fn write(&mut self, address: usize, value: u16) {
         self.cells[(address as u16) as usize] = value;
     }
 
-    pub fn read(&mut self, address: u16) -> u16 {
-        if address == MemoryMappedReg::Kbsr as u16 {
-            if check_key() {
-                self.cells[MemoryMappedReg::Kbsr as usize] = 1 << 15;
-                self.cells[MemoryMappedReg::Kbdr as usize] = get_char() as u16;
-            } else {
-                self.cells[MemoryMappedReg::Kbsr as usize] = 0;
+    pub fn read(&self, address: u16) -> u16 {
+        self.cells[address as usize]
+    }
+
+    /// `load_obj` loads a multi-block `.obj` image, which holds several blocks one after the other until its end.
+    /// Each block is its origin, the number of words in it, then the words, all big-endian; every block is loaded
+    /// at its own origin, and the origin of the first one, the entry point, is returned. Blocks are checked
+    /// before anything is written, so nothing is loaded from a malformed image. An origin at the very end,
+    /// without a word count, is an empty block.
+    /// (A plain `.obj` has a single block and no word count: see `Vm::load_obj` for those.)
+    pub fn load_obj(&mut self, bytes: &[u8]) -> Result<u16, LoadError> {
+        let (first, rest) = hex::obj_words(bytes)?;
+        let mut words = std::iter::once(first).chain(rest);
+        let mut blocks = Vec::new();
+        while let Some(origin) = words.next() {
+            let expected = words.next().map_or(0, usize::from);
+            let block: Vec<u16> = words.by_ref().take(expected).collect();
+            if block.len() < expected {
+                return Err(LoadError::TruncatedBlock {
+                    origin,
+                    expected,
+                    found: block.len(),
+                });
             }
+            hex::check_fits(origin, block.len())?;
+            blocks.push((origin, block));
         }
-        self.cells[address as usize]
+        for (origin, block) in blocks.iter() {
+            for (i, &word) in block.iter().enumerate() {
+                self.write(*origin as usize + i, word);
+            }
+        }
+        Ok(blocks[0].0)
+    }
+
+    /// `write_obj` returns `len` words starting at `origin` as an `.obj` file: the origin, then the words, big-endian.
+    pub fn write_obj(&self, origin: u16, len: u16) -> Vec<u8> {
+        self.write_obj_as(origin, len, Endianness::Big)
+    }
+
+    /// `write_obj_as` is `write_obj` with the given byte order (see `file::read_obj_as` for the reverse).
+    pub fn write_obj_as(&self, origin: u16, len: u16, endianness: Endianness) -> Vec<u8> {
+        std::iter::once(origin)
+            .chain((0..len).map(|i| self.read(origin.wrapping_add(i))))
+            .flat_map(|word| endianness.to_bytes(word))
+            .collect()
+    }
+
+    /// `instructions` decodes every word from `start` to `end` (inclusive), yielding `(address, instruction)` pairs.
+    /// It only reads the cells: nothing is executed and no device register is touched.
+    pub fn instructions(
+        &self,
+        start: u16,
+        end: u16,
+    ) -> impl Iterator<Item = (u16, DecodedInstr)> + '_ {
+        (start..=end).map(move |address| (address, DecodedInstr::decode(self.read(address))))
+    }
+
+    /// `view` lazily yields `len` `(address, word)` pairs starting at `start`, wrapping from xFFFF to x0000.
+    /// Nothing is allocated, so a large region can be rendered piece by piece.
+    pub fn view(&self, start: u16, len: usize) -> impl Iterator<Item = (u16, u16)> + '_ {
+        (0..len).map(move |i| {
+            let address = start.wrapping_add(i as u16);
+            (address, self.read(address))
+        })
+    }
+
+    /// `fill` writes `value` to the `len` words starting at `start`, wrapping from xFFFF to x0000.
+    pub fn fill(&mut self, start: u16, len: usize, value: u16) {
+        for i in 0..len {
+            self.write(start.wrapping_add(i as u16) as usize, value);
+        }
+    }
+
+    /// `copy_within` copies the `len` words starting at `src` to `dst`, wrapping from xFFFF to x0000.
+    /// Like `memmove`, the regions may overlap: `dst` gets the words `src` held before the copy.
+    pub fn copy_within(&mut self, src: u16, dst: u16, len: usize) {
+        let words: Vec<u16> = self.view(src, len).map(|(_, word)| word).collect();
+        for (i, word) in words.into_iter().enumerate() {
+            self.write(dst.wrapping_add(i as u16) as usize, word);
+        }
+    }
+
+    /// `search` returns the first address at or after `start` where the words of `pattern` are stored,
+    /// trying every address once and wrapping from xFFFF to x0000 (a match may wrap too).
+    /// An empty pattern matches at `start`.
+    pub fn search(&self, pattern: &[u16], start: u16) -> Option<u16> {
+        (0..MEMORY_SIZE)
+            .map(|i| start.wrapping_add(i as u16))
+            .find(|&address| {
+                self.view(address, pattern.len())
+                    .map(|(_, word)| word)
+                    .eq(pattern.iter().copied())
+            })
+    }
+
+    /// `protect` restricts the addresses in `range` to `perms`, overriding any earlier region.
+    pub fn protect(&mut self, range: RangeInclusive<u16>, perms: Perms) {
+        self.regions.push((range, perms));
+    }
+
+    /// `perms` returns the permissions of `address` (`Perms::ALL` unless it is in a protected region).
+    pub fn perms(&self, address: u16) -> Perms {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map_or(Perms::ALL, |&(_, perms)| perms)
+    }
+
+    /// `check` returns `VmError::ProtectionFault` if `access` to `address` is not permitted.
+    pub fn check(&self, address: u16, access: Access) -> Result<(), VmError> {
+        if self.perms(address).allows(access) {
+            Ok(())
+        } else {
+            Err(VmError::ProtectionFault {
+                addr: address,
+                access,
+            })
+        }
+    }
+}
+
+/// Printing all 65,536 cells is never useful, so `Debug` only summarizes the memory.
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used = self.cells.iter().filter(|&&cell| cell != 0).count();
+        f.debug_struct("Memory")
+            .field("non_zero_cells", &used)
+            .field("regions", &self.regions)
+            .finish()
     }
 }
 
-impl Clone for Memory {
-    fn clone(&self) -> Memory {
-        *self
+/// `Bus` is the path instructions take to reach memory.
+/// A bus may put devices in front of plain memory (memory-mapped I/O), so both loads and stores can fail.
+pub trait Bus {
+    /// `load` reads the word at `address`.
+    fn load(&mut self, address: u16) -> Result<u16, VmError>;
+    /// `store` writes `value` to `address`.
+    fn store(&mut self, address: u16, value: u16) -> Result<(), VmError>;
+    /// `fetch` reads the instruction at `address`. By default it is the same as `load`.
+    fn fetch(&mut self, address: u16) -> Result<u16, VmError> {
+        self.load(address)
+    }
+}
+
+/// `Memory` on its own is a bus without any devices.
+impl Bus for Memory {
+    fn load(&mut self, address: u16) -> Result<u16, VmError> {
+        self.check(address, Access::Read)?;
+        Ok(self.read(address))
+    }
+
+    fn store(&mut self, address: u16, value: u16) -> Result<(), VmError> {
+        self.check(address, Access::Write)?;
+        self.write(address as usize, value);
+        Ok(())
+    }
+
+    fn fetch(&mut self, address: u16) -> Result<u16, VmError> {
+        self.check(address, Access::Execute)?;
+        Ok(self.read(address))
     }
 }
+
 /// Memory-mapped I/O Input and output are handled by load/store (LDI/STI, LDR/STR) instructions using memory addresses
 /// to designate each I/O device register.
 /// Addresses xFE00 through xFFFF have been allocated to represent the addresses of I/O devices.
-/// The LC-3 has two memory mapped registers that need to be implemented.
-/// They are the keyboard status register (KBSR) and keyboard data register (KBDR).
-/// The KBSR indicates whether a key has been pressed, and the KBDR identifies which key was pressed.
+/// The keyboard status register (KBSR) and keyboard data register (KBDR) are used for input:
+/// the KBSR indicates whether a key has been pressed, and the KBDR identifies which key was pressed.
+/// The display status register (DSR) and display data register (DDR) are used for output, and
+/// the machine control register (MCR) starts and stops the clock.
 pub enum MemoryMappedReg {
     /// keyboard status: The KBSR indicates whether a key has been pressed
     Kbsr = 0xFE00, /* keyboard status */
     /// keyboard data: The KBDR identifies which key was pressed
     Kbdr = 0xFE02, /* keyboard data */
+    /// display status: The DSR indicates whether the display is ready for the next character
+    Dsr = 0xFE04, /* display status */
+    /// display data: A character written to the DDR is sent to the display
+    Ddr = 0xFE06, /* display data */
+    /// machine control: The clock runs while bit [15] of the MCR is set
+    Mcr = 0xFFFE, /* machine control */
 }
 
-fn check_key() -> bool {
-    let mut fd = FdSet::new();
-    fd.insert(STDIN_FILENO);
+#[cfg(test)]
+mod memory_test {
+    use super::*;
+    #[test]
+    fn slice_should_match_per_word_reads() {
+        let mut memory = Memory::new();
+        for (i, &word) in [0xF025, 0x1021, 0xFFFF].iter().enumerate() {
+            memory.write(0x3000 + i, word);
+        }
+        memory.as_mut_slice()[0x4000] = 7;
+        let below_mmio = 0..0xFE00;
+        let slice_sum: u64 = memory.as_slice()[below_mmio.clone()]
+            .iter()
+            .map(|&word| u64::from(word))
+            .sum();
+        let read_sum: u64 = below_mmio.map(|a| u64::from(memory.read(a as u16))).sum();
+        assert_eq!(read_sum, slice_sum);
+        assert_eq!(0xF025 + 0x1021 + 0xFFFF + 7, slice_sum);
+        assert_eq!(MEMORY_SIZE, memory.as_ref().len());
+    }
+
+    #[test]
+    fn search_should_find_a_pattern_and_wrap() {
+        let mut memory = Memory::new();
+        memory.write(0x4000, 0x48);
+        memory.write(0x4001, 0x69);
+        memory.write(0xFFFF, 0x48);
+        assert_eq!(Some(0x4000), memory.search(&[0x48, 0x69], 0x3000));
+        assert_eq!(Some(0x4000), memory.search(&[0x48, 0x69], 0x4001));
+        assert_eq!(Some(0xFFFF), memory.search(&[0x48, 0], 0x4001));
+        assert_eq!(None, memory.search(&[0x48, 0x48], 0));
+    }
+
+    #[test]
+    fn last_address_should_be_addressable() {
+        let mut memory = Memory::new();
+        memory.write(0xFFFF, 7);
+        assert_eq!(7, memory.read(0xFFFF));
+    }
+
+    #[test]
+    fn clone_should_be_equal_and_independent() {
+        let mut memory = Memory::new();
+        memory.write(0x3000, 1);
+        let mut copy = memory.clone();
+        assert_eq!(memory, copy);
+        copy.write(0x3000, 2);
+        assert_ne!(memory, copy);
+        assert_eq!(1, memory.read(0x3000));
+    }
+
+    #[test]
+    fn instructions_should_decode_a_region_in_order() {
+        use crate::hardware::instructions::opcode::OpCode;
+        let mut memory = Memory::new();
+        // ADD R0, R0, #1 ; LD R1, #2 ; BRnzp #-3 ; HALT
+        for (i, &word) in [0x1021, 0x2202, 0x0FFD, 0xF025].iter().enumerate() {
+            memory.write(0x3000 + i, word);
+        }
+        let op_codes: Vec<(u16, OpCode)> = memory
+            .instructions(0x3000, 0x3003)
+            .map(|(address, instr)| (address, instr.op_code()))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x3000, OpCode::Add),
+                (0x3001, OpCode::Ld),
+                (0x3002, OpCode::Br),
+                (0x3003, OpCode::Trap)
+            ],
+            op_codes
+        );
+        assert_eq!(1, memory.instructions(0xFFFF, 0xFFFF).count());
+    }
+
+    #[test]
+    fn view_should_wrap_around_the_end_of_memory() {
+        let mut memory = Memory::new();
+        memory.write(0xFFFE, 1);
+        memory.write(0xFFFF, 2);
+        memory.write(0x0000, 3);
+        let view: Vec<(u16, u16)> = memory.view(0xFFFE, 4).collect();
+        assert_eq!(
+            vec![(0xFFFE, 1), (0xFFFF, 2), (0x0000, 3), (0x0001, 0)],
+            view
+        );
+        assert_eq!(0, memory.view(0x3000, 0).count());
+    }
+
+    #[test]
+    fn fill_should_write_every_word_of_the_range() {
+        let mut memory = Memory::new();
+        memory.fill(0xFFFE, 3, 0xAAAA);
+        assert_eq!(
+            vec![0, 0xAAAA, 0xAAAA, 0xAAAA, 0],
+            memory
+                .view(0xFFFD, 5)
+                .map(|(_, word)| word)
+                .collect::<Vec<u16>>()
+        );
+    }
+
+    #[test]
+    fn overlapping_copy_should_behave_like_memmove() {
+        let mut memory = Memory::new();
+        for i in 0..4 {
+            memory.write(0x3000 + i, i as u16 + 1);
+        }
+        memory.copy_within(0x3000, 0x3002, 4);
+        let words: Vec<u16> = memory.view(0x3000, 6).map(|(_, word)| word).collect();
+        assert_eq!(vec![1, 2, 1, 2, 3, 4], words);
+        memory.copy_within(0x3002, 0x3001, 4);
+        let words: Vec<u16> = memory.view(0x3000, 6).map(|(_, word)| word).collect();
+        assert_eq!(vec![1, 1, 2, 3, 4, 4], words);
+    }
+
+    #[test]
+    fn later_regions_should_override_earlier_ones() {
+        let mut memory = Memory::new();
+        memory.protect(0x3000..=0x30FF, Perms::READ);
+        memory.protect(0x3010..=0x301F, Perms::NONE);
+        assert_eq!(Perms::ALL, memory.perms(0x2FFF));
+        assert_eq!(Perms::READ, memory.perms(0x3000));
+        assert_eq!(Perms::NONE, memory.perms(0x3010));
+        assert_eq!(
+            Err(VmError::ProtectionFault {
+                addr: 0x3000,
+                access: Access::Write
+            }),
+            memory.store(0x3000, 1)
+        );
+        assert_eq!(Ok(0), memory.load(0x3000));
+    }
+
+    #[test]
+    fn load_obj_should_load_every_block_at_its_origin() {
+        let words: [u16; 7] = [0x3000, 2, 0x1021, 0xF025, 0x4000, 1, 0xBEEF];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        let mut memory = Memory::new();
+        assert_eq!(Ok(0x3000), memory.load_obj(&bytes));
+        assert_eq!(0x1021, memory.read(0x3000));
+        assert_eq!(0xF025, memory.read(0x3001));
+        assert_eq!(0xBEEF, memory.read(0x4000));
+        assert_eq!(0, memory.read(0x4001));
 
-    let mut timeout = TimeVal::seconds(0);
-    match select::select(1, &mut fd, None, None, &mut timeout) {
-        Err(_) => false,
-        _ => true,
+        let mut memory = Memory::new();
+        assert_eq!(
+            Err(LoadError::TruncatedBlock {
+                origin: 0x4000,
+                expected: 1,
+                found: 0
+            }),
+            memory.load_obj(&bytes[..bytes.len() - 2])
+        );
+        assert_eq!(0, memory.read(0x3000));
     }
 }
@@ -0,0 +1,66 @@
+//! `protection` : Memory can be split into regions with restricted permissions, e.g. to sandbox a program
+//! by making its code read-only and its data no-execute. Every address is readable, writable and executable by default.
+
+use std::ops::BitOr;
+
+/// `Perms` is a set of permissions (a bitflag), e.g. `Perms::READ | Perms::EXECUTE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Perms(u8);
+
+impl Perms {
+    pub const NONE: Perms = Perms(0);
+    pub const READ: Perms = Perms(1);
+    pub const WRITE: Perms = Perms(1 << 1);
+    pub const EXECUTE: Perms = Perms(1 << 2);
+    pub const ALL: Perms = Perms(0b111);
+
+    /// `contains` returns `true` if every permission in `other` is also in `self`.
+    pub fn contains(self, other: Perms) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// `allows` returns `true` if `access` is permitted.
+    pub fn allows(self, access: Access) -> bool {
+        self.contains(match access {
+            Access::Read => Perms::READ,
+            Access::Write => Perms::WRITE,
+            Access::Execute => Perms::EXECUTE,
+        })
+    }
+}
+
+impl Default for Perms {
+    fn default() -> Perms {
+        Perms::ALL
+    }
+}
+
+impl BitOr for Perms {
+    type Output = Perms;
+
+    fn bitor(self, other: Perms) -> Perms {
+        Perms(self.0 | other.0)
+    }
+}
+
+/// `Access` is the kind of memory access being checked: a load, a store or an instruction fetch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+#[cfg(test)]
+mod protection_test {
+    use super::*;
+    #[test]
+    fn perms_should_combine_as_flags() {
+        let perms = Perms::READ | Perms::EXECUTE;
+        assert!(perms.allows(Access::Read));
+        assert!(!perms.allows(Access::Write));
+        assert!(perms.allows(Access::Execute));
+        assert!(Perms::ALL.contains(perms));
+        assert!(!Perms::NONE.allows(Access::Read));
+    }
+}
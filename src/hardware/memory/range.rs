@@ -0,0 +1,98 @@
+//! `range` : A run of consecutive addresses which may wrap from xFFFF to x0000, for the APIs which work on regions
+//! of memory (protection, breakpoints and watchpoints over a range).
+
+use std::ops::RangeInclusive;
+
+/// `AddrRange` is the `len` addresses starting at `start`, wrapping from xFFFF to x0000.
+/// Ranges are ordered by `start`, then by `len`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddrRange {
+    pub start: u16,
+    pub len: u16,
+}
+
+impl AddrRange {
+    pub fn new(start: u16, len: u16) -> AddrRange {
+        AddrRange { start, len }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `last` is the last address of the range, or `None` if it is empty.
+    pub fn last(&self) -> Option<u16> {
+        self.len
+            .checked_sub(1)
+            .map(|offset| self.start.wrapping_add(offset))
+    }
+
+    /// `contains` tells whether `address` is in the range.
+    pub fn contains(&self, address: u16) -> bool {
+        address.wrapping_sub(self.start) < self.len
+    }
+
+    /// `iter` visits the addresses of the range in order, from `start`, wrapping from xFFFF to x0000.
+    pub fn iter(&self) -> impl Iterator<Item = u16> {
+        let start = self.start;
+        (0..self.len).map(move |offset| start.wrapping_add(offset))
+    }
+
+    /// `overlaps` tells whether the two ranges have an address in common.
+    pub fn overlaps(&self, other: &AddrRange) -> bool {
+        !self.is_empty()
+            && !other.is_empty()
+            && (self.contains(other.start) || other.contains(self.start))
+    }
+
+    /// `segments` splits the range into at most two inclusive ranges which do not wrap.
+    pub fn segments(&self) -> Vec<RangeInclusive<u16>> {
+        match self.last() {
+            None => Vec::new(),
+            Some(last) if last >= self.start => vec![self.start..=last],
+            Some(last) => vec![self.start..=0xFFFF, 0..=last],
+        }
+    }
+}
+
+#[cfg(test)]
+mod range_test {
+    use super::*;
+
+    #[test]
+    fn contains_should_respect_the_bounds() {
+        let range = AddrRange::new(0x3000, 0x10);
+        assert!(range.contains(0x3000));
+        assert!(range.contains(0x300F));
+        assert!(!range.contains(0x3010));
+        assert!(!range.contains(0x2FFF));
+        assert!(!AddrRange::new(0x3000, 0).contains(0x3000));
+        assert_eq!(Some(0x300F), range.last());
+    }
+
+    #[test]
+    fn iteration_should_wrap_around() {
+        let range = AddrRange::new(0xFFFE, 4);
+        assert_eq!(
+            vec![0xFFFE, 0xFFFF, 0x0000, 0x0001],
+            range.iter().collect::<Vec<u16>>()
+        );
+        assert!(range.contains(0x0001));
+        assert!(!range.contains(0x0002));
+        assert_eq!(vec![0xFFFE..=0xFFFF, 0..=1], range.segments());
+    }
+
+    #[test]
+    fn overlap_should_be_symmetric() {
+        let a = AddrRange::new(0x3000, 0x10);
+        let b = AddrRange::new(0x300F, 0x10);
+        let c = AddrRange::new(0x3010, 0x10);
+        assert!(a.overlaps(&b) && b.overlaps(&a));
+        assert!(!a.overlaps(&c) && !c.overlaps(&a));
+        let wrapping = AddrRange::new(0xFFF0, 0x3011);
+        assert!(wrapping.overlaps(&a));
+        assert!(!wrapping.overlaps(&c));
+        assert!(!a.overlaps(&AddrRange::new(0x3004, 0)));
+        assert!(a < c);
+    }
+}
@@ -8,6 +8,7 @@ use crate::hardware::register::Registers;
 /// - `FlPos` represents `Positive` value.
 /// - `FlZro` represents `Zero` value.
 /// - `FlNeg` represents `Negative` value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConditionFlag {
     /// `ConditionFlag::FlPos` represents `Positive`.
     FlPos, // = 1, Positive
@@ -31,18 +32,67 @@ impl ConditionFlag {
     }
 }
 
+/// `Condition` is the state of the condition codes once an instruction has set them: exactly one of N, Z and P.
+/// Registers hold a `Condition`, so a multi-bit (illegal) state cannot arise; the 3-bit `nzp` encoding
+/// (N = 4, Z = 2, P = 1) is only used where the bits are visible, i.e. in `Registers::get(9)` and in BR's nzp field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Negative,
+    Zero,
+    Positive,
+}
+
+impl Condition {
+    /// `of` returns the condition of `value`, read as a two's complement number.
+    pub fn of(value: u16) -> Condition {
+        if value == 0 {
+            Condition::Zero
+        } else if value >> 15 != 0 {
+            // a 1 in the left-most bit indicates negative
+            Condition::Negative
+        } else {
+            Condition::Positive
+        }
+    }
+
+    /// `bits` returns the `nzp` encoding of the condition.
+    pub fn bits(self) -> u16 {
+        match self {
+            Condition::Negative => ConditionFlag::get_flag_value(ConditionFlag::FlNeg),
+            Condition::Zero => ConditionFlag::get_flag_value(ConditionFlag::FlZro),
+            Condition::Positive => ConditionFlag::get_flag_value(ConditionFlag::FlPos),
+        }
+    }
+
+    /// `from_bits` returns the condition encoded by `bits`, or `None` unless exactly one of the 3 bits is set.
+    pub fn from_bits(bits: u16) -> Option<Condition> {
+        [Condition::Negative, Condition::Zero, Condition::Positive]
+            .iter()
+            .copied()
+            .find(|condition| condition.bits() == bits)
+    }
+
+    /// `matches` tells whether a BR with the `nzp` field would branch on this condition.
+    pub fn matches(self, nzp: u16) -> bool {
+        nzp & self.bits() != 0
+    }
+}
+
+impl From<ConditionFlag> for Condition {
+    fn from(flag: ConditionFlag) -> Condition {
+        match flag {
+            ConditionFlag::FlPos => Condition::Positive,
+            ConditionFlag::FlZro => Condition::Zero,
+            ConditionFlag::FlNeg => Condition::Negative,
+        }
+    }
+}
+
 /// The R_COND register stores condition flags which provide information
 /// about the most recently executed calculation.
 /// Any time a value is written to a register, we use  'update_r_cond_register' fn to update the flags to indicate its sign.
 pub fn update_r_cond_register(r: u16, registers: &mut Registers) {
-    if registers.get(r) == 0 {
-        registers.update(9, ConditionFlag::get_flag_value(ConditionFlag::FlZro));
-    } else if (registers.get(r) >> 15) != 0 {
-        // a 1 in the left-most bit indicates negative
-        registers.update(9, ConditionFlag::get_flag_value(ConditionFlag::FlNeg));
-    } else {
-        registers.update(9, ConditionFlag::get_flag_value(ConditionFlag::FlPos));
-    }
+    registers.r_cond = Some(Condition::of(registers.get(r)));
 }
 
 #[cfg(test)]
@@ -61,4 +111,31 @@ mod condition_flag_test {
     fn value_of_flneg_should_be_4() {
         assert_eq!(4, ConditionFlag::get_flag_value(ConditionFlag::FlNeg));
     }
+
+    #[test]
+    fn bits_should_round_trip_for_single_flags_only() {
+        for &condition in [Condition::Negative, Condition::Zero, Condition::Positive].iter() {
+            assert_eq!(Some(condition), Condition::from_bits(condition.bits()));
+        }
+        assert_eq!(None, Condition::from_bits(0));
+        assert_eq!(None, Condition::from_bits(0b110));
+        assert_eq!(None, Condition::from_bits(0b111));
+    }
+
+    #[test]
+    fn condition_of_a_value_should_follow_its_sign() {
+        assert_eq!(Condition::Zero, Condition::of(0));
+        assert_eq!(Condition::Positive, Condition::of(0x7FFF));
+        assert_eq!(Condition::Negative, Condition::of(0x8000));
+        assert_eq!(Condition::Negative, Condition::of(0xFFFF));
+    }
+
+    #[test]
+    fn br_field_should_match_the_tested_flags() {
+        for nzp in 0..8 {
+            assert_eq!(nzp & 0b100 != 0, Condition::Negative.matches(nzp));
+            assert_eq!(nzp & 0b010 != 0, Condition::Zero.matches(nzp));
+            assert_eq!(nzp & 0b001 != 0, Condition::Positive.matches(nzp));
+        }
+    }
 }
@@ -6,18 +6,66 @@
 //! and then storing the final results back in memory.
 /// `PC_START` sets initial value of the program counter (r_pc) = 0x3000.
 pub mod condition_flag;
+pub mod psr;
+
+use self::condition_flag::{Condition, ConditionFlag};
+use self::psr::Psr;
 
 pub const PC_START: u16 = 0x3000;
 
+/// `Radix` selects how `Registers::dump_in` shows the general purpose registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Radix {
+    /// `x00A5`
+    Hex,
+    /// Two's complement decimal: `-1` for xFFFF.
+    Signed,
+    /// `65535` for xFFFF.
+    Unsigned,
+    /// Grouped in nibbles: `1010 0101 1010 0101`.
+    Binary,
+}
+
+impl Radix {
+    /// `parse` reads the name of a radix, as the monitor's `regs` command takes it:
+    /// `hex`, `dec` (signed), `udec` or `bin`.
+    pub fn parse(name: &str) -> Option<Radix> {
+        match name {
+            "hex" => Some(Radix::Hex),
+            "dec" => Some(Radix::Signed),
+            "udec" => Some(Radix::Unsigned),
+            "bin" => Some(Radix::Binary),
+            _ => None,
+        }
+    }
+
+    /// `format` renders `value` in this radix, padded so that every value has the same width.
+    pub fn format(self, value: u16) -> String {
+        match self {
+            Radix::Hex => format!("x{:04X}", value),
+            Radix::Signed => format!("{:>6}", value as i16),
+            Radix::Unsigned => format!("{:>5}", value),
+            Radix::Binary => (0..4)
+                .rev()
+                .map(|nibble| format!("{:04b}", (value >> (nibble * 4)) & 0xF))
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
 /// The LC-3 has 10 total registers, each of which is 16 bits. Most of them are general purpose, but a few have designated roles.
 ///
 /// - 8 general purpose registers (R0-R7)
 /// - 1 program counter (PC) register
 /// - 1 condition flags (COND) register
+///
+/// Besides these, `r_ir` mirrors the instruction register (IR) of the datapath: it holds the last instruction fetched.
+///
 /// The general purpose registers can be used to perform any program calculations.
 /// The program counter is an unsigned integer which is the address of the next instruction in memory to execute.
 /// The condition flags tell us information about the previous calculation.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Registers {
     /// `r_00` is a general purpose register.
     pub r_00: u16, // general purpose register
@@ -35,10 +83,20 @@ pub struct Registers {
     pub r_06: u16, // general purpose register
     /// `r_07` is a general purpose register.
     pub r_07: u16, // general purpose register
-    /// `r_pc` is a register for program counter.
-    pub r_pc: u16, // program counter
+    /// `r_pc` is a register for program counter. It is private so that only the fetch moves it forward:
+    /// see `pc`, `set_pc` and `advance_pc`.
+    r_pc: u16, // program counter
     /// `r_cond` is a register to store cinformation about the previous calculation.
-    pub r_cond: u16, // condition flag
+    /// It is `None` until an instruction sets the condition codes; `get(9)` reads it as `nzp` bits.
+    pub r_cond: Option<Condition>, // condition flag
+    /// `r_ir` is the instruction register: the instruction being (or last) executed.
+    pub r_ir: u16, // instruction register
+}
+
+impl Default for Registers {
+    fn default() -> Registers {
+        Registers::new()
+    }
 }
 
 impl Registers {
@@ -56,10 +114,62 @@ impl Registers {
             r_06: 0,        // general purpose register
             r_07: 0,        // general purpose register
             r_pc: PC_START, // program counter
-            r_cond: 0,      // condition flag
+            r_cond: None,   // condition flag
+            r_ir: 0,        // instruction register
         }
     }
 
+    /// `from_spec` builds a register file from a whitespace-separated list of `NAME=value` entries,
+    /// e.g. `R0=x1234 R6=x6000 PC=x3000 CC=P`, for concise test setups and replays of a captured state.
+    /// The names are `R0` - `R7`, `PC`, `IR` and `CC` (whose value is `N`, `Z` or `P`); the other values are
+    /// hexadecimal with an `x` or `0x` prefix, or decimal with a `#` prefix (and may be negative).
+    /// The registers which are not listed keep their `new()` values. An entry which cannot be read is the error.
+    pub fn from_spec(spec: &str) -> Result<Registers, String> {
+        let mut registers = Registers::new();
+        for entry in spec.split_whitespace() {
+            let invalid = || entry.to_string();
+            let (name, value) = entry.split_once('=').ok_or_else(invalid)?;
+            let name = name.to_ascii_uppercase();
+            if name == "CC" {
+                let flag = match value.to_ascii_uppercase().as_str() {
+                    "N" => ConditionFlag::FlNeg,
+                    "Z" => ConditionFlag::FlZro,
+                    "P" => ConditionFlag::FlPos,
+                    _ => return Err(invalid()),
+                };
+                registers.set_condition(flag);
+                continue;
+            }
+            let value = spec_value(value).ok_or_else(invalid)?;
+            match name.as_str() {
+                "PC" => registers.r_pc = value,
+                "IR" => registers.r_ir = value,
+                r => match r.strip_prefix('R').and_then(|n| n.parse::<u16>().ok()) {
+                    Some(n) if n < 8 => registers.update(n, value),
+                    _ => return Err(invalid()),
+                },
+            }
+        }
+        Ok(registers)
+    }
+
+    /// `pc` is the program counter. While an instruction executes, the fetch has already advanced it,
+    /// so it is the address after the instruction: PC-relative offsets (BR, LD, JSR, ...) are added to that.
+    pub fn pc(&self) -> u16 {
+        self.r_pc
+    }
+
+    /// `set_pc` points the program counter at `address`, e.g. for a jump or to start a program.
+    pub fn set_pc(&mut self, address: u16) {
+        self.r_pc = address;
+    }
+
+    /// `advance_pc` moves the program counter to the next word, wrapping from xFFFF to x0000.
+    /// Only the fetch calls it (once per instruction), so instruction handlers must not advance PC again.
+    pub fn advance_pc(&mut self) {
+        self.r_pc = self.r_pc.wrapping_add(1);
+    }
+
     pub fn update(&mut self, index: u16, value: u16) {
         match index {
             0 => self.r_00 = value,
@@ -71,7 +181,14 @@ impl Registers {
             6 => self.r_06 = value,
             7 => self.r_07 = value,
             8 => self.r_pc = value,
-            9 => self.r_cond = value,
+            9 => {
+                self.r_cond = match value {
+                    0 => None,
+                    bits => {
+                        Some(Condition::from_bits(bits).expect("Exactly one of N, Z and P is set."))
+                    }
+                }
+            }
             _ => panic!("Inxed out of bound. "),
         }
     }
@@ -87,18 +204,249 @@ impl Registers {
             6 => self.r_06,
             7 => self.r_07,
             8 => self.r_pc,
-            9 => self.r_cond,
+            9 => self.r_cond.map_or(0, Condition::bits),
             _ => panic!("Inxed out of bound. "),
         }
     }
+
+    /// `gp_registers` returns a copy of R0 - R7, indexed by register number, e.g. to snapshot or compare them at once.
+    pub fn gp_registers(&self) -> [u16; 8] {
+        [
+            self.r_00, self.r_01, self.r_02, self.r_03, self.r_04, self.r_05, self.r_06, self.r_07,
+        ]
+    }
+
+    /// `set_gp_registers` sets R0 - R7 from `values`, indexed by register number. PC and the condition codes are kept.
+    pub fn set_gp_registers(&mut self, values: [u16; 8]) {
+        [
+            self.r_00, self.r_01, self.r_02, self.r_03, self.r_04, self.r_05, self.r_06, self.r_07,
+        ] = values;
+    }
+
+    /// `set_condition` sets `r_cond` to `flag`, clearing the other two flags.
+    pub fn set_condition(&mut self, flag: ConditionFlag) {
+        self.r_cond = Some(flag.into());
+    }
+
+    /// `is_negative` returns `true` if the N flag is set.
+    pub fn is_negative(&self) -> bool {
+        self.r_cond == Some(Condition::Negative)
+    }
+
+    /// `is_zero` returns `true` if the Z flag is set.
+    pub fn is_zero(&self) -> bool {
+        self.r_cond == Some(Condition::Zero)
+    }
+
+    /// `is_positive` returns `true` if the P flag is set.
+    pub fn is_positive(&self) -> bool {
+        self.r_cond == Some(Condition::Positive)
+    }
+
+    /// `psr` is the PSR of the machine: user mode, priority 0 and the condition codes (see `psr`).
+    pub fn psr(&self) -> Psr {
+        Psr::new(true, 0, self.r_cond)
+    }
+
+    /// `condition_code` returns the condition code as `N`, `Z` or `P`, or `-` while no flag is set.
+    pub fn condition_code(&self) -> char {
+        if self.is_negative() {
+            'N'
+        } else if self.is_zero() {
+            'Z'
+        } else if self.is_positive() {
+            'P'
+        } else {
+            '-'
+        }
+    }
+
+    /// `dump` renders the registers as the classic monitor grid: R0 - R7 in hex and (signed) decimal,
+    /// four per line, then PC, IR and the condition code.
+    ///
+    /// ```text
+    /// R0 x0005      5  R1 xFFFF     -1  R2 x0000      0  R3 x0000      0
+    /// R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x0000      0
+    /// PC x3001  IR x1025  CC P
+    /// ```
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for row in 0..2 {
+            let line: Vec<String> = (row * 4..row * 4 + 4)
+                .map(|r| format!("R{} x{:04X} {:>6}", r, self.get(r), self.get(r) as i16))
+                .collect();
+            dump.push_str(&line.join("  "));
+            dump.push('\n');
+        }
+        dump.push_str(&self.control_line());
+        dump
+    }
+
+    //the last line of a dump
+    fn control_line(&self) -> String {
+        format!(
+            "PC x{:04X}  IR x{:04X}  CC {}\n",
+            self.r_pc,
+            self.r_ir,
+            self.condition_code()
+        )
+    }
+
+    /// `dump_in` is `dump` with R0 - R7 shown in `radix` only, e.g. in binary to follow bit manipulation.
+    /// PC and IR stay in hex.
+    ///
+    /// ```text
+    /// R0 1010 0101 1010 0101  R1 0000 0000 0000 0000  R2 0000 0000 0000 0000  R3 0000 0000 0000 0000
+    /// ```
+    pub fn dump_in(&self, radix: Radix) -> String {
+        let mut dump = String::new();
+        for row in 0..2 {
+            let line: Vec<String> = (row * 4..row * 4 + 4)
+                .map(|r| format!("R{} {}", r, radix.format(self.get(r))))
+                .collect();
+            dump.push_str(&line.join("  "));
+            dump.push('\n');
+        }
+        dump.push_str(&self.control_line());
+        dump
+    }
+}
+
+//a value of `Registers::from_spec`: `x1234`, `0x1234` or `#-5`
+fn spec_value(text: &str) -> Option<u16> {
+    if let Some(decimal) = text.strip_prefix('#') {
+        return decimal
+            .parse::<i32>()
+            .ok()
+            .filter(|v| (i16::MIN as i32..=u16::MAX as i32).contains(v))
+            .map(|v| v as u16);
+    }
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix('x'))?;
+    u16::from_str_radix(hex, 16).ok()
 }
 
 #[cfg(test)]
 mod registers_test {
     use super::*;
+    #[test]
+    fn gp_registers_should_round_trip_and_agree_with_get() {
+        let mut registers = Registers::new();
+        let values = [1, 2, 3, 0xFFFF, 5, 6, 0x8000, 0x3001];
+        registers.set_gp_registers(values);
+        assert_eq!(values, registers.gp_registers());
+        for (r, &value) in values.iter().enumerate() {
+            assert_eq!(value, registers.get(r as u16));
+        }
+        assert_eq!(0x3000, registers.r_pc);
+        registers.update(4, 44);
+        assert_eq!(44, registers.gp_registers()[4]);
+    }
+
     #[test]
     fn value_of_r_pc_in_a_new_register_should_be_0x3000() {
         let registers = Registers::new();
         assert_eq!(0x3000, registers.r_pc);
     }
+
+    #[test]
+    fn advance_pc_should_wrap_at_0xffff() {
+        let mut registers = Registers::new();
+        registers.advance_pc();
+        assert_eq!(0x3001, registers.pc());
+        registers.set_pc(0xFFFF);
+        registers.advance_pc();
+        assert_eq!(0x0000, registers.pc());
+    }
+
+    #[test]
+    fn from_spec_should_set_the_listed_registers() {
+        let registers =
+            Registers::from_spec("R0=x1234 R6=x6000 r7=#-1 PC=x4000 IR=0xF025 CC=P").unwrap();
+        assert_eq!(0x1234, registers.r_00);
+        assert_eq!(0x6000, registers.r_06);
+        assert_eq!(0xFFFF, registers.r_07);
+        assert_eq!(0, registers.r_01);
+        assert_eq!(0x4000, registers.pc());
+        assert_eq!(0xF025, registers.r_ir);
+        assert!(registers.is_positive());
+        assert_eq!(0x3000, Registers::from_spec("").unwrap().pc());
+        assert_eq!(
+            Err("R8=x0001".to_string()),
+            Registers::from_spec("R0=x1 R8=x0001")
+        );
+        assert_eq!(Err("CC=Q".to_string()), Registers::from_spec("CC=Q"));
+    }
+
+    #[test]
+    fn clone_should_be_equal_and_independent() {
+        let registers = Registers::new();
+        let mut copy = registers.clone();
+        assert_eq!(registers, copy);
+        copy.update(3, 42);
+        assert_ne!(registers, copy);
+        assert_eq!(0, registers.r_03);
+    }
+
+    #[test]
+    fn exactly_one_predicate_should_hold_after_set_condition() {
+        let mut registers = Registers::new();
+        registers.set_condition(ConditionFlag::FlNeg);
+        assert_eq!(
+            (true, false, false),
+            (
+                registers.is_negative(),
+                registers.is_zero(),
+                registers.is_positive()
+            )
+        );
+        registers.set_condition(ConditionFlag::FlZro);
+        assert_eq!(
+            (false, true, false),
+            (
+                registers.is_negative(),
+                registers.is_zero(),
+                registers.is_positive()
+            )
+        );
+        registers.set_condition(ConditionFlag::FlPos);
+        assert_eq!(
+            (false, false, true),
+            (
+                registers.is_negative(),
+                registers.is_zero(),
+                registers.is_positive()
+            )
+        );
+    }
+
+    #[test]
+    fn dump_should_show_hex_and_signed_decimal() {
+        let mut registers = Registers::new();
+        registers.update(0, 5);
+        registers.update(1, 0xFFFF);
+        registers.update(7, 0x8000);
+        registers.r_pc = 0x3001;
+        registers.r_ir = 0x1025;
+        registers.set_condition(ConditionFlag::FlNeg);
+        assert_eq!(
+            "R0 x0005      5  R1 xFFFF     -1  R2 x0000      0  R3 x0000      0\n\
+             R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x8000 -32768\n\
+             PC x3001  IR x1025  CC N\n",
+            registers.dump()
+        );
+    }
+
+    #[test]
+    fn radix_should_group_binary_in_nibbles_and_sign_decimal() {
+        assert_eq!("1010 0101 1010 0101", Radix::Binary.format(0xA5A5));
+        assert_eq!("    -1", Radix::Signed.format(0xFFFF));
+        assert_eq!("65535", Radix::Unsigned.format(0xFFFF));
+        assert_eq!("xA5A5", Radix::Hex.format(0xA5A5));
+
+        let mut registers = Registers::new();
+        registers.update(0, 0xA5A5);
+        let dump = registers.dump_in(Radix::Binary);
+        assert!(dump.starts_with("R0 1010 0101 1010 0101  R1 0000 0000 0000 0000  "));
+        assert_eq!(3, dump.lines().count());
+    }
 }
@@ -0,0 +1,105 @@
+//! `psr` : The processor status register. Bit [15] is the privilege mode (1 = user, 0 = supervisor),
+//! bits [10:8] the priority level (0 - 7) and bits [2:0] the condition codes N, Z and P; the other bits are 0.
+//! The VM always runs at priority 0, and in user mode apart from the privilege mode exception handler
+//! (see `vm::interrupt`), so `Registers::psr` is that of a user program, but a PSR read from elsewhere
+//! (e.g. a core dump) can hold any of them.
+
+use super::condition_flag::Condition;
+use std::fmt;
+
+const USER: u16 = 1 << 15;
+const PRIORITY_SHIFT: u16 = 8;
+const PRIORITY: u16 = 0x7 << PRIORITY_SHIFT;
+const CONDITION: u16 = 0x7;
+
+/// `Psr` is a PSR value, with typed access to its fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Psr(u16);
+
+impl Psr {
+    /// `new` builds a PSR from its fields. Panics if `priority` is above 7.
+    pub fn new(user: bool, priority: u8, condition: Option<Condition>) -> Psr {
+        assert!(priority <= 7, "The priority level has 3 bits.");
+        let user = if user { USER } else { 0 };
+        Psr(user | (priority as u16) << PRIORITY_SHIFT | condition.map_or(0, Condition::bits))
+    }
+
+    /// `from_u16` reads a PSR word; the undefined bits are dropped.
+    pub fn from_u16(value: u16) -> Psr {
+        Psr(value & (USER | PRIORITY | CONDITION))
+    }
+
+    pub fn to_u16(self) -> u16 {
+        self.0
+    }
+
+    /// `is_user` tells whether bit [15] is set.
+    pub fn is_user(self) -> bool {
+        self.0 & USER != 0
+    }
+
+    /// `priority` is the priority level in bits [10:8].
+    pub fn priority(self) -> u8 {
+        ((self.0 & PRIORITY) >> PRIORITY_SHIFT) as u8
+    }
+
+    /// `condition_bits` are bits [2:0], as N = 4, Z = 2 and P = 1.
+    pub fn condition_bits(self) -> u16 {
+        self.0 & CONDITION
+    }
+
+    /// `condition` is the condition the bits [2:0] encode, or `None` unless exactly one of them is set.
+    pub fn condition(self) -> Option<Condition> {
+        Condition::from_bits(self.condition_bits())
+    }
+}
+
+/// A PSR is displayed as its fields, e.g. `x8002 (user, priority 0, CC Z)`.
+impl fmt::Display for Psr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cc = match self.condition() {
+            Some(Condition::Negative) => 'N',
+            Some(Condition::Zero) => 'Z',
+            Some(Condition::Positive) => 'P',
+            None => '-',
+        };
+        write!(
+            f,
+            "x{:04X} ({}, priority {}, CC {})",
+            self.0,
+            if self.is_user() { "user" } else { "supervisor" },
+            self.priority(),
+            cc
+        )
+    }
+}
+
+#[cfg(test)]
+mod psr_test {
+    use super::*;
+
+    #[test]
+    fn psr_should_round_trip_through_u16() {
+        for &value in [0x0000, 0x8002, 0x0704, 0x8301].iter() {
+            assert_eq!(value, Psr::from_u16(value).to_u16());
+        }
+        assert_eq!(0x8002, Psr::from_u16(0xF8FA).to_u16());
+    }
+
+    #[test]
+    fn psr_fields_should_decode() {
+        let psr = Psr::from_u16(0x8504);
+        assert!(psr.is_user());
+        assert_eq!(5, psr.priority());
+        assert_eq!(Some(Condition::Negative), psr.condition());
+        assert_eq!(psr, Psr::new(true, 5, Some(Condition::Negative)));
+        assert_eq!("x8504 (user, priority 5, CC N)", psr.to_string());
+
+        let psr = Psr::new(false, 7, None);
+        assert_eq!(0x0700, psr.to_u16());
+        assert!(!psr.is_user());
+        assert_eq!(None, psr.condition());
+        //two condition bits at once are not a condition
+        assert_eq!(None, Psr::from_u16(0x0006).condition());
+    }
+}
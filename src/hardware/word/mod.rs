@@ -0,0 +1,103 @@
+//! `Word` : Every register and memory cell of the LC-3 holds 16 bits, and all arithmetic on them is modulo 2^16.
+//! Widening a value to `u32` or `usize` in the middle of a computation (and forgetting to truncate it again)
+//! silently breaks that, e.g. for an address computed past xFFFF. `Word` can't be widened by accident:
+//! its operators always wrap at 16 bits, and conversion to `usize` (for indexing) has to be asked for with `index`.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// `Word` is a 16-bit LC-3 value whose arithmetic wraps around.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Word(pub u16);
+
+impl Word {
+    /// `sign_extend` turns the low `bit_count` bits of `field` (e.g. a PCoffset9) into a `Word`,
+    /// so that adding it to an address moves backwards when the field is negative.
+    pub fn sign_extend(field: u16, bit_count: u8) -> Word {
+        let field = field & ((1 << bit_count) - 1);
+        if (field >> (bit_count - 1)) & 1 != 0 {
+            Word(field | (0xFFFF << bit_count))
+        } else {
+            Word(field)
+        }
+    }
+
+    /// `index` converts the word into a `usize`, e.g. to index the memory cells.
+    pub fn index(self) -> usize {
+        usize::from(self.0)
+    }
+}
+
+impl From<u16> for Word {
+    fn from(value: u16) -> Word {
+        Word(value)
+    }
+}
+
+impl From<Word> for u16 {
+    fn from(word: Word) -> u16 {
+        word.0
+    }
+}
+
+impl Add for Word {
+    type Output = Word;
+
+    fn add(self, other: Word) -> Word {
+        Word(self.0.wrapping_add(other.0))
+    }
+}
+
+impl AddAssign for Word {
+    fn add_assign(&mut self, other: Word) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Word {
+    type Output = Word;
+
+    fn sub(self, other: Word) -> Word {
+        Word(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl SubAssign for Word {
+    fn sub_assign(&mut self, other: Word) {
+        *self = *self - other;
+    }
+}
+
+/// Words are displayed the LC-3 way, in hex: `x3000`.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x{:04X}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod word_test {
+    use super::*;
+    #[test]
+    fn arithmetic_should_wrap_at_16_bits() {
+        assert_eq!(Word(0x0001), Word(0xFFFF) + Word(2));
+        assert_eq!(Word(0xFFFF), Word(0) - Word(1));
+        let mut pc = Word(0xFFFF);
+        pc += Word(1);
+        assert_eq!(Word(0), pc);
+    }
+
+    #[test]
+    fn sign_extend_should_move_backwards_for_negative_fields() {
+        assert_eq!(Word(0x2FFF), Word(0x3000) + Word::sign_extend(0x1FF, 9));
+        assert_eq!(Word(0x3010), Word(0x3000) + Word::sign_extend(0x010, 9));
+        assert_eq!(Word(0xFFF0), Word::sign_extend(0x30, 5));
+    }
+
+    #[test]
+    fn index_should_be_the_only_way_to_usize() {
+        assert_eq!(65_535usize, Word(0xFFFF).index());
+        assert_eq!(0x3000u16, u16::from(Word(0x3000)));
+        assert_eq!("x00FF", Word(0xFF).to_string());
+    }
+}
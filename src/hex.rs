@@ -0,0 +1,223 @@
+//! `hex` : Programs are often passed around as plain text: whitespace-separated 4-digit hex words,
+//! with comments starting at `;`, e.g.
+//!
+//! ```text
+//! 1021 ; ADD R0, R0, #1
+//! F025 ; HALT
+//! ```
+//!
+//! `obj_to_text` and `text_to_obj` convert between `.obj` files and this format: the origin comes first,
+//! then one word per line, with its address in a comment.
+
+use std::error::Error;
+use std::fmt;
+
+/// `ParseError` is a malformed hex text, together with the (1-based) line the problem was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// `ParseErrorKind` lists the ways in which a hex text can be malformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The token is not a 4-digit hex word (an `x` or `0x` prefix is allowed).
+    InvalidWord(String),
+    /// The text has no words at all, so not even an origin.
+    MissingOrigin,
+}
+
+/// `LoadError` is a malformed or corrupted `.obj` image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoadError {
+    /// The image is too short to hold the origin.
+    MissingOrigin,
+    /// The image has an odd number of bytes, so its last word is incomplete.
+    OddLength(usize),
+    /// The loaded words do not have the expected checksum (see `Vm::load_obj_verified`).
+    ChecksumMismatch { expected: u64, actual: u64 },
+    /// The `len` words of the image, loaded at `origin`, would run past xFFFF instead of wrapping around to x0000.
+    ImageTooLarge { origin: u16, len: usize },
+    /// The block at `origin` of a multi-block image announced `expected` words, but only `found` follow
+    /// (see `Memory::load_obj`).
+    TruncatedBlock {
+        origin: u16,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::InvalidWord(token) => write!(
+                f,
+                "line {}: `{}` is not a 4-digit hex word",
+                self.line, token
+            ),
+            ParseErrorKind::MissingOrigin => write!(f, "line {}: missing origin", self.line),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::MissingOrigin => write!(f, "object file has no origin"),
+            LoadError::OddLength(len) => write!(f, "object file has an odd length ({} bytes)", len),
+            LoadError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "object file has checksum {:016X}, expected {:016X}",
+                actual, expected
+            ),
+            LoadError::ImageTooLarge { origin, len } => write!(
+                f,
+                "object file of {} word(s) at x{:04X} runs past the end of memory",
+                len, origin
+            ),
+            LoadError::TruncatedBlock {
+                origin,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block at x{:04X} has {} of its {} word(s)",
+                origin, found, expected
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// `parse_hex_words` parses every word of `text`, skipping blank lines and comments.
+pub fn parse_hex_words(text: &str) -> Result<Vec<u16>, ParseError> {
+    let mut words = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let code = line.split(';').next().unwrap_or_default();
+        for token in code.split_whitespace() {
+            words.push(parse_word(token).ok_or_else(|| ParseError {
+                line: i + 1,
+                kind: ParseErrorKind::InvalidWord(token.to_string()),
+            })?);
+        }
+    }
+    Ok(words)
+}
+
+/// `obj_to_text` renders an `.obj` image as hex text: the origin, then one word per line, with its address.
+///
+/// ```text
+/// 3000 ; origin
+/// 1021 ; x3000
+/// F025 ; x3001
+/// ```
+pub fn obj_to_text(bytes: &[u8]) -> Result<String, LoadError> {
+    let (origin, words) = obj_words(bytes)?;
+    let mut text = format!("{:04X} ; origin\n", origin);
+    for (i, word) in words.into_iter().enumerate() {
+        let address = origin.wrapping_add(i as u16);
+        text.push_str(&format!("{:04X} ; x{:04X}\n", word, address));
+    }
+    Ok(text)
+}
+
+/// `obj_words` splits a big-endian `.obj` image into its origin and its words.
+pub fn obj_words(bytes: &[u8]) -> Result<(u16, Vec<u16>), LoadError> {
+    if bytes.len() < 2 {
+        return Err(LoadError::MissingOrigin);
+    }
+    if !bytes.len().is_multiple_of(2) {
+        return Err(LoadError::OddLength(bytes.len()));
+    }
+    let mut words = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+    let origin = words.next().expect("The length was checked.");
+    Ok((origin, words.collect()))
+}
+
+/// `check_fits` makes sure `len` words loaded at `origin` stay below x10000.
+pub(crate) fn check_fits(origin: u16, len: usize) -> Result<(), LoadError> {
+    if origin as usize + len > 0x10000 {
+        return Err(LoadError::ImageTooLarge { origin, len });
+    }
+    Ok(())
+}
+
+/// `text_to_obj` turns hex text back into an `.obj` image. The first word is the origin;
+/// comments (such as the addresses written by `obj_to_text`) are ignored.
+pub fn text_to_obj(text: &str) -> Result<Vec<u8>, ParseError> {
+    let words = parse_hex_words(text)?;
+    if words.is_empty() {
+        return Err(ParseError {
+            line: text.lines().count().max(1),
+            kind: ParseErrorKind::MissingOrigin,
+        });
+    }
+    Ok(words.into_iter().flat_map(u16::to_be_bytes).collect())
+}
+
+fn parse_word(token: &str) -> Option<u16> {
+    let digits = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix('x'))
+        .or_else(|| token.strip_prefix('X'))
+        .unwrap_or(token);
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod hex_test {
+    use super::*;
+    #[test]
+    fn words_should_be_parsed_across_lines_and_comments() {
+        let words = parse_hex_words("\n1021 x1022 ; add\n\n  ; nothing here\n0xF025\n").unwrap();
+        assert_eq!(vec![0x1021, 0x1022, 0xF025], words);
+    }
+
+    #[test]
+    fn malformed_token_should_report_its_line() {
+        assert_eq!(
+            Err(ParseError {
+                line: 2,
+                kind: ParseErrorKind::InvalidWord("12345".to_string())
+            }),
+            parse_hex_words("1021\n12345")
+        );
+        assert!(parse_hex_words("G000").is_err());
+    }
+
+    #[test]
+    fn obj_should_round_trip_through_text() {
+        let obj = include_bytes!("../resources/os.obj");
+        let text = obj_to_text(obj).unwrap();
+        assert!(text.starts_with("0000 ; origin\n"));
+        assert_eq!(obj.to_vec(), text_to_obj(&text).unwrap());
+        assert_eq!(
+            "3000 ; origin\nF025 ; x3000\n",
+            obj_to_text(&[0x30, 0x00, 0xF0, 0x25]).unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_obj_and_text_should_be_rejected() {
+        assert_eq!(Err(LoadError::MissingOrigin), obj_to_text(&[0x30]));
+        assert_eq!(Err(LoadError::OddLength(3)), obj_to_text(&[0x30, 0, 1]));
+        assert_eq!(
+            Err(ParseError {
+                line: 1,
+                kind: ParseErrorKind::MissingOrigin
+            }),
+            text_to_obj("; nothing")
+        );
+    }
+}
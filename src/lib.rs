@@ -6,17 +6,27 @@
 //! It has a simplified instruction set compared to x86, but contains all the main ideas used in modern CPUs.
 //!
 //! - Run `lc3_vm` using cargo : cargo run resources/2048.obj
+//! - Debug a program in the interactive monitor : cargo run -- --interactive resources/2048.obj
+//! - Feed the keyboard from a file : cargo run -- --input answers.txt program.obj
+//! - Show the final state (and memory x3000 - x300F) : cargo run -- --dump-on-halt=x3000:16 program.obj
+//! - Log what the VM does (see `logging`) : cargo run -- --log-level lc3_vm::trap=debug program.obj
 
+pub mod analysis;
+pub mod assembler;
 pub mod file;
+mod fnv;
 pub mod hardware;
+pub mod hex;
+pub mod logging;
+pub mod monitor;
 pub mod sys;
+pub mod vm;
 
 use self::hardware::memory::Memory;
-use std::env::Args;
 
 /// `handle_args` fn processes commandline arguments.
 /// If appropriate file path is found in the args, it reads the file into the memory.
-pub fn handle_args(mut args: Args) -> Result<Memory, &'static str> {
+pub fn handle_args<A: Iterator<Item = String>>(mut args: A) -> Result<Memory, &'static str> {
     //skip 0th element
     args.next();
     match args.next() {
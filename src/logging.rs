@@ -0,0 +1,240 @@
+//! `logging` : Diagnostics of the VM itself, as opposed to the instruction trace of the program.
+//! The VM logs through the `log` crate under per-area targets: `lc3_vm::decode` (every instruction, at `trace`),
+//! `lc3_vm::memory` (every store, at `trace`) and `lc3_vm::trap` (every TRAP, at `debug`).
+//! `Logger` prints the records a `LogFilter` lets through; the CLI installs one from `--log-level` or `RUST_LOG`
+//! (see `filter_from_args`).
+//!
+//! A filter is written like `RUST_LOG`: comma-separated directives, each a level (`debug`) which applies to every
+//! target, or `target=level` (`lc3_vm::trap=debug`) which applies to the targets starting with `target`.
+//! The most specific matching directive wins; without one, nothing is logged.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// `DECODE`, `MEMORY` and `TRAP` are the targets the VM logs under.
+pub const DECODE: &str = "lc3_vm::decode";
+pub const MEMORY: &str = "lc3_vm::memory";
+pub const TRAP: &str = "lc3_vm::trap";
+
+/// `LogFilter` decides which records are logged, per target.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    /// The level for targets no directive names.
+    default: Option<LevelFilter>,
+    /// (target prefix, level), longest prefix first.
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// `parse` reads a `RUST_LOG`-style filter; it returns `None` if a level is not one of `off`, `error`,
+    /// `warn`, `info`, `debug` or `trace`.
+    pub fn parse(spec: &str) -> Option<LogFilter> {
+        let mut filter = LogFilter::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => filter
+                    .directives
+                    .push((target.trim().to_string(), level.trim().parse().ok()?)),
+                None => filter.default = Some(directive.parse().ok()?),
+            }
+        }
+        filter
+            .directives
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Some(filter)
+    }
+
+    /// `level` is the most verbose level logged for `target`.
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|&(_, level)| level)
+            .or(self.default)
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    /// `max_level` is the most verbose level of any target, for `log::set_max_level`.
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|&(_, level)| level)
+            .chain(self.default)
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+}
+
+/// `filter_from_args` takes the `--log-level <filter>` option out of the command line `args` and parses its filter,
+/// or `rust_log` (the value of `RUST_LOG`) without the option. It is `Ok(None)` if neither is given, and the message
+/// the CLI exits with if the option has no filter or the filter is invalid.
+pub fn filter_from_args(
+    args: &mut Vec<String>,
+    rust_log: Option<String>,
+) -> Result<Option<LogFilter>, String> {
+    let spec = match args.iter().position(|arg| arg == "--log-level") {
+        Some(i) if i + 1 < args.len() => args.drain(i..i + 2).nth(1),
+        Some(_) => {
+            return Err(
+                "--log-level needs a filter, e.g. `debug` or `lc3_vm::trap=debug`".to_string(),
+            )
+        }
+        None => rust_log,
+    };
+    match spec {
+        Some(spec) => match LogFilter::parse(&spec) {
+            Some(filter) => Ok(Some(filter)),
+            None => Err(format!("invalid log filter `{}`", spec)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// `Logger` writes every record its filter lets through as a line `LEVEL target: message`.
+pub struct Logger<W> {
+    filter: LogFilter,
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> Logger<W> {
+    pub fn new(filter: LogFilter, out: W) -> Logger<W> {
+        Logger {
+            filter,
+            out: Mutex::new(out),
+        }
+    }
+
+    /// `install` makes this the logger of the process. It fails if a logger was installed before.
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        let max_level = self.filter.max_level();
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> Log for Logger<W> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let mut out = self.out.lock().expect("No logging thread panicked.");
+            //diagnostics must not take the VM down
+            let _ = writeln!(
+                out,
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self
+            .out
+            .lock()
+            .expect("No logging thread panicked.")
+            .flush();
+    }
+}
+
+#[cfg(test)]
+mod logging_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+    use std::sync::Arc;
+
+    #[test]
+    fn most_specific_directive_should_win() {
+        let filter = LogFilter::parse("warn, lc3_vm::trap=debug,lc3_vm=info").unwrap();
+        assert_eq!(LevelFilter::Debug, filter.level(TRAP));
+        assert_eq!(LevelFilter::Info, filter.level(DECODE));
+        assert_eq!(LevelFilter::Warn, filter.level("other"));
+        assert_eq!(LevelFilter::Debug, filter.max_level());
+        assert_eq!(LevelFilter::Off, LogFilter::parse("").unwrap().level(TRAP));
+        assert_eq!(None, LogFilter::parse("lc3_vm=loud"));
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn log_level_option_should_be_taken_out_of_the_args() {
+        let mut line = args("lc3_vm --log-level lc3_vm::decode=trace prog.obj");
+        let filter = filter_from_args(&mut line, Some("debug".to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(args("lc3_vm prog.obj"), line);
+        //the option wins over `RUST_LOG`
+        assert_eq!(LevelFilter::Trace, filter.level(DECODE));
+        assert_eq!(LevelFilter::Off, filter.level(TRAP));
+
+        let mut line = args("lc3_vm prog.obj");
+        let filter = filter_from_args(&mut line, Some("lc3_vm::trap=debug".to_string()));
+        assert_eq!(LevelFilter::Debug, filter.unwrap().unwrap().level(TRAP));
+        assert_eq!(Ok(None), filter_from_args(&mut line, None));
+        assert_eq!(args("lc3_vm prog.obj"), line);
+    }
+
+    #[test]
+    fn bad_log_level_should_be_an_error() {
+        assert_eq!(
+            Err("invalid log filter `lc3_vm::decode=loud`".to_string()),
+            filter_from_args(
+                &mut args("lc3_vm --log-level lc3_vm::decode=loud prog.obj"),
+                None
+            )
+        );
+        assert_eq!(
+            Err("invalid log filter `loud`".to_string()),
+            filter_from_args(&mut args("lc3_vm prog.obj"), Some("loud".to_string()))
+        );
+        assert!(filter_from_args(&mut args("lc3_vm prog.obj --log-level"), None).is_err());
+    }
+
+    //a `Write` which the test can still read after the logger took it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn verbose_level_should_log_decoded_instructions() {
+        //the only test which installs the process' logger
+        let out = Shared::default();
+        let filter = LogFilter::parse("lc3_vm::decode=trace,lc3_vm::trap=debug").unwrap();
+        Logger::new(filter, out.clone()).install().unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R0, R0, #1 ; HALT
+        vm.load_words(0x3000, &[0x1021, 0xF025]);
+        assert_eq!(RunResult::Halted, vm.run());
+        let log = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("TRACE lc3_vm::decode: x3000: x1021  ADD R0, R0, #1\n"));
+        assert!(log.contains("DEBUG lc3_vm::trap: TRAP x25 at x3001\n"));
+        assert!(!log.contains(MEMORY));
+
+        //the decode once path logs its instructions too
+        out.0.lock().unwrap().clear();
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R0, R0, #2 ; HALT
+        vm.load_words(0x3000, &[0x1022, 0xF025]);
+        assert_eq!(RunResult::Halted, vm.run_precompiled());
+        let log = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("TRACE lc3_vm::decode: x3000: x1022  ADD R0, R0, #2\n"));
+    }
+}
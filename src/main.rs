@@ -1,24 +1,79 @@
 extern crate signal_hook;
 
-use lc3_vm::hardware;
+use lc3_vm::hardware::io::{FileInputIo, Io, StdIo};
+use lc3_vm::hardware::memory::AddrRange;
+use lc3_vm::logging::{self, Logger};
+use lc3_vm::monitor::{self, Monitor};
 use lc3_vm::sys::terminal;
+use lc3_vm::vm::{RunResult, Vm};
 use std::env;
 use std::error::Error;
+use std::io;
 use std::process;
 
 fn main() -> Result<(), Box<dyn Error>> {
     //setup for interrupt handling.
     terminal::spawn_control_c_handler()?;
-    //handle command line arguments and process instructions
-    match lc3_vm::handle_args(env::args()) {
-        Ok(mem) => {
-            //execute program
-            hardware::execute_program(mem);
-            //restore terminal settings
-            terminal::restore_terminal_settings();
-            //return
-            Ok(())
+    //`--interactive [file]` starts the monitor instead of running the program
+    if env::args().nth(1).as_deref() == Some("--interactive") {
+        let mut monitor = Monitor::new(Vm::new());
+        let mut stdout = io::stdout();
+        if let Some(path) = env::args().nth(2) {
+            monitor.execute(&format!("load {}", path), &mut stdout)?;
+        }
+        //`StdIo` restores the terminal settings after every key press, so there is nothing to restore here
+        monitor.repl(io::stdin().lock(), stdout)?;
+        return Ok(());
+    }
+    let mut args: Vec<String> = env::args().collect();
+    //`--log-level <filter>` (or `RUST_LOG`) logs what the VM does to stderr, see `lc3_vm::logging`
+    match logging::filter_from_args(&mut args, env::var("RUST_LOG").ok()) {
+        Ok(Some(filter)) => Logger::new(filter, io::stderr()).install()?,
+        Ok(None) => {}
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1)
+        }
+    }
+    //`--input <file>` feeds the keyboard from the file; once it is consumed, GETC and IN read x0000 (see `EofPolicy`)
+    let input = match args.iter().position(|arg| arg == "--input") {
+        Some(i) if i + 1 < args.len() => Some(args.drain(i..i + 2).nth(1).expect("Drained.")),
+        Some(_) => {
+            eprintln!("--input needs a file");
+            process::exit(1)
         }
+        None => None,
+    };
+    //`--dump-on-halt[=<addr>:<len>]` prints the final state (and that memory region) when the program stops
+    let dump = match args
+        .iter()
+        .position(|arg| arg.starts_with("--dump-on-halt"))
+    {
+        Some(i) => match args.remove(i).strip_prefix("--dump-on-halt") {
+            Some("") => Some(None),
+            Some(region) => match region.strip_prefix('=').and_then(monitor::parse_region) {
+                Some(region) => Some(Some(region)),
+                None => {
+                    eprintln!("--dump-on-halt expects a region like `x3000:16`");
+                    process::exit(1)
+                }
+            },
+            None => None,
+        },
+        None => None,
+    };
+    //handle command line arguments and process instructions
+    match lc3_vm::handle_args(args.into_iter()) {
+        Ok(mem) => match input {
+            Some(path) => match FileInputIo::open(&path, StdIo::new()) {
+                Ok(io) => run(Vm::with_memory(mem, io), dump),
+                Err(e) => {
+                    eprintln!("cannot read `{}`: {}", path, e);
+                    process::exit(1)
+                }
+            },
+            None => run(Vm::with_memory(mem, StdIo::new()), dump),
+        },
         Err(_) => {
             //restore terminal settings
             terminal::restore_terminal_settings();
@@ -27,3 +82,21 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 }
+
+fn run<I: Io>(mut vm: Vm<I>, dump: Option<Option<AddrRange>>) -> Result<(), Box<dyn Error>> {
+    //execute program
+    let result = vm.run();
+    //restore terminal settings
+    terminal::restore_terminal_settings();
+    if let Some(region) = dump {
+        eprint!("\n{}", monitor::dump_state(&vm, region));
+    }
+    match result {
+        //return
+        RunResult::Error(e) => {
+            eprintln!("\n{}", e);
+            process::exit(1)
+        }
+        _ => Ok(()),
+    }
+}
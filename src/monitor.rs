@@ -0,0 +1,359 @@
+//! # Monitor - an interactive debugger.
+//! Like the monitors of the classic LC-3 simulators, a `Monitor` reads commands one line at a time
+//! and drives a `Vm` with them:
+//!
+//! - `step [n]` : execute `n` (default 1) instructions
+//! - `continue` : run until the machine halts or reaches a breakpoint
+//! - `break <addr>` : set a breakpoint
+//! - `regs [hex|dec|udec|bin]` : show the registers, R0 - R7 in hex and signed decimal or in the given radix
+//! - `mem <addr> [len]` : show (and disassemble, with the annotations) `len` (default 1) words of memory
+//! - `load <file>` : load an `.obj` file and restart at its origin
+//! - `reset` : restart the last loaded program
+//! - `quit` : leave the monitor
+//!
+//! Addresses are hexadecimal (`x3000`, `0x3000` or `3000`), or decimal with a `#` prefix.
+
+use crate::file;
+use crate::hardware::io::Io;
+use crate::hardware::memory::{AddrRange, Memory};
+use crate::hardware::register::Radix;
+use crate::vm::{RunResult, Vm};
+use std::io::{self, BufRead, Write};
+
+/// `PROMPT` is printed before each command is read.
+const PROMPT: &str = "(lc3) ";
+
+/// `Monitor` is an interactive debugger for a `Vm`.
+pub struct Monitor<I: Io> {
+    vm: Vm<I>,
+    /// The memory as it was right after the last `load`, for `reset`.
+    program: Memory,
+    /// The PC to start the program at, for `reset`.
+    entry: u16,
+}
+
+impl<I: Io> Monitor<I> {
+    /// `new` creates a monitor for `vm`. `reset` goes back to the memory and PC `vm` has now.
+    pub fn new(vm: Vm<I>) -> Monitor<I> {
+        Monitor {
+            program: vm.memory().clone(),
+            entry: vm.registers().pc(),
+            vm,
+        }
+    }
+
+    /// `vm` gives access to the machine being debugged.
+    pub fn vm(&self) -> &Vm<I> {
+        &self.vm
+    }
+
+    /// `repl` reads commands from `input` and writes their results to `output`, until `quit` or the end of `input`.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+        for line in input.lines() {
+            if !self.execute(&line?, &mut output)? {
+                return Ok(());
+            }
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// `execute` runs a single command, writing its result to `output`.
+    /// It returns `false` once the monitor should stop (`quit`).
+    pub fn execute<W: Write>(&mut self, command: &str, output: &mut W) -> io::Result<bool> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["q"] => return Ok(false),
+            ["step"] | ["s"] => self.step(1, output)?,
+            ["step", n] | ["s", n] => match n.parse() {
+                Ok(n) => self.step(n, output)?,
+                Err(_) => writeln!(output, "invalid count `{}`", n)?,
+            },
+            ["continue"] | ["c"] => self.continue_execution(output)?,
+            ["break", address] | ["b", address] => match parse_address(address) {
+                Some(address) => {
+                    self.vm.add_breakpoint(address);
+                    writeln!(output, "breakpoint at x{:04X}", address)?;
+                }
+                None => writeln!(output, "invalid address `{}`", address)?,
+            },
+            ["regs"] | ["r"] => self.print_registers(output)?,
+            ["regs", radix] | ["r", radix] => match Radix::parse(radix) {
+                Some(radix) => write!(output, "{}", self.vm.registers().dump_in(radix))?,
+                None => writeln!(output, "invalid radix `{}` (hex, dec, udec or bin)", radix)?,
+            },
+            ["mem", address] | ["m", address] => self.print_memory(address, "1", output)?,
+            ["mem", address, len] | ["m", address, len] => {
+                self.print_memory(address, len, output)?
+            }
+            ["load", path] | ["l", path] => self.load(path, output)?,
+            ["reset"] => {
+                self.vm.reset(self.program.clone(), self.entry);
+                self.print_location(output)?;
+            }
+            ["help"] | ["h"] => writeln!(
+                output,
+                "commands: step [n], continue, break <addr>, regs [radix], mem <addr> [len], load <file>, reset, quit"
+            )?,
+            _ => writeln!(output, "unknown command `{}` (try `help`)", command.trim())?,
+        }
+        Ok(true)
+    }
+
+    fn step<W: Write>(&mut self, n: u64, output: &mut W) -> io::Result<()> {
+        for _ in 0..n {
+            if self.vm.is_halted() {
+                break;
+            }
+            if let Err(e) = self.vm.step() {
+                return writeln!(output, "error: {}", e);
+            }
+        }
+        self.print_location(output)
+    }
+
+    fn continue_execution<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        match self.vm.run() {
+            RunResult::Error(e) => return writeln!(output, "error: {}", e),
+            RunResult::Breakpoint(address) => writeln!(output, "breakpoint at x{:04X}", address)?,
+            RunResult::Watchpoint { addr, old, new } => writeln!(
+                output,
+                "watchpoint at x{:04X}: x{:04X} -> x{:04X}",
+                addr, old, new
+            )?,
+            RunResult::InfiniteLoop { pc } => writeln!(output, "infinite loop at x{:04X}", pc)?,
+            RunResult::PcOutOfBounds { pc } => {
+                writeln!(output, "PC left the program at x{:04X}", pc)?
+            }
+            RunResult::Halted
+            | RunResult::BudgetExhausted
+            | RunResult::Continue
+            | RunResult::Returned { .. }
+            | RunResult::IoTrap { .. }
+            | RunResult::TimedOut => {}
+        }
+        self.print_location(output)
+    }
+
+    fn load<W: Write>(&mut self, path: &str, output: &mut W) -> io::Result<()> {
+        match file::read_obj(path) {
+            Ok((origin, words)) => {
+                for (i, &word) in words.iter().enumerate() {
+                    self.program.write(origin as usize + i, word);
+                }
+                self.entry = origin;
+                self.vm.reset(self.program.clone(), origin);
+                writeln!(output, "loaded {} word(s) at x{:04X}", words.len(), origin)
+            }
+            Err(e) => writeln!(output, "error: cannot load `{}`: {}", path, e),
+        }
+    }
+
+    //the next instruction, or `halted`
+    fn print_location<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        if self.vm.is_halted() {
+            return writeln!(output, "halted");
+        }
+        self.print_word(self.vm.registers().pc(), output)
+    }
+
+    fn print_word<W: Write>(&self, address: u16, output: &mut W) -> io::Result<()> {
+        write!(
+            output,
+            "{}",
+            self.vm.disassemble_region(AddrRange::new(address, 1))
+        )
+    }
+
+    fn print_memory<W: Write>(&self, address: &str, len: &str, output: &mut W) -> io::Result<()> {
+        let address = match parse_address(address) {
+            Some(address) => address,
+            None => return writeln!(output, "invalid address `{}`", address),
+        };
+        let len: u16 = match len.parse() {
+            Ok(len) => len,
+            Err(_) => return writeln!(output, "invalid length `{}`", len),
+        };
+        write!(
+            output,
+            "{}",
+            self.vm.disassemble_region(AddrRange::new(address, len))
+        )
+    }
+
+    fn print_registers<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        write!(output, "{}", self.vm.registers().dump())
+    }
+}
+
+/// `dump_state` renders the state of `vm` as the monitor shows it: the registers (with the condition code),
+/// the number of instructions executed, and the words of `region`, disassembled.
+/// This is what the CLI's `--dump-on-halt` prints when the program stops.
+pub fn dump_state<I: Io>(vm: &Vm<I>, region: Option<AddrRange>) -> String {
+    let mut dump = vm.registers().dump();
+    dump.push_str(&format!(
+        "executed {} instruction(s)\n",
+        vm.steps_executed()
+    ));
+    if let Some(region) = region {
+        dump.push_str(&vm.disassemble_region(region));
+    }
+    dump
+}
+
+/// `parse_region` reads a memory region written as `<addr>:<len>`, e.g. `x3000:16`.
+/// The address is written as in the monitor's commands, the length is decimal.
+pub fn parse_region(text: &str) -> Option<AddrRange> {
+    let (address, len) = text.split_once(':')?;
+    Some(AddrRange::new(parse_address(address)?, len.parse().ok()?))
+}
+
+//`x3000`, `0x3000` and `3000` are hexadecimal, `#12288` is decimal
+fn parse_address(text: &str) -> Option<u16> {
+    if let Some(decimal) = text.strip_prefix('#') {
+        return decimal.parse().ok();
+    }
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('x'))
+        .or_else(|| text.strip_prefix('X'))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod monitor_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+
+    #[test]
+    fn scripted_session_should_report_the_final_state() {
+        // ADD R0, R0, #1 ; ADD R0, R0, #1 ; ADD R0, R0, #-3 ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0x1021, 0x103D, 0xF025]);
+        let mut monitor = Monitor::new(vm);
+        let script = "break x3002\ncontinue\nregs\nstep\nregs\nmem x3002 2\ncontinue\nquit\nregs\n";
+        let mut output = Vec::new();
+        monitor.repl(script.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            "(lc3) breakpoint at x3002\n\
+             (lc3) breakpoint at x3002\n\
+             x3002: x103D  ADD R0, R0, #-3\n\
+             (lc3) R0 x0002      2  R1 x0000      0  R2 x0000      0  R3 x0000      0\n\
+             R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x0000      0\n\
+             PC x3002  IR x1021  CC P\n\
+             (lc3) x3003: xF025  HALT\n\
+             (lc3) R0 xFFFF     -1  R1 x0000      0  R2 x0000      0  R3 x0000      0\n\
+             R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x0000      0\n\
+             PC x3003  IR x103D  CC N\n\
+             (lc3) x3002: x103D  ADD R0, R0, #-3\n\
+             x3003: xF025  HALT\n\
+             (lc3) halted\n\
+             (lc3) ",
+            String::from_utf8(output).unwrap()
+        );
+        assert!(monitor.vm().is_halted());
+        assert_eq!("", monitor.vm().io().output_string());
+    }
+
+    #[test]
+    fn reset_and_unknown_commands_should_be_reported() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF025]);
+        let mut monitor = Monitor::new(vm);
+        let mut output = Vec::new();
+        monitor.execute("continue", &mut output).unwrap();
+        monitor.execute("reset", &mut output).unwrap();
+        monitor.execute("frobnicate", &mut output).unwrap();
+        assert_eq!(
+            "halted\nx3000: xF025  HALT\nunknown command `frobnicate` (try `help`)\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert!(!monitor.vm().is_halted());
+    }
+
+    #[test]
+    fn errors_should_be_reported_readably() {
+        // the reserved opcode, twice
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xD000, 0xD000]);
+        let mut monitor = Monitor::new(vm);
+        let mut output = Vec::new();
+        monitor.execute("step", &mut output).unwrap();
+        monitor.execute("continue", &mut output).unwrap();
+        assert_eq!(
+            "error: illegal instruction xD000 at x3000\n\
+             error: illegal instruction xD000 at x3001\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_should_refuse_an_image_running_past_the_end_of_memory() {
+        let path =
+            std::env::temp_dir().join(format!("lc3_monitor_wrap_{}.obj", std::process::id()));
+        std::fs::write(&path, [0xFF, 0xFF, 0x12, 0x34, 0x56, 0x78]).unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF025]);
+        let mut monitor = Monitor::new(vm);
+        let mut output = Vec::new();
+        let command = format!("load {}", path.display());
+        monitor.execute(&command, &mut output).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            format!(
+                "error: cannot load `{}`: object file of 2 word(s) at xFFFF runs past the end of memory\n",
+                path.display()
+            ),
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(0, monitor.vm().peek(0x0000));
+        assert_eq!(0x3000, monitor.vm().registers().pc());
+    }
+
+    #[test]
+    fn dump_state_should_show_the_registers_count_and_region() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0xF025]);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(
+            "R0 x0001      1  R1 x0000      0  R2 x0000      0  R3 x0000      0\n\
+             R4 x0000      0  R5 x0000      0  R6 x0000      0  R7 x3002  12290\n\
+             PC x3002  IR xF025  CC P\n\
+             executed 2 instruction(s)\n\
+             x3000: x1021  ADD R0, R0, #1\n\
+             x3001: xF025  HALT\n",
+            dump_state(&vm, parse_region("x3000:2"))
+        );
+        assert!(dump_state(&vm, None).ends_with("executed 2 instruction(s)\n"));
+    }
+
+    #[test]
+    fn malformed_regions_should_be_rejected() {
+        assert_eq!(Some(AddrRange::new(0x4000, 16)), parse_region("0x4000:16"));
+        assert_eq!(None, parse_region("x4000"));
+        assert_eq!(None, parse_region("x4000:x10"));
+        assert_eq!(None, parse_region("zz:1"));
+    }
+
+    #[test]
+    fn regs_should_show_the_registers_in_the_given_radix() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.set_reg(1, 0xFFFF);
+        let mut monitor = Monitor::new(vm);
+        let mut output = Vec::new();
+        monitor.execute("regs udec", &mut output).unwrap();
+        monitor.execute("regs oct", &mut output).unwrap();
+        assert_eq!(
+            "R0     0  R1 65535  R2     0  R3     0\n\
+             R4     0  R5     0  R6     0  R7     0\n\
+             PC x3000  IR x0000  CC -\n\
+             invalid radix `oct` (hex, dec, udec or bin)\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
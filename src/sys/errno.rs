@@ -39,13 +39,13 @@ unsafe fn errno_location() -> *mut c_int {
 }
 
 /// Sets the platform-specific errno to no-error
-unsafe fn clear() -> () {
+unsafe fn clear() {
     *errno_location() = 0;
 }
 
 /// Returns the platform-specific value of errno
 pub fn errno() -> i32 {
-    unsafe { (*errno_location()) as i32 }
+    unsafe { *errno_location() }
 }
 
 impl Errno {
@@ -61,7 +61,12 @@ impl Errno {
         from_i32(err)
     }
 
-    pub unsafe fn clear() -> () {
+    /// Sets the platform-specific errno to no-error.
+    ///
+    /// # Safety
+    ///
+    /// Writes through the raw, thread-local errno pointer provided by libc.
+    pub unsafe fn clear() {
         clear()
     }
 
@@ -102,7 +107,7 @@ impl ErrnoSentinel for i64 {
 
 impl ErrnoSentinel for *mut c_void {
     fn sentinel() -> Self {
-        (-1 as isize) as *mut c_void
+        -1isize as *mut c_void
     }
 }
 
@@ -36,7 +36,7 @@ impl Error {
     /// Convert this `Error` to an [`Errno`](enum.Errno.html).
     ///
     pub fn as_errno(&self) -> Option<Errno> {
-        if let &Error::Sys(ref e) = self {
+        if let Error::Sys(e) = self {
             Some(*e)
         } else {
             None
@@ -14,11 +14,19 @@ use libc::FD_SETSIZE;
 #[allow(missing_debug_implementations)]
 pub struct FdSet(libc::fd_set);
 
+impl Default for FdSet {
+    fn default() -> FdSet {
+        FdSet::new()
+    }
+}
+
 impl FdSet {
     pub fn new() -> FdSet {
-	let mut fdset = unsafe { mem::MaybeUninit::uninit().assume_init() };
-        unsafe { libc::FD_ZERO(&mut fdset) };
-        FdSet(fdset)
+        let mut fdset = mem::MaybeUninit::<libc::fd_set>::uninit();
+        unsafe {
+            libc::FD_ZERO(fdset.as_mut_ptr());
+            FdSet(fdset.assume_init())
+        }
     }
 
     pub fn insert(&mut self, fd: RawFd) {
@@ -30,7 +38,7 @@ impl FdSet {
     }
 
     pub fn contains(&mut self, fd: RawFd) -> bool {
-        unsafe { libc::FD_ISSET(fd, &mut self.0) }
+        unsafe { libc::FD_ISSET(fd, &self.0) }
     }
 
     pub fn clear(&mut self) {
@@ -38,7 +38,7 @@ pub fn turn_off_canonical_and_echo_modes() {
 /// And exists the process with process code = 130 (as mentioned here, http://tldp.org/LDP/abs/html/exitcodes.html).
 pub fn spawn_control_c_handler() -> Result<(), Box<dyn Error>> {
     //setup for interrupt handling.
-    let signals = Signals::new(&[SIGINT])?;
+    let signals = Signals::new([SIGINT])?;
     thread::spawn(move || {
         for sig in signals.forever() {
             //Interrupt (Ctrl + C) is handled as follows...
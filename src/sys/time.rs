@@ -1,3 +1,6 @@
+// `time_t`, `suseconds_t` and `c_long` are not `i64` on every target, so the widening casts stay.
+#![allow(clippy::unnecessary_cast)]
+
 use libc::{c_long, timespec, timeval};
 use libc::{suseconds_t, time_t};
 use std::{cmp, fmt, ops};
@@ -54,10 +57,10 @@ const SECS_PER_MINUTE: i64 = 60;
 const SECS_PER_HOUR: i64 = 3600;
 
 #[cfg(target_pointer_width = "64")]
-const TS_MAX_SECONDS: i64 = (::std::i64::MAX / NANOS_PER_SEC) - 1;
+const TS_MAX_SECONDS: i64 = (i64::MAX / NANOS_PER_SEC) - 1;
 
 #[cfg(target_pointer_width = "32")]
-const TS_MAX_SECONDS: i64 = ::std::isize::MAX as i64;
+const TS_MAX_SECONDS: i64 = isize::MAX as i64;
 
 const TS_MIN_SECONDS: i64 = -TS_MAX_SECONDS;
 
@@ -108,7 +111,7 @@ impl TimeValLike for TimeSpec {
     #[inline]
     fn seconds(seconds: i64) -> TimeSpec {
         assert!(
-            seconds >= TS_MIN_SECONDS && seconds <= TS_MAX_SECONDS,
+            (TS_MIN_SECONDS..=TS_MAX_SECONDS).contains(&seconds),
             "TimeSpec out of bounds; seconds={}",
             seconds
         );
@@ -142,7 +145,7 @@ impl TimeValLike for TimeSpec {
     fn nanoseconds(nanoseconds: i64) -> TimeSpec {
         let (secs, nanos) = div_mod_floor_64(nanoseconds, NANOS_PER_SEC);
         assert!(
-            secs >= TS_MIN_SECONDS && secs <= TS_MAX_SECONDS,
+            (TS_MIN_SECONDS..=TS_MAX_SECONDS).contains(&secs),
             "TimeSpec out of bounds"
         );
         TimeSpec(timespec {
@@ -275,10 +278,10 @@ pub struct TimeVal(timeval);
 const MICROS_PER_SEC: i64 = 1_000_000;
 
 #[cfg(target_pointer_width = "64")]
-const TV_MAX_SECONDS: i64 = (::std::i64::MAX / MICROS_PER_SEC) - 1;
+const TV_MAX_SECONDS: i64 = (i64::MAX / MICROS_PER_SEC) - 1;
 
 #[cfg(target_pointer_width = "32")]
-const TV_MAX_SECONDS: i64 = ::std::isize::MAX as i64;
+const TV_MAX_SECONDS: i64 = isize::MAX as i64;
 
 const TV_MIN_SECONDS: i64 = -TV_MAX_SECONDS;
 
@@ -329,7 +332,7 @@ impl TimeValLike for TimeVal {
     #[inline]
     fn seconds(seconds: i64) -> TimeVal {
         assert!(
-            seconds >= TV_MIN_SECONDS && seconds <= TV_MAX_SECONDS,
+            (TV_MIN_SECONDS..=TV_MAX_SECONDS).contains(&seconds),
             "TimeVal out of bounds; seconds={}",
             seconds
         );
@@ -353,7 +356,7 @@ impl TimeValLike for TimeVal {
     fn microseconds(microseconds: i64) -> TimeVal {
         let (secs, micros) = div_mod_floor_64(microseconds, MICROS_PER_SEC);
         assert!(
-            secs >= TV_MIN_SECONDS && secs <= TV_MAX_SECONDS,
+            (TV_MIN_SECONDS..=TV_MAX_SECONDS).contains(&secs),
             "TimeVal out of bounds"
         );
         TimeVal(timeval {
@@ -369,7 +372,7 @@ impl TimeValLike for TimeVal {
         let microseconds = nanoseconds / 1000;
         let (secs, micros) = div_mod_floor_64(microseconds, MICROS_PER_SEC);
         assert!(
-            secs >= TV_MIN_SECONDS && secs <= TV_MAX_SECONDS,
+            (TV_MIN_SECONDS..=TV_MAX_SECONDS).contains(&secs),
             "TimeVal out of bounds"
         );
         TimeVal(timeval {
@@ -0,0 +1,105 @@
+//! `access_counts` : How often the program read and wrote every address, e.g. for a heatmap of memory traffic
+//! or to study the locality of a cache. Only the loads and stores of instructions (and trap routines) count:
+//! instruction fetches do not (see `Vm::enable_profiling` for those), nor do `peek`, `poke` and the loaders.
+
+/// `AccessCounts` holds a read and a write count per address (see `Vm::enable_access_counts`).
+/// Counts saturate at `u32::MAX`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessCounts {
+    reads: Box<[u32]>,
+    writes: Box<[u32]>,
+}
+
+impl Default for AccessCounts {
+    fn default() -> AccessCounts {
+        AccessCounts {
+            reads: vec![0; 0x10000].into_boxed_slice(),
+            writes: vec![0; 0x10000].into_boxed_slice(),
+        }
+    }
+}
+
+impl AccessCounts {
+    /// `reads` is the number of loads from `address`.
+    pub fn reads(&self, address: u16) -> u32 {
+        self.reads[address as usize]
+    }
+
+    /// `writes` is the number of stores to `address`.
+    pub fn writes(&self, address: u16) -> u32 {
+        self.writes[address as usize]
+    }
+
+    /// `accessed` visits every address which was read or written, in ascending order, as (address, reads, writes).
+    pub fn accessed(&self) -> impl Iterator<Item = (u16, u32, u32)> + '_ {
+        (0..=0xFFFF)
+            .map(move |address| (address, self.reads(address), self.writes(address)))
+            .filter(|&(_, reads, writes)| reads != 0 || writes != 0)
+    }
+
+    pub(crate) fn record_read(&mut self, address: u16) {
+        let count = &mut self.reads[address as usize];
+        *count = count.saturating_add(1);
+    }
+
+    pub(crate) fn record_write(&mut self, address: u16) {
+        let count = &mut self.writes[address as usize];
+        *count = count.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod access_counts_test {
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn access_counts_should_count_the_loads_and_stores_of_an_array_loop() {
+        let program = crate::assembler::assemble(
+            ".ORIG x3000
+                   AND R2, R2, #0
+                   ADD R2, R2, #2
+             PASS  LEA R1, ARRAY
+                   AND R3, R3, #0
+                   ADD R3, R3, #3
+             NEXT  LDR R4, R1, #0
+                   ADD R0, R0, R4
+                   ADD R1, R1, #1
+                   ADD R3, R3, #-1
+                   BRp NEXT
+                   ST R0, SUM
+                   ADD R2, R2, #-1
+                   BRp PASS
+                   HALT
+             ARRAY .FILL #1
+                   .FILL #2
+                   .FILL #3
+             SUM   .FILL #0
+                   .END",
+        )
+        .unwrap();
+        let array = program.symbols.get("ARRAY").unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        assert!(vm.access_counts().is_none());
+        vm.enable_access_counts();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(12, vm.peek(array + 3));
+        let counts = vm.access_counts().unwrap();
+        let memory: Vec<(u16, u32, u32)> = counts
+            .accessed()
+            .filter(|&(address, _, _)| address < 0xFE00)
+            .collect();
+        assert_eq!(
+            vec![
+                (array, 2, 0),
+                (array + 1, 2, 0),
+                (array + 2, 2, 0),
+                (array + 3, 0, 2)
+            ],
+            memory
+        );
+        //fetches do not count
+        assert_eq!(0, counts.reads(0x3000));
+    }
+}
@@ -0,0 +1,134 @@
+//! `bank` : Extended memory (the `extended-memory` feature).
+//! The LC-3 can only address 64K words, so extra storage is reached through a window:
+//! the addresses xC000 - xDFFF (8K words) can be paged to one of several extra 64K-word banks.
+//!
+//! The bank select register (BSR, xFE10) chooses what the window shows:
+//! bits [15:3] select the bank (modulo `BANK_COUNT`), and bits [2:0] select which 8K page of that bank.
+//! Bank 0 is the ordinary memory, so with BSR = 0 (its power-on value) the machine behaves like a plain LC-3.
+
+use std::collections::BTreeMap;
+
+/// `BANK_SELECT` is the address of the bank select register.
+pub(crate) const BANK_SELECT: u16 = 0xFE10;
+/// `WINDOW_START` is the first address of the window.
+pub(crate) const WINDOW_START: u16 = 0xC000;
+/// `WINDOW_SIZE` is the number of words in the window (and in a page).
+pub(crate) const WINDOW_SIZE: u16 = 0x2000;
+/// `BANK_COUNT` is the number of banks, including the ordinary memory (bank 0).
+pub(crate) const BANK_COUNT: u16 = 8;
+
+const BANK_SIZE: usize = 0x1_0000;
+
+/// `Banks` holds the extra banks; each one is only allocated once it is written to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Banks {
+    banks: BTreeMap<u16, Vec<u16>>,
+}
+
+impl Banks {
+    //the bank and the index in it that `address` maps to, if the window is paged to an extra bank
+    fn locate(select: u16, address: u16) -> Option<(u16, usize)> {
+        let bank = (select >> 3) % BANK_COUNT;
+        let page = (select & 0x7) as usize;
+        if bank == 0 || !(WINDOW_START..WINDOW_START + WINDOW_SIZE).contains(&address) {
+            return None;
+        }
+        Some((
+            bank,
+            page * WINDOW_SIZE as usize + (address - WINDOW_START) as usize,
+        ))
+    }
+
+    /// `read` returns the word at `address` if the bank register `select` pages it to an extra bank.
+    pub(crate) fn read(&self, select: u16, address: u16) -> Option<u16> {
+        let (bank, index) = Banks::locate(select, address)?;
+        Some(self.banks.get(&bank).map_or(0, |cells| cells[index]))
+    }
+
+    /// `write` stores `value` at `address` if the bank register `select` pages it to an extra bank,
+    /// returning `false` if `address` is ordinary memory.
+    pub(crate) fn write(&mut self, select: u16, address: u16, value: u16) -> bool {
+        match Banks::locate(select, address) {
+            Some((bank, index)) => {
+                self.banks.entry(bank).or_insert_with(|| vec![0; BANK_SIZE])[index] = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod bank_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::hardware::memory::{Bus, Memory};
+    use crate::vm::bus::SystemBus;
+    use crate::vm::Vm;
+
+    #[test]
+    fn banks_should_be_isolated_from_each_other_and_from_memory() {
+        let mut bus = SystemBus::new(Memory::new(), BufferIo::new());
+        bus.store(WINDOW_START, 0x0BAD).unwrap();
+        bus.store(BANK_SELECT, 1 << 3).unwrap();
+        assert_eq!(Ok(0), bus.load(WINDOW_START));
+        bus.store(WINDOW_START, 0x1111).unwrap();
+        bus.store(BANK_SELECT, 2 << 3).unwrap();
+        bus.store(WINDOW_START, 0x2222).unwrap();
+
+        bus.store(BANK_SELECT, 1 << 3).unwrap();
+        assert_eq!(Ok(0x1111), bus.load(WINDOW_START));
+        bus.store(BANK_SELECT, 2 << 3).unwrap();
+        assert_eq!(Ok(0x2222), bus.load(WINDOW_START));
+        bus.store(BANK_SELECT, 0).unwrap();
+        assert_eq!(Ok(0x0BAD), bus.load(WINDOW_START));
+        assert_eq!(0x0BAD, bus.memory.read(WINDOW_START));
+    }
+
+    #[test]
+    fn step_back_should_undo_a_store_into_a_bank() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                    LD R0, SELECT
+                    STI R0, BSR
+                    LD R2, WINDOW
+                    LD R3, VALUE
+                    STR R3, R2, #0
+                    HALT
+             SELECT .FILL x0008
+             BSR    .FILL xFE10
+             WINDOW .FILL xC000
+             VALUE  .FILL x1234
+                    .END",
+        )
+        .unwrap();
+        vm.enable_history(8);
+        vm.step_n(5);
+        assert_eq!(Ok(0x1234), vm.bus.load(WINDOW_START));
+        assert!(vm.step_back());
+        assert_eq!(0x3004, vm.registers().pc());
+        // LDR R4, R2, #0
+        vm.poke(0x3004, 0x6880);
+        vm.step().unwrap();
+        assert_eq!(0, vm.reg(4));
+        assert_eq!(1 << 3, vm.peek(BANK_SELECT));
+        assert_eq!(0, vm.bus.memory.read(WINDOW_START));
+    }
+
+    #[test]
+    fn pages_should_reach_the_whole_bank() {
+        let mut bus = SystemBus::new(Memory::new(), BufferIo::new());
+        bus.store(BANK_SELECT, 1 << 3).unwrap();
+        bus.store(WINDOW_START, 0x0A).unwrap();
+        bus.store(BANK_SELECT, 1 << 3 | 7).unwrap();
+        assert_eq!(Ok(0), bus.load(WINDOW_START));
+        bus.store(WINDOW_START + WINDOW_SIZE - 1, 0x7F).unwrap();
+        assert_eq!(Ok(0x7F), bus.load(WINDOW_START + WINDOW_SIZE - 1));
+        //addresses outside the window are never paged
+        bus.store(WINDOW_START - 1, 0x55).unwrap();
+        assert_eq!(0x55, bus.memory.read(WINDOW_START - 1));
+        bus.store(BANK_SELECT, 1 << 3).unwrap();
+        assert_eq!(Ok(0x0A), bus.load(WINDOW_START));
+    }
+}
@@ -0,0 +1,203 @@
+//! `binary_trace` : A compact binary form of the execution trace (see `vm::trace`), for very long runs.
+//!
+//! Each executed instruction becomes one record:
+//!
+//! - a varint holding the PC delta (the PC after the instruction minus the PC after the previous one,
+//!   starting from x0000, wrapping and zigzag-encoded) shifted left by 2, or-ed with the condition code
+//!   (0 for `-`, 1 for `N`, 2 for `Z` and 3 for `P`),
+//! - a byte with bit `n` set for every general purpose register Rn the instruction changed,
+//! - and a varint with the new value of each of these registers, R0 first.
+//!
+//! Varints are LEB128: 7 bits per byte, least significant first, with bit 7 set on every byte but the last.
+//! A straight-line instruction which changes one small register takes 3 bytes instead of ~25 bytes of text.
+//! `BinaryTraceReader` expands the records back into the text lines.
+
+use crate::hardware::register::Registers;
+use std::io::{self, ErrorKind, Read, Write};
+
+const CONDITION_CODES: [char; 4] = ['-', 'N', 'Z', 'P'];
+
+/// `BinaryTraceWriter` writes a record per executed instruction to `W`.
+#[derive(Debug)]
+pub struct BinaryTraceWriter<W: Write> {
+    writer: W,
+    pc: u16,
+}
+
+impl<W: Write> BinaryTraceWriter<W> {
+    pub fn new(writer: W) -> BinaryTraceWriter<W> {
+        BinaryTraceWriter { writer, pc: 0 }
+    }
+
+    /// `record` writes the record of the instruction which took the registers from `before` to `after`.
+    pub fn record(&mut self, before: &Registers, after: &Registers) -> io::Result<()> {
+        let delta = after.pc().wrapping_sub(self.pc) as i16;
+        let zigzag = ((delta << 1) ^ (delta >> 15)) as u16;
+        let cc = CONDITION_CODES
+            .iter()
+            .position(|&c| c == after.condition_code())
+            .expect("The condition code is one of - N Z P.") as u32;
+        let mut bytes = Vec::new();
+        push_varint(&mut bytes, u32::from(zigzag) << 2 | cc);
+        let changed: Vec<u16> = (0..8).filter(|&r| before.get(r) != after.get(r)).collect();
+        bytes.push(changed.iter().fold(0, |mask, &r| mask | 1 << r));
+        for &r in changed.iter() {
+            push_varint(&mut bytes, u32::from(after.get(r)));
+        }
+        self.pc = after.pc();
+        self.writer.write_all(&bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// `into_inner` returns the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// `BinaryTraceReader` reads the records written by a `BinaryTraceWriter`, as text trace lines.
+/// A trace which ends in the middle of a record gives an `UnexpectedEof` error.
+#[derive(Debug)]
+pub struct BinaryTraceReader<R: Read> {
+    reader: R,
+    pc: u16,
+}
+
+impl<R: Read> BinaryTraceReader<R> {
+    pub fn new(reader: R) -> BinaryTraceReader<R> {
+        BinaryTraceReader { reader, pc: 0 }
+    }
+
+    //the next byte, or `None` at the end of the trace
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        self.next_byte()?
+            .ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))
+    }
+
+    //a varint whose first byte has already been read
+    fn varint(&mut self, first: u8) -> io::Result<u32> {
+        let (mut value, mut shift, mut byte) = (0u32, 0, first);
+        loop {
+            if shift > 21 {
+                return Err(io::Error::new(ErrorKind::InvalidData, "varint is too long"));
+            }
+            value |= u32::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            byte = self.byte()?;
+        }
+    }
+
+    fn line(&mut self, first: u8) -> io::Result<String> {
+        let head = self.varint(first)?;
+        let zigzag = (head >> 2) as u16;
+        let delta = (zigzag >> 1) ^ (zigzag & 1).wrapping_neg();
+        self.pc = self.pc.wrapping_add(delta);
+        let mut line = format!("AFTER x{:04X}:", self.pc);
+        let mask = self.byte()?;
+        for r in (0..8).filter(|r| mask & 1 << r != 0) {
+            let first = self.byte()?;
+            let value = self.varint(first)?;
+            line.push_str(&format!(" R{}=x{:04X}", r, value));
+        }
+        line.push_str(&format!(" CC={}", CONDITION_CODES[(head & 0x3) as usize]));
+        Ok(line)
+    }
+}
+
+impl<R: Read> Iterator for BinaryTraceReader<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        match self.next_byte() {
+            Ok(Some(first)) => Some(self.line(first)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod binary_trace_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::lc3_instr;
+    use crate::vm::Vm;
+
+    #[test]
+    fn binary_trace_should_decode_to_the_text_trace() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        //a `Write` whose bytes stay readable after it was handed to the machine
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(LD R1, #4),
+                lc3_instr!(ADD R0, R0, #3),
+                lc3_instr!(ADD R0, R0, #-1),
+                lc3_instr!(BRp #-2),
+                lc3_instr!(NOT R2, R1),
+                0x1234,
+            ],
+        );
+        let bytes = Shared::default();
+        vm.enable_trace();
+        vm.attach_binary_trace_writer(Box::new(bytes.clone()));
+        for _ in 0..9 {
+            vm.step().unwrap();
+        }
+        vm.flush_trace_writer().unwrap();
+        let bytes = bytes.0.borrow().clone();
+        let text_len: usize = vm.trace().iter().map(|line| line.len() + 1).sum();
+        assert!(bytes.len() * 4 < text_len);
+        let decoded: Vec<String> = BinaryTraceReader::new(&bytes[..])
+            .collect::<io::Result<Vec<String>>>()
+            .unwrap();
+        assert_eq!(vm.trace(), &decoded[..]);
+        assert!(BinaryTraceReader::new(&bytes[..bytes.len() - 1])
+            .last()
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,650 @@
+//! `bus` : The system bus sits between the CPU and memory.
+//! Most addresses are plain memory, but the device register addresses (xFE00 - xFFFF)
+//! are connected to the console (the `Io`) and to the machine control register.
+//! Attached devices (see `hardware::device`) are consulted before both.
+
+use super::access_counts::AccessCounts;
+#[cfg(feature = "extended-memory")]
+use super::bank::{Banks, BANK_SELECT};
+use super::cache::CacheModel;
+use crate::hardware::device::Device;
+use crate::hardware::io::Io;
+use crate::hardware::memory::{Access, Bus, Memory, MemoryMappedReg};
+use crate::logging;
+use crate::vm::VmError;
+use std::ops::RangeInclusive;
+
+/// Bit [15] of a status register is its "ready" bit, and bit [15] of the MCR is the clock enable bit.
+const READY: u16 = 1 << 15;
+/// Bit [14] of the KBSR is its "interrupt enable" bit.
+const INTERRUPT_ENABLE: u16 = 1 << 14;
+
+/// `ReadOnlyPolicy` decides what a program store to a read-only device register does: the KBDR, the DSR
+/// and the ready bit [15] of the KBSR are set by the devices, so a program writing them is usually a bug.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadOnlyPolicy {
+    /// The store goes to memory like any other (the default).
+    #[default]
+    Allow,
+    /// The read-only bits keep their value; the writable bits of the KBSR are still written.
+    Ignore,
+    /// The store fails with `VmError::ReadOnlyWrite`, and nothing is written.
+    Error,
+}
+
+/// `EofPolicy` decides what reading the KBDR (a blocking read, like GETC and IN) gives once the input has ended,
+/// e.g. at the end of a `BufferIo` queue or of an `--input` file. Polling the KBSR is not affected: at the end of input
+/// it reports that no key is ready.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// The read gives this value, x0000 by default.
+    Value(u16),
+    /// The read fails with `VmError::InputExhausted`.
+    Error,
+}
+
+impl Default for EofPolicy {
+    fn default() -> EofPolicy {
+        EofPolicy::Value(0)
+    }
+}
+
+/// `SystemBus` owns the memory and the console devices of a `Vm`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SystemBus<I> {
+    pub(crate) memory: Memory,
+    pub(crate) io: I,
+    /// Attached devices, in the order they were attached.
+    devices: Devices,
+    /// The most bytes the display may receive, if limited.
+    output_limit: Option<usize>,
+    /// The bytes the display received since the limit was set.
+    output_len: usize,
+    /// The most keyboard polls without a key before input gives up, if limited.
+    input_poll_limit: Option<u32>,
+    /// The keyboard polls since the last key.
+    failed_polls: u32,
+    /// What a store to a read-only device register does.
+    read_only_policy: ReadOnlyPolicy,
+    /// What a read of the KBDR gives at the end of input.
+    eof_policy: EofPolicy,
+    /// Set when a read of the KBDR found the end of input under `EofPolicy::Error`.
+    input_exhausted: bool,
+    /// The (address, old value) of every store to memory, while logging (see `Vm::enable_history`).
+    pub(crate) write_log: Option<Vec<(u16, u16)>>,
+    /// The bytes read from the console since the log was started (for time travel), oldest first.
+    pub(crate) input_log: Option<Vec<u8>>,
+    /// The (address, old value) of every store to memory, while a speculation runs (see `Vm::clone_for_speculation`).
+    undo_log: Option<Vec<(u16, u16)>>,
+    /// Every byte sent to the display, while logging (see `Vm::enable_output_events`).
+    pub(crate) output_log: Option<Vec<u8>>,
+    /// A dirty bit per 256-word page, set by every store since the memory was put in.
+    touched_pages: [u64; 4],
+    /// The loads and stores per address, while counting (see `Vm::enable_access_counts`).
+    pub(crate) access_counts: Option<Box<AccessCounts>>,
+    /// The direct-mapped cache the loads and stores go through, while modelled (see `Vm::enable_cache`).
+    pub(crate) cache: Option<CacheModel>,
+    /// Extra memory banks, paged in through the bank select register.
+    #[cfg(feature = "extended-memory")]
+    banks: Banks,
+}
+
+impl<I: Io> SystemBus<I> {
+    pub(crate) fn new(memory: Memory, io: I) -> SystemBus<I> {
+        let mut bus = SystemBus {
+            memory,
+            io,
+            devices: Devices::default(),
+            output_limit: None,
+            output_len: 0,
+            input_poll_limit: None,
+            failed_polls: 0,
+            read_only_policy: ReadOnlyPolicy::default(),
+            eof_policy: EofPolicy::default(),
+            input_exhausted: false,
+            write_log: None,
+            input_log: None,
+            undo_log: None,
+            output_log: None,
+            touched_pages: [0; 4],
+            access_counts: None,
+            cache: None,
+            #[cfg(feature = "extended-memory")]
+            banks: Banks::default(),
+        };
+        bus.power_on();
+        bus
+    }
+
+    /// `replace_memory` swaps in `memory` and puts the device registers back in their power-on state.
+    pub(crate) fn replace_memory(&mut self, memory: Memory) {
+        self.memory = memory;
+        self.touched_pages = [0; 4];
+        self.power_on();
+    }
+
+    /// `attach_device` connects `device` to the addresses in `range`.
+    pub(crate) fn attach_device(&mut self, range: RangeInclusive<u16>, device: Box<dyn Device>) {
+        self.devices.0.push((range, device));
+    }
+
+    /// `set_output_limit` limits the display to `limit` more bytes (or lifts the limit, with `None`).
+    pub(crate) fn set_output_limit(&mut self, limit: Option<usize>) {
+        self.output_limit = limit;
+        self.output_len = 0;
+    }
+
+    /// `set_input_poll_limit` makes input give up after `limit` keyboard polls without a key (or wait, with `None`).
+    pub(crate) fn set_input_poll_limit(&mut self, limit: Option<u32>) {
+        self.input_poll_limit = limit;
+        self.failed_polls = 0;
+    }
+
+    /// `touched_pages` lists the 256-word pages (the high byte of their addresses) stored to, in ascending order.
+    pub(crate) fn touched_pages(&self) -> Vec<u8> {
+        (0..=255u8)
+            .filter(|&page| self.touched_pages[page as usize / 64] & (1 << (page % 64)) != 0)
+            .collect()
+    }
+
+    /// `restore` puts `old` back at `address`, in the extra bank the window shows if it is paged
+    /// (see `Vm::step_back`), without the side effects of a store.
+    pub(crate) fn restore(&mut self, address: u16, old: u16) {
+        #[cfg(feature = "extended-memory")]
+        if self
+            .banks
+            .write(self.memory.read(BANK_SELECT), address, old)
+        {
+            return;
+        }
+        self.memory.write(address as usize, old);
+    }
+
+    /// `set_eof_policy` chooses what a read of the KBDR gives at the end of input.
+    pub(crate) fn set_eof_policy(&mut self, policy: EofPolicy) {
+        self.eof_policy = policy;
+    }
+
+    /// `set_read_only_policy` chooses what a store to a read-only device register does.
+    pub(crate) fn set_read_only_policy(&mut self, policy: ReadOnlyPolicy) {
+        self.read_only_policy = policy;
+    }
+
+    //the value to store at `address` under the read-only policy, or `None` if nothing may be written
+    fn writable(&self, address: u16, value: u16) -> Result<Option<u16>, VmError> {
+        const KBSR: u16 = MemoryMappedReg::Kbsr as u16;
+        const KBDR: u16 = MemoryMappedReg::Kbdr as u16;
+        const DSR: u16 = MemoryMappedReg::Dsr as u16;
+        let current = self.memory.read(address);
+        let kept = match address {
+            KBSR if (value ^ current) & READY != 0 => (current & READY) | (value & !READY),
+            KBDR | DSR => current,
+            _ => return Ok(Some(value)),
+        };
+        match self.read_only_policy {
+            ReadOnlyPolicy::Allow => Ok(Some(value)),
+            ReadOnlyPolicy::Ignore if address == KBSR => Ok(Some(kept)),
+            ReadOnlyPolicy::Ignore => Ok(None),
+            ReadOnlyPolicy::Error => Err(VmError::ReadOnlyWrite { addr: address }),
+        }
+    }
+
+    //counts a keyboard poll which found no key, and gives up once the limit is reached
+    fn count_failed_poll(&mut self) -> Result<(), VmError> {
+        self.failed_polls += 1;
+        match self.input_poll_limit {
+            Some(limit) if self.failed_polls >= limit => {
+                self.failed_polls = 0;
+                Err(VmError::InputTimedOut { polls: limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //with a poll limit, the KBDR waits for a key by polling instead of blocking
+    fn poll_for_key(&mut self) -> Result<(), VmError> {
+        while !self.key_latched() {
+            self.poll_keyboard();
+            if !self.key_latched() {
+                self.count_failed_poll()?;
+            }
+        }
+        Ok(())
+    }
+
+    //the first attached device at `address` which answers the read
+    fn read_device(&mut self, address: u16) -> Option<u16> {
+        self.devices
+            .0
+            .iter_mut()
+            .filter(|(range, _)| range.contains(&address))
+            .find_map(|(_, device)| device.read(address))
+    }
+
+    //`true` if an attached device at `address` took the write
+    fn write_device(&mut self, address: u16, value: u16) -> bool {
+        self.devices
+            .0
+            .iter_mut()
+            .filter(|(range, _)| range.contains(&address))
+            .any(|(_, device)| device.write(address, value))
+    }
+
+    //clock running, no key latched
+    pub(crate) fn power_on(&mut self) {
+        self.memory.write(MemoryMappedReg::Mcr as usize, READY);
+        self.memory.write(MemoryMappedReg::Kbsr as usize, 0);
+        #[cfg(feature = "extended-memory")]
+        self.memory.write(BANK_SELECT as usize, 0);
+    }
+
+    /// `clock_enabled` returns `false` once bit [15] of the MCR has been cleared (e.g. by HALT).
+    pub(crate) fn clock_enabled(&self) -> bool {
+        self.memory.read(MemoryMappedReg::Mcr as u16) & READY != 0
+    }
+
+    /// `start_clock` sets bit [15] of the MCR again, so the machine runs after a HALT.
+    pub(crate) fn start_clock(&mut self) {
+        let mcr = self.memory.read(MemoryMappedReg::Mcr as u16);
+        self.memory
+            .write(MemoryMappedReg::Mcr as usize, mcr | READY);
+    }
+
+    /// `stop_clock` clears bit [15] of the MCR, halting the machine.
+    pub(crate) fn stop_clock(&mut self) {
+        let mcr = self.memory.read(MemoryMappedReg::Mcr as u16);
+        self.memory
+            .write(MemoryMappedReg::Mcr as usize, mcr & !READY);
+    }
+
+    /// `enable_key_interrupt` sets the interrupt enable bit of the KBSR.
+    pub(crate) fn enable_key_interrupt(&mut self) {
+        let kbsr = self.memory.read(MemoryMappedReg::Kbsr as u16);
+        self.memory
+            .write(MemoryMappedReg::Kbsr as usize, kbsr | INTERRUPT_ENABLE);
+    }
+
+    /// `key_interrupt_pending` latches the next input byte, if one is ready, and tells whether a key is latched
+    /// while the KBSR enables keyboard interrupts.
+    pub(crate) fn key_interrupt_pending(&mut self) -> bool {
+        if self.memory.read(MemoryMappedReg::Kbsr as u16) & INTERRUPT_ENABLE == 0 {
+            return false;
+        }
+        self.poll_keyboard();
+        self.key_latched()
+    }
+
+    fn key_latched(&self) -> bool {
+        self.memory.read(MemoryMappedReg::Kbsr as u16) & READY != 0
+    }
+
+    /// `poll_keyboard` latches the next input byte into the KBDR (and sets KBSR[15]) if one is ready.
+    fn poll_keyboard(&mut self) {
+        if !self.key_latched() && self.io.input_ready() {
+            if let Some(byte) = self.read_input() {
+                self.memory
+                    .write(MemoryMappedReg::Kbdr as usize, u16::from(byte));
+                let kbsr = self.memory.read(MemoryMappedReg::Kbsr as u16);
+                self.memory
+                    .write(MemoryMappedReg::Kbsr as usize, kbsr | READY);
+            }
+        }
+    }
+
+    //the next byte of the console, logged for time travel
+    fn read_input(&mut self) -> Option<u8> {
+        let byte = self.io.read_byte();
+        if let (Some(log), Some(byte)) = (self.input_log.as_mut(), byte) {
+            log.push(byte);
+        }
+        byte
+    }
+
+    /// `read_keyboard_data` hands out the latched key and clears KBSR[15] (the interrupt enable bit is kept).
+    /// Unlike the real hardware, reading the KBDR while no key is latched waits for the next key
+    /// (the end of input is read as the `EofPolicy` says), which is what lets GETC be a plain KBDR load.
+    fn read_keyboard_data(&mut self) -> u16 {
+        if !self.key_latched() {
+            let c = match (self.read_input(), self.eof_policy) {
+                (Some(byte), _) => u16::from(byte),
+                (None, EofPolicy::Value(value)) => value,
+                (None, EofPolicy::Error) => {
+                    self.input_exhausted = true;
+                    return self.memory.read(MemoryMappedReg::Kbdr as u16);
+                }
+            };
+            self.memory.write(MemoryMappedReg::Kbdr as usize, c);
+        }
+        let kbsr = self.memory.read(MemoryMappedReg::Kbsr as u16);
+        self.memory
+            .write(MemoryMappedReg::Kbsr as usize, kbsr & !READY);
+        self.memory.read(MemoryMappedReg::Kbdr as u16)
+    }
+
+    /// `read_word` reads `address`, with the side effects of the device registers, but without a permission check.
+    fn read_word(&mut self, address: u16) -> u16 {
+        const KBSR: u16 = MemoryMappedReg::Kbsr as u16;
+        const KBDR: u16 = MemoryMappedReg::Kbdr as u16;
+        const DSR: u16 = MemoryMappedReg::Dsr as u16;
+        if let Some(value) = self.read_device(address) {
+            return value;
+        }
+        match address {
+            KBSR => self.poll_keyboard(),
+            KBDR => return self.read_keyboard_data(),
+            //the display is always ready for the next character
+            DSR => return READY,
+            _ => {}
+        }
+        #[cfg(feature = "extended-memory")]
+        if let Some(value) = self.banks.read(self.memory.read(BANK_SELECT), address) {
+            return value;
+        }
+        self.memory.read(address)
+    }
+}
+
+impl<I: Io> Bus for SystemBus<I> {
+    fn load(&mut self, address: u16) -> Result<u16, VmError> {
+        self.memory.check(address, Access::Read)?;
+        if let Some(counts) = self.access_counts.as_mut() {
+            counts.record_read(address);
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.access(address);
+        }
+        const KBSR: u16 = MemoryMappedReg::Kbsr as u16;
+        const KBDR: u16 = MemoryMappedReg::Kbdr as u16;
+        match (address, self.input_poll_limit) {
+            (KBSR, Some(_)) => {
+                let kbsr = self.read_word(address);
+                if !self.key_latched() {
+                    self.count_failed_poll()?;
+                }
+                Ok(kbsr)
+            }
+            (KBDR, limit) => {
+                if limit.is_some() {
+                    self.poll_for_key()?;
+                }
+                self.failed_polls = 0;
+                let c = self.read_word(address);
+                if std::mem::take(&mut self.input_exhausted) {
+                    return Err(VmError::InputExhausted);
+                }
+                Ok(c)
+            }
+            _ => Ok(self.read_word(address)),
+        }
+    }
+
+    fn store(&mut self, address: u16, value: u16) -> Result<(), VmError> {
+        self.memory.check(address, Access::Write)?;
+        if let Some(counts) = self.access_counts.as_mut() {
+            counts.record_write(address);
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.access(address);
+        }
+        if self.write_device(address, value) {
+            return Ok(());
+        }
+        let value = match self.writable(address, value)? {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if address == MemoryMappedReg::Ddr as u16 {
+            if let Some(limit) = self.output_limit {
+                if self.output_len == limit {
+                    return Err(VmError::OutputLimitExceeded { limit });
+                }
+                self.output_len += 1;
+            }
+            self.io.write_byte(value as u8);
+            self.io.flush();
+            if let Some(log) = self.output_log.as_mut() {
+                log.push(value as u8);
+            }
+        }
+        #[cfg(feature = "extended-memory")]
+        {
+            let select = self.memory.read(BANK_SELECT);
+            if let Some(old) = self.banks.read(select, address) {
+                //the BSR is put back before this store is undone, so `restore` finds the same bank
+                if let Some(log) = self.write_log.as_mut() {
+                    log.push((address, old));
+                }
+                self.banks.write(select, address, value);
+                return Ok(());
+            }
+        }
+        if let Some(log) = self.write_log.as_mut() {
+            log.push((address, self.memory.read(address)));
+        }
+        if let Some(log) = self.undo_log.as_mut() {
+            log.push((address, self.memory.read(address)));
+        }
+        log::trace!(target: logging::MEMORY, "x{:04X} <- x{:04X}", address, value);
+        let page = address >> 8;
+        self.touched_pages[page as usize / 64] |= 1 << (page % 64);
+        self.memory.write(address as usize, value);
+        Ok(())
+    }
+
+    fn fetch(&mut self, address: u16) -> Result<u16, VmError> {
+        self.memory.check(address, Access::Execute)?;
+        Ok(self.read_word(address))
+    }
+}
+
+/// `BusCheckpoint` is the state of a `SystemBus` which `rollback` puts back, apart from the memory below the
+/// device registers: that is restored from the undo log instead.
+#[derive(Clone, Debug)]
+pub(crate) struct BusCheckpoint<I> {
+    io: I,
+    device_registers: Vec<u16>,
+    output_len: usize,
+    failed_polls: u32,
+    input_exhausted: bool,
+    touched_pages: [u64; 4],
+    access_counts: Option<Box<AccessCounts>>,
+    cache: Option<CacheModel>,
+    #[cfg(feature = "extended-memory")]
+    banks: Banks,
+}
+
+//the device register page, which the devices also write behind the bus' back
+const DEVICE_REGISTERS: std::ops::Range<usize> = 0xFE00..0x10000;
+
+impl<I: Io + Clone> SystemBus<I> {
+    /// `checkpoint` saves the state `rollback` goes back to, and starts logging stores.
+    pub(crate) fn checkpoint(&mut self) -> BusCheckpoint<I> {
+        self.undo_log = Some(Vec::new());
+        BusCheckpoint {
+            io: self.io.clone(),
+            device_registers: self.memory.as_slice()[DEVICE_REGISTERS].to_vec(),
+            output_len: self.output_len,
+            failed_polls: self.failed_polls,
+            input_exhausted: self.input_exhausted,
+            touched_pages: self.touched_pages,
+            //paused, like the rest of the instrumentation
+            access_counts: self.access_counts.take(),
+            cache: self.cache.take(),
+            #[cfg(feature = "extended-memory")]
+            banks: self.banks.clone(),
+        }
+    }
+
+    /// `rollback` undoes every store since the `checkpoint`, newest first, and puts the rest of its state back.
+    pub(crate) fn rollback(&mut self, checkpoint: BusCheckpoint<I>) {
+        for (address, old) in self.undo_log.take().unwrap_or_default().into_iter().rev() {
+            self.memory.write(address as usize, old);
+        }
+        self.memory.as_mut_slice()[DEVICE_REGISTERS].copy_from_slice(&checkpoint.device_registers);
+        self.io = checkpoint.io;
+        self.output_len = checkpoint.output_len;
+        self.failed_polls = checkpoint.failed_polls;
+        self.input_exhausted = checkpoint.input_exhausted;
+        self.touched_pages = checkpoint.touched_pages;
+        self.access_counts = checkpoint.access_counts;
+        self.cache = checkpoint.cache;
+        #[cfg(feature = "extended-memory")]
+        {
+            self.banks = checkpoint.banks;
+        }
+    }
+}
+
+/// `Devices` are the attached devices with their address ranges.
+/// Devices are not compared: two buses are equal if their memory and console are.
+#[derive(Clone, Debug, Default)]
+struct Devices(Vec<(RangeInclusive<u16>, Box<dyn Device>)>);
+
+impl PartialEq for Devices {
+    fn eq(&self, _: &Devices) -> bool {
+        true
+    }
+}
+
+impl Eq for Devices {}
+
+#[cfg(test)]
+mod bus_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn attached_device_should_answer_its_addresses() {
+        //counts the reads of its register, and can be reset by writing it
+        #[derive(Clone, Debug)]
+        struct Counter(u16);
+        impl Device for Counter {
+            fn read(&mut self, _: u16) -> Option<u16> {
+                self.0 += 1;
+                Some(self.0)
+            }
+            fn write(&mut self, _: u16, val: u16) -> bool {
+                self.0 = val;
+                true
+            }
+        }
+
+        // LDI R0, #3 ; LDI R1, #2 ; STI R0, #1 ; LDI R2, #0 ; .FILL xFE20
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xA003, 0xA202, 0xB001, 0xA400, 0xFE20]);
+        vm.attach_device(0xFE20..=0xFE20, Box::new(Counter(0)));
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(1, vm.registers().r_00);
+        assert_eq!(2, vm.registers().r_01);
+        assert_eq!(2, vm.registers().r_02);
+        //the store went to the device, not to memory
+        assert_eq!(0, vm.peek(0xFE20));
+    }
+
+    #[test]
+    fn output_limit_should_stop_a_runaway_puts() {
+        // LEA R0, #2 ; PUTS ; HALT ; then "ab" repeated, without a terminator
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xE002, 0xF022, 0xF025]);
+        for i in 0..100 {
+            vm.poke(0x3003 + i, u16::from(b"ab"[i as usize % 2]));
+        }
+        vm.set_max_output(Some(5));
+        assert_eq!(
+            RunResult::Error(VmError::OutputLimitExceeded { limit: 5 }),
+            vm.run()
+        );
+        assert_eq!("ababa", vm.io().output_string());
+        assert_eq!(0x3002, vm.registers().pc());
+    }
+
+    #[test]
+    fn store_to_the_dsr_should_follow_the_read_only_policy() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             STI R0, DSR
+             HALT
+         DSR .FILL xFE04
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.set_read_only_policy(ReadOnlyPolicy::Ignore);
+        vm.set_reg(0, 0x1234);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(0, vm.memory().read(0xFE04));
+
+        vm.load_program(&program);
+        vm.resume_at(0x3000);
+        vm.set_read_only_policy(ReadOnlyPolicy::Error);
+        assert_eq!(
+            RunResult::Error(VmError::ReadOnlyWrite { addr: 0xFE04 }),
+            vm.run()
+        );
+        assert_eq!(0x3001, vm.registers().pc());
+        assert_eq!(0, vm.memory().read(0xFE04));
+    }
+
+    #[test]
+    fn memory_pages_used_should_list_the_written_pages() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+                  ST R0, NEAR
+                  STI R0, FAR
+                  STI R0, FAR
+                  HALT
+             NEAR .FILL #0
+              FAR .FILL x40FF
+                  .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        assert!(vm.memory_pages_used().is_empty());
+        vm.run_for(3);
+        assert_eq!(vec![0x30, 0x40], vm.memory_pages_used());
+        vm.reset(Memory::new(), 0x3000);
+        assert!(vm.memory_pages_used().is_empty());
+    }
+
+    #[test]
+    fn getc_at_the_end_of_input_should_follow_the_eof_policy() {
+        // GETC ; GETC ; HALT
+        let words = [0xF020, 0xF020, 0xF025];
+        //x0000 by default
+        let mut vm = Vm::with_io(BufferIo::with_input("a"));
+        vm.load_words(0x3000, &words);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(0, vm.reg(0));
+
+        let mut vm = Vm::with_io(BufferIo::with_input("a"));
+        vm.load_words(0x3000, &words);
+        vm.set_eof_policy(EofPolicy::Value(0xFFFF));
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(0xFFFF, vm.reg(0));
+
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &words);
+        vm.set_eof_policy(EofPolicy::Error);
+        assert_eq!(RunResult::Error(VmError::InputExhausted), vm.run());
+        assert_eq!(0x3001, vm.registers().pc());
+        vm.io_mut().push_input(b"z");
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(u16::from(b'z'), vm.reg(0));
+    }
+
+    #[test]
+    fn kbsr_at_the_end_of_input_should_report_not_ready() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        // LDI R0, #1 ; HALT ; xFE00 (KBSR)
+        vm.load_words(0x3000, &[0xA001, 0xF025, 0xFE00]);
+        vm.set_eof_policy(EofPolicy::Error);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(0, vm.reg(0) & 0x8000);
+    }
+}
@@ -0,0 +1,125 @@
+//! `cache` : A direct-mapped data cache model, for teaching. It sits between the CPU and memory without holding
+//! any data of its own: it only keeps the tag of each line, to tell whether a load or store would have hit.
+//! The results of the program do not change, so the model can be switched on for any run.
+//! Like `access_counts`, only the loads and stores of instructions (and trap routines) go through it;
+//! instruction fetches do not, and neither do the device registers (xFE00 - xFFFF), which are never cached.
+
+/// `CacheStats` are the hits and misses of the cache model (see `Vm::enable_cache`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// `hit_rate` is the share of accesses which hit, between 0 and 1 (0 before the first access).
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            accesses => self.hits as f64 / accesses as f64,
+        }
+    }
+}
+
+/// `CacheModel` is a direct-mapped cache of `lines` lines of `line_words` words each.
+/// A store allocates its line, like a load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CacheModel {
+    line_words: u16,
+    //the tag held by every line, `None` until the line is first filled
+    tags: Vec<Option<u16>>,
+    stats: CacheStats,
+}
+
+//the first device register, above which nothing is cached
+const UNCACHED: u16 = 0xFE00;
+
+impl CacheModel {
+    /// `new` creates an empty cache. Panics if `lines` or `line_words` is zero.
+    pub(crate) fn new(lines: u16, line_words: u16) -> CacheModel {
+        assert!(
+            lines > 0 && line_words > 0,
+            "A cache needs at least one line of at least one word."
+        );
+        CacheModel {
+            line_words,
+            tags: vec![None; lines as usize],
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// `access` looks `address` up, counts a hit or a miss, and fills its line on a miss.
+    pub(crate) fn access(&mut self, address: u16) {
+        if address >= UNCACHED {
+            return;
+        }
+        let block = address / self.line_words;
+        let index = (block as usize) % self.tags.len();
+        let tag = block / self.tags.len() as u16;
+        if self.tags[index] == Some(tag) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            self.tags[index] = Some(tag);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod cache_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn cache_should_hit_more_often_with_good_locality() {
+        //16 loads from x4000, `stride` words apart
+        let stats = |stride: u16| {
+            let mut vm = Vm::with_io(BufferIo::new());
+            vm.assemble_and_load(&format!(
+                ".ORIG x3000
+                        LD R2, BASE
+                        LD R3, COUNT
+                        LD R4, STRIDE
+                 NEXT   LDR R1, R2, #0
+                        ADD R2, R2, R4
+                        ADD R3, R3, #-1
+                        BRp NEXT
+                        HALT
+                 BASE   .FILL x4000
+                 COUNT  .FILL #16
+                 STRIDE .FILL #{}
+                        .END",
+                stride
+            ))
+            .unwrap();
+            vm.enable_cache(4, 4);
+            assert_eq!(RunResult::Halted, vm.run());
+            vm.cache_stats().unwrap()
+        };
+        //the three constants share a line, then the array misses once per line of 4 words
+        let sequential = stats(1);
+        assert_eq!(
+            CacheStats {
+                hits: 14,
+                misses: 5
+            },
+            sequential
+        );
+        //every load of the array maps to the same line, with a new tag
+        let strided = stats(16);
+        assert_eq!(
+            CacheStats {
+                hits: 2,
+                misses: 17
+            },
+            strided
+        );
+        assert!(sequential.hit_rate() > strided.hit_rate());
+        assert!(Vm::with_io(BufferIo::new()).cache_stats().is_none());
+    }
+}
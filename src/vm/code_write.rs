@@ -0,0 +1,68 @@
+//! `code_write` : Spots a store into the instruction which is fetched next, e.g. an ST whose label is one line off
+//! and lands on the instruction right after it. The instruction then runs with the stored value, not the one in
+//! the listing, which is very confusing to debug; other self-modifying code is not reported.
+
+/// `ImminentCodeWrite` is a store at `pc` to `addr`, the address of the very next instruction it fetched:
+/// that instruction changed from `old` to `new` just before it was executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImminentCodeWrite {
+    pub pc: u16,
+    pub addr: u16,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// `check` returns the writes of `writes` (address, old value) to `next_pc`, which hold `new` now.
+/// Stores do not branch, so the instruction which made them is the one before `next_pc`.
+pub(crate) fn check(next_pc: u16, writes: &[(u16, u16)], new: u16) -> Vec<ImminentCodeWrite> {
+    writes
+        .iter()
+        .filter(|&&(addr, _)| addr == next_pc)
+        .map(|&(addr, old)| ImminentCodeWrite {
+            pc: next_pc.wrapping_sub(1),
+            addr,
+            old,
+            new,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod code_write_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn store_into_the_next_instruction_should_warn() {
+        //the ST overwrites the ADD #1 right after it with an ADD #2
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   LD R1, NEW
+                   ST R1, NEXT
+             NEXT  ADD R0, R0, #1
+                   ST R1, DONE
+             DONE  HALT
+             NEW   ADD R0, R0, #2
+                   .END",
+        )
+        .unwrap();
+        vm.warn_imminent_code_write(true);
+        vm.step_n(3);
+        assert_eq!(
+            &[ImminentCodeWrite {
+                pc: 0x3001,
+                addr: 0x3002,
+                old: 0x1021,
+                new: 0x1022
+            }][..],
+            vm.imminent_code_writes()
+        );
+        //the stored instruction ran
+        assert_eq!(2, vm.reg(0));
+        //the second store, over the HALT, is caught too
+        vm.step().unwrap();
+        assert_eq!(2, vm.imminent_code_writes().len());
+    }
+}
@@ -0,0 +1,174 @@
+//! `convention` : Checks the calling convention of subroutines and trap routines, for teaching.
+//! R0 carries return values and R7 the return address, but a routine is expected to give R1 - R6 back
+//! to its caller as it found them. Every JSR, JSRR and (vectored) TRAP records the registers;
+//! when the routine returns to its caller with RET, the registers it did not restore are reported.
+//! R6 is the stack pointer, so a routine which returns with a different R6 has pushed more than it popped
+//! (or the other way round); that is reported on its own, as an unbalanced stack.
+
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hardware::register::Registers;
+
+/// `CALLEE_SAVED` are the registers a routine must restore before returning, besides the stack pointer.
+const CALLEE_SAVED: std::ops::RangeInclusive<u16> = 1..=5;
+
+/// `STACK_POINTER` is R6.
+pub(crate) const STACK_POINTER: u16 = 6;
+
+/// `ConventionViolation` is a call whose routine returned without restoring what it should have.
+/// A call can give one violation of each kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConventionViolation {
+    /// The address of the JSR, JSRR or TRAP.
+    pub call_pc: u16,
+    /// The address the call jumped to.
+    pub routine: u16,
+    pub kind: ViolationKind,
+}
+
+/// `ViolationKind` tells what a routine did not restore.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The changed registers (numbers 1 - 5), in ascending order.
+    Clobbered(Vec<u16>),
+    /// R6 came back moved by `delta` words: negative after pushes without pops, positive after extra pops.
+    UnbalancedStack { delta: i16 },
+}
+
+//a call which has not returned yet
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Frame {
+    call_pc: u16,
+    routine: u16,
+    saved: Registers,
+}
+
+/// `ConventionChecker` follows calls and returns, step by step.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ConventionChecker {
+    frames: Vec<Frame>,
+    violations: Vec<ConventionViolation>,
+}
+
+impl ConventionChecker {
+    /// `observe` looks at the instruction which took the registers from `before` to `after` (it is in `after.r_ir`).
+    pub(crate) fn observe(&mut self, before: &Registers, after: &Registers) {
+        let return_address = before.pc().wrapping_add(1);
+        match DecodedInstr::decode(after.r_ir) {
+            DecodedInstr::Jsr { .. } | DecodedInstr::Jsrr { .. } => self.call(before, after),
+            //the built-in trap routines return straight away, so only vectored traps are calls
+            DecodedInstr::Trap { .. } if after.pc() != return_address => self.call(before, after),
+            DecodedInstr::Jmp { base: 7 } => {
+                let returns = self
+                    .frames
+                    .last()
+                    .is_some_and(|frame| frame.saved.pc().wrapping_add(1) == after.pc());
+                if returns {
+                    let frame = self.frames.pop().expect("The frame was just seen.");
+                    let clobbered: Vec<u16> = CALLEE_SAVED
+                        .filter(|&r| frame.saved.get(r) != after.get(r))
+                        .collect();
+                    if !clobbered.is_empty() {
+                        self.report(&frame, ViolationKind::Clobbered(clobbered));
+                    }
+                    let delta = after
+                        .get(STACK_POINTER)
+                        .wrapping_sub(frame.saved.get(STACK_POINTER))
+                        as i16;
+                    if delta != 0 {
+                        self.report(&frame, ViolationKind::UnbalancedStack { delta });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, before: &Registers, after: &Registers) {
+        self.frames.push(Frame {
+            call_pc: before.pc(),
+            routine: after.pc(),
+            saved: before.clone(),
+        });
+    }
+
+    fn report(&mut self, frame: &Frame, kind: ViolationKind) {
+        self.violations.push(ConventionViolation {
+            call_pc: frame.call_pc,
+            routine: frame.routine,
+            kind,
+        });
+    }
+
+    pub(crate) fn violations(&self) -> &[ConventionViolation] {
+        &self.violations
+    }
+}
+
+#[cfg(test)]
+mod convention_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn subroutine_clobbering_a_register_should_violate_the_convention() {
+        let program = crate::assembler::assemble(
+            ".ORIG x3000
+                   AND R2, R2, #0
+                   JSR GOOD
+                   JSR BAD
+                   HALT
+             GOOD  ST R2, SAVE
+                   ADD R2, R2, #1
+                   LD R2, SAVE
+                   RET
+             BAD   ADD R2, R2, #1
+                   RET
+             SAVE  .FILL #0
+                   .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_convention_check();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(
+            &[ConventionViolation {
+                call_pc: 0x3002,
+                routine: program.symbols.get("BAD").unwrap(),
+                kind: ViolationKind::Clobbered(vec![2])
+            }],
+            vm.convention_violations()
+        );
+    }
+
+    #[test]
+    fn subroutine_pushing_without_popping_should_unbalance_the_stack() {
+        let program = crate::assembler::assemble(
+            ".ORIG x3000
+                   LD R6, STACK
+                   JSR PUSH2
+                   HALT
+             PUSH2 ADD R6, R6, #-1
+                   STR R0, R6, #0
+                   ADD R6, R6, #-1
+                   STR R1, R6, #0
+                   RET
+             STACK .FILL xFE00
+                   .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_convention_check();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(
+            &[ConventionViolation {
+                call_pc: 0x3001,
+                routine: program.symbols.get("PUSH2").unwrap(),
+                kind: ViolationKind::UnbalancedStack { delta: -2 }
+            }],
+            vm.convention_violations()
+        );
+    }
+}
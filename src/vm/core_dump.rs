@@ -0,0 +1,221 @@
+//! `core_dump` : The whole state of a machine in one file, for analysing a failed run later.
+//!
+//! Layout (all big-endian): the magic `LC3D`, the registers R0 - R7, PC, IR and the PSR (u16 each),
+//! the number of instructions executed (u64), whether an instruction failed (u8, 0 or 1) and its address
+//! (u16, xFFFF if none), the reason as length (u16) and UTF-8 bytes (empty if none), and all 65,536 memory cells.
+//! A dump written by `Vm::save_state` goes on with the console of its `BufferIo`: the magic `CONS`, then
+//! the pending input and the output so far, each as length (u32) and bytes.
+//!
+//! The PSR (see `register::psr`) is always written as user mode at priority 0; only the condition codes are read back.
+//! A dump does not record the privilege mode, the saved stack pointer or a running interrupt or exception handler
+//! (see `vm::interrupt`): a machine which loads one is in user mode, with no handler running and the supervisor
+//! stack pointer at its power-on value, even if it was dumped inside a handler.
+
+use crate::hardware::memory::Memory;
+use crate::hardware::register::psr::Psr;
+use crate::hardware::register::Registers;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read};
+
+const MAGIC: &[u8; 4] = b"LC3D";
+
+/// `CoreDumpInfo` is what a core dump says about the run, besides the registers and memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoreDumpInfo {
+    /// The number of instructions executed (see `Vm::steps_executed`).
+    pub steps: u64,
+    /// The address of the instruction which failed, if the machine stopped on an error.
+    pub failing_pc: Option<u16>,
+    /// The error the machine stopped on, as text.
+    pub reason: Option<String>,
+}
+
+const CONSOLE_MAGIC: &[u8; 4] = b"CONS";
+
+/// `Console` is the console section of a dump: the input not read yet and the output so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Console {
+    pub(crate) input: Vec<u8>,
+    pub(crate) output: Vec<u8>,
+}
+
+//what `read` gives back
+type CoreDump = (Registers, Memory, CoreDumpInfo, Option<Console>);
+
+/// `write` serializes a core dump in the layout described in the module documentation,
+/// with a console section if `console` is given.
+pub(crate) fn write(
+    registers: &Registers,
+    memory: &Memory,
+    info: &CoreDumpInfo,
+    console: Option<&Console>,
+) -> Vec<u8> {
+    //writing to a `Vec` cannot fail
+    let mut bytes = MAGIC.to_vec();
+    for r in 0..=8 {
+        bytes.write_u16::<BigEndian>(registers.get(r)).unwrap();
+    }
+    bytes.write_u16::<BigEndian>(registers.r_ir).unwrap();
+    bytes
+        .write_u16::<BigEndian>(registers.psr().to_u16())
+        .unwrap();
+    bytes.write_u64::<BigEndian>(info.steps).unwrap();
+    match info.failing_pc {
+        Some(pc) => {
+            bytes.push(1);
+            bytes.write_u16::<BigEndian>(pc).unwrap();
+        }
+        None => {
+            bytes.push(0);
+            bytes.write_u16::<BigEndian>(0xFFFF).unwrap();
+        }
+    }
+    let reason = info.reason.as_deref().unwrap_or_default();
+    bytes.write_u16::<BigEndian>(reason.len() as u16).unwrap();
+    bytes.extend_from_slice(reason.as_bytes());
+    for &cell in memory.cells.iter() {
+        bytes.write_u16::<BigEndian>(cell).unwrap();
+    }
+    if let Some(console) = console {
+        bytes.extend_from_slice(CONSOLE_MAGIC);
+        for part in [&console.input, &console.output].iter() {
+            bytes.write_u32::<BigEndian>(part.len() as u32).unwrap();
+            bytes.extend_from_slice(part);
+        }
+    }
+    bytes
+}
+
+/// `read` reads a core dump written by `write`, with its console section if it has one.
+/// Malformed or truncated input gives an `InvalidData` or `UnexpectedEof` error.
+pub(crate) fn read(bytes: &[u8]) -> io::Result<CoreDump> {
+    let mut rdr = Cursor::new(bytes);
+    let mut magic = [0; 4];
+    rdr.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a core dump"));
+    }
+    let mut registers = Registers::new();
+    for r in 0..=8 {
+        registers.update(r, rdr.read_u16::<BigEndian>()?);
+    }
+    registers.r_ir = rdr.read_u16::<BigEndian>()?;
+    let psr = Psr::from_u16(rdr.read_u16::<BigEndian>()?);
+    registers.r_cond = match psr.condition_bits() {
+        0 => None,
+        _ => Some(psr.condition().ok_or_else(|| invalid_data("invalid PSR"))?),
+    };
+    let steps = rdr.read_u64::<BigEndian>()?;
+    let failed = rdr.read_u8()?;
+    let pc = rdr.read_u16::<BigEndian>()?;
+    let failing_pc = match failed {
+        0 => None,
+        1 => Some(pc),
+        _ => return Err(invalid_data("invalid failing PC")),
+    };
+    let mut reason = vec![0; rdr.read_u16::<BigEndian>()? as usize];
+    rdr.read_exact(&mut reason)?;
+    let reason = String::from_utf8(reason).map_err(|_| invalid_data("reason is not UTF-8"))?;
+    let mut memory = Memory::new();
+    rdr.read_u16_into::<BigEndian>(&mut memory.cells[..])?;
+    let console = match rdr.position() as usize == bytes.len() {
+        true => None,
+        false => Some(read_console(&mut rdr)?),
+    };
+    if rdr.position() as usize != bytes.len() {
+        return Err(invalid_data("trailing bytes after the console"));
+    }
+    let info = CoreDumpInfo {
+        steps,
+        failing_pc,
+        reason: Some(reason).filter(|reason| !reason.is_empty()),
+    };
+    Ok((registers, memory, info, console))
+}
+
+fn read_console(rdr: &mut Cursor<&[u8]>) -> io::Result<Console> {
+    let mut magic = [0; 4];
+    rdr.read_exact(&mut magic)?;
+    if &magic != CONSOLE_MAGIC {
+        return Err(invalid_data("trailing bytes after memory"));
+    }
+    let mut read_part = || -> io::Result<Vec<u8>> {
+        let mut part = vec![0; rdr.read_u32::<BigEndian>()? as usize];
+        rdr.read_exact(&mut part)?;
+        Ok(part)
+    };
+    let input = read_part()?;
+    let output = read_part()?;
+    Ok(Console { input, output })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod core_dump_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm, VmError};
+
+    #[test]
+    fn core_dump_should_round_trip_after_an_error() {
+        // ADD R0, R0, #5 ; ST R0, #1 ; .FILL xD000 (reserved) ; data
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1025, 0x3001, 0xD000]);
+        let error = VmError::IllegalOpcode {
+            pc: 0x3002,
+            instr: 0xD000,
+        };
+        assert_eq!(RunResult::Error(error), vm.run());
+        assert_eq!(Some((0x3002, error)), vm.last_error());
+        let dump = vm.export_core_dump();
+
+        let mut inspector = Vm::with_io(BufferIo::new());
+        let info = inspector.import_core_dump(&dump).unwrap();
+        assert_eq!(
+            CoreDumpInfo {
+                steps: 2,
+                failing_pc: Some(0x3002),
+                reason: Some(error.to_string()),
+            },
+            info
+        );
+        assert_eq!(vm.registers(), inspector.registers());
+        assert_eq!(vm.memory(), inspector.memory());
+        assert_eq!(5, inspector.peek(0x3003));
+        assert_eq!(2, inspector.steps_executed());
+        assert!(inspector.import_core_dump(&dump[..dump.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn load_state_should_resume_the_console_where_it_was_saved() {
+        let mut vm = Vm::with_io(BufferIo::with_input("abc"));
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   GETC
+                   OUT
+                   GETC
+                   OUT
+                   HALT
+                   .END",
+        )
+        .unwrap();
+        vm.step_n(2);
+        let state = vm.save_state();
+
+        let mut resumed = Vm::with_io(BufferIo::new());
+        resumed.load_state(&state).unwrap();
+        assert_eq!(0x3002, resumed.registers.pc());
+        assert_eq!(b"a", resumed.io().output());
+        resumed.step().unwrap();
+        assert_eq!(u16::from(b'b'), resumed.registers.r_00);
+        assert_eq!(b"c".to_vec(), resumed.io().pending_bytes());
+
+        //a state without a console leaves it alone
+        let mut vm = Vm::with_io(BufferIo::with_input("z"));
+        vm.load_state(&resumed.export_core_dump()).unwrap();
+        assert_eq!(1, vm.io().pending_input());
+    }
+}
@@ -0,0 +1,189 @@
+use super::interrupt;
+use crate::assembler::AssembleError;
+use crate::hardware::memory::Access;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// `VmError` lists the ways in which executing a program can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VmError {
+    /// The instruction at `pc` uses an opcode that the VM does not execute (`Rti` or `Res`).
+    IllegalOpcode { pc: u16, instr: u16 },
+    /// An `access` to `addr` was denied by a protected memory region (see `Vm::protect`).
+    ProtectionFault { addr: u16, access: Access },
+    /// In strict CC mode, the BR at `pc` tested the condition codes before any instruction had set them
+    /// (see `Vm::set_strict_condition`). PC is left at the BR.
+    UninitializedCondition { pc: u16 },
+    /// The TRAP at `pc` would have used the console, which `Vm::deny_io` forbids.
+    IoDenied { pc: u16, vector: u8 },
+    /// A byte was written to the display after `limit` bytes had been output (see `Vm::set_max_output`).
+    /// When a trap routine overran the limit, PC is left after the TRAP.
+    OutputLimitExceeded { limit: usize },
+    /// The keyboard was polled `polls` times without a key arriving (see `Vm::set_input_poll_limit`).
+    /// PC is left after the instruction which gave up (the TRAP, or the load of the OS polling loop).
+    InputTimedOut { polls: u32 },
+    /// A store to the read-only device register at `addr` was refused (see `Vm::set_read_only_policy`).
+    /// PC is left after the store.
+    ReadOnlyWrite { addr: u16 },
+    /// A blocking read of the keyboard (GETC, IN, or a load of the KBDR) found the end of input, under
+    /// `EofPolicy::Error` (see `Vm::set_eof_policy`). PC is left after the instruction which read.
+    InputExhausted,
+    /// In strict encoding mode, the instruction `instr` at `pc` has reserved bits which are not as the ISA requires
+    /// (see `Vm::set_strict_encoding`). PC is left at the instruction.
+    MalformedInstruction { pc: u16, instr: u16 },
+    /// With the vector table guard on, the program jumped to `pc`, in the trap or interrupt vector table
+    /// (see `Vm::set_vector_table_guard`). PC is left there.
+    ExecInVectorTable { pc: u16 },
+    /// A TRAP used the built-in trap routines with a `vector` which none of them (nor a registered routine) handles.
+    /// PC is left after the TRAP.
+    UnknownTrap { vector: u8 },
+}
+
+impl VmError {
+    /// `is_recoverable` tells whether the error is an exception the program can handle, so that
+    /// `Vm::continue_from_error` can resume it: an illegal opcode or a protection fault. The others are fatal.
+    pub fn is_recoverable(&self) -> bool {
+        interrupt::exception_vector(self).is_some()
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::IllegalOpcode { pc, instr } => {
+                write!(f, "illegal instruction x{:04X} at x{:04X}", instr, pc)
+            }
+            VmError::ProtectionFault { addr, access } => {
+                let access = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                    Access::Execute => "execute",
+                };
+                write!(
+                    f,
+                    "protection fault: {} access to x{:04X} denied",
+                    access, addr
+                )
+            }
+            VmError::UninitializedCondition { pc } => write!(
+                f,
+                "BR at x{:04X} tested the condition codes before they were set",
+                pc
+            ),
+            VmError::IoDenied { pc, vector } => write!(
+                f,
+                "TRAP x{:02X} at x{:04X} uses the console, which is denied",
+                vector, pc
+            ),
+            VmError::OutputLimitExceeded { limit } => {
+                write!(f, "output exceeded the limit of {} byte(s)", limit)
+            }
+            VmError::InputTimedOut { polls } => {
+                write!(f, "no input after polling the keyboard {} time(s)", polls)
+            }
+            VmError::ReadOnlyWrite { addr } => {
+                write!(f, "write to read-only device register x{:04X}", addr)
+            }
+            VmError::InputExhausted => write!(f, "read past the end of input"),
+            VmError::MalformedInstruction { pc, instr } => write!(
+                f,
+                "instruction x{:04X} at x{:04X} has reserved bits set",
+                instr, pc
+            ),
+            VmError::ExecInVectorTable { pc } => {
+                write!(f, "executed the vector table at x{:04X}", pc)
+            }
+            VmError::UnknownTrap { vector } => {
+                write!(f, "no trap routine for TRAP x{:02X}", vector)
+            }
+        }
+    }
+}
+
+impl Error for VmError {}
+
+/// `BuildError` is a failure to turn a source file into a loaded program (see `Vm::load_source_file`).
+/// The underlying error is its `source`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The source file could not be read.
+    Io { path: String, error: io::Error },
+    /// The source file did not assemble.
+    Assemble { path: String, error: AssembleError },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Io { path, .. } => write!(f, "cannot read `{}`", path),
+            BuildError::Assemble { path, .. } => write!(f, "cannot assemble `{}`", path),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Io { error, .. } => Some(error),
+            BuildError::Assemble { error, .. } => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn build_error_should_chain_to_the_assemble_error() {
+        use std::error::Error;
+        let path = std::env::temp_dir().join(format!("lc3_vm_build_{}.asm", std::process::id()));
+        std::fs::write(&path, ".ORIG x3000\nFOO R0\n.END\n").unwrap();
+        let error = Vm::with_io(BufferIo::new())
+            .load_source_file(&path)
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        let mut chain = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+        assert_eq!(
+            vec![
+                format!("cannot assemble `{}`", path.display()),
+                "line 2: unknown opcode `R0`".to_string()
+            ],
+            chain
+        );
+        assert!(matches!(
+            Vm::with_io(BufferIo::new()).load_source_file(&path),
+            Err(BuildError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn vm_errors_should_be_readable() {
+        assert_eq!(
+            "illegal instruction x8000 at x3000",
+            VmError::IllegalOpcode {
+                pc: 0x3000,
+                instr: 0x8000
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "protection fault: write access to x0200 denied",
+            VmError::ProtectionFault {
+                addr: 0x0200,
+                access: Access::Write
+            }
+            .to_string()
+        );
+    }
+}
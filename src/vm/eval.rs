@@ -0,0 +1,150 @@
+//! `eval` : Runs one instruction typed by a user against the machine, and shows what it changed,
+//! for an interactive tutorial of the ISA.
+
+use super::{Vm, VmError};
+use crate::assembler::{self, AssembleError, SymbolTable};
+use crate::hardware::io::Io;
+use crate::hardware::register::Registers;
+use std::error::Error;
+use std::fmt;
+
+/// `StepDelta` is everything one instruction changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StepDelta {
+    /// The instruction word which was executed.
+    pub instr: u16,
+    /// Every changed general purpose register as (register, old, new), R0 first.
+    pub registers: Vec<(u16, u16, u16)>,
+    /// PC before and after.
+    pub pc: (u16, u16),
+    /// The condition code before and after (`N`, `Z`, `P`, or `-` while unset), if it changed.
+    pub cond: Option<(char, char)>,
+    /// Every changed memory cell as (address, old, new), in address order.
+    pub memory: Vec<(u16, u16, u16)>,
+}
+
+/// `EvalError` is why `Vm::eval` could not run the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EvalError {
+    /// The input is neither a hex word nor an instruction which assembles.
+    Assemble(AssembleError),
+    /// The instruction failed (nothing is undone).
+    Execute(VmError),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Assemble(error) => error.fmt(f),
+            EvalError::Execute(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for EvalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EvalError::Assemble(error) => Some(error),
+            EvalError::Execute(error) => Some(error),
+        }
+    }
+}
+
+impl<I: Io> Vm<I> {
+    /// `eval` executes one instruction, given in assembly (`ADD R0, R0, #5`) or as a hex word (`x1025`),
+    /// as if it were stored at PC, and returns what it changed. Labels cannot be used, as there is no program;
+    /// PC-relative operands are plain offsets (`BRz #3`). The cell at PC keeps its old contents
+    /// (unless the instruction stores to it).
+    pub fn eval(&mut self, input: &str) -> Result<StepDelta, EvalError> {
+        let pc = self.registers.pc();
+        let instr = match parse_word(input.trim()) {
+            Some(word) => word,
+            None => assembler::assemble_line(input, pc, &SymbolTable::new())
+                .map_err(EvalError::Assemble)?,
+        };
+        let before = self.registers.clone();
+        let cells = self.bus.memory.cells.clone();
+        let replaced = self.bus.memory.read(pc);
+        self.bus.memory.write(pc as usize, instr);
+        let executed = self.step();
+        if self.bus.memory.read(pc) == instr {
+            self.bus.memory.write(pc as usize, replaced);
+        }
+        executed.map_err(EvalError::Execute)?;
+
+        let memory = cells
+            .iter()
+            .zip(self.bus.memory.cells.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (&old, &new))| (address as u16, old, new))
+            .collect();
+        Ok(StepDelta::new(instr, &before, &self.registers, memory))
+    }
+}
+
+impl StepDelta {
+    /// `new` is the delta of `instr`, which took the registers from `before` to `after` and changed `memory`.
+    pub(crate) fn new(
+        instr: u16,
+        before: &Registers,
+        after: &Registers,
+        memory: Vec<(u16, u16, u16)>,
+    ) -> StepDelta {
+        let (old_cc, new_cc) = (before.condition_code(), after.condition_code());
+        StepDelta {
+            instr,
+            registers: (0..8)
+                .filter(|&r| before.get(r) != after.get(r))
+                .map(|r| (r, before.get(r), after.get(r)))
+                .collect(),
+            pc: (before.pc(), after.pc()),
+            cond: Some((old_cc, new_cc)).filter(|_| old_cc != new_cc),
+            memory,
+        }
+    }
+}
+
+//`x1025` or `0x1025`
+fn parse_word(input: &str) -> Option<u16> {
+    let digits = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix(['x', 'X']))?;
+    if digits.len() != 4 {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod eval_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+
+    #[test]
+    fn eval_should_return_the_delta_of_one_instruction() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.set_reg(0, 3);
+        assert_eq!(
+            StepDelta {
+                instr: 0x1025,
+                registers: vec![(0, 3, 8)],
+                pc: (0x3000, 0x3001),
+                cond: Some(('-', 'P')),
+                memory: Vec::new(),
+            },
+            vm.eval("ADD R0, R0, #5").unwrap()
+        );
+        assert_eq!(0, vm.peek(0x3000));
+
+        // ST R0, #4
+        let delta = vm.eval("x3004").unwrap();
+        assert_eq!(vec![(0x3006, 0, 8)], delta.memory);
+        assert!(delta.registers.is_empty() && delta.cond.is_none());
+        assert!(matches!(
+            vm.eval("ADD R0, LOOP"),
+            Err(EvalError::Assemble(_))
+        ));
+    }
+}
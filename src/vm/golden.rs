@@ -0,0 +1,133 @@
+//! `golden` : Checks an execution against a golden trace, e.g. one recorded with a reference simulator.
+//! A golden trace has one line per executed instruction, with the whole machine state after it:
+//!
+//! ```text
+//! PC=x3001 R0=x0000 R1=x0000 R2=x0000 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000 CC=Z
+//! ```
+//!
+//! Fields are separated by whitespace, hex digits may be in either case, and empty lines and lines starting
+//! with `;` are skipped. Unlike the diff-oriented format of `vm::trace`, every register is listed,
+//! so a divergence is caught at the instruction which caused it.
+
+use super::Vm;
+use crate::hardware::io::Io;
+use crate::hardware::register::Registers;
+use std::fmt;
+
+/// `TraceDivergence` is the first step at which an execution did not match its golden trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// The 1-based line of the golden trace.
+    pub line: usize,
+    /// The number of instructions executed before the diverging one.
+    pub step: usize,
+    /// The address of the diverging instruction.
+    pub pc: u16,
+    /// The golden state, as written in the trace.
+    pub expected: String,
+    /// The state the VM reached, in the golden format (or why it did not reach one).
+    pub actual: String,
+    /// The state before the diverging instruction, in the golden format.
+    pub before: String,
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "step {} (golden line {}) diverged at x{:04X}",
+            self.step, self.line, self.pc
+        )?;
+        writeln!(f, "  before:   {}", self.before)?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        write!(f, "  actual:   {}", self.actual)
+    }
+}
+
+/// `state_line` renders `registers` in the golden trace format.
+pub fn state_line(registers: &Registers) -> String {
+    let mut line = format!("PC=x{:04X}", registers.pc());
+    for r in 0..8 {
+        line.push_str(&format!(" R{}=x{:04X}", r, registers.get(r)));
+    }
+    line.push_str(&format!(" CC={}", registers.condition_code()));
+    line
+}
+
+/// `check_golden_trace` single-steps `vm` once per line of `golden`, comparing the state after each step
+/// with the line. It returns the number of steps executed, or the first divergence: a different state,
+/// an instruction that failed, or a line which is not a state.
+pub fn check_golden_trace<I: Io>(vm: &mut Vm<I>, golden: &str) -> Result<usize, TraceDivergence> {
+    let mut step = 0;
+    for (i, expected) in golden.lines().enumerate() {
+        let expected = expected.trim();
+        if expected.is_empty() || expected.starts_with(';') {
+            continue;
+        }
+        let before = state_line(vm.registers());
+        let pc = vm.registers().pc();
+        let actual = match vm.step() {
+            Ok(()) => state_line(vm.registers()),
+            Err(error) => format!("error: {}", error),
+        };
+        if normalize(expected) != actual {
+            return Err(TraceDivergence {
+                line: i + 1,
+                step,
+                pc,
+                expected: expected.to_string(),
+                actual,
+                before,
+            });
+        }
+        step += 1;
+    }
+    Ok(step)
+}
+
+//single spaces and upper-case hex digits, as `state_line` writes them
+fn normalize(line: &str) -> String {
+    line.split_whitespace()
+        .map(|field| match field.split_once('=') {
+            Some((name, value)) => format!(
+                "{}={}",
+                name.to_ascii_uppercase(),
+                match value.strip_prefix(['x', 'X']) {
+                    Some(hex) => format!("x{}", hex.to_ascii_uppercase()),
+                    None => value.to_ascii_uppercase(),
+                }
+            ),
+            None => field.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod golden_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+
+    #[test]
+    fn execution_should_match_the_golden_trace() {
+        let program = assembler::assemble(include_str!("../../resources/golden.asm")).unwrap();
+        let golden = include_str!("../../resources/golden.trace");
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.io_mut().push_input(b"!");
+        let steps = check_golden_trace(&mut vm, golden).unwrap_or_else(|d| panic!("{}", d));
+        assert_eq!(35, steps);
+        assert!(vm.is_halted());
+
+        //a wrong register is reported at the step which wrote it, with the state before it
+        let tampered = golden.replacen("R1=x000E", "R1=x000F", 1);
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.io_mut().push_input(b"!");
+        let divergence = check_golden_trace(&mut vm, &tampered).unwrap_err();
+        assert_eq!((2, 0x3002), (divergence.step, divergence.pc));
+        assert!(divergence.before.contains("R0=x0007 R1=x0000"));
+        assert!(divergence.actual.contains("R1=x000E"));
+    }
+}
@@ -0,0 +1,102 @@
+//! `grade` : Runs one program against a batch of test cases, for grading. Every case starts a fresh machine
+//! (built-in trap routines, console on a `BufferIo`) with the program loaded and the case's input queued,
+//! runs it to the end, and compares the output with the expected output.
+
+use super::{RunResult, Vm};
+use crate::assembler::Program;
+use crate::hardware::io::BufferIo;
+
+/// `GRADE_STEP_LIMIT` is how many instructions a case may execute before it is stopped
+/// (its halt reason is then `RunResult::BudgetExhausted`), so a looping program cannot hang the batch.
+pub const GRADE_STEP_LIMIT: u64 = 10_000_000;
+
+/// `CaseResult` is how the program did on one case of `grade`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaseResult {
+    /// The output matched the expected output.
+    pub passed: bool,
+    /// Why the run stopped.
+    pub halt: RunResult,
+    /// The output of the program.
+    pub actual: String,
+    /// The lines which differ, as `- expected` and `+ actual` pairs; empty if the case passed.
+    pub diff: String,
+}
+
+/// `grade` runs `program` once per case of `cases`, given as (input, expected output), and returns the results
+/// in the same order. A case passes if its output is exactly the expected output, whatever stopped the run.
+pub fn grade(program: &Program, cases: &[(&str, &str)]) -> Vec<CaseResult> {
+    cases
+        .iter()
+        .map(|&(input, expected)| {
+            let mut vm = Vm::with_io(BufferIo::with_input(input));
+            vm.load_program(program);
+            let halt = vm.run_for(GRADE_STEP_LIMIT);
+            let actual = vm.io().output_string();
+            CaseResult {
+                passed: actual == expected,
+                halt,
+                diff: diff(expected, &actual),
+                actual,
+            }
+        })
+        .collect()
+}
+
+//the lines of `expected` and `actual` which differ, line by line
+fn diff(expected: &str, actual: &str) -> String {
+    let mut diff = String::new();
+    if expected == actual {
+        return diff;
+    }
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    for i in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(i), actual.get(i));
+        if old == new {
+            continue;
+        }
+        if let Some(line) = old {
+            diff.push_str(&format!("- {}\n", line));
+        }
+        if let Some(line) = new {
+            diff.push_str(&format!("+ {}\n", line));
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod grade_test {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn grade_should_pass_and_fail_cases_with_a_diff() {
+        //echoes one character, then a newline
+        let program = assemble(
+            ".ORIG x3000
+                   GETC
+                   OUT
+                   LD R0, NL
+                   OUT
+                   HALT
+             NL    .FILL x0A
+                   .END",
+        )
+        .unwrap();
+        let results = grade(&program, &[("a", "a\n"), ("b", "c\n")]);
+        assert_eq!(
+            CaseResult {
+                passed: true,
+                halt: RunResult::Halted,
+                actual: "a\n".to_string(),
+                diff: String::new(),
+            },
+            results[0]
+        );
+        assert!(!results[1].passed);
+        assert_eq!(RunResult::Halted, results[1].halt);
+        assert_eq!("- c\n+ b\n", results[1].diff);
+    }
+}
@@ -0,0 +1,207 @@
+//! `history` : Time travel, kept small. Instead of a snapshot of the machine per step, every step records
+//! a delta: the registers before it and the old value of every memory cell it stored to. `step_back` undoes
+//! the last step by putting these back, so no keyframes are needed, and the cost of a step is a few dozen bytes
+//! (plus four per store) however large memory is. Only the last `capacity` steps are kept.
+//!
+//! The console is undone as far as it can be: a step records how much output the console had collected and which
+//! input bytes it consumed (and the keyboard registers, which latch them), so that with a buffered console
+//! (like `BufferIo`) stepping back over an OUT takes the output back and stepping back over a GETC re-queues
+//! the key. Output sent to a terminal cannot be taken back, and attached devices are not undone.
+//!
+//! A step also records the state of the run around the registers: the interrupted program and the privilege mode
+//! (so stepping back over an interrupt, an exception or their RTI leaves the machine in or out of the handler),
+//! the number of instructions executed, and the timer with its status register.
+
+use super::interrupt::{InterruptFrame, Privilege};
+use super::timer::Timer;
+use crate::hardware::register::Registers;
+use std::collections::VecDeque;
+use std::mem;
+
+/// `Delta` is what one step changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Delta {
+    /// The registers before the step.
+    pub(crate) registers: Registers,
+    /// Every (address, old value) the step stored to, in order.
+    pub(crate) writes: Vec<(u16, u16)>,
+    /// How much output the console had collected before the step, if it keeps its output.
+    pub(crate) output_len: Option<usize>,
+    /// The bytes the step read from the console, in order.
+    pub(crate) input: Vec<u8>,
+    /// The KBSR and KBDR before the step.
+    pub(crate) keyboard: [u16; 2],
+    /// The interrupted program before the step.
+    pub(crate) interrupted: Option<InterruptFrame>,
+    /// The privilege mode before the step.
+    pub(crate) privilege: Privilege,
+    /// The number of instructions executed before the step.
+    pub(crate) steps_executed: u64,
+    /// The timer and the TMR before the step, if one is attached.
+    pub(crate) timer: Option<Box<(Timer, u16)>>,
+}
+
+/// `History` holds the deltas of the last `capacity` steps, oldest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct History {
+    capacity: usize,
+    steps: VecDeque<Delta>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            steps: VecDeque::new(),
+        }
+    }
+
+    /// `push` records the delta of a step, forgetting the oldest one if the history is full.
+    pub(crate) fn push(&mut self, delta: Delta) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(delta);
+    }
+
+    /// `pop` takes the delta of the most recent step.
+    pub(crate) fn pop(&mut self) -> Option<Delta> {
+        self.steps.pop_back()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    /// `memory_usage` estimates the bytes the recorded deltas take up.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|delta| {
+                mem::size_of::<Delta>()
+                    + delta.writes.capacity() * mem::size_of::<(u16, u16)>()
+                    + delta.input.capacity()
+                    + delta
+                        .timer
+                        .as_ref()
+                        .map_or(0, |_| mem::size_of::<(Timer, u16)>())
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod history_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{TimerInterval, Vm};
+
+    #[test]
+    fn step_back_should_undo_steps_with_bounded_history() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+        LOOP ADD R0, R0, #1
+             ST R0, COUNT
+             BRnzp LOOP
+       COUNT .FILL #0
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_history(64);
+        for _ in 0..1000 {
+            vm.step().unwrap();
+        }
+        assert_eq!(64, vm.history_len());
+        let usage = vm.history_memory_usage();
+        assert!(usage <= 64 * 128, "history takes {} bytes", usage);
+        //1000 = 333 loops and one ADD
+        assert_eq!(334, vm.reg(0));
+        assert_eq!(333, vm.peek(0x3003));
+
+        for _ in 0..3 {
+            assert!(vm.step_back());
+        }
+        assert_eq!(333, vm.reg(0));
+        assert_eq!(332, vm.peek(0x3003));
+        assert_eq!(0x3001, vm.registers().pc());
+        vm.step().unwrap();
+        assert_eq!(333, vm.peek(0x3003));
+
+        while vm.step_back() {}
+        assert_eq!(0, vm.history_len());
+    }
+
+    #[test]
+    fn step_back_should_undo_an_interrupt_and_its_rti() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R0, R0, #1 ; BRnzp #-2
+        vm.load_words(0x3000, &[0x1021, 0x0FFE]);
+        // ADD R1, R1, #1 ; RTI
+        vm.load_words(0x4000, &[0x1261, 0x8000]);
+        vm.poke(0x0181, 0x4000);
+        vm.attach_timer(Timer::new(TimerInterval::Instructions(3), 0x81));
+        vm.enable_history(16);
+        //the tick interrupts the BR
+        vm.step_n(5);
+        assert_eq!(0x3001, vm.registers().pc());
+        assert_eq!(1, vm.reg(1));
+
+        //stepping back over the RTI goes back into the handler, whose RTI still returns
+        assert!(vm.step_back());
+        assert_eq!(0x4001, vm.registers().pc());
+        assert_eq!(4, vm.steps_executed());
+        vm.step().unwrap();
+        assert_eq!(0x3001, vm.registers().pc());
+        vm.step().unwrap();
+        assert_eq!(0x3000, vm.registers().pc());
+
+        //stepping back over the interrupt leaves the handler, and the tick is due again
+        assert!(vm.step_back());
+        assert!(vm.step_back());
+        assert!(vm.step_back());
+        assert_eq!(0x3001, vm.registers().pc());
+        assert_eq!(0, vm.reg(1));
+        assert_eq!(3, vm.steps_executed());
+        assert_eq!(None, vm.interrupted);
+        assert_eq!(0x4000, vm.peek(0xFE08));
+        vm.step().unwrap();
+        assert_eq!(0x4001, vm.registers().pc());
+        assert_eq!(1, vm.reg(1));
+        assert_eq!(0xC000, vm.peek(0xFE08));
+    }
+
+    #[test]
+    fn step_back_should_take_back_buffered_output_and_requeue_input() {
+        // GETC ; OUT ; OUT
+        let mut vm = Vm::with_io(BufferIo::with_input("ab"));
+        vm.load_words(0x3000, &[0xF020, 0xF021, 0xF021]);
+        vm.enable_history(8);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!("a", vm.io().output_string());
+        assert_eq!(1, vm.io().pending_input());
+
+        assert!(vm.step_back());
+        assert_eq!("", vm.io().output_string());
+        assert!(vm.step_back());
+        assert_eq!(2, vm.io().pending_input());
+        assert_eq!(0x3000, vm.registers().pc());
+
+        //the replay reads the same key again
+        vm.step().unwrap();
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!("aa", vm.io().output_string());
+        assert_eq!(1, vm.io().pending_input());
+    }
+}
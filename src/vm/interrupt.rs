@@ -0,0 +1,238 @@
+//! `interrupt` : Keyboard interrupts (see `Vm::inject_interrupt_on_key`) and the privilege mode exception.
+//! While the KBSR enables interrupts (bit [14]), a latched key interrupts the program before its next instruction:
+//! PC jumps to the handler whose address is in the interrupt vector table entry of the keyboard, and RTI returns
+//! to the interrupted instruction with its condition codes. The timer (see `vm::timer`) interrupts the same way.
+//!
+//! RTI outside of a handler is what a user-mode program gets a privilege mode violation for. If a handler is
+//! installed in the exception vector table entry x0100 (any address but x0000), the machine takes the exception
+//! like the LC-3 does: it saves R6 as the user stack pointer, switches R6 to the supervisor stack (which starts at
+//! x3000 and grows down), pushes the PSR and then the PC of the instruction after the faulting one, and jumps to
+//! the handler in supervisor mode. The handler's RTI pops PC and the PSR back, saves the supervisor stack pointer
+//! and switches R6 back to the user stack. Without a handler, RTI is an illegal opcode.
+//!
+//! Two more failures have exception vectors: an illegal opcode (x0101) and a protection fault (the access control
+//! violation, x0102). They still stop the machine with their `VmError`, as the VM has no handler of its own for them,
+//! but `Vm::continue_from_error` can then take the exception through the installed handler, which returns with RTI
+//! to the instruction after the faulting one.
+//!
+//! Interrupts and the exceptions taken by `Vm::continue_from_error` do not use the supervisor stack: the
+//! interrupted PC and condition codes (the PSR) are kept by the VM rather than being pushed, R6 is not switched,
+//! and they do not nest.
+
+use super::VmError;
+use crate::hardware::memory::Bus;
+use crate::hardware::register::psr::Psr;
+use crate::hardware::register::Registers;
+
+/// `KEYBOARD_VECTOR` is the entry for the keyboard (INTV x80) in the interrupt vector table at x0100.
+pub(crate) const KEYBOARD_VECTOR: u16 = 0x0180;
+
+/// `PRIVILEGE_VECTOR` is the entry for the privilege mode violation (exception x00) in the vector table at x0100.
+pub(crate) const PRIVILEGE_VECTOR: u16 = 0x0100;
+
+/// `ILLEGAL_OPCODE_VECTOR` is the entry for the illegal opcode exception (x01).
+pub(crate) const ILLEGAL_OPCODE_VECTOR: u16 = 0x0101;
+
+/// `ACCESS_VIOLATION_VECTOR` is the entry for the access control violation (exception x02), i.e. a protection fault.
+pub(crate) const ACCESS_VIOLATION_VECTOR: u16 = 0x0102;
+
+/// `VECTOR_TABLES_END` is the first address above the trap vector table (x0000 - x00FF) and the interrupt vector
+/// table (x0100 - x01FF).
+pub(crate) const VECTOR_TABLES_END: u16 = 0x0200;
+
+/// `SUPERVISOR_STACK` is the supervisor stack pointer after a reset.
+pub(crate) const SUPERVISOR_STACK: u16 = 0x3000;
+
+const STACK_POINTER: u16 = 6;
+
+/// `InterruptFrame` is what RTI restores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InterruptFrame {
+    pub(crate) pc: u16,
+    pub(crate) psr: Psr,
+}
+
+/// `Privilege` is the privilege mode, with the stack pointer of the mode the machine is not in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Privilege {
+    /// The user stack pointer, while a privilege mode exception handler runs in supervisor mode.
+    pub(crate) saved_usp: Option<u16>,
+    /// The supervisor stack pointer, while the machine is in user mode.
+    pub(crate) saved_ssp: u16,
+}
+
+impl Default for Privilege {
+    fn default() -> Privilege {
+        Privilege {
+            saved_usp: None,
+            saved_ssp: SUPERVISOR_STACK,
+        }
+    }
+}
+
+impl Privilege {
+    /// `is_supervisor` tells whether a privilege mode exception handler is running.
+    pub(crate) fn is_supervisor(&self) -> bool {
+        self.saved_usp.is_some()
+    }
+
+    /// `raise` takes the privilege mode exception: R6 switches to the supervisor stack, the PSR and PC are
+    /// pushed on it, and PC jumps to `handler`.
+    pub(crate) fn raise<B: Bus>(
+        &mut self,
+        registers: &mut Registers,
+        bus: &mut B,
+        handler: u16,
+    ) -> Result<(), VmError> {
+        let psr = registers.psr();
+        let mut sp = self.saved_ssp;
+        sp = sp.wrapping_sub(1);
+        bus.store(sp, psr.to_u16())?;
+        sp = sp.wrapping_sub(1);
+        bus.store(sp, registers.pc())?;
+        self.saved_usp = Some(registers.get(STACK_POINTER));
+        registers.update(STACK_POINTER, sp);
+        registers.set_pc(handler);
+        Ok(())
+    }
+
+    /// `return_to_user` is the handler's RTI: PC and then the PSR are popped off the supervisor stack,
+    /// and R6 switches back to the user stack.
+    pub(crate) fn return_to_user<B: Bus>(
+        &mut self,
+        registers: &mut Registers,
+        bus: &mut B,
+    ) -> Result<(), VmError> {
+        let sp = registers.get(STACK_POINTER);
+        let pc = bus.load(sp)?;
+        let psr = Psr::from_u16(bus.load(sp.wrapping_add(1))?);
+        let usp = self
+            .saved_usp
+            .take()
+            .expect("RTI returns to user mode from supervisor mode.");
+        self.saved_ssp = sp.wrapping_add(2);
+        registers.update(STACK_POINTER, usp);
+        registers.set_pc(pc);
+        registers.r_cond = psr.condition();
+        Ok(())
+    }
+}
+
+/// `exception_vector` is the vector table entry of the exception `error` is, if it is one.
+pub(crate) fn exception_vector(error: &VmError) -> Option<u16> {
+    match error {
+        VmError::IllegalOpcode { .. } => Some(ILLEGAL_OPCODE_VECTOR),
+        VmError::ProtectionFault { .. } => Some(ACCESS_VIOLATION_VECTOR),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod interrupt_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::{BufferIo, Io};
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn key_interrupt_should_run_the_handler_and_return() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R0, R0, #1 ; BRnzp #-2
+        vm.load_words(0x3000, &[0x1021, 0x0FFE]);
+        // LDI R1, #2 ; AND R2, R2, #0 ; RTI ; xFE02 (KBDR)
+        vm.load_words(0x4000, &[0xA202, 0x54A0, 0x8000, 0xFE02]);
+        vm.inject_interrupt_on_key(0x4000);
+        vm.step().unwrap();
+        assert_eq!(1, vm.registers().r_00);
+        vm.io_mut().push_input(b"k");
+        vm.step().unwrap();
+        assert_eq!(u16::from(b'k'), vm.registers().r_01);
+        assert_eq!(0x4001, vm.registers().pc());
+        vm.step().unwrap();
+        assert!(vm.registers().is_zero());
+        vm.step().unwrap();
+        //back at the BR, with the condition codes of the ADD
+        assert_eq!(0x3001, vm.registers().pc());
+        assert!(vm.registers().is_positive());
+        assert!(!vm.io_mut().input_ready());
+        assert_eq!(0x4000, vm.peek(0xFE00));
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(2, vm.registers().r_00);
+        assert_eq!(0x3001, vm.registers().pc());
+    }
+
+    #[test]
+    fn rti_in_user_mode_should_vector_to_the_installed_handler() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        let program = assembler::assemble(
+            ".ORIG x3000
+                  ADD R0, R0, #-1
+                  RTI
+                  ADD R2, R2, #1
+                  HALT
+                  .END",
+        )
+        .unwrap();
+        vm.load_program(&program);
+        // ADD R1, R1, #1 ; RTI
+        vm.load_words(0x4000, &[0x1261, 0x8000]);
+        vm.poke(0x0100, 0x4000);
+        vm.registers.update(6, 0xFE00);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(0x4000, vm.registers().pc());
+        //the PSR and then the PC after the RTI are on the supervisor stack, in R6
+        assert_eq!(0x2FFE, vm.reg(6));
+        assert_eq!(0x3002, vm.peek(0x2FFE));
+        assert_eq!(0x8004, vm.peek(0x2FFF));
+        assert_eq!(RunResult::Halted, vm.run());
+        //the handler ran, then the program carried on after its RTI, on the user stack again
+        assert_eq!(1, vm.reg(1));
+        assert_eq!(1, vm.reg(2));
+        assert_eq!(0xFE00, vm.reg(6));
+        assert_eq!(None, vm.attempted_unimplemented());
+
+        //without a handler, RTI is still illegal
+        vm.poke(0x0100, 0);
+        vm.reset(vm.memory().clone(), 0x3000);
+        assert!(matches!(
+            vm.run(),
+            RunResult::Error(VmError::IllegalOpcode { pc: 0x3001, .. })
+        ));
+    }
+
+    #[test]
+    fn continue_from_error_should_take_the_exception_through_its_handler() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   ADD R0, R0, #1
+                   .FILL xD000
+                   ADD R2, R2, #1
+                   HALT
+                   .END",
+        )
+        .unwrap();
+        // ADD R1, R1, #1 ; RTI
+        vm.load_words(0x4000, &[0x1261, 0x8000]);
+        let error = VmError::IllegalOpcode {
+            pc: 0x3001,
+            instr: 0xD000,
+        };
+        assert!(error.is_recoverable());
+        assert_eq!(RunResult::Error(error), vm.run());
+        //no handler yet
+        assert_eq!(Err(error), vm.continue_from_error());
+
+        vm.poke(0x0101, 0x4000);
+        assert_eq!(Ok(()), vm.continue_from_error());
+        assert_eq!(0x4000, vm.registers().pc());
+        assert_eq!(None, vm.last_error());
+        assert_eq!(RunResult::Halted, vm.run());
+        //the handler ran once, then the program carried on after the faulting word
+        assert_eq!([1, 1, 1], [vm.reg(0), vm.reg(1), vm.reg(2)]);
+
+        assert!(!VmError::InputExhausted.is_recoverable());
+    }
+}
@@ -0,0 +1,83 @@
+//! `listener` : Callbacks for a reactive debugger UI, so it does not have to poll the whole register file every frame.
+
+use crate::hardware::register::Registers;
+use std::fmt;
+
+/// `RegisterListener` is called with `(register, old, new)`.
+pub(crate) type RegisterListener = Box<dyn FnMut(u16, u16, u16)>;
+
+/// `RegisterListeners` are called with `(register, old, new)` for every general purpose register (R0 - R7)
+/// an instruction changed. Like `TraceWriter`, they are not part of the machine state:
+/// a clone of them is empty, and they never affect equality.
+#[derive(Default)]
+pub(crate) struct RegisterListeners(Vec<RegisterListener>);
+
+impl RegisterListeners {
+    pub(crate) fn add(&mut self, listener: RegisterListener) {
+        self.0.push(listener);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `notify` calls every listener for each register which differs between `before` and `after`.
+    pub(crate) fn notify(&mut self, before: &Registers, after: &Registers) {
+        for r in 0..8 {
+            let (old, new) = (before.get(r), after.get(r));
+            if old != new {
+                for listener in self.0.iter_mut() {
+                    listener(r, old, new);
+                }
+            }
+        }
+    }
+}
+
+impl Clone for RegisterListeners {
+    fn clone(&self) -> RegisterListeners {
+        RegisterListeners::default()
+    }
+}
+
+impl PartialEq for RegisterListeners {
+    fn eq(&self, _: &RegisterListeners) -> bool {
+        true
+    }
+}
+
+impl Eq for RegisterListeners {}
+
+impl fmt::Debug for RegisterListeners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RegisterListeners")
+            .field("count", &self.0.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod listener_test {
+    use crate::hardware::io::BufferIo;
+    use crate::lc3_instr;
+    use crate::vm::Vm;
+
+    #[test]
+    fn register_listener_should_fire_once_per_changed_register() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[lc3_instr!(ADD R3, R3, #5), lc3_instr!(AND R0, R0, #0)],
+        );
+        let seen = Rc::clone(&changes);
+        vm.on_register_change(move |r, old, new| seen.borrow_mut().push((r, old, new)));
+        vm.step().unwrap();
+        assert_eq!(vec![(3, 0, 5)], *changes.borrow());
+        //AND leaves R0 at 0, so nothing changed
+        vm.step().unwrap();
+        assert_eq!(1, changes.borrow().len());
+    }
+}
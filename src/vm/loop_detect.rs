@@ -0,0 +1,68 @@
+//! `loop_detect` : Finds programs which are stuck: if the machine comes back to a PC with exactly the
+//! registers and condition codes it had there before, and nothing else changes in between, it will go round forever.
+//! Memory is not compared, so a loop which only makes progress in memory (or waits for a device) looks stuck too.
+
+use crate::hardware::register::Registers;
+use std::collections::VecDeque;
+
+/// PC, R0 - R7 and COND
+type State = [u16; 10];
+
+/// `LoopDetector` remembers the states of the last `window` steps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LoopDetector {
+    window: usize,
+    recent: VecDeque<State>,
+}
+
+impl LoopDetector {
+    pub(crate) fn new(window: usize) -> LoopDetector {
+        LoopDetector {
+            window,
+            recent: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// `repeats` records the state in `registers`, returning `true` if it was already seen within the window.
+    pub(crate) fn repeats(&mut self, registers: &Registers) -> bool {
+        let mut state = [0; 10];
+        for (r, value) in state.iter_mut().enumerate() {
+            *value = registers.get(r as u16);
+        }
+        if self.recent.contains(&state) {
+            return true;
+        }
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(state);
+        false
+    }
+
+    /// `forget` clears the recorded states, e.g. after the machine was stopped and resumed.
+    pub(crate) fn forget(&mut self) {
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod loop_detect_test {
+    use crate::hardware::io::BufferIo;
+    use crate::lc3_instr;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn self_loop_should_be_detected_before_the_budget_runs_out() {
+        // AND R0, R0, #0 ; BRz #-1
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[lc3_instr!(AND R0, R0, #0), lc3_instr!(BRz #-1)]);
+        vm.enable_loop_detection(16);
+        assert_eq!(RunResult::InfiniteLoop { pc: 0x3001 }, vm.run_for(1000));
+
+        //a loop which counts is making progress
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[lc3_instr!(ADD R0, R0, #1), lc3_instr!(BRnzp #-2)]);
+        vm.enable_loop_detection(16);
+        assert_eq!(RunResult::BudgetExhausted, vm.run_for(1000));
+    }
+}
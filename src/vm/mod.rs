@@ -0,0 +1,2930 @@
+//! # Vm - The LC-3 machine.
+//! A `Vm` puts the hardware components together: the registers, the memory and the console devices (`Io`),
+//! connected through the system bus. It fetches, decodes and executes instructions until the program halts.
+mod access_counts;
+#[cfg(feature = "extended-memory")]
+mod bank;
+mod binary_trace;
+mod bus;
+mod cache;
+mod code_write;
+mod convention;
+mod core_dump;
+mod error;
+mod eval;
+mod golden;
+mod grade;
+mod history;
+mod interrupt;
+mod listener;
+mod loop_detect;
+mod output_events;
+mod overflow;
+mod precompiled;
+mod preview;
+mod profile;
+mod register_trace;
+#[cfg(feature = "async")]
+mod run_async;
+mod scheduler;
+mod speculation;
+mod timer;
+mod trace;
+mod trap_hook;
+
+pub use self::access_counts::AccessCounts;
+pub use self::binary_trace::{BinaryTraceReader, BinaryTraceWriter};
+pub use self::bus::{EofPolicy, ReadOnlyPolicy};
+pub use self::cache::CacheStats;
+pub use self::code_write::ImminentCodeWrite;
+pub use self::convention::{ConventionViolation, ViolationKind};
+pub use self::core_dump::CoreDumpInfo;
+pub use self::error::{BuildError, VmError};
+pub use self::eval::{EvalError, StepDelta};
+pub use self::golden::{check_golden_trace, state_line, TraceDivergence};
+pub use self::grade::{grade, CaseResult, GRADE_STEP_LIMIT};
+pub use self::output_events::{OutputEvent, OutputSource};
+pub use self::overflow::OverflowEvent;
+pub use self::profile::{format_hotspots, BranchStats};
+pub use self::register_trace::RegisterAccess;
+#[cfg(feature = "async")]
+pub use self::run_async::AsyncIo;
+pub use self::scheduler::{Scheduler, SchedulerEvent};
+pub use self::speculation::Speculation;
+pub use self::timer::{Timer, TimerInterval};
+pub use self::trace::TraceFormat;
+pub use self::trap_hook::TrapHandled;
+
+use self::bus::SystemBus;
+use self::cache::CacheModel;
+use self::convention::{ConventionChecker, STACK_POINTER};
+use self::history::{Delta, History};
+use self::interrupt::{InterruptFrame, Privilege};
+use self::listener::RegisterListeners;
+use self::loop_detect::LoopDetector;
+use self::output_events::OutputEvents;
+use self::precompiled::Precompiled;
+use self::profile::Profile;
+use self::trace::TraceWriter;
+use self::trap_hook::{NamedTraps, TrapHook};
+use crate::assembler::{self, AssembleError, AssembleWarning, Program, SymbolTable};
+use crate::fnv::Fnv64;
+use crate::hardware::device::Device;
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hardware::instructions::fields;
+use crate::hardware::instructions::opcode::{self, OpCode};
+use crate::hardware::instructions::trap;
+use crate::hardware::io::{BufferIo, Io, StdIo};
+use crate::hardware::memory::{AddrRange, Bus, Memory, MemoryMappedReg, Perms, MEMORY_SIZE};
+#[cfg(debug_assertions)]
+use crate::hardware::register::condition_flag::Condition;
+use crate::hardware::register::{Registers, PC_START};
+use crate::hex::{self, LoadError, ParseError};
+use crate::logging;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// `OS_IMAGE` is a minimal LC-3 operating system (see `resources/os.asm`), in `.obj` format.
+/// It provides the trap vector table and the standard trap routines.
+const OS_IMAGE: &[u8] = include_bytes!("../../resources/os.obj");
+
+/// `RunResult` tells why `run` (or `run_for`) stopped.
+/// Like the error enums of the crate, it is `#[non_exhaustive]`: new reasons to stop may be added,
+/// so a match outside the crate needs a wildcard arm.
+///
+/// ```
+/// use lc3_vm::vm::{RunResult, VmError};
+///
+/// fn describe(result: RunResult) -> String {
+///     match result {
+///         RunResult::Halted => "halted".to_string(),
+///         RunResult::Breakpoint(pc) => format!("breakpoint at x{:04X}", pc),
+///         RunResult::Watchpoint { addr, .. } => format!("x{:04X} changed", addr),
+///         RunResult::BudgetExhausted | RunResult::TimedOut => "out of time".to_string(),
+///         RunResult::Error(VmError::InputExhausted) => "no more input".to_string(),
+///         RunResult::Error(error) => error.to_string(),
+///         RunResult::InfiniteLoop { pc } | RunResult::Returned { pc } => format!("stopped at x{:04X}", pc),
+///         RunResult::IoTrap { vector, .. } => format!("TRAP x{:02X}", vector),
+///         _ => "stopped".to_string(),
+///     }
+/// }
+///
+/// assert_eq!("halted", describe(RunResult::Halted));
+/// assert_eq!("breakpoint at x3000", describe(RunResult::Breakpoint(0x3000)));
+/// assert_eq!("no more input", describe(RunResult::Error(VmError::InputExhausted)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RunResult {
+    /// The machine halted (the clock was stopped).
+    Halted,
+    /// PC reached a breakpoint; the instruction at the address has not been executed yet.
+    Breakpoint(u16),
+    /// The last instruction changed a watched cell from `old` to `new`.
+    Watchpoint { addr: u16, old: u16, new: u16 },
+    /// The step budget ran out before anything else stopped the machine.
+    BudgetExhausted,
+    /// An instruction failed (see `VmError` for where PC is left).
+    Error(VmError),
+    /// The machine came back to `pc` with the same registers and condition codes (see `enable_loop_detection`).
+    InfiniteLoop { pc: u16 },
+    /// The current subroutine returned to `pc` (see `run_to_ret`).
+    Returned { pc: u16 },
+    /// PC reached the console TRAP at `pc` with trap vector `vector`, which has not been executed yet
+    /// (see `run_to_next_io`).
+    IoTrap { pc: u16, vector: u8 },
+    /// The wall-clock time ran out before anything else stopped the machine (see `run_with_timeout`).
+    TimedOut,
+    /// The last instruction took PC to `pc`, outside of everything which was loaded (see `set_pc_bounds_check`).
+    PcOutOfBounds { pc: u16 },
+    /// All the steps of a chunk ran and the machine can go on (see `step_n`).
+    Continue,
+}
+
+/// `LoadInfo` is where a loader put a program, e.g. to set up a disassembly range or `protect_range`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadInfo {
+    /// The address of the first word.
+    pub origin: u16,
+    /// The number of words loaded.
+    pub len: u16,
+    /// The address after the last word (`origin + len`, wrapping past xFFFF).
+    pub end: u16,
+}
+
+impl LoadInfo {
+    /// `range` is the loaded words as an `AddrRange`.
+    pub fn range(&self) -> AddrRange {
+        AddrRange::new(self.origin, self.len)
+    }
+}
+
+/// `VmFinalState` is what `Vm::consume_run` leaves of a machine once it has stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmFinalState<I: Io = StdIo> {
+    /// Why the run stopped.
+    pub halt: RunResult,
+    pub registers: Registers,
+    /// The `memory_checksum` of the final memory.
+    pub memory_checksum: u64,
+    /// The console, with whatever it captured (e.g. the output of a `BufferIo`).
+    pub io: I,
+}
+
+/// `TIMEOUT_CHECK_INTERVAL` is how many instructions `run_with_timeout` executes between two reads of the clock.
+const TIMEOUT_CHECK_INTERVAL: u32 = 1024;
+
+/// `Vm` is an LC-3 machine, whose console is connected to `I`.
+/// Cloning a `Vm` gives an independent snapshot of the whole machine (registers, memory and console).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vm<I: Io = StdIo> {
+    registers: Registers,
+    bus: SystemBus<I>,
+    /// When `true`, TRAP vectors through the trap vector table in memory instead of using the built-in routines.
+    os_traps: bool,
+    /// Execution counts per address, while profiling is enabled.
+    profile: Option<Profile>,
+    /// `run` stops before executing the instruction at any of these addresses.
+    breakpoints: BTreeSet<u16>,
+    /// `run` stops after an instruction changes the cell at any of these addresses.
+    watchpoints: BTreeSet<u16>,
+    /// One line per executed instruction, while tracing is enabled.
+    trace: Option<Vec<String>>,
+    /// The style of the trace lines.
+    trace_format: TraceFormat,
+    /// Receives a trace line per executed instruction, once attached.
+    trace_writer: TraceWriter,
+    /// The states of the last steps, while loop detection is enabled.
+    loop_detector: Option<LoopDetector>,
+    /// When `true`, the console trap routines fail with `VmError::IoDenied`.
+    io_denied: bool,
+    /// Whether the built-in HALT prints its banner, when not left to the console (see `set_halt_banner`).
+    halt_banner: Option<bool>,
+    /// What the built-in IN routine prints before reading, if anything (see `set_input_prompt`).
+    input_prompt: Option<String>,
+    /// When `true`, a BR which tests the condition codes before they were ever set is an error.
+    strict_condition: bool,
+    /// When `true`, an instruction whose reserved bits are not as the ISA requires is an error.
+    strict_encoding: bool,
+    /// When `true`, executing the trap or interrupt vector table outside of an interrupt handler is an error.
+    vector_table_guard: bool,
+    /// Follows calls and returns, while the calling convention check is enabled.
+    convention: Option<ConventionChecker>,
+    /// The R6 when stack tracking was enabled, and the deepest the stack has grown below it since, in words.
+    stack_depth: Option<(u16, u16)>,
+    /// Every store into the next instruction, while those warnings are on.
+    imminent_code_writes: Option<Vec<ImminentCodeWrite>>,
+    /// Every ADD which overflowed, while the overflow log is enabled.
+    overflows: Option<Vec<OverflowEvent>>,
+    /// Every register read or written, while the register trace is enabled.
+    register_accesses: Option<Vec<RegisterAccess>>,
+    /// The display output tagged with its source, while output events are enabled.
+    output_events: Option<OutputEvents>,
+    /// When `true`, a latched key interrupts the program (see `inject_interrupt_on_key`).
+    key_interrupts: bool,
+    /// The timer, once attached (see `attach_timer`).
+    timer: Option<Timer>,
+    /// The interrupted program, while an interrupt handler runs.
+    interrupted: Option<InterruptFrame>,
+    /// The privilege mode and the saved stack pointer (see `vm::interrupt`).
+    privilege: Privilege,
+    /// Called for every register an instruction changes.
+    register_listeners: RegisterListeners,
+    /// What the last steps changed, while time travel is enabled.
+    history: Option<History>,
+    /// The address and opcode of the first unimplemented instruction the program tried to execute.
+    attempted_unimplemented: Option<(u16, OpCode)>,
+    /// Sees every TRAP before the default routine (see `set_trap_hook`).
+    trap_hook: TrapHook,
+    /// The Rust routines registered by vector (see `register_named_trap`).
+    named_traps: NamedTraps,
+    /// The number of instructions executed since the last `reset`.
+    steps_executed: u64,
+    /// The address of the instruction which failed last, and its error.
+    last_error: Option<(u16, VmError)>,
+    /// The labels of the last program loaded with `load_program`.
+    symbols: SymbolTable,
+    /// The notes `disassemble_region` prints next to addresses (see `annotate`).
+    annotations: BTreeMap<u16, String>,
+    /// The regions written by `load_words` (and the loaders built on it) since the last `reset`.
+    loaded: Vec<AddrRange>,
+    /// When `true`, a run stops once PC leaves the loaded regions (see `set_pc_bounds_check`).
+    pc_bounds_check: bool,
+}
+
+impl Default for Vm<StdIo> {
+    fn default() -> Vm<StdIo> {
+        Vm::new()
+    }
+}
+
+impl Vm<StdIo> {
+    /// `new()` creates a machine connected to the terminal, using the built-in trap routines.
+    pub fn new() -> Vm<StdIo> {
+        Vm::with_io(StdIo::new())
+    }
+
+    /// `new_with_os()` creates a machine connected to the terminal, with the operating system image loaded.
+    pub fn new_with_os() -> Vm<StdIo> {
+        let mut vm = Vm::new();
+        vm.load_os();
+        vm
+    }
+}
+
+/// Only a buffered console can be saved with the machine: what a terminal has read or shown cannot be taken back.
+impl Vm<BufferIo> {
+    /// `save_state` is `export_core_dump` with the console appended: the input the program has not read yet
+    /// and the output so far, so a session can be resumed where it was saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let info = CoreDumpInfo {
+            steps: self.steps_executed,
+            failing_pc: self.last_error.map(|(pc, _)| pc),
+            reason: self.last_error.map(|(_, error)| error.to_string()),
+        };
+        let console = core_dump::Console {
+            input: self.io().pending_bytes(),
+            output: self.io().output().to_vec(),
+        };
+        core_dump::write(&self.registers, &self.bus.memory, &info, Some(&console))
+    }
+
+    /// `load_state` loads a state written by `save_state` like `import_core_dump` does, and also gives the console
+    /// its input queue and output back. The console is kept as it is if the dump has none (see `export_core_dump`).
+    pub fn load_state(&mut self, bytes: &[u8]) -> std::io::Result<CoreDumpInfo> {
+        let (registers, memory, info, console) = core_dump::read(bytes)?;
+        self.registers = registers;
+        self.bus.memory = memory;
+        self.steps_executed = info.steps;
+        self.last_error = None;
+        self.interrupted = None;
+        self.privilege = Privilege::default();
+        if let Some(console) = console {
+            self.io_mut().restore(&console.input, &console.output);
+        }
+        Ok(info)
+    }
+}
+
+impl<I: Io> Vm<I> {
+    /// `with_io` creates a machine with empty memory, whose console is connected to `io`.
+    pub fn with_io(io: I) -> Vm<I> {
+        Vm::with_memory(Memory::new(), io)
+    }
+
+    /// `with_buffer` creates a machine with empty memory in the buffer `cells`, whose console is connected to `io`.
+    /// Together with `into_memory`, this lets a host which runs many short programs reuse one 128kb buffer
+    /// instead of allocating a new one per machine.
+    pub fn with_buffer(cells: Box<[u16; MEMORY_SIZE]>, io: I) -> Vm<I> {
+        Vm::with_memory(Memory::from_cells(cells), io)
+    }
+
+    /// `into_memory` consumes the machine and gives back the buffer of its memory, for the next `with_buffer`.
+    pub fn into_memory(self) -> Box<[u16; MEMORY_SIZE]> {
+        self.bus.memory.into_cells()
+    }
+
+    /// `with_memory` creates a machine whose memory starts out as `memory` (e.g. a program read by `file::read_file`).
+    pub fn with_memory(memory: Memory, io: I) -> Vm<I> {
+        Vm {
+            registers: Registers::new(),
+            bus: SystemBus::new(memory, io),
+            os_traps: false,
+            profile: None,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            trace: None,
+            trace_format: TraceFormat::default(),
+            trace_writer: TraceWriter::default(),
+            loop_detector: None,
+            io_denied: false,
+            halt_banner: None,
+            input_prompt: Some(trap::IN_PROMPT.to_string()),
+            strict_condition: false,
+            strict_encoding: false,
+            vector_table_guard: false,
+            convention: None,
+            stack_depth: None,
+            imminent_code_writes: None,
+            overflows: None,
+            register_accesses: None,
+            output_events: None,
+            key_interrupts: false,
+            timer: None,
+            interrupted: None,
+            privilege: Privilege::default(),
+            register_listeners: RegisterListeners::default(),
+            history: None,
+            attempted_unimplemented: None,
+            trap_hook: TrapHook::default(),
+            named_traps: NamedTraps::default(),
+            steps_executed: 0,
+            last_error: None,
+            symbols: SymbolTable::new(),
+            annotations: BTreeMap::new(),
+            loaded: Vec::new(),
+            pc_bounds_check: false,
+        }
+    }
+
+    /// `load_os` loads the operating system image into low memory.
+    /// From then on, TRAP instructions vector through the OS trap vector table (as on a real LC-3),
+    /// rather than using the built-in Rust trap routines.
+    pub fn load_os(&mut self) {
+        let mut words = OS_IMAGE
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+        let origin = words.next().expect("OS image has an origin.");
+        let words: Vec<u16> = words.collect();
+        self.load_words(origin, &words);
+        self.os_traps = true;
+    }
+
+    /// `load_words` copies `words` into memory, starting at `origin`.
+    pub fn load_words(&mut self, origin: u16, words: &[u16]) {
+        for (i, &word) in words.iter().enumerate() {
+            self.bus
+                .memory
+                .write(origin.wrapping_add(i as u16) as usize, word);
+        }
+        let len = words.len() as u16;
+        match self.loaded.last_mut() {
+            //a load which continues the last one, e.g. the next line of `assembler::assemble_into`
+            Some(last) if last.start.wrapping_add(last.len) == origin => {
+                last.len = last.len.saturating_add(len)
+            }
+            _ if len > 0 => self.loaded.push(AddrRange::new(origin, len)),
+            _ => {}
+        }
+    }
+
+    /// `load_program` loads every section of an assembled `program`, sets the PC to its entry point
+    /// and keeps its labels (see `symbols`).
+    pub fn load_program(&mut self, program: &Program) {
+        for section in program.sections.iter() {
+            self.load_words(section.origin, &section.words);
+        }
+        self.registers.set_pc(program.entry());
+        self.symbols = program.symbols.clone();
+    }
+
+    /// `assemble_and_load` assembles `source` and loads it like `load_program`, returning the warnings of the
+    /// assembler. Nothing is loaded if it does not assemble. `.INCBIN` and `.INCLUDE` paths are relative to the
+    /// current directory; see `load_source_file` for a source file.
+    pub fn assemble_and_load(
+        &mut self,
+        source: &str,
+    ) -> Result<Vec<AssembleWarning>, AssembleError> {
+        let program = assembler::assemble(source)?;
+        self.load_program(&program);
+        Ok(program.warnings)
+    }
+
+    /// `symbols` are the labels of the last program loaded with `load_program` (or `assemble_and_load`,
+    /// or `load_source_file`), e.g. to show symbolic addresses in a debugger. They are empty before that.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub(crate) fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// `find_symbol_at` names `address` after the closest preceding label of the loaded program, with the
+    /// offset from it (see `SymbolTable::nearest_below`), e.g. to report a PC as `FUNC+3`.
+    pub fn find_symbol_at(&self, address: u16) -> Option<(&str, u16)> {
+        self.symbols.nearest_below(address)
+    }
+
+    /// `explain` describes the instruction at PC in plain English, for a tutorial mode (see `DecodedInstr::explain`).
+    pub fn explain(&self) -> String {
+        let pc = self.registers.pc();
+        DecodedInstr::decode(self.peek(pc)).explain(pc)
+    }
+
+    /// `annotate` attaches the free-form `note` to `address`, replacing its previous note, e.g. to record
+    /// what a routine found while reverse-engineering it does. `disassemble_region` prints it after the instruction.
+    pub fn annotate(&mut self, address: u16, note: String) {
+        self.annotations.insert(address, note);
+    }
+
+    /// `annotation` is the note attached to `address` with `annotate`, if any.
+    pub fn annotation(&self, address: u16) -> Option<&str> {
+        self.annotations.get(&address).map(String::as_str)
+    }
+
+    /// `disassemble_region` renders the words of `region`, one line each: the address, the word and its instruction,
+    /// followed by `; note` if the address is annotated.
+    ///
+    /// ```text
+    /// x3000: x1021  ADD R0, R0, #1 ; count the key
+    /// x3001: xF025  HALT
+    /// ```
+    pub fn disassemble_region(&self, region: AddrRange) -> String {
+        let mut text = String::new();
+        for address in region.iter() {
+            let word = self.peek(address);
+            text.push_str(&format!(
+                "x{:04X}: x{:04X}  {}",
+                address,
+                word,
+                DecodedInstr::decode(word)
+            ));
+            if let Some(note) = self.annotation(address) {
+                text.push_str(" ; ");
+                text.push_str(note);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// `load_source_file` reads and assembles the source file at `path` (`.INCBIN` paths are relative to its directory),
+    /// then loads it like `load_program`.
+    pub fn load_source_file(&mut self, path: &Path) -> Result<(), BuildError> {
+        let display = path.display().to_string();
+        let source = std::fs::read_to_string(path).map_err(|error| BuildError::Io {
+            path: display.clone(),
+            error,
+        })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let program =
+            assembler::assemble_in(&source, dir).map_err(|error| BuildError::Assemble {
+                path: display,
+                error,
+            })?;
+        self.load_program(&program);
+        Ok(())
+    }
+
+    /// `load_hex_string` parses `text` as whitespace-separated 4-digit hex words (see `crate::hex`),
+    /// copies them into memory starting at `origin` and points PC at `origin`.
+    /// Nothing is loaded if `text` is malformed.
+    pub fn load_hex_string(&mut self, origin: u16, text: &str) -> Result<(), ParseError> {
+        let words = hex::parse_hex_words(text)?;
+        self.load_words(origin, &words);
+        self.registers.set_pc(origin);
+        Ok(())
+    }
+
+    /// `load_obj_verified` loads the `.obj` image `bytes` and points PC at its origin, if the checksum of its words
+    /// (as `region_checksum` computes it once they are loaded) is `expected_checksum`.
+    /// Nothing is loaded if the image is malformed or corrupted.
+    pub fn load_obj_verified(
+        &mut self,
+        bytes: &[u8],
+        expected_checksum: u64,
+    ) -> Result<(), LoadError> {
+        let (origin, words) = hex::obj_words(bytes)?;
+        hex::check_fits(origin, words.len())?;
+        let actual = words_checksum(words.iter().copied());
+        if actual != expected_checksum {
+            return Err(LoadError::ChecksumMismatch {
+                expected: expected_checksum,
+                actual,
+            });
+        }
+        self.load_words(origin, &words);
+        self.registers.set_pc(origin);
+        Ok(())
+    }
+
+    /// `load_obj` loads the `.obj` image `bytes` at the origin in the image, points PC at it, and tells where
+    /// the words went. Nothing is loaded if the image is malformed or does not fit below xFFFF.
+    pub fn load_obj(&mut self, bytes: &[u8]) -> Result<LoadInfo, LoadError> {
+        let (origin, words) = hex::obj_words(bytes)?;
+        hex::check_fits(origin, words.len())?;
+        self.load_words(origin, &words);
+        self.registers.set_pc(origin);
+        let len = words.len() as u16;
+        Ok(LoadInfo {
+            origin,
+            len,
+            end: origin.wrapping_add(len),
+        })
+    }
+
+    /// `load_obj_at` loads the body of the `.obj` image `bytes` at `new_origin` instead of the origin in the image,
+    /// and points PC at `new_origin`. Only position-independent code works after such a move: PC-relative
+    /// instructions (BR, LD, LEA, JSR, ...) do, but absolute addresses stored in the program (`.FILL LABEL`,
+    /// see `AssembleOptions::relocation_warnings`) still point into the old location.
+    pub fn load_obj_at(&mut self, bytes: &[u8], new_origin: u16) -> Result<(), LoadError> {
+        let (_, words) = hex::obj_words(bytes)?;
+        hex::check_fits(new_origin, words.len())?;
+        self.load_words(new_origin, &words);
+        self.registers.set_pc(new_origin);
+        Ok(())
+    }
+
+    /// `memory_pages_used` lists the 256-word pages the program has written to, by number (the high byte of
+    /// their addresses, so page x30 is x3000 - x30FF), in ascending order, e.g. to draw a program's footprint.
+    /// Only the program's stores count, not the words put in by the loaders, `poke` or `reset`, which forgets them.
+    pub fn memory_pages_used(&self) -> Vec<u8> {
+        self.bus.touched_pages()
+    }
+
+    /// `reset` restarts the machine with `memory`, at `entry`: the other registers and the device registers
+    /// get their power-on values, while the console, the breakpoints and the trap mode are kept.
+    pub fn reset(&mut self, memory: Memory, entry: u16) {
+        self.restart(entry);
+        self.loaded.clear();
+        self.bus.replace_memory(memory);
+    }
+
+    /// `soft_reset` is `reset` without touching memory, e.g. to run the loaded program again: the registers and
+    /// the device registers get their power-on values (so PC is back at x3000 and the clock runs), while the
+    /// program, its data and everything else `reset` keeps stay as they are. A program which changed its own data
+    /// sees the changed data on the next run.
+    pub fn soft_reset(&mut self) {
+        self.restart(PC_START);
+        self.bus.power_on();
+    }
+
+    //the registers and the state of the run, as `reset` and `soft_reset` leave them
+    fn restart(&mut self, entry: u16) {
+        self.registers = Registers::new();
+        self.registers.set_pc(entry);
+        self.interrupted = None;
+        self.privilege = Privilege::default();
+        if let Some(timer) = self.timer.as_mut() {
+            timer.take();
+        }
+        self.attempted_unimplemented = None;
+        self.steps_executed = 0;
+        self.last_error = None;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+    }
+
+    /// `resume_at` restarts a halted machine at `address`, e.g. for a supervisor which runs user programs
+    /// one after the other: unlike `reset`, the memory, the registers (except PC) and the devices are kept.
+    /// `run` reports each HALT as `RunResult::Halted`, after which the supervisor picks the next entry point.
+    pub fn resume_at(&mut self, address: u16) {
+        self.bus.start_clock();
+        self.registers.set_pc(address);
+    }
+
+    /// `boot_from_reset_vector` boots the machine the way many processors come out of reset: the registers and
+    /// the state of the run (e.g. `steps_executed`) get their power-on values as with `soft_reset`, the clock is
+    /// started, and PC is loaded from the word at `vector` (rather than starting at x3000), so the boot code in
+    /// memory decides where execution begins. It returns that entry point.
+    /// Memory, the console and the devices are kept, so load the image (and its reset vector) first.
+    pub fn boot_from_reset_vector(&mut self, vector: u16) -> u16 {
+        let entry = self.bus.memory.read(vector);
+        self.restart(entry);
+        self.bus.start_clock();
+        entry
+    }
+
+    /// `registers` gives read access to the registers.
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// `registers_mut` gives write access to the registers, e.g. for a debugger.
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// `reg` returns register `index`, numbered as in `Registers::get`: 0 - 7 are R0 - R7, 8 is PC
+    /// and 9 the condition codes.
+    pub fn reg(&self, index: u16) -> u16 {
+        self.registers.get(index)
+    }
+
+    /// `set_reg` sets register `index` (numbered as in `reg`) to `value`.
+    pub fn set_reg(&mut self, index: u16, value: u16) {
+        self.registers.update(index, value);
+    }
+
+    /// `add_breakpoint` makes `run` stop before executing the instruction at `address`.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// `add_breakpoint_range` sets a breakpoint at every address of `range`, e.g. to stop anywhere in a routine.
+    pub fn add_breakpoint_range(&mut self, range: AddrRange) {
+        self.breakpoints.extend(range.iter());
+    }
+
+    /// `remove_breakpoint` removes the breakpoint at `address`, returning `false` if there was none.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// `breakpoints` returns the breakpoint addresses in ascending order.
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    /// `clear_all_breakpoints` removes every breakpoint.
+    pub fn clear_all_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// `add_watchpoint` makes `run` stop after an instruction changes the cell at `address`.
+    pub fn add_watchpoint(&mut self, address: u16) {
+        self.watchpoints.insert(address);
+    }
+
+    /// `add_watchpoint_range` watches every cell of `range`, e.g. a whole array.
+    pub fn add_watchpoint_range(&mut self, range: AddrRange) {
+        self.watchpoints.extend(range.iter());
+    }
+
+    /// `remove_watchpoint` removes the watchpoint at `address`, returning `false` if there was none.
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    /// `watchpoints` returns the watched addresses in ascending order.
+    pub fn watchpoints(&self) -> Vec<u16> {
+        self.watchpoints.iter().copied().collect()
+    }
+
+    /// `clear_all_watchpoints` removes every watchpoint.
+    pub fn clear_all_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// `peek` reads the cell at `address` for a debugger: device registers are read without side effects
+    /// (a KBDR peek does not consume a key) and protected regions are ignored.
+    pub fn peek(&self, address: u16) -> u16 {
+        self.bus.memory.read(address)
+    }
+
+    /// `poke` writes the cell at `address` for a debugger: device registers are written without side effects
+    /// (a DDR poke prints nothing) and protected regions are ignored.
+    pub fn poke(&mut self, address: u16, value: u16) {
+        self.bus.memory.write(address as usize, value);
+    }
+
+    /// `memory_checksum` is a stable 64-bit hash (FNV-1a) of all 64K words of memory.
+    /// Equal memories always have equal checksums, so comparing checksums is a cheap "did anything change?".
+    pub fn memory_checksum(&self) -> u64 {
+        self.memory_hash().finish()
+    }
+
+    /// `region_checksum` is the FNV-1a hash of the `len` words starting at `origin`, e.g. of a loaded program
+    /// (see `load_obj_verified`).
+    pub fn region_checksum(&self, origin: u16, len: u16) -> u64 {
+        words_checksum((0..len).map(|i| self.peek(origin.wrapping_add(i))))
+    }
+
+    /// `memory_region_equal` tells whether the words starting at `start` are `expected`, e.g. to grade the output
+    /// region of a program. Cells are read as by `peek`; a region running past xFFFF wraps to x0000.
+    pub fn memory_region_equal(&self, start: u16, expected: &[u16]) -> bool {
+        self.first_region_mismatch(start, expected).is_none()
+    }
+
+    /// `first_region_mismatch` is the lowest-offset address in the region of `memory_region_equal` whose word
+    /// differs from `expected`, or `None` if the whole region matches.
+    pub fn first_region_mismatch(&self, start: u16, expected: &[u16]) -> Option<u16> {
+        (0..expected.len())
+            .map(|i| start.wrapping_add(i as u16))
+            .zip(expected)
+            .find(|&(address, &word)| self.peek(address) != word)
+            .map(|(address, _)| address)
+    }
+
+    /// `state_checksum` is `memory_checksum` extended with the registers (R0 - R7, PC, COND and IR).
+    pub fn state_checksum(&self) -> u64 {
+        let mut hash = self.memory_hash();
+        for r in 0..10 {
+            hash.write_u16(self.registers.get(r));
+        }
+        hash.write_u16(self.registers.r_ir);
+        hash.finish()
+    }
+
+    fn memory_hash(&self) -> Fnv64 {
+        let mut hash = Fnv64::new();
+        for address in 0..=0xFFFF {
+            hash.write_u16(self.peek(address));
+        }
+        hash
+    }
+
+    /// `memory` gives read access to the memory, without any memory-mapped I/O side effects.
+    pub fn memory(&self) -> &Memory {
+        &self.bus.memory
+    }
+
+    /// `memory_mut` gives write access to the memory, without any memory-mapped I/O side effects.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.bus.memory
+    }
+
+    /// `io` gives access to the console, e.g. to inspect the output of a `BufferIo`.
+    pub fn io(&self) -> &I {
+        &self.bus.io
+    }
+
+    /// `io_mut` gives mutable access to the console, e.g. to queue more input for a `BufferIo`.
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.bus.io
+    }
+
+    /// `protect` restricts the addresses in `range` to `perms`, e.g. `Perms::READ` for a read-only region.
+    /// A load, store or fetch which is not permitted fails with `VmError::ProtectionFault`.
+    /// Regions added later override earlier ones; every address starts out with `Perms::ALL`.
+    pub fn protect(&mut self, range: RangeInclusive<u16>, perms: Perms) {
+        self.bus.memory.protect(range, perms);
+    }
+
+    /// `protect_range` is `protect` for an `AddrRange`, which may wrap from xFFFF to x0000.
+    pub fn protect_range(&mut self, range: AddrRange, perms: Perms) {
+        for segment in range.segments() {
+            self.bus.memory.protect(segment, perms);
+        }
+    }
+
+    /// `attach_device` connects a memory-mapped `device` to the addresses in `range`.
+    /// Loads, stores and fetches try the attached devices first (the earliest attached first),
+    /// then the built-in device registers, then memory.
+    pub fn attach_device(&mut self, range: RangeInclusive<u16>, device: Box<dyn Device>) {
+        self.bus.attach_device(range, device);
+    }
+
+    /// `enable_profiling` starts counting how many times each address is executed (see `hotspots`) and how often
+    /// each BR is taken (see `branch_stats`).
+    /// Profiling is off by default, as it slows every step down.
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Profile::default);
+    }
+
+    /// `enable_access_counts` starts counting the loads and stores of every address (see `access_counts`),
+    /// e.g. for a heatmap of memory traffic. It is off by default, as it costs 512kb and slows every access down.
+    pub fn enable_access_counts(&mut self) {
+        self.bus.access_counts.get_or_insert_with(Default::default);
+    }
+
+    /// `access_counts` returns the loads and stores counted since `enable_access_counts`, if it was called.
+    pub fn access_counts(&self) -> Option<&AccessCounts> {
+        self.bus.access_counts.as_deref()
+    }
+
+    /// `enable_cache` models a direct-mapped data cache of `lines` lines of `line_words` words between the CPU
+    /// and memory, starting empty, and counts its hits and misses (see `cache_stats`). The program runs as before.
+    /// Calling it again starts over with the new geometry. Panics if `lines` or `line_words` is zero.
+    pub fn enable_cache(&mut self, lines: u16, line_words: u16) {
+        self.bus.cache = Some(CacheModel::new(lines, line_words));
+    }
+
+    /// `cache_stats` returns the hits and misses since `enable_cache`, if it was called.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.bus.cache.as_ref().map(CacheModel::stats)
+    }
+
+    /// `hotspots` returns the `top_n` most executed addresses with their execution counts, most executed first.
+    /// It is empty unless profiling was enabled; `format_hotspots` shows them with label names.
+    pub fn hotspots(&self, top_n: usize) -> Vec<(u16, u64)> {
+        self.profile
+            .as_ref()
+            .map_or_else(Vec::new, |profile| profile.hotspots(top_n))
+    }
+
+    /// `branch_stats` returns, for every BR executed while profiling, how often it was taken and how often
+    /// it fell through, in address order. An unconditional BR is always taken, and one with no condition never is.
+    pub fn branch_stats(&self) -> Vec<(u16, BranchStats)> {
+        self.profile
+            .as_ref()
+            .map_or_else(Vec::new, Profile::branch_stats)
+    }
+
+    /// `stats_csv` returns the number of executions and the estimated cycles of every opcode as CSV,
+    /// one `opcode,count,cycles` row per opcode and a final `total` row. The counts are 0 unless profiling was enabled.
+    pub fn stats_csv(&self) -> String {
+        self.profile
+            .as_ref()
+            .map_or_else(|| Profile::default().stats_csv(), Profile::stats_csv)
+    }
+
+    /// `performance_report` summarises a profiled run for a writeup, in sections: the number of instructions and
+    /// the estimated cycles, the share of every opcode executed, the outcomes of every BR, and the maximum stack depth
+    /// (see `enable_stack_tracking`). The counts are 0 unless profiling was enabled.
+    pub fn performance_report(&self) -> String {
+        self.profile
+            .as_ref()
+            .map_or_else(Profile::default, Profile::clone)
+            .report(self.max_stack_depth())
+    }
+
+    /// `enable_trace` starts recording a line per executed instruction, in the reference format
+    /// `AFTER x3001: R0=x0005 CC=P` (see `vm::trace`), so that runs can be diffed against other simulators.
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// `set_trace_format` chooses the style of the trace lines recorded from now on, by `enable_trace` and an attached
+    /// trace writer (the default is the reference format, `TraceFormat::LcThree`). The binary trace is not affected.
+    pub fn set_trace_format(&mut self, format: TraceFormat) {
+        self.trace_format = format;
+    }
+
+    /// `trace` returns the lines recorded since tracing was enabled (none, if it is not).
+    pub fn trace(&self) -> &[String] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// `attach_trace_writer` streams the trace (in the same format as `trace`) to `writer`, one line per executed
+    /// instruction. Lines are buffered: call `flush_trace_writer` to be sure they have reached `writer`.
+    /// A clone of the machine does not inherit the writer.
+    pub fn attach_trace_writer(&mut self, writer: Box<dyn Write>) {
+        self.trace_writer.attach(writer);
+    }
+
+    /// `attach_binary_trace_writer` is `attach_trace_writer`, but streams the compact binary form of the trace
+    /// (see `vm::binary_trace`); `BinaryTraceReader` turns it back into trace lines. It replaces any attached writer.
+    pub fn attach_binary_trace_writer(&mut self, writer: Box<dyn Write>) {
+        self.trace_writer.attach_binary(writer);
+    }
+
+    /// `flush_trace_writer` writes out the buffered trace lines, or returns the first error met while writing them.
+    pub fn flush_trace_writer(&mut self) -> io::Result<()> {
+        self.trace_writer.flush()
+    }
+
+    /// `enable_loop_detection` makes `run` stop with `RunResult::InfiniteLoop` once the machine is back at a PC
+    /// with the same registers and condition codes as at one of the last `window` steps. Memory is not compared,
+    /// so a loop which only makes progress in memory (or polls a device) is reported as well.
+    pub fn enable_loop_detection(&mut self, window: usize) {
+        self.loop_detector = Some(LoopDetector::new(window));
+    }
+
+    /// `enable_history` turns on time travel: from now on, the last `capacity` steps can be undone with `step_back`.
+    /// Each step is stored as a delta (the registers before it and the old values of the cells it stored to),
+    /// so a long session costs a few dozen bytes per step rather than a snapshot of memory
+    /// (see `history_memory_usage`). A step back also restores what the run keeps outside the registers: whether an
+    /// interrupt or exception handler was running, `steps_executed` and the timer (see `vm::history`).
+    /// Console output is only taken back, and consumed input re-queued, with a console which buffers them
+    /// (like `BufferIo`, see `Io::truncate_output` and `Io::unread`); devices are not undone.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    /// `step_back` undoes the most recent recorded step, returning `false` if there is none
+    /// (time travel is disabled, or the history is used up).
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.as_mut().and_then(History::pop) {
+            Some(delta) => delta,
+            None => return false,
+        };
+        for &(address, old) in delta.writes.iter().rev() {
+            self.bus.restore(address, old);
+        }
+        if let Some(len) = delta.output_len {
+            self.bus.io.truncate_output(len);
+        }
+        self.bus.io.unread(&delta.input);
+        let [kbsr, kbdr] = delta.keyboard;
+        self.bus.memory.write(MemoryMappedReg::Kbsr as usize, kbsr);
+        self.bus.memory.write(MemoryMappedReg::Kbdr as usize, kbdr);
+        if let Some((timer, status)) = delta.timer.map(|timer| *timer) {
+            self.bus.memory.write(timer::TIMER_STATUS as usize, status);
+            self.timer = Some(timer);
+        }
+        self.registers = delta.registers;
+        self.interrupted = delta.interrupted;
+        self.privilege = delta.privilege;
+        self.steps_executed = delta.steps_executed;
+        true
+    }
+
+    /// `history_len` is the number of steps `step_back` can undo.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// `history_memory_usage` estimates the bytes the time travel history takes up.
+    pub fn history_memory_usage(&self) -> usize {
+        self.history.as_ref().map_or(0, History::memory_usage)
+    }
+
+    /// `deny_io` sandboxes the machine for pure computation: from now on, the console trap routines
+    /// (GETC, OUT, PUTS, IN, PUTSP, PUTD and REGS) fail with `VmError::IoDenied` instead of touching the console.
+    /// HALT still stops the machine, but silently, as its message would be output too.
+    /// Only TRAPs are checked: the device registers can still be reached with loads and stores (see `protect`).
+    pub fn deny_io(&mut self) {
+        self.io_denied = true;
+    }
+
+    /// `set_halt_banner` chooses whether the built-in HALT routine prints its `HALT` banner. By default the
+    /// console decides (`Io::halt_banner`): a terminal shows it, a `BufferIo` does not, so captured output is
+    /// only the program's own. The HALT routine of the OS image (see `load_os`) is a program, and always prints.
+    pub fn set_halt_banner(&mut self, enabled: bool) {
+        self.halt_banner = Some(enabled);
+    }
+
+    /// `set_input_prompt` chooses what the built-in IN routine prints before it reads a character:
+    /// `trap::IN_PROMPT` by default, while `None` (or an empty prompt) reads without printing anything.
+    /// The IN routine of the OS image (see `load_os`) is a program, and keeps its own prompt.
+    pub fn set_input_prompt(&mut self, prompt: Option<String>) {
+        self.input_prompt = prompt;
+    }
+
+    fn prints_halt_banner(&self) -> bool {
+        self.halt_banner
+            .unwrap_or_else(|| self.bus.io.halt_banner())
+    }
+
+    /// `inject_interrupt_on_key` routes keystrokes into keyboard interrupts, handled by the routine at `handler`:
+    /// it puts `handler` in the interrupt vector table (x0180) and sets the interrupt enable bit of the KBSR.
+    /// From then on, whenever a key is queued and no handler is running, the program is interrupted before its next
+    /// instruction; the handler reads the KBDR and returns with RTI (see `vm::interrupt` for the simplifications).
+    pub fn inject_interrupt_on_key(&mut self, handler: u16) {
+        self.bus
+            .memory
+            .write(interrupt::KEYBOARD_VECTOR as usize, handler);
+        self.bus.enable_key_interrupt();
+        self.key_interrupts = true;
+    }
+
+    /// `attach_timer` connects `timer`, replacing the previous one, and sets the interrupt enable bit of its status
+    /// register (TMR, xFE08). From then on, every tick interrupts the program before its next instruction, through the
+    /// timer's entry of the interrupt vector table, which the program installs its handler in (see `vm::timer`).
+    /// A key interrupt which is due at the same time goes first.
+    pub fn attach_timer(&mut self, timer: Timer) {
+        timer::enable(&mut self.bus.memory);
+        self.timer = Some(timer);
+    }
+
+    /// `detach_timer` disconnects the timer; the TMR keeps its last value.
+    pub fn detach_timer(&mut self) {
+        self.timer = None;
+    }
+
+    /// `set_max_output` limits how many bytes the program may send to the display from now on, so e.g. a PUTS
+    /// of memory without a terminator cannot spew all 64K words: the write after the last allowed byte
+    /// fails with `VmError::OutputLimitExceeded`. `None` (the default) lifts the limit.
+    pub fn set_max_output(&mut self, limit: Option<usize>) {
+        self.bus.set_output_limit(limit);
+    }
+
+    /// `set_read_only_policy` chooses what a program store to a read-only device register (the KBDR, the DSR or
+    /// the ready bit of the KBSR) does: by default it is allowed, as it always was.
+    /// Loads and stores through `poke` or `memory_mut` are never checked.
+    pub fn set_read_only_policy(&mut self, policy: ReadOnlyPolicy) {
+        self.bus.set_read_only_policy(policy);
+    }
+
+    /// `set_eof_policy` chooses what GETC and IN (any blocking read of the KBDR) give once the input has ended:
+    /// by default xFFFF, as before, or another value, or a `VmError::InputExhausted` for batch runs
+    /// which must not read past their input.
+    pub fn set_eof_policy(&mut self, policy: EofPolicy) {
+        self.bus.set_eof_policy(policy);
+    }
+
+    /// `set_input_poll_limit` bounds how long input waits, e.g. for automated grading with an empty input queue:
+    /// after `limit` keyboard polls without a key (a built-in GETC or IN polls until it gets one, the OS image
+    /// reads the KBSR in a loop), the read fails with `VmError::InputTimedOut`. `None` (the default) waits
+    /// for the console as before; the end of input of a `BufferIo` is then read as the `EofPolicy` says.
+    pub fn set_input_poll_limit(&mut self, limit: Option<u32>) {
+        self.bus.set_input_poll_limit(limit);
+    }
+
+    /// `set_strict_condition` turns strict CC mode on or off (it is off by default).
+    /// In strict mode, a BR which tests any flag while no instruction has set the condition codes yet
+    /// (`r_cond` is still `None`, as after power-on or `reset`) fails with `VmError::UninitializedCondition`.
+    pub fn set_strict_condition(&mut self, enabled: bool) {
+        self.strict_condition = enabled;
+    }
+
+    /// `set_strict_encoding` turns strict encoding mode on or off (it is off by default, like the hardware,
+    /// which ignores reserved bits). In strict mode, an instruction which is not `fields::is_well_formed`
+    /// (e.g. an ADD register form with bits [4:3] set) fails with `VmError::MalformedInstruction`.
+    pub fn set_strict_encoding(&mut self, enabled: bool) {
+        self.strict_encoding = enabled;
+    }
+
+    /// `set_pc_bounds_check` turns the PC bounds check on or off (it is off by default). While it is on, a run stops
+    /// with `RunResult::PcOutOfBounds` as soon as an instruction takes PC outside of every region loaded with
+    /// `load_words` or the loaders built on it (`load_program`, `load_obj`, `load_os`, ...), e.g. when a program without
+    /// a HALT runs off its last instruction into whatever follows. Memory given to `with_memory`, `reset` or
+    /// `poke` is not a loaded region. A halted machine is not checked, as PC is left after the HALT.
+    pub fn set_pc_bounds_check(&mut self, enabled: bool) {
+        self.pc_bounds_check = enabled;
+    }
+
+    /// `set_vector_table_guard` turns the vector table guard on or off (it is off by default). The vector tables
+    /// (x0000 - x01FF) hold addresses rather than code, so a program which ends up executing there has almost
+    /// always jumped through a bad pointer; with the guard on, that fails with `VmError::ExecInVectorTable`.
+    /// Only interrupt handlers and the privilege mode exception handler are exempt: leave the guard off for
+    /// an OS image which puts code in low memory.
+    pub fn set_vector_table_guard(&mut self, enabled: bool) {
+        self.vector_table_guard = enabled;
+    }
+
+    /// `enable_convention_check` starts checking that subroutines and vectored trap routines give R1 - R5 and the
+    /// stack pointer R6 back unchanged when they RET (see `convention_violations`). This is instrumentation for teaching, off by default.
+    pub fn enable_convention_check(&mut self) {
+        self.convention
+            .get_or_insert_with(ConventionChecker::default);
+    }
+
+    /// `convention_violations` returns the calls which returned with callee-saved registers changed
+    /// or the stack unbalanced (none, if the check is not enabled).
+    pub fn convention_violations(&self) -> &[ConventionViolation] {
+        self.convention
+            .as_ref()
+            .map_or(&[], |convention| convention.violations())
+    }
+
+    /// `enable_stack_tracking` starts tracking how deep the stack grows (see `max_stack_depth`), e.g. for a memory
+    /// budget. The stack pointer is R6 and the stack is taken to grow downward, as in the LC-3 calling convention:
+    /// the depth is how far R6 has gone below the value it holds now, so set up R6 before enabling the tracking.
+    pub fn enable_stack_tracking(&mut self) {
+        self.stack_depth = Some((self.registers.get(STACK_POINTER), 0));
+    }
+
+    /// `max_stack_depth` returns the largest number of words R6 went below its value at `enable_stack_tracking`,
+    /// if it was called. Popping above that value does not count as depth.
+    pub fn max_stack_depth(&self) -> Option<u16> {
+        self.stack_depth.map(|(_, depth)| depth)
+    }
+
+    /// `warn_imminent_code_write` turns the warning about stores into the next instruction on or off (it is off by
+    /// default). While it is on, a store to the address the machine fetches next (PC, after the store) is logged as
+    /// a warning and recorded (see `imminent_code_writes`); the instruction then executes as stored, as on hardware.
+    /// Turning it off forgets what was recorded.
+    pub fn warn_imminent_code_write(&mut self, enabled: bool) {
+        self.imminent_code_writes = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// `imminent_code_writes` returns the stores into the next instruction recorded since the warning was turned on.
+    pub fn imminent_code_writes(&self) -> &[ImminentCodeWrite] {
+        self.imminent_code_writes.as_deref().unwrap_or_default()
+    }
+
+    /// `enable_overflow_log` starts recording every ADD whose signed result overflows (see `overflow_events`).
+    /// The LC-3 itself has no overflow flag; this is instrumentation for teaching two's complement, off by default.
+    pub fn enable_overflow_log(&mut self) {
+        self.overflows.get_or_insert_with(Vec::new);
+    }
+
+    /// `overflow_events` returns the overflows recorded since the log was enabled (none, if it is not).
+    pub fn overflow_events(&self) -> &[OverflowEvent] {
+        self.overflows.as_deref().unwrap_or_default()
+    }
+
+    /// `set_register_trace` turns the register access trace on or off (it is off by default). While it is on,
+    /// every instruction records the registers it reads and writes (see `register_accesses`), e.g. to build
+    /// a data-flow graph. Turning it off drops the recorded accesses.
+    pub fn set_register_trace(&mut self, enabled: bool) {
+        if !enabled {
+            self.register_accesses = None;
+        } else {
+            self.register_accesses.get_or_insert_with(Vec::new);
+        }
+    }
+
+    /// `register_accesses` returns the accesses recorded since the trace was turned on, oldest first.
+    pub fn register_accesses(&self) -> &[RegisterAccess] {
+        self.register_accesses.as_deref().unwrap_or_default()
+    }
+
+    /// `enable_output_events` starts tagging every chunk of display output with the trap (or the store) which wrote it
+    /// and its PC (see `output_events`). The bytes still go to the console as usual.
+    pub fn enable_output_events(&mut self) {
+        self.output_events.get_or_insert_with(OutputEvents::default);
+    }
+
+    /// `output_events` returns the output written since the events were enabled, a chunk per trap or store, oldest first.
+    pub fn output_events(&self) -> &[OutputEvent] {
+        self.output_events
+            .as_ref()
+            .map_or(&[], |events| events.events())
+    }
+
+    /// `on_register_change` subscribes `listener` to register changes: after every executed instruction, it is called
+    /// with `(register, old, new)` for each of R0 - R7 whose value changed. A clone of the `Vm` has no listeners.
+    pub fn on_register_change<F: FnMut(u16, u16, u16) + 'static>(&mut self, listener: F) {
+        self.register_listeners.add(Box::new(listener));
+    }
+
+    /// `is_halted` returns `true` once the clock has been stopped (bit [15] of the MCR is cleared).
+    pub fn is_halted(&self) -> bool {
+        !self.bus.clock_enabled()
+    }
+
+    /// `step` fetches the instruction at PC, increments PC and executes the instruction.
+    pub fn step(&mut self) -> Result<(), VmError> {
+        let tracing = self.trace.is_some() || self.trace_writer.is_attached();
+        let before = if tracing || self.convention.is_some() || !self.register_listeners.is_empty()
+        {
+            Some(self.registers.clone())
+        } else {
+            None
+        };
+        let logging_writes = self.history.is_some() || self.imminent_code_writes.is_some();
+        if logging_writes {
+            self.bus.write_log = Some(Vec::new());
+        }
+        let history_before = if self.history.is_some() {
+            self.bus.input_log = Some(Vec::new());
+            let keyboard = [
+                self.bus.memory.read(MemoryMappedReg::Kbsr as u16),
+                self.bus.memory.read(MemoryMappedReg::Kbdr as u16),
+            ];
+            let timer = self.timer.clone().map(|timer| {
+                let status = self.bus.memory.read(timer::TIMER_STATUS);
+                Box::new((timer, status))
+            });
+            Some((
+                self.registers.clone(),
+                self.bus.io.output_len(),
+                keyboard,
+                timer,
+            ))
+        } else {
+            None
+        };
+        if self.output_events.is_some() {
+            self.bus.output_log = Some(Vec::new());
+        }
+        let pc = self.registers.pc();
+        let (interrupted, privilege, steps_executed) =
+            (self.interrupted, self.privilege, self.steps_executed);
+        let executed = self.execute_next();
+        if let (Some(events), Some(bytes)) =
+            (self.output_events.as_mut(), self.bus.output_log.take())
+        {
+            events.observe(pc, self.registers.r_ir, self.registers.pc(), bytes);
+        }
+        match executed {
+            Ok(()) => self.steps_executed += 1,
+            Err(error) => self.last_error = Some((pc, error)),
+        }
+        if let (Some(timer), true) = (self.timer.as_mut(), executed.is_ok()) {
+            timer.count();
+        }
+        let writes = self.bus.write_log.take().unwrap_or_default();
+        if let (Some(warnings), true) = (self.imminent_code_writes.as_mut(), executed.is_ok()) {
+            let next_pc = self.registers.pc();
+            let new = self.bus.memory.read(next_pc);
+            for warning in code_write::check(next_pc, &writes, new) {
+                log::warn!(
+                    "the store at x{:04X} changed the next instruction, at x{:04X}, from x{:04X} to x{:04X}",
+                    warning.pc,
+                    warning.addr,
+                    warning.old,
+                    warning.new
+                );
+                warnings.push(warning);
+            }
+        }
+        if let Some((registers, output_len, keyboard, timer)) = history_before {
+            let input = self.bus.input_log.take().unwrap_or_default();
+            if executed.is_ok() {
+                if let Some(history) = self.history.as_mut() {
+                    history.push(Delta {
+                        registers,
+                        writes,
+                        output_len,
+                        input,
+                        keyboard,
+                        interrupted,
+                        privilege,
+                        steps_executed,
+                        timer,
+                    });
+                }
+            }
+        }
+        executed?;
+        #[cfg(debug_assertions)]
+        self.check_condition_codes();
+        if let Some((base, max_depth)) = self.stack_depth.as_mut() {
+            let depth = base.wrapping_sub(self.registers.get(STACK_POINTER)) as i16;
+            *max_depth = (*max_depth).max(depth.max(0) as u16);
+        }
+        if let (Some(convention), Some(before)) = (self.convention.as_mut(), before.as_ref()) {
+            convention.observe(before, &self.registers);
+        }
+        if let Some(before) = before.as_ref() {
+            self.register_listeners.notify(before, &self.registers);
+        }
+        if let (true, Some(before)) = (tracing, before) {
+            let line = self.trace_format.line(&before, &self.registers);
+            self.trace_writer.write(&before, &self.registers, &line);
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// `check_condition_codes` (debug builds only) asserts that an instruction which sets the condition codes
+    /// left exactly one of N, Z and P set, matching the sign of its destination register.
+    /// A failure is a bug in the VM, not in the program.
+    #[cfg(debug_assertions)]
+    fn check_condition_codes(&self) {
+        let instr = self.registers.r_ir;
+        let sets_flags = [
+            OpCode::Add,
+            OpCode::And,
+            OpCode::Not,
+            OpCode::Ld,
+            OpCode::Ldi,
+            OpCode::Ldr,
+            OpCode::Lea,
+        ]
+        .iter()
+        .any(|&op_code| instr >> 12 == op_code as u16);
+        if sets_flags {
+            let value = self.registers.get(fields::dr(instr));
+            assert_eq!(
+                Some(Condition::of(value)),
+                self.registers.r_cond,
+                "x{:04X} left the wrong condition codes for x{:04X}",
+                instr,
+                value
+            );
+            assert_eq!(1, self.registers.get(9).count_ones());
+        }
+    }
+
+    fn execute_next(&mut self) -> Result<(), VmError> {
+        if self.key_interrupts && self.interrupted.is_none() && self.bus.key_interrupt_pending() {
+            self.interrupted = Some(InterruptFrame {
+                pc: self.registers.pc(),
+                psr: self.registers.psr(),
+            });
+            self.registers
+                .set_pc(self.bus.memory.read(interrupt::KEYBOARD_VECTOR));
+        }
+        if let Some(timer) = self.timer.as_mut() {
+            if self.interrupted.is_none() && timer.is_due() && timer::is_enabled(&self.bus.memory) {
+                timer.take();
+                timer::mark_tick(&mut self.bus.memory);
+                self.interrupted = Some(InterruptFrame {
+                    pc: self.registers.pc(),
+                    psr: self.registers.psr(),
+                });
+                self.registers
+                    .set_pc(self.bus.memory.read(timer.vector_entry()));
+            }
+        }
+        if self.vector_table_guard
+            && self.interrupted.is_none()
+            && !self.privilege.is_supervisor()
+            && self.registers.pc() < interrupt::VECTOR_TABLES_END
+        {
+            return Err(VmError::ExecInVectorTable {
+                pc: self.registers.pc(),
+            });
+        }
+        //read instruction
+        let instruction = self.bus.fetch(self.registers.pc())?;
+        self.registers.r_ir = instruction;
+        log::trace!(
+            target: logging::DECODE,
+            "x{:04X}: x{:04X}  {}",
+            self.registers.pc(),
+            instruction,
+            DecodedInstr::decode(instruction)
+        );
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(self.registers.pc(), instruction);
+            if instruction >> 12 == OpCode::Br as u16 {
+                let taken = self
+                    .registers
+                    .r_cond
+                    .is_some_and(|cond| cond.matches(fields::dr(instruction)));
+                profile.record_branch(self.registers.pc(), taken);
+            }
+        }
+        if let Some(overflows) = self.overflows.as_mut() {
+            overflows.extend(overflow::check(
+                self.registers.pc(),
+                instruction,
+                &self.registers,
+            ));
+        }
+        if let Some(accesses) = self.register_accesses.as_mut() {
+            accesses.extend(register_trace::accesses(self.registers.pc(), instruction));
+        }
+
+        if self.strict_condition
+            && self.registers.r_cond.is_none()
+            && instruction >> 12 == OpCode::Br as u16
+            && fields::dr(instruction) != 0
+        {
+            return Err(VmError::UninitializedCondition {
+                pc: self.registers.pc(),
+            });
+        }
+
+        if self.strict_encoding && !fields::is_well_formed(instruction) {
+            return Err(VmError::MalformedInstruction {
+                pc: self.registers.pc(),
+                instr: instruction,
+            });
+        }
+
+        //increment program counter
+        self.registers.advance_pc();
+
+        if instruction >> 12 == OpCode::Trap as u16 {
+            self.registers.r_07 = self.registers.pc();
+            let vector = fields::trapvect8(instruction);
+            log::debug!(
+                target: logging::TRAP,
+                "TRAP x{:02X} at x{:04X}",
+                vector,
+                self.registers.pc().wrapping_sub(1)
+            );
+            let mut handled =
+                self.trap_hook
+                    .call(vector, &mut self.registers, &mut self.bus.memory);
+            if handled == TrapHandled::NotHandled {
+                handled = self
+                    .named_traps
+                    .call(vector, &mut self.registers, &mut self.bus.memory);
+            }
+            if handled == TrapHandled::Handled {
+                return Ok(());
+            }
+        }
+
+        if self.io_denied && instruction >> 12 == OpCode::Trap as u16 {
+            match fields::trapvect8(instruction) {
+                0x25 => {
+                    self.registers.r_07 = self.registers.pc();
+                    self.bus.stop_clock();
+                    return Ok(());
+                }
+                vector if trap::is_console_trap(vector) => {
+                    return Err(VmError::IoDenied {
+                        pc: self.registers.pc().wrapping_sub(1),
+                        vector,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        //the built-in HALT, without its banner
+        if instruction >> 12 == OpCode::Trap as u16
+            && fields::trapvect8(instruction) == 0x25
+            && !self.os_traps
+            && !self.prints_halt_banner()
+        {
+            self.bus.stop_clock();
+            return Ok(());
+        }
+
+        //the built-in IN, with the chosen prompt
+        if instruction >> 12 == OpCode::Trap as u16
+            && fields::trapvect8(instruction) == 0x23
+            && !self.os_traps
+        {
+            self.registers.r_07 = self.registers.pc();
+            let prompt = self.input_prompt.as_deref().unwrap_or_default();
+            return trap::input(prompt, &mut self.registers, &mut self.bus);
+        }
+
+        if instruction >> 12 == OpCode::Rti as u16 {
+            if let Some(frame) = self.interrupted.take() {
+                self.registers.set_pc(frame.pc);
+                self.registers.r_cond = frame.psr.condition();
+                return Ok(());
+            }
+            if self.privilege.is_supervisor() {
+                return self
+                    .privilege
+                    .return_to_user(&mut self.registers, &mut self.bus);
+            }
+            //a user-mode RTI: take the privilege mode exception, if a handler is installed
+            let handler = self.bus.memory.read(interrupt::PRIVILEGE_VECTOR);
+            if handler != 0 {
+                return self
+                    .privilege
+                    .raise(&mut self.registers, &mut self.bus, handler);
+            }
+        }
+
+        //extract op_code and execute operation...
+        let executed = if self.os_traps && instruction >> 12 == OpCode::Trap as u16 {
+            trap::trap_through_vector_table(instruction, &mut self.registers, &mut self.bus)
+        } else {
+            opcode::execute_instruction(instruction, &mut self.registers, &mut self.bus)
+        };
+        if let Err(VmError::IllegalOpcode { pc, instr }) = executed {
+            if let Some(op_code) = opcode::extract_op_code(&instr) {
+                self.attempted_unimplemented.get_or_insert((pc, op_code));
+            }
+        }
+        executed
+    }
+
+    /// `steps_executed` is the number of instructions executed since the machine was created or last `reset`.
+    pub fn steps_executed(&self) -> u64 {
+        self.steps_executed
+    }
+
+    /// `last_error` returns the address of the last instruction which failed, and its error.
+    pub fn last_error(&self) -> Option<(u16, VmError)> {
+        self.last_error
+    }
+
+    /// `continue_from_error` resumes a machine which stopped on a recoverable error (see `VmError::is_recoverable`)
+    /// by taking the exception: PC jumps to the handler in the error's exception vector table entry (see
+    /// `vm::interrupt`), whose RTI returns to the instruction after the faulting one; the next `run` or `step` starts
+    /// in the handler. The error is then forgotten. Nothing changes and the error is returned if it is fatal,
+    /// if no handler is installed, or if a handler is already running, as exceptions do not nest.
+    /// Without a last error, there is nothing to continue from and nothing happens.
+    pub fn continue_from_error(&mut self) -> Result<(), VmError> {
+        let (pc, error) = match self.last_error {
+            Some(last_error) => last_error,
+            None => return Ok(()),
+        };
+        let handler =
+            interrupt::exception_vector(&error).map(|vector| self.bus.memory.read(vector));
+        match handler {
+            Some(handler) if handler != 0 && self.interrupted.is_none() => {
+                self.interrupted = Some(InterruptFrame {
+                    pc: pc.wrapping_add(1),
+                    psr: self.registers.psr(),
+                });
+                self.registers.set_pc(handler);
+                self.last_error = None;
+                Ok(())
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// `export_core_dump` captures the registers, the PSR, all of memory (device registers included),
+    /// the number of instructions executed and the last error (see `last_error`) in one file (see `vm::core_dump`
+    /// for the layout), to analyse a failed run later with `import_core_dump`.
+    pub fn export_core_dump(&self) -> Vec<u8> {
+        let info = CoreDumpInfo {
+            steps: self.steps_executed,
+            failing_pc: self.last_error.map(|(pc, _)| pc),
+            reason: self.last_error.map(|(_, error)| error.to_string()),
+        };
+        core_dump::write(&self.registers, &self.bus.memory, &info, None)
+    }
+
+    /// `import_core_dump` loads a core dump written by `export_core_dump` into this machine for inspection:
+    /// it gets the registers, memory and instruction count of the dump, and its last error is forgotten.
+    /// The console and the settings of the machine are kept. The failing PC and reason are returned.
+    /// Nothing is loaded if the dump is malformed. The console of a dump written by `save_state` is ignored.
+    /// The machine is left in user mode with no handler running, as a dump does not record them (see `vm::core_dump`).
+    pub fn import_core_dump(&mut self, bytes: &[u8]) -> std::io::Result<CoreDumpInfo> {
+        let (registers, memory, info, _) = core_dump::read(bytes)?;
+        self.registers = registers;
+        self.bus.memory = memory;
+        self.steps_executed = info.steps;
+        self.last_error = None;
+        self.interrupted = None;
+        self.privilege = Privilege::default();
+        Ok(info)
+    }
+
+    /// `set_trap_hook` makes `hook` see every TRAP before the default routine (built-in or OS image, and before
+    /// `deny_io`), with the trap vector, the registers (PC and R7 already point after the TRAP) and memory.
+    /// Returning `TrapHandled::Handled` skips the default routine, e.g. for a custom system call, while
+    /// `TrapHandled::NotHandled` lets it run. Setting a hook replaces the previous one.
+    pub fn set_trap_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(u8, &mut Registers, &mut Memory) -> TrapHandled + 'static,
+    {
+        self.trap_hook.set(Box::new(hook));
+    }
+
+    /// `register_named_trap` implements `TRAP vector` with `routine`, a Rust function of the registers
+    /// (PC and R7 already point after the TRAP) and memory, e.g. for a small standard library of custom traps.
+    /// A registered routine replaces the default one (built-in or OS image), but the trap hook still sees
+    /// the TRAP first. Registering a vector again replaces its routine and name.
+    pub fn register_named_trap<F>(&mut self, vector: u8, name: &str, routine: F)
+    where
+        F: FnMut(&mut Registers, &mut Memory) + 'static,
+    {
+        self.named_traps.register(vector, name, Box::new(routine));
+    }
+
+    /// `trap_routines` lists the vectors and names of the registered trap routines, in vector order.
+    pub fn trap_routines(&self) -> Vec<(u8, &str)> {
+        self.named_traps.names()
+    }
+
+    /// `attempted_unimplemented` tells whether the program tried to execute an opcode the VM does not implement
+    /// (see `OpCode::is_implemented`), giving the address and opcode of the first such instruction since the last `reset`.
+    /// This explains a `VmError::IllegalOpcode`, e.g. from an RTI outside an interrupt handler.
+    pub fn attempted_unimplemented(&self) -> Option<(u16, OpCode)> {
+        self.attempted_unimplemented
+    }
+
+    /// `run` executes instructions until the machine halts, an instruction fails, PC reaches a breakpoint
+    /// or a watched cell changes, and tells which of these happened.
+    /// The instruction at PC when `run` is called is always executed, so calling `run` again continues past a breakpoint.
+    pub fn run(&mut self) -> RunResult {
+        self.run_for(u64::MAX)
+    }
+
+    /// `consume_run` is `run` for a pipeline which does not need the machine afterwards: it runs until the machine
+    /// stops and gives back why, with the final state, consuming the machine.
+    pub fn consume_run(mut self) -> (RunResult, VmFinalState<I>) {
+        let halt = self.run();
+        let memory_checksum = self.memory_checksum();
+        let state = VmFinalState {
+            halt,
+            registers: self.registers,
+            memory_checksum,
+            io: self.bus.io,
+        };
+        (halt, state)
+    }
+
+    /// `run_precompiled` is `run` for a program which does not modify its own code, e.g. for benchmarks: the loaded
+    /// regions are decoded once, up front, and executed from their decoded form (see `vm::precompiled`).
+    /// A store into the code is still seen, as an instruction whose word has changed takes the normal path.
+    /// Breakpoints, watchpoints, key interrupts and the instrumentation (profiling, tracing, time travel and the
+    /// other checks) need the normal path for every instruction, so with any of them on this is simply `run`.
+    pub fn run_precompiled(&mut self) -> RunResult {
+        if self.needs_every_step() {
+            return self.run();
+        }
+        let code = Precompiled::new(&self.bus.memory, &self.loaded);
+        loop {
+            if self.is_halted() {
+                break RunResult::Halted;
+            }
+            let pc = self.registers.pc();
+            let executed = match code.get(&self.bus.memory, pc) {
+                Some((word, instr)) => {
+                    self.registers.r_ir = word;
+                    log::trace!(
+                        target: logging::DECODE,
+                        "x{:04X}: x{:04X}  {}",
+                        pc,
+                        word,
+                        instr
+                    );
+                    self.registers.advance_pc();
+                    let executed = precompiled::execute(instr, &mut self.registers, &mut self.bus);
+                    match executed {
+                        Ok(()) => self.steps_executed += 1,
+                        Err(error) => self.last_error = Some((pc, error)),
+                    }
+                    executed
+                }
+                None => self.step(),
+            };
+            if let Err(error) = executed {
+                break RunResult::Error(error);
+            }
+        }
+    }
+
+    //whether something watches every step, so that `run_precompiled` must take the normal path
+    fn needs_every_step(&self) -> bool {
+        self.profile.is_some()
+            || self.trace.is_some()
+            || self.trace_writer.is_attached()
+            || self.loop_detector.is_some()
+            || self.convention.is_some()
+            || self.stack_depth.is_some()
+            || self.overflows.is_some()
+            || self.imminent_code_writes.is_some()
+            || self.register_accesses.is_some()
+            || self.output_events.is_some()
+            || self.history.is_some()
+            || !self.register_listeners.is_empty()
+            || !self.breakpoints.is_empty()
+            || !self.watchpoints.is_empty()
+            || self.key_interrupts
+            || self.timer.is_some()
+            || self.strict_condition
+            || self.strict_encoding
+            || self.vector_table_guard
+            || self.pc_bounds_check
+    }
+
+    /// `run_for` is `run`, but executes at most `max_steps` instructions (`RunResult::BudgetExhausted` after that).
+    pub fn run_for(&mut self, max_steps: u64) -> RunResult {
+        self.run_steps(max_steps, |_| false)
+            .expect("The run is never paused.")
+    }
+
+    //the loop of `run_for`, which also returns `None` (before executing anything more) as soon as `pause` holds
+    fn run_steps<F: FnMut(&mut Vm<I>) -> bool>(
+        &mut self,
+        max_steps: u64,
+        mut pause: F,
+    ) -> Option<RunResult> {
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.forget();
+        }
+        let mut steps = 0;
+        Some(loop {
+            if self.is_halted() {
+                break RunResult::Halted;
+            }
+            if steps > 0 && self.breakpoints.contains(&self.registers.pc()) {
+                break RunResult::Breakpoint(self.registers.pc());
+            }
+            if steps == max_steps {
+                break RunResult::BudgetExhausted;
+            }
+            if pause(self) {
+                return None;
+            }
+            let watched: Vec<(u16, u16)> = self
+                .watchpoints
+                .iter()
+                .map(|&addr| (addr, self.peek(addr)))
+                .collect();
+            if let Err(e) = self.step() {
+                break RunResult::Error(e);
+            }
+            steps += 1;
+            if let Some(&(addr, old)) = watched.iter().find(|&&(addr, old)| self.peek(addr) != old)
+            {
+                break RunResult::Watchpoint {
+                    addr,
+                    old,
+                    new: self.peek(addr),
+                };
+            }
+            if let Some(detector) = self.loop_detector.as_mut() {
+                if detector.repeats(&self.registers) {
+                    break RunResult::InfiniteLoop {
+                        pc: self.registers.pc(),
+                    };
+                }
+            }
+            let pc = self.registers.pc();
+            if self.pc_bounds_check
+                && !self.is_halted()
+                && !self.loaded.iter().any(|region| region.contains(pc))
+            {
+                break RunResult::PcOutOfBounds { pc };
+            }
+        })
+    }
+
+    /// `step_n` runs a chunk of at most `n` instructions, e.g. between two frames of a UI, and returns early
+    /// on the first event which stops the machine (halt, breakpoint, watchpoint, error or loop).
+    /// Once all `n` instructions have run, it returns `RunResult::Continue`: the chunk is done, not the program.
+    pub fn step_n(&mut self, n: u64) -> RunResult {
+        match self.run_for(n) {
+            RunResult::BudgetExhausted => RunResult::Continue,
+            result => result,
+        }
+    }
+
+    /// `run_with_timeout` is `run`, but gives up with `RunResult::TimedOut` once `timeout` has passed, e.g. to stop
+    /// a program which spins waiting for input that never comes. The clock is only read every
+    /// `TIMEOUT_CHECK_INTERVAL` instructions, so the run may take a little longer than `timeout`.
+    /// A single instruction which blocks (a built-in GETC reading the terminal) cannot be interrupted.
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> RunResult {
+        let start = Instant::now();
+        let mut countdown = TIMEOUT_CHECK_INTERVAL;
+        let result = self.run_steps(u64::MAX, |_| {
+            countdown -= 1;
+            if countdown == 0 {
+                countdown = TIMEOUT_CHECK_INTERVAL;
+                return start.elapsed() >= timeout;
+            }
+            false
+        });
+        result.unwrap_or(RunResult::TimedOut)
+    }
+
+    /// `run_to_ret` finishes the current subroutine, like gdb's `finish`: it runs until a RET executes at the
+    /// current call depth, and returns `RunResult::Returned` with the PC it returned to. Every JSR and JSRR
+    /// (and, with the OS image loaded, every TRAP) executed on the way is a call one level deeper, whose own RET
+    /// does not count. Halts, breakpoints, watchpoints and errors stop it as they stop `run`.
+    pub fn run_to_ret(&mut self) -> RunResult {
+        let mut depth: u64 = 0;
+        let mut returned = false;
+        let os_traps = self.os_traps;
+        let result = self.run_steps(u64::MAX, |vm| {
+            if returned {
+                return true;
+            }
+            let instr = vm.peek(vm.registers.pc());
+            match instr >> 12 {
+                op if op == OpCode::Jsr as u16 => depth += 1,
+                op if op == OpCode::Trap as u16 && os_traps => depth += 1,
+                op if op == OpCode::Jmp as u16 && fields::sr1(instr) == 7 => match depth {
+                    0 => returned = true,
+                    _ => depth -= 1,
+                },
+                _ => {}
+            }
+            false
+        });
+        match result {
+            //a breakpoint at the return site is checked before the run pauses there
+            Some(RunResult::Breakpoint(pc)) if returned => RunResult::Returned { pc },
+            Some(result) => result,
+            None => RunResult::Returned {
+                pc: self.registers.pc(),
+            },
+        }
+    }
+
+    /// `run_to_next_io` runs at full speed through pure computation, and stops before the next TRAP which uses
+    /// the console (GETC, OUT, PUTS, IN, PUTSP, PUTD or REGS, as `deny_io` has them) with `RunResult::IoTrap`,
+    /// e.g. so a UI only redraws when there is something to show. Like a breakpoint, the instruction PC is at
+    /// when it is called does not stop it.
+    /// HALT is executed, giving `RunResult::Halted`; breakpoints, watchpoints and errors stop it as they stop `run`.
+    pub fn run_to_next_io(&mut self) -> RunResult {
+        let mut first = true;
+        let mut io_trap = None;
+        let result = self.run_steps(u64::MAX, |vm| {
+            let instr = vm.peek(vm.registers.pc());
+            let vector = fields::trapvect8(instr);
+            if !std::mem::take(&mut first)
+                && instr >> 12 == OpCode::Trap as u16
+                && trap::is_console_trap(vector)
+            {
+                io_trap = Some(RunResult::IoTrap {
+                    pc: vm.registers.pc(),
+                    vector,
+                });
+                return true;
+            }
+            false
+        });
+        result.or(io_trap).expect("Paused at a console TRAP.")
+    }
+
+    /// `run_until` steps until `pred` holds, `max_steps` instructions have been executed, or the machine halts.
+    /// `pred` is checked before every step (so no step is taken if it already holds).
+    /// It returns the number of instructions executed.
+    pub fn run_until<F: Fn(&Vm<I>) -> bool>(
+        &mut self,
+        pred: F,
+        max_steps: u64,
+    ) -> Result<u64, VmError> {
+        let mut steps = 0;
+        while steps < max_steps && !self.is_halted() && !pred(self) {
+            self.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+}
+
+fn words_checksum(words: impl Iterator<Item = u16>) -> u64 {
+    let mut hash = Fnv64::new();
+    for word in words {
+        hash.write_u16(word);
+    }
+    hash.finish()
+}
+
+#[cfg(test)]
+mod vm_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::hardware::memory::Access;
+    use crate::lc3_instr;
+
+    fn vm_with_os(program: &[u16]) -> Vm<BufferIo> {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_os();
+        vm.load_words(0x3000, program);
+        vm
+    }
+
+    #[test]
+    fn halt_should_work_through_the_os_image() {
+        // TRAP x25
+        let mut vm = vm_with_os(&[0xF025]);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert!(vm.is_halted());
+        assert_eq!("HALT", vm.io().output_string());
+        // the machine stopped inside the OS routine, which was entered through the vector table
+        assert!(vm.registers.pc() >= 0x0200 && vm.registers.pc() < 0x3000);
+    }
+
+    #[test]
+    fn puts_and_putsp_should_work_through_the_os_image() {
+        // LEA R0, #4 ; PUTS ; LEA R0, #5 ; PUTSP ; HALT ; "hi" ; "yo!"
+        let mut vm = vm_with_os(&[
+            0xE004, 0xF022, 0xE005, 0xF024, 0xF025, 0x0068, 0x0069, 0x0000, 0x6F79, 0x0021, 0x0000,
+        ]);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("hiyo!HALT", vm.io().output_string());
+    }
+
+    #[test]
+    fn getc_and_out_should_work_through_the_os_image() {
+        // GETC ; OUT ; HALT
+        let mut vm = vm_with_os(&[0xF020, 0xF021, 0xF025]);
+        vm.io_mut().push_input(b"x");
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("xHALT", vm.io().output_string());
+    }
+
+    #[test]
+    fn vectored_trap_should_return_even_if_it_calls_a_subroutine() {
+        let program = crate::assembler::assemble(
+            ".ORIG x3000
+                     AND R0, R0, #0
+                     TRAP x30
+                     ADD R0, R0, #1
+                     HALT
+             ROUTINE ST R7, SAVE_R7
+                     JSR SUB
+                     LD R7, SAVE_R7
+                     RET
+             SUB     ADD R0, R0, #5
+                     RET
+             SAVE_R7 .FILL x0000
+                     .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_os();
+        vm.load_program(&program);
+        vm.poke(0x0030, program.symbols.get("ROUTINE").unwrap());
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(0x3002, vm.registers().r_07);
+        //stop before HALT, whose OS routine uses R0
+        vm.add_breakpoint(0x3003);
+        assert_eq!(RunResult::Breakpoint(0x3003), vm.run());
+        assert_eq!(0x3003, vm.registers().pc());
+        assert_eq!(6, vm.registers().r_00);
+    }
+
+    #[test]
+    fn clone_should_not_affect_the_original() {
+        // ADD R0, R0, #1
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021]);
+        let original = vm.clone();
+        let mut copy = vm.clone();
+        copy.step().unwrap();
+        copy.memory_mut().write(0x4000, 0xBEEF);
+        assert_eq!(original, vm);
+        assert_ne!(vm, copy);
+        assert_eq!(0, vm.registers.r_00);
+        assert_eq!(0, vm.memory().read(0x4000));
+        assert_eq!(1, copy.registers.r_00);
+    }
+
+    #[test]
+    fn reserved_opcode_should_be_an_error() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xD000]);
+        assert_eq!(
+            Err(VmError::IllegalOpcode {
+                pc: 0x3000,
+                instr: 0xD000
+            }),
+            vm.step()
+        );
+    }
+
+    #[test]
+    fn run_should_stop_at_breakpoints_and_continue_past_them() {
+        // ADD R0, R0, #1 (x3) ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0x1021, 0x1021, 0xF025]);
+        vm.add_breakpoint(0x3002);
+        assert_eq!(RunResult::Breakpoint(0x3002), vm.run());
+        assert_eq!(0x3002, vm.registers().pc());
+        assert_eq!(2, vm.registers().r_00);
+        assert!(!vm.is_halted());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert!(vm.is_halted());
+        assert_eq!(3, vm.registers().r_00);
+    }
+
+    #[test]
+    fn reset_should_restart_with_the_given_memory() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0xF025]);
+        let memory = vm.memory().clone();
+        assert_eq!(RunResult::Halted, vm.run());
+        vm.reset(memory, 0x3000);
+        assert!(!vm.is_halted());
+        assert_eq!(0x3000, vm.registers().pc());
+        assert_eq!(0, vm.registers().r_00);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(1, vm.registers().r_00);
+    }
+
+    #[test]
+    fn peek_and_poke_should_bypass_the_devices() {
+        use crate::hardware::memory::MemoryMappedReg;
+        let mut vm = Vm::with_io(BufferIo::with_input("k"));
+        vm.poke(MemoryMappedReg::Kbdr as u16, 0x41);
+        assert_eq!(0x41, vm.peek(MemoryMappedReg::Kbdr as u16));
+        assert_eq!(0, vm.peek(MemoryMappedReg::Kbsr as u16));
+        assert_eq!(1, vm.io().pending_input());
+        vm.poke(MemoryMappedReg::Ddr as u16, 0x42);
+        assert!(vm.io().output().is_empty());
+        vm.protect(0x3000..=0x3000, Perms::NONE);
+        vm.poke(0x3000, 7);
+        assert_eq!(7, vm.peek(0x3000));
+    }
+
+    #[test]
+    fn hex_string_should_be_loaded_at_origin() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_hex_string(0x4000, "1021 ; add\nF025").unwrap();
+        assert_eq!(0x1021, vm.memory().read(0x4000));
+        assert_eq!(0xF025, vm.memory().read(0x4001));
+        assert_eq!(0x4000, vm.registers.pc());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(1, vm.registers.r_00);
+    }
+
+    #[test]
+    fn write_to_a_read_only_region_should_fault() {
+        // ST R0, #1 ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x3001, 0xF025]);
+        vm.protect(0x3000..=0x3FFF, Perms::READ | Perms::EXECUTE);
+        assert_eq!(
+            RunResult::Error(VmError::ProtectionFault {
+                addr: 0x3002,
+                access: Access::Write
+            }),
+            vm.run()
+        );
+        assert_eq!(0, vm.memory().read(0x3002));
+    }
+
+    #[test]
+    fn fetch_from_a_no_execute_region_should_fault() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[lc3_instr!(AND R0, R0, #0), lc3_instr!(BRnzp #0xFE)],
+        );
+        vm.load_words(0x3100, &[0xF025]);
+        vm.protect(0x3100..=0x31FF, Perms::READ | Perms::WRITE);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(
+            Err(VmError::ProtectionFault {
+                addr: 0x3100,
+                access: Access::Execute
+            }),
+            vm.step()
+        );
+    }
+
+    #[test]
+    fn run_until_should_stop_once_the_predicate_holds() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(AND R1, R1, #0),
+                lc3_instr!(ADD R1, R1, #1),
+                lc3_instr!(BRnzp #-2),
+            ],
+        );
+        let steps = vm.run_until(|vm| vm.registers().r_01 == 10, 1000).unwrap();
+        assert_eq!(10, vm.registers().r_01);
+        //AND, then 9 (ADD, BR) iterations, then the final ADD
+        assert_eq!(20, steps);
+        assert_eq!(5, vm.run_until(|_| false, 5).unwrap());
+    }
+
+    #[test]
+    fn run_should_report_a_watched_cell_changing() {
+        // ADD R0, R0, #1 ; ST R0, #2 ; ST R0, #1 ; HALT ; .FILL x0000
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0x3002, 0x3001, 0xF025, 0x0000]);
+        vm.add_watchpoint(0x3004);
+        assert_eq!(
+            RunResult::Watchpoint {
+                addr: 0x3004,
+                old: 0,
+                new: 1
+            },
+            vm.run()
+        );
+        assert_eq!(0x3002, vm.registers().pc());
+        //storing the same value again is not a change
+        assert_eq!(RunResult::Halted, vm.run());
+    }
+
+    #[test]
+    fn run_for_should_stop_when_the_budget_is_exhausted() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[lc3_instr!(ADD R0, R0, #1), lc3_instr!(BRnzp #-2)]);
+        assert_eq!(RunResult::BudgetExhausted, vm.run_for(5));
+        assert_eq!(3, vm.registers().r_00);
+        assert_eq!(RunResult::BudgetExhausted, vm.run_for(0));
+    }
+
+    #[test]
+    fn checksums_should_change_with_the_state_only() {
+        // AND R0, R0, #0 ; ST R0, #1 ; HALT ; .FILL x1234
+        let program = [0x5020, 0x3001, 0xF025, 0x1234];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        let mut twin = Vm::with_io(BufferIo::new());
+        twin.load_words(0x3000, &program);
+        assert_eq!(vm.memory_checksum(), twin.memory_checksum());
+        assert_eq!(vm.state_checksum(), twin.state_checksum());
+
+        let (memory, state) = (vm.memory_checksum(), vm.state_checksum());
+        vm.step().unwrap();
+        assert_eq!(memory, vm.memory_checksum());
+        assert_ne!(state, vm.state_checksum());
+        vm.step().unwrap();
+        assert_ne!(memory, vm.memory_checksum());
+    }
+
+    #[test]
+    fn halted_machine_should_resume_at_a_new_entry_point() {
+        let first = crate::assembler::assemble(
+            ".ORIG x3000
+             AND R1, R1, #0
+             ADD R1, R1, #2
+             HALT
+             .END
+             .ORIG x4000
+             ADD R1, R1, R1
+             HALT
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&first);
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(2, vm.registers().r_01);
+        vm.resume_at(0x4000);
+        assert!(!vm.is_halted());
+        assert_eq!(RunResult::Halted, vm.run());
+        //the second program saw what the first one left behind
+        assert_eq!(4, vm.registers().r_01);
+        assert_eq!("", vm.io().output_string());
+    }
+
+    #[test]
+    fn branch_on_uninitialized_condition_should_fail_in_strict_mode() {
+        let program = [lc3_instr!(BRz #1), lc3_instr!(HALT), lc3_instr!(HALT)];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.set_strict_condition(true);
+        assert_eq!(
+            Err(VmError::UninitializedCondition { pc: 0x3000 }),
+            vm.step()
+        );
+        assert_eq!(0x3000, vm.registers().pc());
+
+        //off by default: the branch is simply not taken
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.step().unwrap();
+        assert_eq!(0x3001, vm.registers().pc());
+    }
+
+    #[test]
+    fn console_traps_should_fail_when_io_is_denied() {
+        // LEA R0, #2 ; PUTS ; HALT ; "a"
+        let program = [0xE002, 0xF022, 0xF025, 0x61, 0];
+        let mut vm = vm_with_os(&program);
+        vm.deny_io();
+        assert_eq!(
+            RunResult::Error(VmError::IoDenied {
+                pc: 0x3001,
+                vector: 0x22
+            }),
+            vm.run()
+        );
+        assert_eq!("", vm.io().output_string());
+
+        // ADD R0, R0, #1 ; HALT
+        let mut vm = vm_with_os(&[0x1021, 0xF025]);
+        vm.deny_io();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(1, vm.registers().r_00);
+        assert_eq!("", vm.io().output_string());
+    }
+
+    #[test]
+    fn exactly_one_condition_should_be_active_after_each_flag_setting_instruction() {
+        use crate::hardware::register::condition_flag::Condition;
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(AND R0, R0, #0),
+                lc3_instr!(ADD R0, R0, #5),
+                lc3_instr!(NOT R1, R0),
+                lc3_instr!(LD R2, #1),
+                lc3_instr!(LEA R3, #-4),
+                0x0000,
+            ],
+        );
+        let expected = [
+            Condition::Zero,
+            Condition::Positive,
+            Condition::Negative,
+            Condition::Zero,
+            Condition::Positive,
+        ];
+        for &condition in expected.iter() {
+            vm.step().unwrap();
+            let registers = vm.registers();
+            assert_eq!(Some(condition), registers.r_cond);
+            let active = [
+                registers.is_negative(),
+                registers.is_zero(),
+                registers.is_positive(),
+            ];
+            assert_eq!(1, active.iter().filter(|&&flag| flag).count());
+            assert_eq!(condition.bits(), registers.get(9));
+        }
+    }
+
+    #[test]
+    fn ret_should_resume_after_the_jsr() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(JSR #2),
+                lc3_instr!(ADD R2, R2, #1),
+                0x0000,
+                lc3_instr!(ADD R1, R1, #1),
+                lc3_instr!(RET),
+            ],
+        );
+        vm.step().unwrap();
+        assert_eq!(0x3003, vm.registers().pc());
+        assert_eq!(0x3001, vm.registers().r_07);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(0x3001, vm.registers().pc());
+        vm.step().unwrap();
+        assert_eq!(0x3002, vm.registers().pc());
+        assert_eq!(1, vm.registers().r_01);
+        assert_eq!(1, vm.registers().r_02);
+    }
+
+    #[test]
+    fn load_obj_verified_should_reject_a_corrupted_image() {
+        // x3000: ADD R0, R0, #1 ; HALT
+        let image = [0x30, 0x00, 0x10, 0x21, 0xF0, 0x25];
+        let mut reference = Vm::with_io(BufferIo::new());
+        reference.load_words(0x3000, &[0x1021, 0xF025]);
+        let checksum = reference.region_checksum(0x3000, 2);
+
+        let mut vm = Vm::with_io(BufferIo::new());
+        let mut corrupted = image;
+        corrupted[3] ^= 0x01;
+        assert!(matches!(
+            vm.load_obj_verified(&corrupted, checksum),
+            Err(LoadError::ChecksumMismatch { expected, .. }) if expected == checksum
+        ));
+        assert_eq!(0, vm.peek(0x3000));
+
+        assert_eq!(Ok(()), vm.load_obj_verified(&image, checksum));
+        assert_eq!(0x1021, vm.peek(0x3000));
+        assert_eq!(0x3000, vm.registers().pc());
+        assert_eq!(checksum, vm.region_checksum(0x3000, 2));
+    }
+
+    #[test]
+    fn step_n_should_stop_early_at_a_breakpoint() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        let program = [lc3_instr!(ADD R0, R0, #1); 10];
+        vm.load_words(0x3000, &program);
+        vm.add_breakpoint(0x3003);
+        assert_eq!(RunResult::Breakpoint(0x3003), vm.step_n(5));
+        //3 of the 5 steps ran
+        assert_eq!(3, vm.registers().r_00);
+        assert_eq!(RunResult::Continue, vm.step_n(5));
+        assert_eq!(8, vm.registers().r_00);
+        assert_eq!(0x3008, vm.registers().pc());
+    }
+
+    #[test]
+    fn golden_program_should_execute_every_opcode() {
+        let program = assembler::assemble(include_str!("../../resources/golden.asm")).unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.io_mut().push_input(b"!");
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("ok!", vm.io().output_string());
+
+        let registers: Vec<u16> = (0..8).map(|r| vm.registers().get(r)).collect();
+        assert_eq!(
+            vec![0x0021, 43, 0xFFF1, 0x1234, 0x301C, 0x3015, 0x1234, 0x301A],
+            registers
+        );
+        assert_eq!(14, vm.peek(0x3021));
+        assert_eq!(0xFFF1, vm.peek(0x3023));
+        assert_eq!(7, vm.peek(0x3024));
+
+        for &instr in [0x8000, 0xD000].iter() {
+            let mut vm = Vm::with_io(BufferIo::new());
+            vm.load_words(0x3000, &[instr]);
+            assert_eq!(
+                RunResult::Error(VmError::IllegalOpcode { pc: 0x3000, instr }),
+                vm.run()
+            );
+        }
+    }
+
+    #[test]
+    fn registers_set_through_the_api_should_be_used_by_instructions() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[lc3_instr!(ADD R1, R0, #1)]);
+        vm.set_reg(0, 41);
+        vm.step().unwrap();
+        assert_eq!(42, vm.reg(1));
+        vm.registers_mut().set_pc(0x3000);
+        vm.registers_mut().update(0, 0xFFFF);
+        vm.step().unwrap();
+        assert_eq!(0, vm.reg(1));
+        assert!(vm.registers().is_zero());
+    }
+
+    #[test]
+    fn input_poll_limit_should_make_getc_give_up() {
+        // GETC ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF020, 0xF025]);
+        vm.set_input_poll_limit(Some(3));
+        assert_eq!(
+            RunResult::Error(VmError::InputTimedOut { polls: 3 }),
+            vm.run()
+        );
+        assert_eq!(0x3001, vm.registers().pc());
+
+        //the OS routine polls the KBSR instead
+        let mut vm = vm_with_os(&[0xF020, 0xF025]);
+        vm.set_input_poll_limit(Some(3));
+        assert_eq!(
+            RunResult::Error(VmError::InputTimedOut { polls: 3 }),
+            vm.run()
+        );
+
+        //a key which arrives in time is read as usual
+        // GETC ; OUT ; HALT
+        let mut vm = vm_with_os(&[0xF020, 0xF021, 0xF025]);
+        vm.set_input_poll_limit(Some(3));
+        vm.io_mut().push_input(b"y");
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("yHALT", vm.io().output_string());
+    }
+
+    #[test]
+    fn jsrr_r7_should_jump_to_the_old_r7() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[lc3_instr!(JSRR R7)]);
+        vm.set_reg(7, 0x4000);
+        vm.step().unwrap();
+        assert_eq!(0x4000, vm.registers().pc());
+        assert_eq!(0x3001, vm.registers().r_07);
+    }
+
+    #[test]
+    fn load_obj_should_tell_where_the_program_went() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        //origin x4000, then ADD R0, R0, #1 ; ADD R0, R0, #1 ; HALT
+        let bytes = [0x40, 0x00, 0x10, 0x21, 0x10, 0x21, 0xF0, 0x25];
+        let info = vm.load_obj(&bytes).unwrap();
+        assert_eq!(
+            LoadInfo {
+                origin: 0x4000,
+                len: 3,
+                end: 0x4003
+            },
+            info
+        );
+        assert_eq!(AddrRange::new(0x4000, 3), info.range());
+        assert_eq!(0x4000, vm.registers().pc());
+        assert!(vm.memory_region_equal(0x4000, &[0x1021, 0x1021, 0xF025]));
+        assert!(vm.load_obj(&[0x40]).is_err());
+    }
+
+    #[test]
+    fn load_obj_at_should_run_position_independent_code_elsewhere() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             LEA R0, MSG
+             PUTS
+             HALT
+         MSG .STRINGZ \"moved\"
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_obj_at(&program.sections[0].to_obj(), 0x5000)
+            .unwrap();
+        assert_eq!(0x5000, vm.registers().pc());
+        assert_eq!(0, vm.peek(0x3000));
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!("moved", vm.io().output_string());
+    }
+
+    #[test]
+    fn condition_codes_should_stay_consistent_across_varied_instructions() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(LD R1, #8),
+                lc3_instr!(NOT R2, R1),
+                lc3_instr!(ADD R3, R1, R2),
+                lc3_instr!(AND R4, R3, #0),
+                lc3_instr!(LEA R5, #5),
+                lc3_instr!(LDR R6, R5, #0),
+                lc3_instr!(LDI R0, #4),
+                lc3_instr!(ADD R1, R1, #-1),
+                lc3_instr!(BRp #-2),
+                0x0003,
+                0x3009,
+            ],
+        );
+        //a state no instruction produces, to check that the next one overwrites it
+        vm.registers_mut().update(9, 0b010);
+        vm.registers_mut().update(2, 0x8000);
+        for _ in 0..16 {
+            //`step` checks the invariant itself in debug builds
+            vm.step().unwrap();
+            assert_eq!(1, vm.reg(9).count_ones());
+        }
+        assert_eq!(0, vm.reg(1));
+    }
+
+    #[test]
+    fn run_to_ret_should_finish_the_current_subroutine() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             JSR OUTER
+             ADD R2, R2, #1
+             HALT
+       OUTER ADD R6, R7, #0
+             JSR INNER
+             JSR INNER
+             ADD R7, R6, #0
+             RET
+       INNER ADD R1, R1, #1
+             RET
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(0x3004, vm.registers().pc());
+        assert_eq!(RunResult::Returned { pc: 0x3001 }, vm.run_to_ret());
+        assert_eq!(2, vm.reg(1));
+        assert_eq!(0, vm.reg(2));
+
+        //a breakpoint at the return site does not hide the return
+        vm.reset(vm.memory().clone(), 0x3000);
+        vm.add_breakpoint(0x3001);
+        vm.step().unwrap();
+        assert_eq!(RunResult::Returned { pc: 0x3001 }, vm.run_to_ret());
+        //while one on the way still stops it
+        vm.add_breakpoint(0x3006);
+        vm.reset(vm.memory().clone(), 0x3000);
+        vm.step().unwrap();
+        assert_eq!(RunResult::Breakpoint(0x3006), vm.run_to_ret());
+    }
+
+    #[test]
+    fn memory_buffer_should_be_reused_across_machines() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             LD R0, VALUE
+             ADD R0, R0, #1
+             ST R0, VALUE
+             HALT
+       VALUE .FILL #41
+             .END",
+        )
+        .unwrap();
+        let mut first = Vm::with_io(BufferIo::new());
+        first.load_program(&program);
+        first.poke(0x4000, 0xDEAD);
+        assert_eq!(RunResult::Halted, first.run());
+        assert_eq!(42, first.peek(0x3004));
+        let buffer = first.into_memory();
+        let address = buffer.as_ptr();
+
+        let mut second = Vm::with_buffer(buffer, BufferIo::new());
+        assert_eq!(0, second.peek(0x4000));
+        second.load_program(&program);
+        assert_eq!(RunResult::Halted, second.run());
+        assert_eq!(42, second.reg(0));
+        let buffer = second.into_memory();
+        assert_eq!(address, buffer.as_ptr());
+    }
+
+    #[test]
+    fn rti_in_user_mode_should_be_tracked_as_unimplemented() {
+        // ADD R0, R0, #1 ; RTI
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0x8000]);
+        assert_eq!(None, vm.attempted_unimplemented());
+        assert_eq!(
+            RunResult::Error(VmError::IllegalOpcode {
+                pc: 0x3001,
+                instr: 0x8000
+            }),
+            vm.run()
+        );
+        let (pc, op_code) = vm.attempted_unimplemented().unwrap();
+        assert_eq!((0x3001, OpCode::Rti), (pc, op_code));
+        assert!(!op_code.is_implemented());
+    }
+
+    #[test]
+    fn ranges_should_set_breakpoints_watchpoints_and_protection() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.add_breakpoint_range(AddrRange::new(0xFFFF, 2));
+        vm.add_watchpoint_range(AddrRange::new(0x4000, 3));
+        assert_eq!(vec![0x0000, 0xFFFF], vm.breakpoints());
+        vm.protect_range(AddrRange::new(0xFFF0, 0x20), Perms::READ);
+        assert_eq!(Perms::READ, vm.memory().perms(0x000F));
+        assert_eq!(Perms::READ, vm.memory().perms(0xFFF0));
+        assert_eq!(Perms::ALL, vm.memory().perms(0x0010));
+        // ST R0, #0 into x4002
+        vm.load_words(0x4001, &[0x3000]);
+        vm.resume_at(0x4001);
+        vm.set_reg(0, 9);
+        assert!(matches!(
+            vm.run_for(1),
+            RunResult::Watchpoint { addr: 0x4002, .. }
+        ));
+    }
+
+    #[test]
+    fn run_to_next_io_should_pause_at_the_out() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+                  LD R1, COUNT
+             LOOP ADD R0, R0, #1
+                  ADD R1, R1, #-1
+                  BRp LOOP
+                  OUT
+                  LEA R0, EMPTY
+                  PUTS
+                  TRAP x31
+                  HALT
+            COUNT .FILL #33
+            EMPTY .FILL #0
+                  .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        assert_eq!(
+            RunResult::IoTrap {
+                pc: 0x3004,
+                vector: 0x21
+            },
+            vm.run_to_next_io()
+        );
+        assert_eq!(1 + 3 * 33, vm.steps_executed());
+        assert_eq!("", vm.io().output_string());
+        assert_eq!(
+            RunResult::IoTrap {
+                pc: 0x3006,
+                vector: 0x22
+            },
+            vm.run_to_next_io()
+        );
+        //PUTD, of the address of EMPTY
+        assert_eq!(
+            RunResult::IoTrap {
+                pc: 0x3007,
+                vector: 0x31
+            },
+            vm.run_to_next_io()
+        );
+        assert_eq!(RunResult::Halted, vm.run_to_next_io());
+        assert_eq!("!12298", vm.io().output_string());
+    }
+
+    #[test]
+    fn memory_region_equal_should_report_the_first_mismatch() {
+        let mut vm = Vm::new();
+        vm.load_words(0x4000, &[1, 2, 3, 4]);
+        assert!(vm.memory_region_equal(0x4000, &[1, 2, 3, 4]));
+        assert_eq!(None, vm.first_region_mismatch(0x4000, &[1, 2, 3]));
+        assert!(!vm.memory_region_equal(0x4000, &[1, 2, 9, 4]));
+        assert_eq!(
+            Some(0x4002),
+            vm.first_region_mismatch(0x4000, &[1, 2, 9, 0])
+        );
+        assert!(vm.memory_region_equal(0x4000, &[]));
+    }
+
+    #[test]
+    fn stray_reserved_bits_should_fail_in_strict_encoding_mode() {
+        //ADD R0, R1, R2 with bit [3] set
+        let instr = crate::hardware::instructions::encode::add_reg(0, 1, 2) | 0x0008;
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[instr]);
+        vm.set_reg(1, 3);
+        vm.set_reg(2, 4);
+        let lenient = vm.clone();
+        vm.set_strict_encoding(true);
+        assert_eq!(
+            Err(VmError::MalformedInstruction { pc: 0x3000, instr }),
+            vm.step()
+        );
+        assert_eq!(0x3000, vm.registers().pc());
+
+        let mut vm = lenient;
+        vm.step().unwrap();
+        assert_eq!(7, vm.reg(0));
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints_should_be_enumerated_and_cleared() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        for address in [0x3005, 0x3000, 0x4000] {
+            vm.add_breakpoint(address);
+        }
+        vm.add_watchpoint_range(AddrRange::new(0x5000, 2));
+        assert_eq!(vec![0x3000, 0x3005, 0x4000], vm.breakpoints());
+        assert_eq!(vec![0x5000, 0x5001], vm.watchpoints());
+
+        vm.clear_all_breakpoints();
+        vm.clear_all_watchpoints();
+        assert_eq!(0, vm.breakpoints().len());
+        assert_eq!(0, vm.watchpoints().len());
+    }
+
+    #[test]
+    fn run_with_timeout_should_stop_an_infinite_loop() {
+        //LOOP ADD R0, R0, #0 ; BRnzp LOOP
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1020, 0x0FFE]);
+        let start = Instant::now();
+        assert_eq!(
+            RunResult::TimedOut,
+            vm.run_with_timeout(Duration::from_millis(20))
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(vm.steps_executed() > 0);
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn assemble_and_load_should_return_the_warnings() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        let warnings = vm
+            .assemble_and_load(
+                ".ORIG x4000
+                 ADD R0, R0, #1
+                 HALT
+           DEAD  .FILL #0
+                 .END",
+            )
+            .unwrap();
+        assert_eq!(
+            vec![AssembleWarning::new(
+                4,
+                assembler::WarningKind::UnusedLabel("DEAD".to_string())
+            )],
+            warnings
+        );
+        assert_eq!(Some(0x4002), vm.symbols().get("DEAD"));
+        assert_eq!(0x4000, vm.registers().pc());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(1, vm.reg(0));
+
+        let error = vm
+            .assemble_and_load(".ORIG x5000\nADD R0, R0, #99\n.END")
+            .unwrap_err();
+        assert_eq!(2, error.line);
+        assert_eq!(0x4002, vm.registers().pc());
+    }
+
+    #[test]
+    fn load_obj_should_reject_an_image_which_runs_past_the_end_of_memory() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        let mut bytes = vec![0xFF, 0xF0];
+        bytes.extend(std::iter::repeat_n([0x12, 0x34], 17).flatten());
+        assert_eq!(
+            Err(LoadError::ImageTooLarge {
+                origin: 0xFFF0,
+                len: 17
+            }),
+            vm.load_obj(&bytes)
+        );
+        assert_eq!(0, vm.peek(0x0000));
+        assert_eq!(0, vm.peek(0xFFF0));
+        assert_eq!(
+            Err(LoadError::ImageTooLarge {
+                origin: 0xFFF1,
+                len: 17
+            }),
+            vm.load_obj_at(&bytes, 0xFFF1)
+        );
+        //exactly up to xFFFF is fine
+        bytes.truncate(bytes.len() - 2);
+        assert_eq!(0xFFF0, vm.load_obj(&bytes).unwrap().origin);
+        assert_eq!(0x1234, vm.peek(0xFFFF));
+        assert_eq!(0, vm.peek(0x0000));
+    }
+
+    #[test]
+    fn disassemble_region_should_print_annotations() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1021, 0xF025]);
+        vm.annotate(0x3000, "count the key".to_string());
+        assert_eq!(Some("count the key"), vm.annotation(0x3000));
+        assert_eq!(None, vm.annotation(0x3001));
+        assert_eq!(
+            "x3000: x1021  ADD R0, R0, #1 ; count the key\n\
+             x3001: xF025  HALT\n",
+            vm.disassemble_region(AddrRange::new(0x3000, 2))
+        );
+    }
+
+    #[test]
+    fn br_offset_should_be_relative_to_the_advanced_pc() {
+        // ADD R0, R0, #0 ; BRnzp #1 ; HALT ; ADD R0, R0, #1
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1020, 0x0E01, 0xF025, 0x1021]);
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(0x3003, vm.registers().pc());
+    }
+
+    #[test]
+    fn find_symbol_at_should_name_the_nearest_preceding_label() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+             FIRST  .BLKW 16
+             SECOND .BLKW 16
+                    .END",
+        )
+        .unwrap();
+        assert_eq!(Some(("FIRST", 5)), vm.find_symbol_at(0x3005));
+        assert_eq!(Some(("SECOND", 0)), vm.find_symbol_at(0x3010));
+        assert_eq!(Some(("SECOND", 0x100)), vm.find_symbol_at(0x3110));
+        assert_eq!(None, vm.find_symbol_at(0x2FFF));
+    }
+
+    #[test]
+    fn boot_from_reset_vector_should_start_at_the_stored_entry() {
+        // at x0200: ADD R0, R0, #2 ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x0200, &[0x1022, 0xF025]);
+        vm.poke(0x00FF, 0x0200);
+        vm.set_reg(0, 40);
+        vm.step().unwrap();
+        assert_eq!(0x0200, vm.boot_from_reset_vector(0x00FF));
+        assert_eq!(0x0200, vm.registers().pc());
+        assert_eq!(0, vm.reg(0));
+        assert_eq!(0, vm.steps_executed());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(2, vm.reg(0));
+    }
+
+    #[test]
+    fn explain_should_describe_the_next_instruction() {
+        // ADD R0, R1, #5 ; LDR R2, R6, #-1 ; BRzp #-3
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1065, 0x65BF, 0x07FD]);
+        assert_eq!(
+            "Add the value in R1 to 5 and store the result in R0, then set condition codes.",
+            vm.explain()
+        );
+        vm.step().unwrap();
+        assert_eq!(
+            "Load the word at the address in R6 plus -1 into R2, then set condition codes.",
+            vm.explain()
+        );
+        vm.step().unwrap();
+        assert_eq!(
+            "Jump to x3000 if the last result was zero or positive.",
+            vm.explain()
+        );
+    }
+
+    #[test]
+    fn max_stack_depth_should_match_the_recursion_count() {
+        //counts R0 down to zero, pushing the return address for every call
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   AND R0, R0, #0
+                   ADD R0, R0, #5
+                   JSR DOWN
+                   HALT
+             DOWN  ADD R6, R6, #-1
+                   STR R7, R6, #0
+                   ADD R0, R0, #-1
+                   BRz BACK
+                   JSR DOWN
+             BACK  LDR R7, R6, #0
+                   ADD R6, R6, #1
+                   RET
+                   .END",
+        )
+        .unwrap();
+        assert_eq!(None, vm.max_stack_depth());
+        vm.set_reg(6, 0x4000);
+        vm.enable_stack_tracking();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(Some(5), vm.max_stack_depth());
+        assert_eq!(0x4000, vm.reg(6));
+    }
+
+    #[test]
+    fn vector_table_guard_should_stop_execution_in_the_vector_tables() {
+        //JMP through a null pointer
+        let program = [lc3_instr!(AND R1, R1, #0), lc3_instr!(JMP R1)];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.set_vector_table_guard(true);
+        assert_eq!(
+            RunResult::Error(VmError::ExecInVectorTable { pc: 0x0000 }),
+            vm.run()
+        );
+        assert_eq!(0x0000, vm.registers().pc());
+
+        //off by default: x0000 is executed as an instruction (a BR which tests nothing)
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.step_n(3);
+        assert_eq!(0x0001, vm.registers().pc());
+    }
+
+    #[test]
+    fn assemble_into_should_load_like_assemble_then_load() {
+        let source = ".ORIG x3000
+                   LEA R0, MSG
+                   PUTS
+                   HALT
+             MSG   .STRINGZ \"hi\"
+                   .END
+                   .ORIG x4000
+             DATA  .FILL MSG
+                   .END";
+        let mut expected = Vm::with_io(BufferIo::new());
+        expected.assemble_and_load(source).unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        assert_eq!(
+            LoadInfo {
+                origin: 0x3000,
+                len: 6,
+                end: 0x3006
+            },
+            assembler::assemble_into(source, &mut vm).unwrap()
+        );
+        assert_eq!(expected.memory_checksum(), vm.memory_checksum());
+        assert_eq!(0x3003, vm.peek(0x4000));
+        assert_eq!(expected.registers(), vm.registers());
+        assert_eq!(Some(0x4000), vm.symbols().get("DATA"));
+    }
+
+    #[test]
+    fn soft_reset_should_keep_memory_and_run_the_program_again() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   LD R0, COUNT
+                   ADD R0, R0, #1
+                   ST R0, COUNT
+                   HALT
+             COUNT .FILL #0
+                   .END",
+        )
+        .unwrap();
+        assert_eq!(RunResult::Halted, vm.run());
+        vm.soft_reset();
+        assert_eq!(1, vm.peek(0x3004));
+        assert_eq!(&Registers::new(), vm.registers());
+        assert_eq!(0, vm.steps_executed());
+        assert!(!vm.is_halted());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(2, vm.peek(0x3004));
+    }
+
+    #[test]
+    fn performance_report_should_summarise_the_counters() {
+        //sums 3 + 2 + 1 with a loop
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   AND R0, R0, #0
+                   ADD R1, R0, #3
+             LOOP  ADD R0, R0, R1
+                   ADD R1, R1, #-1
+                   BRp LOOP
+                   HALT
+                   .END",
+        )
+        .unwrap();
+        vm.enable_profiling();
+        vm.enable_stack_tracking();
+        assert_eq!(RunResult::Halted, vm.run());
+        let report = vm.performance_report();
+        assert!(report.starts_with("Instructions executed: 12\nEstimated cycles: 65\n"));
+        assert!(
+            report.contains("\nOpcodes:\n  BR             3   25.0%\n  ADD            7   58.3%\n")
+        );
+        assert!(report.contains("\nBranches:\n  x3004 taken 2 not taken 1\n"));
+        assert!(report.ends_with("\nStack:\n  max depth 0 word(s)\n"));
+    }
+
+    #[test]
+    fn pc_bounds_check_should_stop_a_program_which_runs_off_its_code() {
+        //the HALT is missing
+        let source = ".ORIG x3000
+                   AND R0, R0, #0
+                   ADD R0, R0, #1
+                   .END";
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(source).unwrap();
+        vm.set_pc_bounds_check(true);
+        assert_eq!(RunResult::PcOutOfBounds { pc: 0x3002 }, vm.run());
+        assert_eq!(2, vm.steps_executed());
+
+        //a program which halts never leaves its code
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(".ORIG x3000\n HALT\n .END").unwrap();
+        vm.set_pc_bounds_check(true);
+        assert_eq!(RunResult::Halted, vm.run());
+    }
+
+    #[test]
+    fn consume_run_should_give_back_the_final_state() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+                   LEA R0, MSG
+                   PUTS
+                   ST R0, SAVE
+                   HALT
+             MSG   .STRINGZ \"ok\"
+             SAVE  .BLKW 1
+                   .END",
+        )
+        .unwrap();
+        let mut inspected = vm.clone();
+        assert_eq!(RunResult::Halted, inspected.run());
+
+        let (halt, state) = vm.consume_run();
+        assert_eq!(RunResult::Halted, halt);
+        assert_eq!(halt, state.halt);
+        assert_eq!(*inspected.registers(), state.registers);
+        assert_eq!(inspected.memory_checksum(), state.memory_checksum);
+        assert_eq!("ok", state.io.output_string());
+    }
+}
@@ -0,0 +1,117 @@
+//! `output_events` : Tags the display output with where it came from (see `Vm::enable_output_events`),
+//! so captured output can be matched with the program logic which produced it.
+//! A TRAP's output is one event, even when it is written by an OS routine over many instructions;
+//! the routine runs until it returns to the instruction after the TRAP.
+
+use crate::hardware::instructions::fields;
+use crate::hardware::instructions::opcode::OpCode;
+
+/// `OutputSource` is what wrote an `OutputEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputSource {
+    /// The trap routine with this vector (e.g. x21 for OUT, x22 for PUTS).
+    Trap(u8),
+    /// A store to the DDR by the program itself.
+    Store,
+}
+
+/// `OutputEvent` is a chunk of output, written by `source` on behalf of the instruction at `pc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputEvent {
+    pub pc: u16,
+    pub source: OutputSource,
+    pub bytes: Vec<u8>,
+}
+
+/// `OutputEvents` groups the bytes written by each step into events.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct OutputEvents {
+    events: Vec<OutputEvent>,
+    /// The (pc, vector) of the TRAP whose OS routine is running.
+    trap: Option<(u16, u8)>,
+    /// `true` while the last event belongs to the running trap routine, so its bytes are appended to it.
+    open: bool,
+}
+
+impl OutputEvents {
+    /// `observe` records `bytes`, written by the instruction `instr` at `pc`, after which PC is `next_pc`.
+    pub(crate) fn observe(&mut self, pc: u16, instr: u16, next_pc: u16, bytes: Vec<u8>) {
+        let (pc, source) = if instr >> 12 == OpCode::Trap as u16 {
+            let vector = fields::trapvect8(instr);
+            //a vectored TRAP jumps into its routine
+            self.trap = Some((pc, vector)).filter(|_| next_pc != pc.wrapping_add(1));
+            self.open = false;
+            (pc, OutputSource::Trap(vector))
+        } else if let Some((trap_pc, vector)) = self.trap {
+            if next_pc == trap_pc.wrapping_add(1) {
+                self.trap = None;
+            }
+            (trap_pc, OutputSource::Trap(vector))
+        } else {
+            self.open = false;
+            (pc, OutputSource::Store)
+        };
+        if bytes.is_empty() {
+            return;
+        }
+        match self.events.last_mut() {
+            Some(event) if self.open => event.bytes.extend(bytes),
+            _ => self.events.push(OutputEvent { pc, source, bytes }),
+        }
+        self.open = self.trap.is_some();
+    }
+
+    pub(crate) fn events(&self) -> &[OutputEvent] {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod output_events_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn output_events_should_tell_puts_from_out() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+                  LEA R0, MSG
+                  PUTS
+                  LD R0, BANG
+                  OUT
+                  HALT
+             BANG .FILL x21
+              MSG .STRINGZ \"hi\"
+                  .END",
+        )
+        .unwrap();
+        let expected = [
+            OutputEvent {
+                pc: 0x3001,
+                source: OutputSource::Trap(0x22),
+                bytes: b"hi".to_vec(),
+            },
+            OutputEvent {
+                pc: 0x3003,
+                source: OutputSource::Trap(0x21),
+                bytes: b"!".to_vec(),
+            },
+        ];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_output_events();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(expected, vm.output_events()[..2]);
+
+        //the OS routines write a byte at a time, but each TRAP is still one event
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_os();
+        vm.load_program(&program);
+        vm.enable_output_events();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(expected, vm.output_events()[..2]);
+        assert_eq!("hi!", vm.io().output_string().get(..3).unwrap());
+    }
+}
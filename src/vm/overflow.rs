@@ -0,0 +1,68 @@
+//! `overflow` : The LC-3 has no overflow flag, but for teaching two's complement it helps to see
+//! when an ADD overflowed: both operands have the same sign and the result has the other one.
+
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hardware::register::Registers;
+
+/// `OverflowEvent` is an ADD at `pc` whose signed result did not fit in 16 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverflowEvent {
+    pub pc: u16,
+    pub lhs: u16,
+    pub rhs: u16,
+    /// The (wrapped) value written to the destination register.
+    pub result: u16,
+}
+
+/// `check` returns the event for `instr` at `pc`, if it is an ADD which overflows with the operands in `registers`
+/// (the registers before the instruction is executed).
+pub(crate) fn check(pc: u16, instr: u16, registers: &Registers) -> Option<OverflowEvent> {
+    let (lhs, rhs) = match DecodedInstr::decode(instr) {
+        DecodedInstr::AddReg { sr1, sr2, .. } => (registers.get(sr1), registers.get(sr2)),
+        DecodedInstr::AddImm { sr1, imm5, .. } => (registers.get(sr1), imm5 as u16),
+        _ => return None,
+    };
+    let result = lhs.wrapping_add(rhs);
+    let sign = |value: u16| value >> 15;
+    if sign(lhs) == sign(rhs) && sign(result) != sign(lhs) {
+        Some(OverflowEvent {
+            pc,
+            lhs,
+            rhs,
+            result,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod overflow_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn overflowing_add_should_be_logged_when_enabled() {
+        // LD R0, #3 ; ADD R0, R0, #1 ; ADD R0, R0, #1 ; HALT ; .FILL x7FFF
+        let program = [0x2003, 0x1021, 0x1021, 0xF025, 0x7FFF];
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.run();
+        assert!(vm.overflow_events().is_empty());
+
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &program);
+        vm.enable_overflow_log();
+        vm.run();
+        assert_eq!(
+            &[OverflowEvent {
+                pc: 0x3001,
+                lhs: 0x7FFF,
+                rhs: 0x0001,
+                result: 0x8000
+            }],
+            vm.overflow_events()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! `precompiled` : The "decode once, execute many" path of `Vm::run_precompiled`. Every word of the loaded regions
+//! is decoded once, before the run, and the instructions are then executed from their decoded form.
+//! Before it is used, a decoded instruction is checked against the word in memory, so a program which stores
+//! into its own code still runs correctly: the changed instruction goes through the normal path instead.
+//! TRAP, RTI and the reserved opcode always take the normal path, as do words which cannot be fetched without
+//! side effects (the device registers and, with extended memory, the bank window) or may not be executed at all.
+
+#[cfg(feature = "extended-memory")]
+use super::bank::{WINDOW_SIZE, WINDOW_START};
+use super::bus::SystemBus;
+use super::VmError;
+use crate::hardware::instructions::decode::DecodedInstr;
+use crate::hardware::io::Io;
+use crate::hardware::memory::{Access, AddrRange, Bus, Memory};
+use crate::hardware::register::condition_flag;
+use crate::hardware::register::Registers;
+
+//the first device register; device registers are never decoded ahead
+const DEVICE_REGISTERS: u16 = 0xFE00;
+
+//a word with its decoding, or `None` where the normal path is needed
+type Slot = Option<(u16, DecodedInstr)>;
+
+/// `Precompiled` holds, per loaded region, the slot of every word.
+pub(crate) struct Precompiled {
+    regions: Vec<(AddrRange, Vec<Slot>)>,
+}
+
+impl Precompiled {
+    /// `new` decodes the words of `regions` as they are in `memory`.
+    pub(crate) fn new(memory: &Memory, regions: &[AddrRange]) -> Precompiled {
+        let regions = regions
+            .iter()
+            .map(|&region| {
+                let words = region
+                    .iter()
+                    .map(|address| {
+                        let word = memory.read(address);
+                        let instr = DecodedInstr::decode(word);
+                        Some((word, instr))
+                            .filter(|_| is_decodable(memory, address) && is_straight_line(instr))
+                    })
+                    .collect();
+                (region, words)
+            })
+            .collect();
+        Precompiled { regions }
+    }
+
+    /// `get` returns the word at `address` with its decoding, if it was decoded and memory still holds that word.
+    pub(crate) fn get(&self, memory: &Memory, address: u16) -> Option<(u16, DecodedInstr)> {
+        let (region, words) = self
+            .regions
+            .iter()
+            .find(|(region, _)| region.contains(address))?;
+        words[address.wrapping_sub(region.start) as usize]
+            .filter(|&(word, _)| memory.read(address) == word)
+    }
+}
+
+//whether the word at `address` can be fetched ahead of time, without going through the bus
+fn is_decodable(memory: &Memory, address: u16) -> bool {
+    #[cfg(feature = "extended-memory")]
+    if (WINDOW_START..WINDOW_START + WINDOW_SIZE).contains(&address) {
+        return false;
+    }
+    address < DEVICE_REGISTERS && memory.check(address, Access::Execute).is_ok()
+}
+
+//whether `execute` handles the instruction
+fn is_straight_line(instr: DecodedInstr) -> bool {
+    !matches!(
+        instr,
+        DecodedInstr::Trap { .. } | DecodedInstr::Rti | DecodedInstr::Reserved(_)
+    )
+}
+
+/// `execute` executes a decoded instruction like `opcode::execute_instruction` does (PC must already point to
+/// the next instruction). TRAP, RTI and the reserved opcode are never decoded ahead, so they do not occur.
+pub(crate) fn execute<I: Io>(
+    instr: DecodedInstr,
+    registers: &mut Registers,
+    bus: &mut SystemBus<I>,
+) -> Result<(), VmError> {
+    let pc = registers.pc();
+    let relative = |offset: i16| pc.wrapping_add(offset as u16);
+    match instr {
+        DecodedInstr::AddReg { dr, sr1, sr2 } => set(
+            registers,
+            dr,
+            registers.get(sr1).wrapping_add(registers.get(sr2)),
+        ),
+        DecodedInstr::AddImm { dr, sr1, imm5 } => {
+            set(registers, dr, registers.get(sr1).wrapping_add(imm5 as u16))
+        }
+        DecodedInstr::AndReg { dr, sr1, sr2 } => {
+            set(registers, dr, registers.get(sr1) & registers.get(sr2))
+        }
+        DecodedInstr::AndImm { dr, sr1, imm5 } => {
+            set(registers, dr, registers.get(sr1) & imm5 as u16)
+        }
+        DecodedInstr::Not { dr, sr } => set(registers, dr, !registers.get(sr)),
+        DecodedInstr::Br { nzp, offset9 } => {
+            if registers.r_cond.is_some_and(|cond| cond.matches(nzp)) {
+                registers.set_pc(relative(offset9));
+            }
+        }
+        DecodedInstr::Jmp { base } => registers.set_pc(registers.get(base)),
+        DecodedInstr::Jsr { offset11 } => {
+            registers.r_07 = pc;
+            registers.set_pc(relative(offset11));
+        }
+        DecodedInstr::Jsrr { base } => {
+            let target = registers.get(base);
+            registers.r_07 = pc;
+            registers.set_pc(target);
+        }
+        DecodedInstr::Ld { dr, offset9 } => {
+            let value = bus.load(relative(offset9))?;
+            set(registers, dr, value);
+        }
+        DecodedInstr::Ldi { dr, offset9 } => {
+            let address = bus.load(relative(offset9))?;
+            let value = bus.load(address)?;
+            set(registers, dr, value);
+        }
+        DecodedInstr::Ldr { dr, base, offset6 } => {
+            let value = bus.load(registers.get(base).wrapping_add(offset6 as u16))?;
+            set(registers, dr, value);
+        }
+        DecodedInstr::Lea { dr, offset9 } => set(registers, dr, relative(offset9)),
+        DecodedInstr::St { sr, offset9 } => bus.store(relative(offset9), registers.get(sr))?,
+        DecodedInstr::Sti { sr, offset9 } => {
+            let address = bus.load(relative(offset9))?;
+            bus.store(address, registers.get(sr))?;
+        }
+        DecodedInstr::Str { sr, base, offset6 } => bus.store(
+            registers.get(base).wrapping_add(offset6 as u16),
+            registers.get(sr),
+        )?,
+        DecodedInstr::Trap { .. } | DecodedInstr::Rti | DecodedInstr::Reserved(_) => {
+            unreachable!("Only straight-line instructions are decoded ahead.")
+        }
+    }
+    Ok(())
+}
+
+//writes `value` to register `dr` and sets the condition codes from it
+fn set(registers: &mut Registers, dr: u16, value: u16) {
+    registers.update(dr, value);
+    condition_flag::update_r_cond_register(dr, registers);
+}
+
+#[cfg(test)]
+mod precompiled_test {
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn run_precompiled_should_match_run() {
+        //sorts an array in place, and patches its own code once: the ADD at PATCH becomes an ADD #2
+        let source = ".ORIG x3000
+                   LD R6, NEWADD
+                   ST R6, PATCH
+             PATCH ADD R5, R5, #1
+                   LEA R0, ARRAY
+                   LD R1, LEN
+             OUTER ADD R1, R1, #-1
+                   BRnz DONE
+                   ADD R2, R1, #0
+                   LEA R3, ARRAY
+             INNER LDR R4, R3, #0
+                   LDR R5, R3, #1
+                   NOT R6, R4
+                   ADD R6, R6, #1
+                   ADD R6, R6, R5
+                   BRzp KEEP
+                   STR R5, R3, #0
+                   STR R4, R3, #1
+             KEEP  ADD R3, R3, #1
+                   ADD R2, R2, #-1
+                   BRp INNER
+                   BRnzp OUTER
+             DONE  LDI R7, FIRST
+                   PUTS
+                   HALT
+             NEWADD ADD R5, R5, #2
+             LEN   .FILL #5
+             FIRST .FILL ARRAY
+             ARRAY .FILL #5
+                   .FILL #3
+                   .FILL #4
+                   .FILL #1
+                   .FILL #2
+                   .FILL #0
+                   .END";
+        let mut expected = Vm::with_io(BufferIo::new());
+        expected.assemble_and_load(source).unwrap();
+        let mut vm = expected.clone();
+        assert_eq!(RunResult::Halted, expected.run());
+        assert_eq!(RunResult::Halted, vm.run_precompiled());
+        assert_eq!(0x1B62, vm.peek(0x3002));
+        assert_eq!(expected.registers(), vm.registers());
+        assert_eq!(expected.memory_checksum(), vm.memory_checksum());
+        assert_eq!(expected.steps_executed(), vm.steps_executed());
+        assert_eq!(expected.io().output(), vm.io().output());
+    }
+}
@@ -0,0 +1,120 @@
+//! `preview` : What the next instruction would do, without doing it (see `Vm::preview_step`),
+//! for a debugger's "what happens next" view.
+//!
+//! The instruction runs against a copy of the registers and a scratch bus, which reads the machine's memory and
+//! keeps the stores to itself. Device registers are plain cells there: nothing is read from the keyboard or written
+//! to the display, so e.g. a GETC previews as a load of whatever the KBDR holds, and an OUT as a store to the DDR.
+//! Trap hooks, named traps and attached devices are not run.
+
+use super::interrupt;
+use super::{StepDelta, Vm, VmError};
+use crate::hardware::instructions::opcode::{self, OpCode};
+use crate::hardware::instructions::trap;
+use crate::hardware::io::Io;
+use crate::hardware::memory::{Access, Bus, Memory};
+use std::collections::BTreeMap;
+
+/// `ScratchBus` reads through to `memory`, and keeps every store in `writes`.
+struct ScratchBus<'a> {
+    memory: &'a Memory,
+    writes: BTreeMap<u16, u16>,
+}
+
+impl ScratchBus<'_> {
+    fn read(&self, address: u16) -> u16 {
+        self.writes
+            .get(&address)
+            .copied()
+            .unwrap_or_else(|| self.memory.read(address))
+    }
+}
+
+impl Bus for ScratchBus<'_> {
+    fn load(&mut self, address: u16) -> Result<u16, VmError> {
+        self.memory.check(address, Access::Read)?;
+        Ok(self.read(address))
+    }
+
+    fn store(&mut self, address: u16, value: u16) -> Result<(), VmError> {
+        self.memory.check(address, Access::Write)?;
+        self.writes.insert(address, value);
+        Ok(())
+    }
+
+    fn fetch(&mut self, address: u16) -> Result<u16, VmError> {
+        self.memory.check(address, Access::Execute)?;
+        Ok(self.read(address))
+    }
+}
+
+impl<I: Io> Vm<I> {
+    /// `preview_step` returns what `step` would change, leaving the machine (and its console) untouched.
+    /// An instruction which would fail gives its error instead. See `vm::preview` for what is simulated.
+    pub fn preview_step(&self) -> Result<StepDelta, VmError> {
+        let mut registers = self.registers.clone();
+        let mut bus = ScratchBus {
+            memory: &self.bus.memory,
+            writes: BTreeMap::new(),
+        };
+        let instr = bus.fetch(registers.pc())?;
+        registers.r_ir = instr;
+        registers.advance_pc();
+        let handler = self.bus.memory.read(interrupt::PRIVILEGE_VECTOR);
+        let mut privilege = self.privilege;
+        match (instr >> 12, self.interrupted) {
+            (op, Some(frame)) if op == OpCode::Rti as u16 => {
+                registers.set_pc(frame.pc);
+                registers.r_cond = frame.psr.condition();
+            }
+            (op, None) if op == OpCode::Rti as u16 && privilege.is_supervisor() => {
+                privilege.return_to_user(&mut registers, &mut bus)?
+            }
+            (op, None) if op == OpCode::Rti as u16 && handler != 0 => {
+                privilege.raise(&mut registers, &mut bus, handler)?
+            }
+            (op, _) if op == OpCode::Trap as u16 && self.os_traps => {
+                trap::trap_through_vector_table(instr, &mut registers, &mut bus)?
+            }
+            _ => opcode::execute_instruction(instr, &mut registers, &mut bus)?,
+        }
+        let memory = bus
+            .writes
+            .iter()
+            .map(|(&address, &new)| (address, self.bus.memory.read(address), new))
+            .filter(|(_, old, new)| old != new)
+            .collect();
+        Ok(StepDelta::new(instr, &self.registers, &registers, memory))
+    }
+}
+
+#[cfg(test)]
+mod preview_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+
+    #[test]
+    fn preview_step_should_not_change_the_machine() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R2, R1, #5 ; ST R2, #1 ; OUT
+        vm.load_words(0x3000, &[0x1465, 0x3401, 0xF021]);
+        vm.set_reg(1, 10);
+        let snapshot = vm.clone();
+        let delta = vm.preview_step().unwrap();
+        assert_eq!(snapshot, vm);
+        assert_eq!(vec![(2, 0, 15)], delta.registers);
+        assert_eq!((0x3000, 0x3001), delta.pc);
+        assert_eq!(Some(('-', 'P')), delta.cond);
+        assert!(delta.memory.is_empty());
+        vm.step().unwrap();
+        assert_eq!(15, vm.reg(2));
+        let delta = vm.preview_step().unwrap();
+        assert_eq!(vec![(0x3003, 0, 15)], delta.memory);
+        assert_eq!(0, vm.peek(0x3003));
+        //an OUT previews as a store to the DDR, and nothing reaches the console
+        vm.step().unwrap();
+        vm.set_reg(0, u16::from(b'!'));
+        let delta = vm.preview_step().unwrap();
+        assert_eq!(vec![(0xFE06, 0, 0x21)], delta.memory);
+        assert_eq!("", vm.io().output_string());
+    }
+}
@@ -0,0 +1,241 @@
+//! `profile` : Counts how many times each address and each opcode is executed, to find the hot spots of a program
+//! and estimate how long it would take on real hardware. Every BR also counts whether it was taken.
+
+use crate::assembler::SymbolTable;
+use crate::hardware::instructions::opcode::OpCode;
+use std::collections::{BTreeMap, HashMap};
+
+/// `BranchStats` counts the outcomes of one BR instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BranchStats {
+    /// The executions which jumped to the target.
+    pub taken: u64,
+    /// The executions which fell through to the next instruction.
+    pub not_taken: u64,
+}
+
+/// `Profile` holds an execution counter per instruction address and per opcode, and the outcomes per BR address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Profile {
+    counts: HashMap<u16, u64>,
+    op_codes: [u64; 16],
+    branches: BTreeMap<u16, BranchStats>,
+}
+
+impl Profile {
+    /// `record_branch` counts one execution of the BR at `pc`, which was `taken` or not.
+    pub(crate) fn record_branch(&mut self, pc: u16, taken: bool) {
+        let stats = self.branches.entry(pc).or_default();
+        if taken {
+            stats.taken += 1;
+        } else {
+            stats.not_taken += 1;
+        }
+    }
+
+    /// `branch_stats` returns the outcomes of every executed BR, by address.
+    pub(crate) fn branch_stats(&self) -> Vec<(u16, BranchStats)> {
+        self.branches
+            .iter()
+            .map(|(&pc, &stats)| (pc, stats))
+            .collect()
+    }
+
+    /// `record` counts one execution of the instruction `instr` at `pc`.
+    pub(crate) fn record(&mut self, pc: u16, instr: u16) {
+        *self.counts.entry(pc).or_insert(0) += 1;
+        self.op_codes[(instr >> 12) as usize] += 1;
+    }
+
+    /// `stats_csv` renders the opcode histogram as `opcode,count,cycles` rows, in opcode order,
+    /// followed by a `total` row. See `estimated_cycles` for the cycle model.
+    pub(crate) fn stats_csv(&self) -> String {
+        let mut csv = String::from("opcode,count,cycles\n");
+        let (mut total_count, mut total_cycles) = (0, 0);
+        for (value, &count) in self.op_codes.iter().enumerate() {
+            let op_code = OpCode::get(value as u16).expect("There are 16 opcodes.");
+            let cycles = count * estimated_cycles(op_code);
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                format!("{:?}", op_code).to_uppercase(),
+                count,
+                cycles
+            ));
+            total_count += count;
+            total_cycles += cycles;
+        }
+        csv.push_str(&format!("total,{},{}\n", total_count, total_cycles));
+        csv
+    }
+
+    /// `report` renders the counters as a summary for reading: the totals, the opcodes which were executed, the
+    /// outcomes of every BR and, if it was tracked, `max_stack_depth`.
+    pub(crate) fn report(&self, max_stack_depth: Option<u16>) -> String {
+        let (count, cycles) =
+            self.op_codes
+                .iter()
+                .enumerate()
+                .fold((0, 0), |(count, cycles), (value, &n)| {
+                    let op_code = OpCode::get(value as u16).expect("There are 16 opcodes.");
+                    (count + n, cycles + n * estimated_cycles(op_code))
+                });
+        let mut report = format!(
+            "Instructions executed: {}\nEstimated cycles: {}\n\nOpcodes:\n",
+            count, cycles
+        );
+        for (value, &n) in self.op_codes.iter().enumerate().filter(|&(_, &n)| n > 0) {
+            let op_code = OpCode::get(value as u16).expect("There are 16 opcodes.");
+            report.push_str(&format!(
+                "  {:<5} {:>10} {:>6.1}%\n",
+                format!("{:?}", op_code).to_uppercase(),
+                n,
+                100.0 * n as f64 / count as f64
+            ));
+        }
+        report.push_str("\nBranches:\n");
+        if self.branches.is_empty() {
+            report.push_str("  none executed\n");
+        }
+        for (pc, stats) in self.branches.iter() {
+            report.push_str(&format!(
+                "  x{:04X} taken {} not taken {}\n",
+                pc, stats.taken, stats.not_taken
+            ));
+        }
+        report.push_str("\nStack:\n");
+        match max_stack_depth {
+            Some(depth) => report.push_str(&format!("  max depth {} word(s)\n", depth)),
+            None => report.push_str("  not tracked\n"),
+        }
+        report
+    }
+
+    /// `hotspots` returns the `top_n` most executed addresses with their counts, most executed first.
+    /// Addresses executed equally often are ordered by address.
+    pub(crate) fn hotspots(&self, top_n: usize) -> Vec<(u16, u64)> {
+        let mut hotspots: Vec<(u16, u64)> = self
+            .counts
+            .iter()
+            .map(|(&address, &count)| (address, count))
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hotspots.truncate(top_n);
+        hotspots
+    }
+}
+
+/// `estimated_cycles` is the number of clock cycles `op_code` takes in the LC-3 microarchitecture of Patt & Patel,
+/// assuming memory answers in a single cycle and branches are taken. The 4 cycles of the fetch and decode are included;
+/// RTI and the reserved opcode are counted as the fetch only, as the VM does not execute them.
+pub(crate) fn estimated_cycles(op_code: OpCode) -> u64 {
+    let execute = match op_code {
+        OpCode::Add | OpCode::And | OpCode::Not | OpCode::Lea | OpCode::Jmp => 1,
+        OpCode::Br | OpCode::Jsr => 2,
+        OpCode::Ld | OpCode::Ldr | OpCode::St | OpCode::Str | OpCode::Trap => 3,
+        OpCode::Ldi | OpCode::Sti => 5,
+        OpCode::Rti | OpCode::Res => 0,
+    };
+    4 + execute
+}
+
+/// `format_hotspots` renders `hotspots` one per line, as `x3002 LOOP 10`,
+/// using the label at each address from `symbols` (or nothing, if there is none).
+pub fn format_hotspots(hotspots: &[(u16, u64)], symbols: &SymbolTable) -> String {
+    hotspots
+        .iter()
+        .map(|&(address, count)| match symbols.label_at(address) {
+            Some(label) => format!("x{:04X} {} {}\n", address, label, count),
+            None => format!("x{:04X} {}\n", address, count),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod profile_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::lc3_instr;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn hotspots_should_rank_the_loop_body_highest() {
+        let program = crate::assembler::assemble(
+            ".ORIG x3000
+                   AND R1, R1, #0
+                   ADD R1, R1, #10
+             LOOP  ADD R1, R1, #-1
+                   BRp LOOP
+                   HALT
+                   .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_profiling();
+        assert_eq!(RunResult::Halted, vm.run());
+        let hotspots = vm.hotspots(2);
+        assert_eq!(vec![(0x3002, 10), (0x3003, 10)], hotspots);
+        assert_eq!(
+            "x3002 LOOP 10\nx3003 10\n",
+            format_hotspots(&hotspots, &program.symbols)
+        );
+    }
+
+    #[test]
+    fn stats_csv_should_count_executions_per_opcode() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(
+            0x3000,
+            &[
+                lc3_instr!(LEA R1, #4),
+                lc3_instr!(ADD R0, R0, #1),
+                lc3_instr!(LDR R2, R1, #0),
+                lc3_instr!(ADD R0, R0, #2),
+                lc3_instr!(LDR R3, R1, #0),
+                lc3_instr!(ADD R0, R0, #3),
+            ],
+        );
+        vm.enable_profiling();
+        for _ in 0..6 {
+            vm.step().unwrap();
+        }
+        let csv = vm.stats_csv();
+        assert!(csv.starts_with("opcode,count,cycles\n"));
+        assert!(csv.contains("\nADD,3,15\n"));
+        assert!(csv.contains("\nLDR,2,14\n"));
+        assert!(csv.contains("\nLEA,1,5\n"));
+        assert!(csv.ends_with("\ntotal,6,34\n"));
+        assert_eq!(18, csv.lines().count());
+    }
+
+    #[test]
+    fn branch_stats_should_count_the_back_edge() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             AND R0, R0, #0
+             ADD R1, R0, #5
+        LOOP ADD R0, R0, #2
+             ADD R1, R1, #-1
+             BRp LOOP
+             HALT
+             .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.enable_profiling();
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(10, vm.reg(0));
+        assert_eq!(
+            vec![(
+                0x3004,
+                BranchStats {
+                    taken: 4,
+                    not_taken: 1
+                }
+            )],
+            vm.branch_stats()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! `register_trace` : Which registers each instruction reads and writes, for data-flow debugging
+//! (see `Vm::set_register_trace`). The accesses follow from the operands of the instruction:
+//! sources and base registers are read, destinations are written, and JSR, JSRR and TRAP write the return address to R7.
+//! What a trap routine does with the registers is not traced.
+
+use crate::hardware::instructions::decode::DecodedInstr;
+
+/// `RegisterAccess` is one read or write of register `reg` (0 - 7) by the instruction at `pc`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterAccess {
+    pub pc: u16,
+    pub reg: u16,
+    pub is_write: bool,
+}
+
+/// `accesses` returns the register accesses of `instr` at `pc`: the reads in operand order, then the write.
+pub(crate) fn accesses(pc: u16, instr: u16) -> Vec<RegisterAccess> {
+    let (reads, write): (&[u16], Option<u16>) = match DecodedInstr::decode(instr) {
+        DecodedInstr::AddReg { dr, sr1, sr2 } | DecodedInstr::AndReg { dr, sr1, sr2 } => {
+            (&[sr1, sr2], Some(dr))
+        }
+        DecodedInstr::AddImm { dr, sr1, .. } | DecodedInstr::AndImm { dr, sr1, .. } => {
+            (&[sr1], Some(dr))
+        }
+        DecodedInstr::Not { dr, sr } => (&[sr], Some(dr)),
+        DecodedInstr::Jmp { base } => (&[base], None),
+        DecodedInstr::Jsr { .. } | DecodedInstr::Trap { .. } => (&[], Some(7)),
+        DecodedInstr::Jsrr { base } => (&[base], Some(7)),
+        DecodedInstr::Ld { dr, .. }
+        | DecodedInstr::Ldi { dr, .. }
+        | DecodedInstr::Lea { dr, .. } => (&[], Some(dr)),
+        DecodedInstr::Ldr { dr, base, .. } => (&[base], Some(dr)),
+        DecodedInstr::St { sr, .. } | DecodedInstr::Sti { sr, .. } => (&[sr], None),
+        DecodedInstr::Str { sr, base, .. } => (&[sr, base], None),
+        DecodedInstr::Br { .. } | DecodedInstr::Rti | DecodedInstr::Reserved(_) => (&[], None),
+    };
+    let access = |reg, is_write| RegisterAccess { pc, reg, is_write };
+    reads
+        .iter()
+        .map(|&reg| access(reg, false))
+        .chain(write.map(|reg| access(reg, true)))
+        .collect()
+}
+
+#[cfg(test)]
+mod register_trace_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn register_trace_should_tell_reads_from_writes() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        // ADD R3, R1, R2 ; STR R3, R6, #0
+        vm.load_words(0x3000, &[0x1642, 0x7780]);
+        vm.set_register_trace(true);
+        vm.step().unwrap();
+        let access = |reg, is_write| RegisterAccess {
+            pc: 0x3000,
+            reg,
+            is_write,
+        };
+        assert_eq!(
+            &[access(1, false), access(2, false), access(3, true)],
+            vm.register_accesses()
+        );
+        vm.step().unwrap();
+        assert_eq!(5, vm.register_accesses().len());
+        assert!(vm.register_accesses()[3..].iter().all(|a| !a.is_write));
+        vm.set_register_trace(false);
+        assert!(vm.register_accesses().is_empty());
+    }
+}
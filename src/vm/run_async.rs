@@ -0,0 +1,116 @@
+//! `run_async` : Runs a `Vm` from an async runtime, e.g. in a web or TUI app whose keyboard is async
+//! (behind the `async` feature). The program runs synchronously until a built-in GETC or IN needs a key
+//! which has not arrived yet; then the run awaits the next byte of an `AsyncIo` instead of blocking.
+//!
+//! The console of the `Vm` is a `BufferIo`: the awaited bytes are queued as its input, and the app drains its
+//! output as it likes. With the OS image loaded (`Vm::load_os`), the OS polls the keyboard itself, so the run
+//! never waits; queue the input up front then.
+
+use super::{RunResult, Vm};
+use crate::hardware::instructions::opcode::OpCode;
+use crate::hardware::io::{BufferIo, Io};
+use std::future::Future;
+
+/// `AsyncIo` is an async keyboard.
+pub trait AsyncIo {
+    /// `read_byte` completes with the next input byte, or with `None` when there is no more input.
+    fn read_byte(&mut self) -> impl Future<Output = Option<u8>>;
+}
+
+impl Vm<BufferIo> {
+    /// `run_async` is `run`, but awaits `input` whenever a built-in GETC or IN would otherwise find no input.
+    pub async fn run_async<A: AsyncIo>(&mut self, input: &mut A) -> RunResult {
+        loop {
+            match self.run_steps(u64::MAX, Vm::waits_for_input) {
+                Some(result) => return result,
+                None => match input.read_byte().await {
+                    Some(byte) => self.io_mut().push_input(&[byte]),
+                    //let the trap find the input empty, as `run` would
+                    None => {
+                        if let Err(e) = self.step() {
+                            return RunResult::Error(e);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    //whether the next instruction is a built-in GETC or IN, and no input is queued
+    fn waits_for_input(&mut self) -> bool {
+        let instr = self.peek(self.registers.pc());
+        !self.os_traps
+            && instr >> 12 == OpCode::Trap as u16
+            && matches!(instr & 0xFF, 0x20 | 0x23)
+            && !self.io_mut().input_ready()
+    }
+}
+
+#[cfg(test)]
+mod run_async_test {
+    use super::*;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    //polls `future` until it is ready
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    //a keyboard whose single key arrives on the second poll
+    struct OneKey(Option<u8>);
+
+    struct NextKey<'a> {
+        key: &'a mut Option<u8>,
+        polled: bool,
+    }
+
+    impl Future for NextKey<'_> {
+        type Output = Option<u8>;
+
+        fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<u8>> {
+            if self.polled {
+                Poll::Ready(self.key.take())
+            } else {
+                self.polled = true;
+                context.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncIo for OneKey {
+        fn read_byte(&mut self) -> impl Future<Output = Option<u8>> {
+            NextKey {
+                key: &mut self.0,
+                polled: false,
+            }
+        }
+    }
+
+    #[test]
+    fn getc_should_complete_with_the_awaited_byte() {
+        // GETC ; OUT ; HALT
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0xF020, 0xF021, 0xF025]);
+        let mut keyboard = OneKey(Some(b'k'));
+        assert_eq!(RunResult::Halted, block_on(vm.run_async(&mut keyboard)));
+        assert_eq!(u16::from(b'k'), vm.registers().get(0));
+        assert_eq!("k", vm.io().output_string());
+        assert_eq!(None, keyboard.0);
+    }
+}
@@ -0,0 +1,142 @@
+//! `scheduler` : Time-slices several machines, e.g. for a multi-process teaching demo.
+//! Every round gives each machine which can still run a quantum of instructions (with `Vm::step_n`),
+//! in the order the machines were added.
+
+use super::{RunResult, Vm};
+use crate::hardware::io::Io;
+
+/// `SchedulerEvent` is a machine stopping for another reason than the end of its quantum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchedulerEvent {
+    /// The index of the machine (see `Scheduler::add`).
+    pub vm: usize,
+    /// The round in which it stopped, counting from 0.
+    pub round: u64,
+    /// Why it stopped: `Halted`, `Error`, a breakpoint ...
+    pub result: RunResult,
+}
+
+/// `Scheduler` runs its machines round-robin, `quantum` instructions at a time.
+/// A machine is runnable until it halts or fails; a breakpoint or watchpoint is reported,
+/// and the machine carries on in the next round.
+#[derive(Clone, Debug)]
+pub struct Scheduler<I: Io> {
+    vms: Vec<Vm<I>>,
+    failed: Vec<bool>,
+    quantum: u64,
+    round: u64,
+    events: Vec<SchedulerEvent>,
+}
+
+impl<I: Io> Scheduler<I> {
+    /// `new` creates a scheduler without machines, which gives each one `quantum` instructions per round.
+    pub fn new(quantum: u64) -> Scheduler<I> {
+        Scheduler {
+            vms: Vec::new(),
+            failed: Vec::new(),
+            quantum,
+            round: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// `add` schedules `vm`, after the machines added before it, and returns its index.
+    pub fn add(&mut self, vm: Vm<I>) -> usize {
+        self.vms.push(vm);
+        self.failed.push(false);
+        self.vms.len() - 1
+    }
+
+    /// `is_runnable` tells whether the machine at `index` has neither halted nor failed.
+    pub fn is_runnable(&self, index: usize) -> bool {
+        !self.failed[index] && !self.vms[index].is_halted()
+    }
+
+    /// `run_round` gives every runnable machine one quantum, and returns how many machines ran.
+    pub fn run_round(&mut self) -> usize {
+        let mut ran = 0;
+        for index in 0..self.vms.len() {
+            if !self.is_runnable(index) {
+                continue;
+            }
+            ran += 1;
+            match self.vms[index].step_n(self.quantum) {
+                RunResult::Continue => {}
+                result => {
+                    self.failed[index] = matches!(result, RunResult::Error(_));
+                    self.events.push(SchedulerEvent {
+                        vm: index,
+                        round: self.round,
+                        result,
+                    });
+                }
+            }
+        }
+        self.round += 1;
+        ran
+    }
+
+    /// `run` runs rounds until no machine is runnable, or `max_rounds` rounds have run.
+    /// It returns the number of rounds.
+    pub fn run(&mut self, max_rounds: u64) -> u64 {
+        let mut rounds = 0;
+        while rounds < max_rounds && self.run_round() > 0 {
+            rounds += 1;
+        }
+        rounds
+    }
+
+    /// `events` returns the stops reported so far, in the order they happened.
+    pub fn events(&self) -> &[SchedulerEvent] {
+        &self.events
+    }
+
+    pub fn vm(&self, index: usize) -> &Vm<I> {
+        &self.vms[index]
+    }
+
+    pub fn vm_mut(&mut self, index: usize) -> &mut Vm<I> {
+        &mut self.vms[index]
+    }
+
+    /// `into_vms` gives the machines back, in the order they were added.
+    pub fn into_vms(self) -> Vec<Vm<I>> {
+        self.vms
+    }
+}
+
+#[cfg(test)]
+mod scheduler_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+
+    #[test]
+    fn scheduler_should_interleave_programs_until_both_halt() {
+        let counter = |count: u16| {
+            let mut vm = Vm::with_io(BufferIo::new());
+            // LD R1, #4 ; ADD R0, R0, #1 ; ADD R1, R1, #-1 ; BRp #-3 ; HALT ; count
+            vm.load_words(0x3000, &[0x2204, 0x1021, 0x127F, 0x03FD, 0xF025, count]);
+            vm
+        };
+        let mut scheduler = Scheduler::new(4);
+        let short = scheduler.add(counter(2));
+        let long = scheduler.add(counter(10));
+        assert_eq!(2, scheduler.run_round());
+        assert_eq!(4, scheduler.vm(short).steps_executed());
+        assert_eq!(4, scheduler.vm(long).steps_executed());
+        let rounds = scheduler.run(100);
+        assert!(rounds < 100);
+        assert!(!scheduler.is_runnable(short) && !scheduler.is_runnable(long));
+        assert_eq!(2, scheduler.vm(short).reg(0));
+        assert_eq!(10, scheduler.vm(long).reg(0));
+        let events = scheduler.events();
+        assert_eq!(
+            vec![(short, RunResult::Halted), (long, RunResult::Halted)],
+            events
+                .iter()
+                .map(|event| (event.vm, event.result))
+                .collect::<Vec<_>>()
+        );
+        assert!(events[0].round < events[1].round);
+    }
+}
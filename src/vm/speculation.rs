@@ -0,0 +1,203 @@
+//! `speculation` : Runs a machine ahead and then forgets it, e.g. for a debugger which previews the next N steps
+//! (see `Vm::clone_for_speculation`).
+//!
+//! Rather than cloning all 128kb of memory, a speculation runs on the machine itself, logs the old value of every
+//! store, and undoes the stores (newest first) when it is dropped. Forking costs a copy of the registers, the
+//! console and the 512 words of the device register page; running costs one log entry per store; discarding
+//! costs one write per store. The instrumentation (profile, traces, time travel, the teaching checks, output events,
+//! access counts and register listeners) is paused while the speculation runs, so it neither sees nor records the speculative
+//! steps. Trap hooks, named traps and attached devices do run, and what they do outside the machine is not undone.
+
+use super::bus::BusCheckpoint;
+use super::convention::ConventionChecker;
+use super::history::History;
+use super::interrupt::{InterruptFrame, Privilege};
+use super::listener::RegisterListeners;
+use super::loop_detect::LoopDetector;
+use super::output_events::OutputEvents;
+use super::profile::Profile;
+use super::timer::Timer;
+use super::trace::TraceWriter;
+use super::{OverflowEvent, RegisterAccess, RunResult, Vm, VmError};
+use crate::hardware::instructions::opcode::OpCode;
+use crate::hardware::io::Io;
+use crate::hardware::register::Registers;
+use std::mem;
+use std::ops::Deref;
+
+/// `Speculation` is a fork of a machine (see `Vm::clone_for_speculation`). It can be stepped and inspected
+/// like the machine; dropping it puts the machine back as it was when the speculation started.
+pub struct Speculation<'a, I: Io + Clone> {
+    vm: &'a mut Vm<I>,
+    saved: Option<Saved<I>>,
+}
+
+//what the machine had when the speculation started, apart from its memory
+struct Saved<I> {
+    registers: Registers,
+    bus: BusCheckpoint<I>,
+    interrupted: Option<InterruptFrame>,
+    privilege: Privilege,
+    timer: Option<Timer>,
+    attempted_unimplemented: Option<(u16, OpCode)>,
+    steps_executed: u64,
+    last_error: Option<(u16, VmError)>,
+    loop_detector: Option<LoopDetector>,
+    profile: Option<Profile>,
+    trace: Option<Vec<String>>,
+    trace_writer: TraceWriter,
+    convention: Option<ConventionChecker>,
+    overflows: Option<Vec<OverflowEvent>>,
+    register_accesses: Option<Vec<RegisterAccess>>,
+    output_events: Option<OutputEvents>,
+    register_listeners: RegisterListeners,
+    history: Option<History>,
+}
+
+impl<I: Io + Clone> Vm<I> {
+    /// `clone_for_speculation` forks the machine cheaply: the returned `Speculation` can run steps, and when
+    /// it is dropped every register, memory cell and console byte is as it was before the fork. See `vm::speculation`
+    /// for the costs, and for what is paused or not undone.
+    pub fn clone_for_speculation(&mut self) -> Speculation<'_, I> {
+        let saved = Saved {
+            registers: self.registers.clone(),
+            bus: self.bus.checkpoint(),
+            interrupted: self.interrupted,
+            privilege: self.privilege,
+            timer: self.timer.clone(),
+            attempted_unimplemented: self.attempted_unimplemented,
+            steps_executed: self.steps_executed,
+            last_error: self.last_error,
+            loop_detector: self.loop_detector.clone(),
+            profile: self.profile.take(),
+            trace: self.trace.take(),
+            trace_writer: mem::take(&mut self.trace_writer),
+            convention: self.convention.take(),
+            overflows: self.overflows.take(),
+            register_accesses: self.register_accesses.take(),
+            output_events: self.output_events.take(),
+            register_listeners: mem::take(&mut self.register_listeners),
+            history: self.history.take(),
+        };
+        Speculation {
+            vm: self,
+            saved: Some(saved),
+        }
+    }
+}
+
+impl<I: Io + Clone> Speculation<'_, I> {
+    /// `step` executes one instruction on the fork (see `Vm::step`).
+    pub fn step(&mut self) -> Result<(), VmError> {
+        self.vm.step()
+    }
+
+    /// `run_for` runs the fork for at most `max_steps` instructions (see `Vm::run_for`).
+    pub fn run_for(&mut self, max_steps: u64) -> RunResult {
+        self.vm.run_for(max_steps)
+    }
+}
+
+impl<I: Io + Clone> Deref for Speculation<'_, I> {
+    type Target = Vm<I>;
+
+    fn deref(&self) -> &Vm<I> {
+        self.vm
+    }
+}
+
+impl<I: Io + Clone> Drop for Speculation<'_, I> {
+    fn drop(&mut self) {
+        let saved = self.saved.take().expect("A speculation is dropped once.");
+        let vm = &mut *self.vm;
+        vm.bus.rollback(saved.bus);
+        vm.registers = saved.registers;
+        vm.interrupted = saved.interrupted;
+        vm.privilege = saved.privilege;
+        vm.timer = saved.timer;
+        vm.attempted_unimplemented = saved.attempted_unimplemented;
+        vm.steps_executed = saved.steps_executed;
+        vm.last_error = saved.last_error;
+        vm.loop_detector = saved.loop_detector;
+        vm.profile = saved.profile;
+        vm.trace = saved.trace;
+        vm.trace_writer = saved.trace_writer;
+        vm.convention = saved.convention;
+        vm.overflows = saved.overflows;
+        vm.register_accesses = saved.register_accesses;
+        vm.output_events = saved.output_events;
+        vm.register_listeners = saved.register_listeners;
+        vm.history = saved.history;
+    }
+}
+
+#[cfg(test)]
+mod speculation_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::timer::TimerInterval;
+
+    #[test]
+    fn speculation_should_leave_the_machine_unchanged() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+             LOOP ADD R0, R0, #1
+                  ST R0, SEEN
+                  OUT
+                  BR LOOP
+             SEEN .FILL #0
+                  .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::with_input("keep"));
+        vm.load_program(&program);
+        vm.set_reg(0, 0x40);
+        vm.enable_profiling();
+        vm.step().unwrap();
+        let before = vm.clone();
+        {
+            let mut fork = vm.clone_for_speculation();
+            assert_eq!(RunResult::BudgetExhausted, fork.run_for(9));
+            assert_eq!(0x43, fork.reg(0));
+            assert_eq!(0x43, fork.peek(0x3004));
+            assert_eq!("AB", fork.io().output_string());
+            assert_eq!(10, fork.steps_executed());
+        }
+        assert_eq!(before, vm);
+        assert_eq!(0, vm.peek(0x3004));
+        assert_eq!("", vm.io().output_string());
+        assert_eq!(4, vm.io().pending_input());
+        assert_eq!(before.hotspots(10), vm.hotspots(10));
+        assert_eq!(1, vm.steps_executed());
+    }
+
+    #[test]
+    fn speculation_should_leave_the_timer_unchanged() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+             LOOP  ADD R0, R0, #1
+                   BRnzp LOOP
+                   .END",
+        )
+        .unwrap();
+        // ADD R1, R1, #1 ; RTI
+        vm.load_words(0x4000, &[0x1261, 0x8000]);
+        vm.poke(0x0181, 0x4000);
+        vm.attach_timer(Timer::new(TimerInterval::Instructions(5), 0x81));
+        vm.step_n(3);
+        let mut expected = vm.clone();
+        {
+            let mut fork = vm.clone_for_speculation();
+            assert_eq!(RunResult::BudgetExhausted, fork.run_for(3));
+            assert_eq!(0x4001, fork.registers().pc());
+        }
+        assert_eq!(expected, vm);
+        //the timer is still 3 instructions into its interval
+        vm.step_n(3);
+        expected.step_n(3);
+        assert_eq!(0x4001, vm.registers().pc());
+        assert_eq!(expected, vm);
+    }
+}
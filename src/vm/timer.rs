@@ -0,0 +1,172 @@
+//! `timer` : A periodic timer interrupt, as an exercise in interrupt handling (see `Vm::attach_timer`).
+//! The timer ticks every `n` instructions, or every `n` milliseconds of wall-clock time, and each tick interrupts
+//! the program like a key does (see `vm::interrupt`): PC jumps to the handler in the interrupt vector table entry
+//! of the timer's vector, and the handler returns with RTI.
+//!
+//! The timer status register (TMR, xFE08) works like the KBSR: bit [14] enables the interrupt (it is set when
+//! the timer is attached, and a program can clear it to mask the timer), and bit [15] is set whenever the timer
+//! interrupts the program; the handler may clear it.
+//! A tick which cannot interrupt the program, because the timer is masked or a handler is running, waits until it can;
+//! ticks do not add up, so a program which was masked for a long time gets one interrupt.
+
+use crate::hardware::memory::Memory;
+use std::time::{Duration, Instant};
+
+/// `TIMER_STATUS` is the address of the TMR.
+pub(crate) const TIMER_STATUS: u16 = 0xFE08;
+
+const READY: u16 = 1 << 15;
+const INTERRUPT_ENABLE: u16 = 1 << 14;
+
+/// `TimerInterval` is how often a `Timer` ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerInterval {
+    /// Every `n` instructions executed, the handlers included.
+    Instructions(u64),
+    /// Every `n` milliseconds of wall-clock time (the real-time mode); instructions are not counted.
+    Millis(u64),
+}
+
+/// `Timer` is a timer which interrupts through the vector table entry x0100 + `vector`.
+#[derive(Clone, Debug)]
+pub struct Timer {
+    interval: TimerInterval,
+    vector: u8,
+    //the instructions executed since the last tick
+    executed: u64,
+    //when the last tick happened, in the real-time mode
+    last_tick: Instant,
+}
+
+impl Timer {
+    /// `new` creates a timer ticking every `interval`. Panics if the interval is zero.
+    pub fn new(interval: TimerInterval, vector: u8) -> Timer {
+        assert!(
+            !matches!(
+                interval,
+                TimerInterval::Instructions(0) | TimerInterval::Millis(0)
+            ),
+            "A timer needs an interval of at least one."
+        );
+        Timer {
+            interval,
+            vector,
+            executed: 0,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// `vector_entry` is the address of the timer's entry in the interrupt vector table.
+    pub(crate) fn vector_entry(&self) -> u16 {
+        0x0100 + u16::from(self.vector)
+    }
+
+    /// `count` counts an instruction executed.
+    pub(crate) fn count(&mut self) {
+        self.executed += 1;
+    }
+
+    /// `is_due` tells whether a tick has happened since the last one was taken.
+    pub(crate) fn is_due(&self) -> bool {
+        match self.interval {
+            TimerInterval::Instructions(n) => self.executed >= n,
+            TimerInterval::Millis(ms) => self.last_tick.elapsed() >= Duration::from_millis(ms),
+        }
+    }
+
+    /// `take` starts the next interval, once a tick has interrupted the program.
+    pub(crate) fn take(&mut self) {
+        self.executed = 0;
+        self.last_tick = Instant::now();
+    }
+}
+
+//timers are equal when they tick alike and are as far into their interval, in instructions;
+//the time of the last tick is left out, so that two machines can be equal however far apart they were started
+impl PartialEq for Timer {
+    fn eq(&self, other: &Timer) -> bool {
+        self.interval == other.interval
+            && self.vector == other.vector
+            && self.executed == other.executed
+    }
+}
+
+impl Eq for Timer {}
+
+/// `enable` sets the interrupt enable bit of the TMR.
+pub(crate) fn enable(memory: &mut Memory) {
+    let status = memory.read(TIMER_STATUS);
+    memory.write(TIMER_STATUS as usize, status | INTERRUPT_ENABLE);
+}
+
+/// `is_enabled` tells whether the TMR enables the timer interrupt.
+pub(crate) fn is_enabled(memory: &Memory) -> bool {
+    memory.read(TIMER_STATUS) & INTERRUPT_ENABLE != 0
+}
+
+/// `mark_tick` sets the ready bit of the TMR.
+pub(crate) fn mark_tick(memory: &mut Memory) {
+    let status = memory.read(TIMER_STATUS);
+    memory.write(TIMER_STATUS as usize, status | READY);
+}
+
+#[cfg(test)]
+mod timer_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn timer_should_interrupt_every_interval() {
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.assemble_and_load(
+            ".ORIG x3000
+             LOOP  ADD R0, R0, #1
+                   BRnzp LOOP
+                   .END",
+        )
+        .unwrap();
+        // ADD R1, R1, #1 ; RTI
+        vm.load_words(0x4000, &[0x1261, 0x8000]);
+        vm.poke(0x0181, 0x4000);
+        vm.attach_timer(Timer::new(TimerInterval::Instructions(5), 0x81));
+        assert_eq!(0x4000, vm.peek(0xFE08));
+
+        let mut fired = Vec::new();
+        for _ in 0..20 {
+            vm.step().unwrap();
+            if vm.registers().r_ir == 0x1261 {
+                fired.push(vm.steps_executed());
+            }
+        }
+        //5 instructions after each tick, the handler's own two included
+        assert_eq!(vec![6, 11, 16], fired);
+        assert_eq!(3, vm.reg(1));
+        assert_eq!(0xC000, vm.peek(0xFE08));
+
+        //masked, the timer waits
+        vm.poke(0xFE08, 0);
+        vm.step_n(20);
+        assert_eq!(3, vm.reg(1));
+        //and interrupts once it is enabled again
+        vm.poke(0xFE08, 0x4000);
+        vm.step().unwrap();
+        assert_eq!(4, vm.reg(1));
+
+        //a reset starts the interval again
+        vm.step_n(3);
+        vm.soft_reset();
+        vm.step_n(5);
+        assert_eq!(0x3001, vm.registers().pc());
+        vm.step().unwrap();
+        assert_eq!(0x4001, vm.registers().pc());
+    }
+
+    #[test]
+    fn timers_should_be_equal_whenever_they_were_started() {
+        let timer = Timer::new(TimerInterval::Millis(10), 0x81);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(timer, Timer::new(TimerInterval::Millis(10), 0x81));
+        assert_ne!(timer, Timer::new(TimerInterval::Millis(10), 0x82));
+    }
+}
@@ -0,0 +1,246 @@
+//! `trace` : An execution trace has one line per executed instruction, in the format
+//!
+//! ```text
+//! AFTER x3001: R0=x0005 CC=P
+//! ```
+//!
+//! - `AFTER x3001` is the PC after the instruction was executed,
+//! - followed by every general purpose register the instruction changed (R0 first), as `Rn=xHHHH`,
+//! - and the condition code after the instruction, `CC=N`, `CC=Z` or `CC=P` (`CC=-` while no flag is set).
+//!
+//! Every field is separated by a single space, and hex digits are upper case,
+//! so traces from different simulators can be compared with `diff`.
+//!
+//! That is `TraceFormat::LcThree`, the default. `Vm::set_trace_format` picks one of the other styles:
+//!
+//! ```text
+//! 3000 1025 R0=0005 P
+//! {"pc":12288,"ir":4133,"changed":{"R0":5},"cc":"P"}
+//! ```
+//!
+//! `Compact` gives the address and the word of the instruction (rather than the PC after it), the changed registers
+//! and the condition code, in bare hex. `Json` gives the same fields as one JSON object per line, with numbers in decimal.
+
+use super::binary_trace::BinaryTraceWriter;
+use crate::hardware::register::Registers;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+
+/// `TraceFormat` is the style of the trace lines (see the module documentation).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// `AFTER x3001: R0=x0005 CC=P`, the format of the reference traces.
+    #[default]
+    LcThree,
+    /// `3000 1025 R0=0005 P`
+    Compact,
+    /// `{"pc":12288,"ir":4133,"changed":{"R0":5},"cc":"P"}`
+    Json,
+}
+
+impl TraceFormat {
+    /// `line` describes the instruction which took the registers from `before` to `after`, in this format.
+    pub fn line(self, before: &Registers, after: &Registers) -> String {
+        let changed = (0..8).filter(|&r| before.get(r) != after.get(r));
+        match self {
+            TraceFormat::LcThree => trace_line(before, after),
+            TraceFormat::Compact => {
+                let mut line = format!("{:04X} {:04X}", before.pc(), after.r_ir);
+                for r in changed {
+                    line.push_str(&format!(" R{}={:04X}", r, after.get(r)));
+                }
+                line.push_str(&format!(" {}", after.condition_code()));
+                line
+            }
+            TraceFormat::Json => {
+                let changed: Vec<String> = changed
+                    .map(|r| format!("\"R{}\":{}", r, after.get(r)))
+                    .collect();
+                format!(
+                    "{{\"pc\":{},\"ir\":{},\"changed\":{{{}}},\"cc\":\"{}\"}}",
+                    before.pc(),
+                    after.r_ir,
+                    changed.join(","),
+                    after.condition_code()
+                )
+            }
+        }
+    }
+}
+
+/// `trace_line` describes the instruction which took the registers from `before` to `after`.
+pub(crate) fn trace_line(before: &Registers, after: &Registers) -> String {
+    let mut line = format!("AFTER x{:04X}:", after.pc());
+    for r in 0..8 {
+        if before.get(r) != after.get(r) {
+            line.push_str(&format!(" R{}=x{:04X}", r, after.get(r)));
+        }
+    }
+    line.push_str(&format!(" CC={}", after.condition_code()));
+    line
+}
+
+/// `TraceWriter` streams trace lines to a writer, through a buffer, so a trace does not have to fit in memory.
+/// The writer is not part of the machine state: a clone of it has no writer, and it never affects equality.
+/// After the first write error nothing more is written, and the error is kept for `flush`.
+#[derive(Default)]
+pub(crate) struct TraceWriter {
+    sink: Option<Sink>,
+    error: Option<io::Error>,
+}
+
+//the text lines, or their binary records (see `vm::binary_trace`)
+enum Sink {
+    Text(BufWriter<Box<dyn Write>>),
+    Binary(BinaryTraceWriter<BufWriter<Box<dyn Write>>>),
+}
+
+impl TraceWriter {
+    pub(crate) fn attach(&mut self, writer: Box<dyn Write>) {
+        self.sink = Some(Sink::Text(BufWriter::new(writer)));
+        self.error = None;
+    }
+
+    pub(crate) fn attach_binary(&mut self, writer: Box<dyn Write>) {
+        self.sink = Some(Sink::Binary(BinaryTraceWriter::new(BufWriter::new(writer))));
+        self.error = None;
+    }
+
+    pub(crate) fn is_attached(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// `write` records the instruction which took the registers from `before` to `after`, whose trace line is `line`.
+    pub(crate) fn write(&mut self, before: &Registers, after: &Registers, line: &str) {
+        if let (Some(sink), None) = (self.sink.as_mut(), self.error.as_ref()) {
+            let written = match sink {
+                Sink::Text(writer) => writeln!(writer, "{}", line),
+                Sink::Binary(writer) => writer.record(before, after),
+            };
+            if let Err(e) = written {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// `flush` pushes the buffered lines to the writer, or returns the first error met while writing.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match self.sink.as_mut() {
+            Some(Sink::Text(writer)) => writer.flush(),
+            Some(Sink::Binary(writer)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Clone for TraceWriter {
+    fn clone(&self) -> TraceWriter {
+        TraceWriter::default()
+    }
+}
+
+impl PartialEq for TraceWriter {
+    fn eq(&self, _: &TraceWriter) -> bool {
+        true
+    }
+}
+
+impl Eq for TraceWriter {}
+
+impl fmt::Debug for TraceWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceWriter")
+            .field("attached", &self.is_attached())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod trace_test {
+    use super::*;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::Vm;
+
+    #[test]
+    fn trace_should_record_changed_registers_and_cc() {
+        // ADD R0, R0, #5 ; ADD R1, R0, #-6 ; NOT R2, R2
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1025, 0x123A, 0x94BF]);
+        assert!(vm.trace().is_empty());
+        vm.enable_trace();
+        vm.step().unwrap();
+        assert_eq!(["AFTER x3001: R0=x0005 CC=P"], vm.trace());
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!("AFTER x3002: R1=xFFFF CC=N", vm.trace()[1]);
+        assert_eq!("AFTER x3003: R2=xFFFF CC=N", vm.trace()[2]);
+    }
+
+    #[test]
+    fn attached_trace_writer_should_receive_a_line_per_instruction() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        //a `Vec<u8>` the test can still read once the VM owns the writer
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+        impl Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = SharedBuffer::default();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1025, 0x1021, 0xF025]);
+        vm.attach_trace_writer(Box::new(buffer.clone()));
+        vm.step().unwrap();
+        vm.flush_trace_writer().unwrap();
+        assert_eq!(
+            b"AFTER x3001: R0=x0005 CC=P\n",
+            buffer.0.borrow().as_slice()
+        );
+        vm.run();
+        vm.flush_trace_writer().unwrap();
+        let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(3, text.lines().count());
+        assert_eq!(Some("AFTER x3002: R0=x0006 CC=P"), text.lines().nth(1));
+        //tracing to a writer does not also keep the trace in memory
+        assert!(vm.trace().is_empty());
+    }
+
+    #[test]
+    fn trace_format_should_change_the_rendering_of_an_add() {
+        // ADD R0, R0, #5
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_words(0x3000, &[0x1025]);
+        vm.enable_trace();
+        let lines: Vec<String> = [
+            TraceFormat::LcThree,
+            TraceFormat::Compact,
+            TraceFormat::Json,
+        ]
+        .iter()
+        .map(|&format| {
+            let mut vm = vm.clone();
+            vm.set_trace_format(format);
+            vm.step().unwrap();
+            vm.trace()[0].clone()
+        })
+        .collect();
+        assert_eq!(
+            vec![
+                "AFTER x3001: R0=x0005 CC=P",
+                "3000 1025 R0=0005 P",
+                "{\"pc\":12288,\"ir\":4133,\"changed\":{\"R0\":5},\"cc\":\"P\"}",
+            ],
+            lines
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! `trap_hook` : Lets an embedder implement trap vectors in Rust, e.g. custom system calls,
+//! before the built-in routines (or the OS image) get to see them: either with one hook which sees every TRAP,
+//! or with a registry of named routines, one per vector.
+
+use crate::hardware::memory::Memory;
+use crate::hardware::register::Registers;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// `TrapHandled` is the answer of a trap hook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapHandled {
+    /// The hook did the work of the TRAP; the default routine is skipped.
+    Handled,
+    /// The TRAP is not the hook's business; the default routine runs as usual.
+    NotHandled,
+}
+
+/// `TrapHookFn` is called with the trap vector, the registers and memory.
+pub(crate) type TrapHookFn = Box<dyn FnMut(u8, &mut Registers, &mut Memory) -> TrapHandled>;
+
+/// `TrapHook` is the hook set by `Vm::set_trap_hook`, if any. Like `RegisterListeners`, it is not part of
+/// the machine state: a clone has no hook, and it never affects equality.
+#[derive(Default)]
+pub(crate) struct TrapHook(Option<TrapHookFn>);
+
+impl TrapHook {
+    pub(crate) fn set(&mut self, hook: TrapHookFn) {
+        self.0 = Some(hook);
+    }
+
+    /// `call` gives the TRAP to the hook, returning `NotHandled` when there is none.
+    pub(crate) fn call(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory,
+    ) -> TrapHandled {
+        match self.0.as_mut() {
+            Some(hook) => hook(vector, registers, memory),
+            None => TrapHandled::NotHandled,
+        }
+    }
+}
+
+impl Clone for TrapHook {
+    fn clone(&self) -> TrapHook {
+        TrapHook::default()
+    }
+}
+
+impl PartialEq for TrapHook {
+    fn eq(&self, _: &TrapHook) -> bool {
+        true
+    }
+}
+
+impl Eq for TrapHook {}
+
+impl fmt::Debug for TrapHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrapHook")
+            .field("set", &self.0.is_some())
+            .finish()
+    }
+}
+
+/// `TrapRoutineFn` is a named trap routine, called with the registers and memory.
+pub(crate) type TrapRoutineFn = Box<dyn FnMut(&mut Registers, &mut Memory)>;
+
+/// `NamedTraps` are the routines registered with `Vm::register_named_trap`, by vector.
+/// Like `TrapHook`, they are not part of the machine state: a clone has none, and they never affect equality.
+#[derive(Default)]
+pub(crate) struct NamedTraps(BTreeMap<u8, (String, TrapRoutineFn)>);
+
+impl NamedTraps {
+    pub(crate) fn register(&mut self, vector: u8, name: &str, routine: TrapRoutineFn) {
+        self.0.insert(vector, (name.to_string(), routine));
+    }
+
+    /// `call` runs the routine registered for `vector`, returning `NotHandled` when there is none.
+    pub(crate) fn call(
+        &mut self,
+        vector: u8,
+        registers: &mut Registers,
+        memory: &mut Memory,
+    ) -> TrapHandled {
+        match self.0.get_mut(&vector) {
+            Some((_, routine)) => {
+                routine(registers, memory);
+                TrapHandled::Handled
+            }
+            None => TrapHandled::NotHandled,
+        }
+    }
+
+    /// `names` lists the vectors and names of the routines, in vector order.
+    pub(crate) fn names(&self) -> Vec<(u8, &str)> {
+        self.0
+            .iter()
+            .map(|(&vector, (name, _))| (vector, name.as_str()))
+            .collect()
+    }
+}
+
+impl Clone for NamedTraps {
+    fn clone(&self) -> NamedTraps {
+        NamedTraps::default()
+    }
+}
+
+impl PartialEq for NamedTraps {
+    fn eq(&self, _: &NamedTraps) -> bool {
+        true
+    }
+}
+
+impl Eq for NamedTraps {}
+
+impl fmt::Debug for NamedTraps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod trap_hook_test {
+    use super::*;
+    use crate::assembler;
+    use crate::hardware::io::BufferIo;
+    use crate::vm::{RunResult, Vm};
+
+    #[test]
+    fn trap_hook_should_override_out() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let program = assembler::assemble(
+            ".ORIG x3000
+             OUT
+             OUT
+             HALT
+             .END",
+        )
+        .unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.set_trap_hook(move |vector, registers, _| {
+            if vector != 0x21 {
+                return TrapHandled::NotHandled;
+            }
+            counter.set(counter.get() + 1);
+            registers.r_01 = registers.r_07;
+            TrapHandled::Handled
+        });
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(2, calls.get());
+        assert_eq!(0x3002, vm.reg(1));
+        assert_eq!("", vm.io().output_string());
+    }
+
+    #[test]
+    fn named_traps_should_be_listed_and_callable() {
+        let program = assembler::assemble(
+            ".ORIG x3000
+                  TRAP x40
+                  TRAP x41
+                  HALT
+                  .END",
+        )
+        .unwrap();
+        let mut vm = Vm::with_io(BufferIo::new());
+        vm.load_program(&program);
+        vm.register_named_trap(0x41, "DOUBLE", |registers, _| {
+            registers.update(0, registers.r_00.wrapping_mul(2))
+        });
+        vm.register_named_trap(0x40, "SEED", |registers, memory| {
+            registers.update(0, 21);
+            memory.write(0x4000, 1);
+        });
+        assert_eq!(vec![(0x40, "SEED"), (0x41, "DOUBLE")], vm.trap_routines());
+        assert_eq!(RunResult::Halted, vm.run());
+        assert_eq!(42, vm.reg(0));
+        assert_eq!(1, vm.peek(0x4000));
+        assert_eq!("", vm.io().output_string());
+    }
+}